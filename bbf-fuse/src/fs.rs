@@ -0,0 +1,192 @@
+//! The FUSE side: answers lookups and directory listings from the `Tree`
+//! and reads page files straight out of the mapped book.
+
+use crate::tree::{Kind, ROOT, Tree};
+use bbf::BBFReader;
+use bbf::format::AssetFlags;
+use bbf::reader::decode_asset;
+use fuser::{
+    Errno, FileAttr, FileHandle, FileType, Filesystem, Generation, INodeNo, LockOwner, OpenFlags,
+    ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, Request,
+};
+use memmap2::Mmap;
+use std::ffi::OsStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// The book never changes while mounted, so the kernel may cache freely.
+const TTL: Duration = Duration::from_hours(1);
+
+pub struct BookFs {
+    reader: BBFReader<Mmap>,
+    tree: Tree,
+    uid: u32,
+    gid: u32,
+    mtime: SystemTime,
+    /// The last compressed page read, since readers fetch a file in several
+    /// chunks and each would otherwise decompress it again.
+    decoded: Mutex<Option<(u32, Arc<[u8]>)>>,
+}
+
+impl BookFs {
+    pub fn new(reader: BBFReader<Mmap>, uid: u32, gid: u32, mtime: SystemTime) -> Self {
+        let tree = Tree::new(&reader);
+        Self {
+            reader,
+            tree,
+            uid,
+            gid,
+            mtime,
+            decoded: Mutex::new(None),
+        }
+    }
+
+    fn attr(&self, ino: u64) -> Option<FileAttr> {
+        let node = self.tree.get(ino)?;
+        let (kind, size, perm, nlink) = match node.kind {
+            Kind::Dir(_) => (FileType::Directory, 0, 0o555, 2),
+            Kind::Page { size, .. } => (FileType::RegularFile, size, 0o444, 1),
+        };
+        Some(FileAttr {
+            ino: INodeNo(ino),
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.mtime,
+            mtime: self.mtime,
+            ctime: self.mtime,
+            crtime: self.mtime,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            flags: 0,
+            blksize: 4096,
+        })
+    }
+
+    fn page_data(&self, asset: u32) -> Option<PageData<'_>> {
+        let stored = self.reader.get_asset(asset).ok()?;
+        let entry = &self.reader.assets()[asset as usize];
+        if !entry.asset_flags().contains(AssetFlags::COMPRESSED) {
+            return Some(PageData::Stored(stored));
+        }
+
+        let mut cache = self.decoded.lock().ok()?;
+        if let Some((cached, data)) = cache.as_ref()
+            && *cached == asset
+        {
+            return Some(PageData::Decoded(Arc::clone(data)));
+        }
+        let data: Arc<[u8]> = decode_asset(entry, stored).ok()?.into();
+        *cache = Some((asset, Arc::clone(&data)));
+        Some(PageData::Decoded(data))
+    }
+}
+
+enum PageData<'a> {
+    Stored(&'a [u8]),
+    Decoded(Arc<[u8]>),
+}
+
+impl AsRef<[u8]> for PageData<'_> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Stored(data) => data,
+            Self::Decoded(data) => data,
+        }
+    }
+}
+
+impl Filesystem for BookFs {
+    fn lookup(&self, _req: &Request, parent: INodeNo, name: &OsStr, reply: ReplyEntry) {
+        match name
+            .to_str()
+            .and_then(|name| self.tree.lookup(parent.0, name))
+            .and_then(|ino| self.attr(ino))
+        {
+            Some(attr) => reply.entry(&TTL, &attr, Generation(0)),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn getattr(&self, _req: &Request, ino: INodeNo, _fh: Option<FileHandle>, reply: ReplyAttr) {
+        match self.attr(ino.0) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(Errno::ENOENT),
+        }
+    }
+
+    fn read(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        size: u32,
+        _flags: OpenFlags,
+        _lock_owner: Option<LockOwner>,
+        reply: ReplyData,
+    ) {
+        let Some(node) = self.tree.get(ino.0) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+        let Kind::Page { asset, .. } = node.kind else {
+            reply.error(Errno::EISDIR);
+            return;
+        };
+        let Some(data) = self.page_data(asset) else {
+            log::error!("Failed to read asset {asset}");
+            reply.error(Errno::EIO);
+            return;
+        };
+        let data = data.as_ref();
+
+        let start = usize::try_from(offset).map_or(data.len(), |o| o.min(data.len()));
+        let end = start.saturating_add(size as usize).min(data.len());
+        reply.data(&data[start..end]);
+    }
+
+    fn readdir(
+        &self,
+        _req: &Request,
+        ino: INodeNo,
+        _fh: FileHandle,
+        offset: u64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(node) = self.tree.get(ino.0) else {
+            reply.error(Errno::ENOENT);
+            return;
+        };
+        let Kind::Dir(children) = &node.kind else {
+            reply.error(Errno::ENOTDIR);
+            return;
+        };
+
+        let parent = if ino.0 == ROOT { ROOT } else { node.parent };
+        let entries = [
+            (ino.0, FileType::Directory, "."),
+            (parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(children.iter().filter_map(|&c| {
+            let child = self.tree.get(c)?;
+            let kind = match child.kind {
+                Kind::Dir(_) => FileType::Directory,
+                Kind::Page { .. } => FileType::RegularFile,
+            };
+            Some((c, kind, child.name.as_str()))
+        }));
+
+        for (i, (ino, kind, name)) in entries.enumerate().skip(offset as usize) {
+            // The offset given is where the next call should resume.
+            if reply.add(INodeNo(ino), i as u64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
@@ -0,0 +1,82 @@
+//! `bbf-fuse`: mounts a book read-only, with sections as directories and
+//! pages as image files, so any viewer or file manager can browse it without
+//! extracting anything. Unmount with `fusermount -u` (or `umount`).
+
+#![allow(clippy::cast_possible_truncation)]
+
+#[cfg(unix)]
+mod fs;
+mod tree;
+
+use anyhow::Result;
+use clap::Parser;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Book to mount
+    book: PathBuf,
+    /// Empty directory to mount it on
+    mountpoint: PathBuf,
+    /// Let other users see the mount (needs `user_allow_other` in
+    /// /etc/fuse.conf)
+    #[arg(long)]
+    allow_other: bool,
+    /// Unmount when this process exits
+    #[arg(long)]
+    auto_unmount: bool,
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .parse_default_env()
+        .init();
+    run(&Cli::parse())
+}
+
+#[cfg(unix)]
+fn run(cli: &Cli) -> Result<()> {
+    use anyhow::Context;
+    use bbf::BBFReader;
+    use fuser::{Config, MountOption, SessionACL};
+    use memmap2::Mmap;
+    use std::fs::File;
+    use std::os::unix::fs::MetadataExt;
+
+    let file = File::open(&cli.book).context("Failed to open BBF")?;
+    let meta = file.metadata()?;
+    let mmap = unsafe { Mmap::map(&file).context("Failed to mmap BBF")? };
+    let reader = BBFReader::new(mmap).context("Failed to parse BBF")?;
+    let fs = fs::BookFs::new(reader, meta.uid(), meta.gid(), meta.modified()?);
+
+    let name = cli
+        .book
+        .file_name()
+        .map_or_else(|| "bbf".into(), |n| n.to_string_lossy());
+    let mut config = Config::default();
+    config.mount_options = vec![
+        MountOption::RO,
+        MountOption::FSName(name.into_owned()),
+        MountOption::Subtype("bbf".to_string()),
+    ];
+    if cli.auto_unmount {
+        config.mount_options.push(MountOption::AutoUnmount);
+    }
+    if cli.allow_other {
+        config.acl = SessionACL::All;
+    }
+
+    eprintln!(
+        "Mounted {} on {} (unmount to exit)",
+        cli.book.display(),
+        cli.mountpoint.display()
+    );
+    fuser::mount(fs, &cli.mountpoint, &config).context("Failed to mount")
+}
+
+#[cfg(not(unix))]
+fn run(_cli: &Cli) -> Result<()> {
+    anyhow::bail!("bbf-fuse needs FUSE, which this platform doesn't have.")
+}
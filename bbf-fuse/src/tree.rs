@@ -0,0 +1,172 @@
+//! The directory layout of a mounted book: each section is a directory
+//! inside its parent's, and each page a file in the directory of the section
+//! it falls under, named after the file it was built from.
+
+use bbf::BBFMediaType;
+use bbf::BBFReader;
+use bbf::format::AssetFlags;
+use std::collections::HashSet;
+
+pub const ROOT: u64 = 1;
+
+pub enum Kind {
+    Dir(Vec<u64>),
+    Page { asset: u32, size: u64 },
+}
+
+pub struct Node {
+    pub parent: u64,
+    pub name: String,
+    pub kind: Kind,
+}
+
+/// Nodes by inode number; inode `n` is `nodes[n - 1]`.
+pub struct Tree {
+    nodes: Vec<Node>,
+    /// Names already used in each directory, by inode.
+    taken: Vec<HashSet<String>>,
+}
+
+impl Tree {
+    pub fn new<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Self {
+        let mut tree = Self {
+            nodes: vec![Node {
+                parent: ROOT,
+                name: String::new(),
+                kind: Kind::Dir(Vec::new()),
+            }],
+            taken: vec![HashSet::new()],
+        };
+
+        // Parents are written before their subsections; a parent index that
+        // isn't earlier in the table is broken, and the section goes at the
+        // top level.
+        let mut section_dirs = Vec::with_capacity(reader.sections().len());
+        for (i, section) in reader.sections().iter().enumerate() {
+            let parent = section.parent_section_index.get() as usize;
+            let parent_dir = if parent < i {
+                section_dirs[parent]
+            } else {
+                ROOT
+            };
+            let title = reader
+                .get_string(section.section_title_offset.get())
+                .unwrap_or("");
+            let name = if title.trim().is_empty() {
+                format!("Section {}", i + 1)
+            } else {
+                sanitize(title)
+            };
+            section_dirs.push(tree.add(parent_dir, name, Kind::Dir(Vec::new())));
+        }
+
+        let assets = reader.assets();
+        for (page, owner) in owning_sections(reader).into_iter().enumerate() {
+            let asset = reader.pages()[page].asset_index.get();
+            let Some(entry) = assets.get(asset as usize) else {
+                log::warn!("Page {} points at a missing asset, skipping.", page + 1);
+                continue;
+            };
+            let name = reader.page_name(page as u32).map_or_else(
+                || {
+                    let ext = BBFMediaType::from(entry.type_).as_extension();
+                    format!("{:04}{ext}", page + 1)
+                },
+                sanitize,
+            );
+            let size = if !entry.asset_flags().contains(AssetFlags::COMPRESSED) {
+                entry.length.get()
+            } else {
+                entry.decoded_length.get()
+            };
+            let dir = owner.map_or(ROOT, |s| section_dirs[s]);
+            tree.add(dir, name, Kind::Page { asset, size });
+        }
+
+        tree
+    }
+
+    pub fn get(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get(usize::try_from(ino).ok()?.checked_sub(1)?)
+    }
+
+    pub fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        let Kind::Dir(children) = &self.get(parent)?.kind else {
+            return None;
+        };
+        children
+            .iter()
+            .copied()
+            .find(|&c| self.get(c).is_some_and(|n| n.name == name))
+    }
+
+    fn add(&mut self, parent: u64, name: String, kind: Kind) -> u64 {
+        let name = unique(&mut self.taken[parent as usize - 1], name);
+        self.nodes.push(Node { parent, name, kind });
+        self.taken.push(HashSet::new());
+        let ino = self.nodes.len() as u64;
+
+        if let Kind::Dir(children) = &mut self.nodes[parent as usize - 1].kind {
+            children.push(ino);
+        }
+        ino
+    }
+}
+
+/// For each page, the section it falls under: the one with the greatest start
+/// index at or before the page. On ties the later table entry wins, since
+/// subsections are written after their parents.
+fn owning_sections<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<Option<usize>> {
+    let mut starts: Vec<(u32, usize)> = reader
+        .sections()
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.section_start_index.get(), i))
+        .collect();
+    starts.sort_unstable();
+
+    let mut owners = vec![None; reader.pages().len()];
+    let mut next = 0;
+    let mut current = None;
+    for (page, owner) in owners.iter_mut().enumerate() {
+        while next < starts.len() && starts[next].0 as usize <= page {
+            current = Some(starts[next].1);
+            next += 1;
+        }
+        *owner = current;
+    }
+    owners
+}
+
+/// A file name from a title or stored page name: its last path component,
+/// with no separators or NULs left.
+fn sanitize(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let clean: String = base
+        .chars()
+        .map(|c| if c == '\0' { '_' } else { c })
+        .collect();
+    match clean.as_str() {
+        "" | "." | ".." => "_".to_string(),
+        _ => clean,
+    }
+}
+
+/// `name`, or `name (2)`, `name (3)`, ... before its extension if taken.
+fn unique(taken: &mut HashSet<String>, name: String) -> String {
+    if taken.insert(name.clone()) {
+        return name;
+    }
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name.as_str(), ""),
+    };
+    let mut n = 2;
+    loop {
+        let candidate = format!("{stem} ({n}){ext}");
+        if taken.insert(candidate.clone()) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
@@ -0,0 +1,528 @@
+//! Python bindings, built into a `bbf` module with maturin. Books open from
+//! a path (memory-mapped) or from bytes; pages iterate lazily and expose
+//! their image through the buffer protocol, so `bytes(page)` and
+//! `memoryview(page)` work without going through a method.
+
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::needless_pass_by_value
+)]
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ffi::{c_int, c_void};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use ::bbf::diff::Change;
+use ::bbf::hash;
+use ::bbf::reader::BBFError;
+use ::bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use memmap2::Mmap;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use xxhash_rust::xxh3::xxh3_64;
+
+fn bbf_error(e: BBFError) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Bytes of an open book, mapped from a file or handed over from Python.
+enum Data {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for Data {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(m) => m,
+            Self::Owned(v) => v,
+        }
+    }
+}
+
+/// `BbfReader(source)` opens the book at a path, or in a bytes-like object.
+#[pyclass(frozen, module = "bbf")]
+struct BbfReader {
+    reader: BBFReader<Data>,
+}
+
+#[pymethods]
+impl BbfReader {
+    #[new]
+    fn new(source: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let data = if let Ok(bytes) = source.extract::<Vec<u8>>() {
+            Data::Owned(bytes)
+        } else {
+            let file = File::open(source.extract::<PathBuf>()?)?;
+            // SAFETY: the mapping is read-only; a file changed underneath it
+            // shows up as corrupt pages, as in bbfmux.
+            Data::Mapped(unsafe { Mmap::map(&file)? })
+        };
+        Ok(Self {
+            reader: BBFReader::new(data).map_err(bbf_error)?,
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.reader.pages().len()
+    }
+
+    /// Page `index`, counting from the end when negative.
+    fn __getitem__(slf: &Bound<'_, Self>, index: isize) -> PyResult<Page> {
+        let len = slf.get().reader.pages().len();
+        let index = if index < 0 {
+            index + len as isize
+        } else {
+            index
+        };
+        match usize::try_from(index) {
+            Ok(index) if index < len => Page::new(slf, index as u32),
+            _ => Err(PyIndexError::new_err("page index out of range")),
+        }
+    }
+
+    /// The pages in reading order, read one at a time.
+    fn pages(slf: &Bound<'_, Self>) -> PageIter {
+        PageIter {
+            reader: slf.clone().unbind(),
+            next: 0,
+        }
+    }
+
+    fn sections(&self) -> Vec<Section> {
+        self.reader
+            .sections()
+            .iter()
+            .map(|s| {
+                let parent = s.parent_section_index.get();
+                Section {
+                    title: self
+                        .reader
+                        .get_string(s.section_title_offset.get())
+                        .unwrap_or("")
+                        .to_string(),
+                    start_page: s.section_start_index.get(),
+                    parent: (parent != u32::MAX).then_some(parent),
+                }
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> HashMap<String, String> {
+        self.reader
+            .metadata()
+            .iter()
+            .map(|m| {
+                let key = self.reader.get_string(m.key_offset.get()).unwrap_or("");
+                let value = self.reader.get_string(m.val_offset.get()).unwrap_or("");
+                (key.to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    /// The embedded thumbnail of page `index`, if the book has one.
+    fn thumbnail<'py>(&self, py: Python<'py>, index: u32) -> PyResult<Option<Bound<'py, PyBytes>>> {
+        let Some(asset) = self.reader.thumbnail(index) else {
+            return Ok(None);
+        };
+        let data = self.reader.get_asset_decoded(asset).map_err(bbf_error)?;
+        Ok(Some(PyBytes::new(py, &data)))
+    }
+
+    #[getter]
+    fn index_hash(&self) -> u64 {
+        self.reader.footer.index_hash.get()
+    }
+
+    /// Indices of the assets whose bytes don't match their hash in the
+    /// index; empty when the book is intact. Raises `ValueError` for books
+    /// hashed with anything but XXH3, which this can't check.
+    fn verify(&self, py: Python<'_>) -> PyResult<Vec<u32>> {
+        let reader = &self.reader;
+        let algorithm = reader.hash_algorithm();
+        if algorithm != hash::XXH3 {
+            return Err(PyValueError::new_err(format!(
+                "unsupported hash: assets are hashed with {}, only XXH3 can be checked",
+                hash::name(algorithm).map_or_else(|| format!("#{algorithm}"), str::to_string)
+            )));
+        }
+        Ok(py.detach(|| {
+            (0..reader.assets().len() as u32)
+                .filter(|&i| {
+                    let expected = reader.assets()[i as usize].xxh3_hash.get();
+                    reader
+                        .get_asset(i)
+                        .map_or(true, |data| xxh3_64(data) != expected)
+                })
+                .collect()
+        }))
+    }
+}
+
+#[pyclass(module = "bbf")]
+struct PageIter {
+    reader: Py<BbfReader>,
+    next: u32,
+}
+
+#[pymethods]
+impl PageIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Page>> {
+        let reader = self.reader.bind(py);
+        if self.next as usize >= reader.get().reader.pages().len() {
+            return Ok(None);
+        }
+        let page = Page::new(reader, self.next)?;
+        self.next += 1;
+        Ok(Some(page))
+    }
+}
+
+/// One page. Its image is read through the buffer protocol, straight out of
+/// the book unless it had to be decompressed.
+#[pyclass(frozen, module = "bbf")]
+struct Page {
+    reader: Py<BbfReader>,
+    #[pyo3(get)]
+    index: u32,
+    #[pyo3(get)]
+    asset_index: u32,
+    #[pyo3(get)]
+    flags: u32,
+    decoded: Option<Vec<u8>>,
+}
+
+impl Page {
+    fn new(reader: &Bound<'_, BbfReader>, index: u32) -> PyResult<Self> {
+        let r = &reader.get().reader;
+        let entry = &r.pages()[index as usize];
+        let asset_index = entry.asset_index.get();
+        let decoded = match r.get_asset_decoded(asset_index).map_err(bbf_error)? {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(data) => Some(data),
+        };
+        Ok(Self {
+            reader: reader.clone().unbind(),
+            index,
+            asset_index,
+            flags: entry.flags.get(),
+            decoded,
+        })
+    }
+
+    fn data(&self) -> &[u8] {
+        match &self.decoded {
+            Some(data) => data,
+            None => self
+                .reader
+                .get()
+                .reader
+                .get_asset(self.asset_index)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn entry(&self) -> &::bbf::format::BBFAssetEntry {
+        &self.reader.get().reader.assets()[self.asset_index as usize]
+    }
+}
+
+#[pymethods]
+impl Page {
+    /// The image format as a file extension without the dot, such as `png`.
+    #[getter]
+    fn media_type(&self) -> &'static str {
+        &BBFMediaType::from(self.entry().type_).as_extension()[1..]
+    }
+
+    /// The media type byte as stored, including values `media_type` can
+    /// only report as `bin`.
+    #[getter]
+    fn media_type_id(&self) -> u8 {
+        self.entry().type_
+    }
+
+    /// The file name the page was built from, if the book kept it.
+    #[getter]
+    fn name(&self) -> Option<String> {
+        self.reader
+            .get()
+            .reader
+            .page_name(self.index)
+            .map(str::to_string)
+    }
+
+    fn __len__(&self) -> usize {
+        self.data().len()
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, self.data())
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "<Page {} ({}, {} bytes)>",
+            self.index,
+            self.media_type(),
+            self.data().len()
+        )
+    }
+
+    /// # Safety
+    ///
+    /// `view` comes from Python's buffer machinery. The data it points at
+    /// lives as long as the page, which the view keeps a reference to.
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let data = slf.get().data();
+        let filled = unsafe {
+            ffi::PyBuffer_FillInfo(
+                view,
+                slf.as_ptr(),
+                data.as_ptr().cast_mut().cast::<c_void>(),
+                data.len() as ffi::Py_ssize_t,
+                1,
+                flags,
+            )
+        };
+        if filled == -1 {
+            return Err(PyErr::fetch(slf.py()));
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(frozen, get_all, module = "bbf")]
+struct Section {
+    title: String,
+    /// First page, counting from zero.
+    start_page: u32,
+    /// Index of the enclosing section.
+    parent: Option<u32>,
+}
+
+#[pymethods]
+impl Section {
+    fn __repr__(&self) -> String {
+        format!("<Section {:?} at page {}>", self.title, self.start_page)
+    }
+}
+
+/// What `compare` found: `identical` is true when no page, section or
+/// metadata entry differs.
+#[pyclass(frozen, get_all, module = "bbf")]
+struct BookDiff {
+    identical: bool,
+    pages: Vec<PageDelta>,
+    sections: Vec<SectionDelta>,
+    metadata: Vec<MetaDelta>,
+}
+
+#[pymethods]
+impl BookDiff {
+    fn __repr__(&self) -> String {
+        format!(
+            "<BookDiff identical={} pages={} sections={} metadata={}>",
+            if self.identical { "True" } else { "False" },
+            self.pages.len(),
+            self.sections.len(),
+            self.metadata.len()
+        )
+    }
+}
+
+/// A page that differs by position; hashes are hex, `None` past either end.
+#[pyclass(frozen, get_all, skip_from_py_object, module = "bbf")]
+#[derive(Clone)]
+struct PageDelta {
+    index: u32,
+    change: &'static str,
+    old_hash: Option<String>,
+    new_hash: Option<String>,
+}
+
+#[pyclass(frozen, get_all, skip_from_py_object, module = "bbf")]
+#[derive(Clone)]
+struct SectionDelta {
+    title: String,
+    change: &'static str,
+    old_start: Option<u32>,
+    new_start: Option<u32>,
+}
+
+#[pyclass(frozen, get_all, skip_from_py_object, module = "bbf")]
+#[derive(Clone)]
+struct MetaDelta {
+    key: String,
+    change: &'static str,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+const fn change_name(change: Change) -> &'static str {
+    match change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Changed => "changed",
+    }
+}
+
+/// Compares two books page by page (by asset hash), section by section (by
+/// title) and key by key.
+#[pyfunction]
+fn compare(py: Python<'_>, a: &BbfReader, b: &BbfReader) -> BookDiff {
+    let diff = py.detach(|| ::bbf::compare(&a.reader, &b.reader));
+    let hex = |h: Option<u64>| h.map(|h| format!("{h:016x}"));
+    BookDiff {
+        identical: diff.identical,
+        pages: diff
+            .pages
+            .into_iter()
+            .map(|p| PageDelta {
+                index: p.index,
+                change: change_name(p.change),
+                old_hash: hex(p.old_hash),
+                new_hash: hex(p.new_hash),
+            })
+            .collect(),
+        sections: diff
+            .sections
+            .into_iter()
+            .map(|s| SectionDelta {
+                title: s.title,
+                change: change_name(s.change),
+                old_start: s.old_start,
+                new_start: s.new_start,
+            })
+            .collect(),
+        metadata: diff
+            .metadata
+            .into_iter()
+            .map(|m| MetaDelta {
+                key: m.key,
+                change: change_name(m.change),
+                old: m.old,
+                new: m.new,
+            })
+            .collect(),
+    }
+}
+
+/// A page format, named by extension or given as its type byte.
+#[derive(FromPyObject)]
+enum MediaTypeArg {
+    Id(u8),
+    Extension(String),
+}
+
+impl From<MediaTypeArg> for BBFMediaType {
+    fn from(arg: MediaTypeArg) -> Self {
+        match arg {
+            MediaTypeArg::Id(id) => Self::from(id),
+            MediaTypeArg::Extension(ext) => {
+                Self::from_extension(&format!(".{}", ext.trim_start_matches('.')))
+            }
+        }
+    }
+}
+
+/// `BbfBuilder(path)` writes a new book to `path`. As a context manager it
+/// finalizes the book when the block exits without an error.
+#[pyclass(module = "bbf")]
+struct BbfBuilder {
+    builder: Option<BBFBuilder<BufWriter<File>>>,
+}
+
+impl BbfBuilder {
+    fn builder(&mut self) -> PyResult<&mut BBFBuilder<BufWriter<File>>> {
+        self.builder
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the book has already been finalized"))
+    }
+}
+
+#[pymethods]
+impl BbfBuilder {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Ok(Self {
+            builder: Some(BBFBuilder::new(file)?),
+        })
+    }
+
+    /// Appends a page. `media_type` names its format by extension, such as
+    /// `"png"` or `".jpg"`, or is a type byte like `Page.media_type_id`.
+    /// Returns the index of the asset holding it, which is shared with
+    /// earlier pages of identical content.
+    #[pyo3(signature = (data, media_type, flags = 0))]
+    fn add_page(&mut self, data: &[u8], media_type: MediaTypeArg, flags: u32) -> PyResult<u32> {
+        Ok(self.builder()?.add_page(data, media_type.into(), flags)?)
+    }
+
+    #[pyo3(signature = (title, start_page, parent = None))]
+    fn add_section(&mut self, title: &str, start_page: u32, parent: Option<u32>) -> PyResult<()> {
+        self.builder()?.add_section(title, start_page, parent);
+        Ok(())
+    }
+
+    fn add_metadata(&mut self, key: &str, value: &str) -> PyResult<()> {
+        self.builder()?.add_metadata(key, value);
+        Ok(())
+    }
+
+    /// Writes the index and closes the file.
+    fn finalize(&mut self) -> PyResult<()> {
+        let builder = self
+            .builder
+            .take()
+            .ok_or_else(|| PyValueError::new_err("the book has already been finalized"))?;
+        builder.finalize()?;
+        Ok(())
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        exc_type: Option<&Bound<'_, PyAny>>,
+        _exc_value: Option<&Bound<'_, PyAny>>,
+        _traceback: Option<&Bound<'_, PyAny>>,
+    ) -> PyResult<bool> {
+        if exc_type.is_none() && self.builder.is_some() {
+            self.finalize()?;
+        }
+        Ok(false)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "bbf")]
+fn bbf_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<BbfReader>()?;
+    m.add_class::<BbfBuilder>()?;
+    m.add_class::<Page>()?;
+    m.add_class::<Section>()?;
+    m.add_class::<BookDiff>()?;
+    m.add_class::<PageDelta>()?;
+    m.add_class::<SectionDelta>()?;
+    m.add_class::<MetaDelta>()?;
+    m.add_function(wrap_pyfunction!(compare, m)?)?;
+    Ok(())
+}
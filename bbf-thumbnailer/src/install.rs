@@ -0,0 +1,85 @@
+//! The files that tell a desktop what a `.bbf` is and how to preview it,
+//! and putting them where shared-mime-info and thumbnailers look.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const BINARY: &str = "bbf-thumbnailer";
+
+pub const MIME_TYPE: &str = "application/x-bbf";
+
+pub const MIME_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<mime-info xmlns="http://www.freedesktop.org/standards/shared-mime-info">
+  <mime-type type="application/x-bbf">
+    <comment>Bound Book Format book</comment>
+    <generic-icon name="x-office-document"/>
+    <magic priority="60">
+      <match type="string" value="BBF1" offset="0"/>
+    </magic>
+    <glob pattern="*.bbf"/>
+  </mime-type>
+</mime-info>
+"#;
+
+/// A `.thumbnailer` entry that runs `exec`.
+pub fn thumbnailer_entry(exec: &str) -> String {
+    format!(
+        "[Thumbnailer Entry]\n\
+         TryExec={exec}\n\
+         Exec={exec} thumbnail --size %s %i %o\n\
+         MimeType={MIME_TYPE};\n"
+    )
+}
+
+/// Writes `mime/packages/bbf.xml` and `thumbnailers/bbf.thumbnailer` under
+/// `prefix` (the user's data directory by default), then runs
+/// `update-mime-database` if it's installed.
+pub fn install(prefix: Option<PathBuf>) -> Result<()> {
+    let prefix = match prefix {
+        Some(p) => p,
+        None => user_data_dir()?,
+    };
+
+    let mime_dir = prefix.join("mime");
+    write(&mime_dir.join("packages/bbf.xml"), MIME_XML)?;
+
+    // The thumbnailer may run without our PATH, so point at this binary.
+    let exe = env::current_exe().context("Failed to locate this binary")?;
+    let entry = thumbnailer_entry(&exe.to_string_lossy());
+    write(&prefix.join("thumbnailers/bbf.thumbnailer"), &entry)?;
+
+    match Command::new("update-mime-database").arg(&mime_dir).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("Warning: update-mime-database failed ({status})"),
+        Err(_) => eprintln!(
+            "Warning: update-mime-database not found; run it on {} once installed",
+            mime_dir.display()
+        ),
+    }
+    println!(
+        "Installed. File managers may need restarting, and previously failed thumbnails \
+         clearing from ~/.cache/thumbnails/fail."
+    );
+    Ok(())
+}
+
+fn user_data_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    let home = env::var_os("HOME").context("Neither XDG_DATA_HOME nor HOME is set")?;
+    Ok(Path::new(&home).join(".local/share"))
+}
+
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! `bbf-thumbnailer`: renders a book's cover as a PNG for file managers
+//! that follow the freedesktop.org thumbnail spec (Nautilus, Nemo, Caja,
+//! Thunar through tumbler), and registers `.bbf` files with them: the
+//! shared-mime-info type, matched by name and by the `BBF1` magic, and a
+//! `.thumbnailer` entry pointing here.
+
+mod install;
+
+use anyhow::{Context, Result, bail};
+use bbf::{BBFMediaType, BBFReader, imaging, thumbs};
+use clap::{Parser, Subcommand};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the cover of INPUT to OUTPUT as a PNG; what file managers run
+    Thumbnail {
+        /// Longest side of the thumbnail in pixels
+        #[arg(short, long, default_value_t = 256)]
+        size: u32,
+        /// Book to render, as a path or `file://` URI
+        input: String,
+        /// PNG to write
+        output: PathBuf,
+    },
+    /// Print the shared-mime-info XML that defines `application/x-bbf`
+    MimeXml,
+    /// Print the `.thumbnailer` entry that runs this binary
+    Entry {
+        /// Command to put in `Exec`, if not `bbf-thumbnailer` on PATH
+        #[arg(long)]
+        exec: Option<String>,
+    },
+    /// Install both for the current user (or under --prefix) and refresh
+    /// the MIME database
+    Install {
+        /// Data directory to install into [default: the user's, usually
+        /// ~/.local/share]
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+    },
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Thumbnail {
+            size,
+            input,
+            output,
+        } => thumbnail(&input_path(&input)?, &output, size),
+        Command::MimeXml => {
+            print!("{}", install::MIME_XML);
+            Ok(())
+        }
+        Command::Entry { exec } => {
+            print!(
+                "{}",
+                install::thumbnailer_entry(exec.as_deref().unwrap_or(install::BINARY))
+            );
+            Ok(())
+        }
+        Command::Install { prefix } => install::install(prefix),
+    }
+}
+
+fn thumbnail(input: &Path, output: &Path, size: u32) -> Result<()> {
+    let file = File::open(input).with_context(|| format!("Failed to open {}", input.display()))?;
+    let mmap = unsafe { Mmap::map(&file).context("Failed to mmap BBF")? };
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    let Some(cover) = thumbs::cover(&reader, size)? else {
+        bail!("Book has no pages");
+    };
+    let png = imaging::encode(&cover, BBFMediaType::Png, 100)?;
+    fs::write(output, png).with_context(|| format!("Failed to write {}", output.display()))
+}
+
+/// Thumbnailers get `%i` as a local path, but `%u` entries and some file
+/// managers pass a `file://` URI.
+fn input_path(input: &str) -> Result<PathBuf> {
+    let Some(rest) = input.strip_prefix("file://") else {
+        return Ok(PathBuf::from(input));
+    };
+    let path = rest.trim_start_matches("localhost");
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hex = [iter.next(), iter.next()];
+            let [Some(hi), Some(lo)] = hex else {
+                bail!("Malformed URI {input}");
+            };
+            let byte = std::str::from_utf8(&[hi, lo])
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .with_context(|| format!("Malformed URI {input}"))?;
+            bytes.push(byte);
+        } else {
+            bytes.push(b);
+        }
+    }
+    Ok(PathBuf::from(
+        String::from_utf8(bytes).with_context(|| format!("Malformed URI {input}"))?,
+    ))
+}
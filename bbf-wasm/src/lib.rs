@@ -0,0 +1,331 @@
+//! `wasm-bindgen` bindings for reading and writing books from JavaScript,
+//! for projects that don't go through the Leptos webapp. Pages come back as
+//! `Uint8Array`s and the index as plain objects, typed in the generated
+//! TypeScript definitions.
+//!
+//! Built without the `zstd` feature, so pages of compressed books fail to
+//! decode.
+
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::missing_errors_doc,
+    clippy::must_use_candidate
+)]
+
+use std::io::Cursor;
+
+use bbf::diff::Change;
+use bbf::format::BBFAssetEntry;
+use bbf::hash;
+use bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use wasm_bindgen::prelude::*;
+use xxhash_rust::xxh3::xxh3_64;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_TYPES: &str = r#"
+export interface BbfSection {
+    title: string;
+    /** First page, counting from zero. */
+    startPage: number;
+    /** Index of the enclosing section, if any. */
+    parent?: number;
+}
+
+export interface BbfMetadata {
+    key: string;
+    value: string;
+}
+
+export type BbfChange = "added" | "removed" | "changed";
+
+export interface BbfBookDiff {
+    /** No page, section or metadata differs. */
+    identical: boolean;
+    /** Pages compared by position; hashes are hex, absent past either end. */
+    pages: { index: number; change: BbfChange; oldHash?: string; newHash?: string }[];
+    sections: { title: string; change: BbfChange; oldStart?: number; newStart?: number }[];
+    metadata: { key: string; change: BbfChange; old?: string; new?: string }[];
+}
+"#;
+
+const fn mime_type(media_type: BBFMediaType) -> &'static str {
+    match media_type {
+        BBFMediaType::Png => "image/png",
+        BBFMediaType::Jpg => "image/jpeg",
+        BBFMediaType::Avif => "image/avif",
+        BBFMediaType::Webp => "image/webp",
+        BBFMediaType::Jxl => "image/jxl",
+        BBFMediaType::Bmp => "image/bmp",
+        BBFMediaType::Gif => "image/gif",
+        BBFMediaType::Tiff => "image/tiff",
+        BBFMediaType::Unknown | BBFMediaType::Other(_) => "application/octet-stream",
+    }
+}
+
+/// A plain object from `(key, value)` pairs.
+fn object(fields: &[(&str, JsValue)]) -> Object {
+    let obj = Object::new();
+    for (key, value) in fields {
+        let _ = Reflect::set(&obj, &(*key).into(), value);
+    }
+    obj
+}
+
+const fn change_name(change: Change) -> &'static str {
+    match change {
+        Change::Added => "added",
+        Change::Removed => "removed",
+        Change::Changed => "changed",
+    }
+}
+
+/// Pages, sections and metadata that differ between two books.
+#[wasm_bindgen(js_name = compareBooks, unchecked_return_type = "BbfBookDiff")]
+pub fn compare_books(a: &WasmBbfReader, b: &WasmBbfReader) -> Object {
+    let diff = bbf::compare(&a.reader, &b.reader);
+    let opt = |key: &'static str, value: Option<JsValue>| value.map(|v| (key, v));
+
+    let pages: Array = diff
+        .pages
+        .iter()
+        .map(|p| {
+            let mut fields = vec![
+                ("index", p.index.into()),
+                ("change", change_name(p.change).into()),
+            ];
+            fields.extend(opt(
+                "oldHash",
+                p.old_hash.map(|h| format!("{h:016x}").into()),
+            ));
+            fields.extend(opt(
+                "newHash",
+                p.new_hash.map(|h| format!("{h:016x}").into()),
+            ));
+            JsValue::from(object(&fields))
+        })
+        .collect();
+    let sections: Array = diff
+        .sections
+        .iter()
+        .map(|s| {
+            let mut fields = vec![
+                ("title", s.title.as_str().into()),
+                ("change", change_name(s.change).into()),
+            ];
+            fields.extend(opt("oldStart", s.old_start.map(Into::into)));
+            fields.extend(opt("newStart", s.new_start.map(Into::into)));
+            JsValue::from(object(&fields))
+        })
+        .collect();
+    let metadata: Array = diff
+        .metadata
+        .iter()
+        .map(|m| {
+            let mut fields = vec![
+                ("key", m.key.as_str().into()),
+                ("change", change_name(m.change).into()),
+            ];
+            fields.extend(opt("old", m.old.as_deref().map(Into::into)));
+            fields.extend(opt("new", m.new.as_deref().map(Into::into)));
+            JsValue::from(object(&fields))
+        })
+        .collect();
+
+    object(&[
+        ("identical", diff.identical.into()),
+        ("pages", pages.into()),
+        ("sections", sections.into()),
+        ("metadata", metadata.into()),
+    ])
+}
+
+/// A book held in memory.
+#[wasm_bindgen(js_name = BbfReader)]
+pub struct WasmBbfReader {
+    reader: BBFReader<Vec<u8>>,
+}
+
+#[wasm_bindgen(js_class = BbfReader)]
+impl WasmBbfReader {
+    /// Parses the book in `data`, which is copied into wasm memory.
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>) -> Result<Self, JsError> {
+        Ok(Self {
+            reader: BBFReader::new(data)?,
+        })
+    }
+
+    #[wasm_bindgen(getter, js_name = pageCount)]
+    pub fn page_count(&self) -> u32 {
+        self.reader.pages().len() as u32
+    }
+
+    #[wasm_bindgen(getter, js_name = assetCount)]
+    pub fn asset_count(&self) -> u32 {
+        self.reader.assets().len() as u32
+    }
+
+    /// The index hash in hex. It identifies the book across renames and is
+    /// too wide for a JS number.
+    #[wasm_bindgen(getter, js_name = indexHash)]
+    pub fn index_hash(&self) -> String {
+        format!("{:016x}", self.reader.footer.index_hash.get())
+    }
+
+    /// The image of page `index`, decompressed if it was stored compressed.
+    pub fn page(&self, index: u32) -> Result<Uint8Array, JsError> {
+        let data = self.reader.get_page(index)?;
+        Ok(Uint8Array::from(&data[..]))
+    }
+
+    /// MIME type of page `index`, for building a `Blob` around it.
+    #[wasm_bindgen(js_name = pageMimeType)]
+    pub fn page_mime_type(&self, index: u32) -> Result<String, JsError> {
+        let entry = self.page_entry(index)?;
+        Ok(mime_type(BBFMediaType::from(entry.type_)).to_string())
+    }
+
+    /// The media type byte of page `index` as stored, including values
+    /// this version has no MIME type for.
+    #[wasm_bindgen(js_name = pageMediaTypeId)]
+    pub fn page_media_type_id(&self, index: u32) -> Result<u8, JsError> {
+        Ok(self.page_entry(index)?.type_)
+    }
+
+    /// The embedded thumbnail of page `index`, if the book has one.
+    pub fn thumbnail(&self, index: u32) -> Result<Option<Uint8Array>, JsError> {
+        let Some(asset) = self.reader.thumbnail(index) else {
+            return Ok(None);
+        };
+        let data = self.reader.get_asset_decoded(asset)?;
+        Ok(Some(Uint8Array::from(&data[..])))
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "BbfSection[]")]
+    pub fn sections(&self) -> Array {
+        self.reader
+            .sections()
+            .iter()
+            .map(|s| {
+                let title = self.reader.get_string(s.section_title_offset.get());
+                let parent = s.parent_section_index.get();
+                let mut fields = vec![
+                    ("title", JsValue::from(title.unwrap_or(""))),
+                    ("startPage", s.section_start_index.get().into()),
+                ];
+                if parent != u32::MAX {
+                    fields.push(("parent", parent.into()));
+                }
+                JsValue::from(object(&fields))
+            })
+            .collect()
+    }
+
+    #[wasm_bindgen(unchecked_return_type = "BbfMetadata[]")]
+    pub fn metadata(&self) -> Array {
+        self.reader
+            .metadata()
+            .iter()
+            .map(|m| {
+                let key = self.reader.get_string(m.key_offset.get()).unwrap_or("");
+                let value = self.reader.get_string(m.val_offset.get()).unwrap_or("");
+                JsValue::from(object(&[("key", key.into()), ("value", value.into())]))
+            })
+            .collect()
+    }
+
+    /// Indices of the assets whose bytes don't match their hash in the
+    /// index; empty when the book is intact. Throws for books hashed with
+    /// anything but XXH3, which this can't check.
+    #[wasm_bindgen(js_name = corruptAssets)]
+    pub fn corrupt_assets(&self) -> Result<Vec<u32>, JsError> {
+        let algorithm = self.reader.hash_algorithm();
+        if algorithm != hash::XXH3 {
+            return Err(JsError::new(&format!(
+                "Unsupported hash: assets are hashed with {}, only XXH3 can be checked",
+                hash::name(algorithm).map_or_else(|| format!("#{algorithm}"), str::to_string)
+            )));
+        }
+        Ok((0..self.reader.assets().len() as u32)
+            .filter(|&i| {
+                let expected = self.reader.assets()[i as usize].xxh3_hash.get();
+                self.reader
+                    .get_asset(i)
+                    .map_or(true, |data| xxh3_64(data) != expected)
+            })
+            .collect())
+    }
+}
+
+impl WasmBbfReader {
+    fn page_asset(&self, index: u32) -> Result<u32, JsError> {
+        self.reader
+            .pages()
+            .get(index as usize)
+            .map(|p| p.asset_index.get())
+            .ok_or_else(|| JsError::new("Page index out of bounds"))
+    }
+
+    /// The asset entry page `index` points at.
+    fn page_entry(&self, index: u32) -> Result<&BBFAssetEntry, JsError> {
+        let asset = self.page_asset(index)?;
+        self.reader
+            .assets()
+            .get(asset as usize)
+            .ok_or_else(|| JsError::new("Asset index out of bounds"))
+    }
+}
+
+/// Writes a book in memory; `finish` hands back its bytes.
+#[wasm_bindgen(js_name = BbfBuilder)]
+pub struct WasmBbfBuilder {
+    builder: BBFBuilder<Cursor<Vec<u8>>>,
+}
+
+#[wasm_bindgen(js_class = BbfBuilder)]
+impl WasmBbfBuilder {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<Self, JsError> {
+        Ok(Self {
+            builder: BBFBuilder::new(Cursor::new(Vec::new()))?,
+        })
+    }
+
+    /// Appends a page. `extension` names its format, such as `"png"` or
+    /// `".jpg"`. Returns the index of the asset holding it, which is shared
+    /// with earlier pages of identical content.
+    #[wasm_bindgen(js_name = addPage)]
+    pub fn add_page(&mut self, data: &[u8], extension: &str) -> Result<u32, JsError> {
+        let ext = format!(".{}", extension.trim_start_matches('.'));
+        Ok(self
+            .builder
+            .add_page(data, BBFMediaType::from_extension(&ext), 0)?)
+    }
+
+    /// Like `addPage`, with the format given as its type byte, such as one
+    /// from `pageMediaTypeId`.
+    #[wasm_bindgen(js_name = addPageWithTypeId)]
+    pub fn add_page_with_type_id(&mut self, data: &[u8], type_id: u8) -> Result<u32, JsError> {
+        Ok(self
+            .builder
+            .add_page(data, BBFMediaType::from(type_id), 0)?)
+    }
+
+    #[wasm_bindgen(js_name = addSection)]
+    pub fn add_section(&mut self, title: &str, start_page: u32, parent: Option<u32>) {
+        self.builder.add_section(title, start_page, parent);
+    }
+
+    #[wasm_bindgen(js_name = addMetadata)]
+    pub fn add_metadata(&mut self, key: &str, value: &str) {
+        self.builder.add_metadata(key, value);
+    }
+
+    /// Writes the index and returns the finished book. The builder can't be
+    /// used afterwards.
+    pub fn finish(self) -> Result<Uint8Array, JsError> {
+        let cursor = self.builder.finish()?;
+        Ok(Uint8Array::from(&cursor.get_ref()[..]))
+    }
+}
@@ -0,0 +1,103 @@
+//! Builder hot paths: adding pages with and without deduplication, adding
+//! many small pages that each need padding, and finalizing a book with
+//! large tables.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::hint::black_box;
+use std::io;
+
+use bbf::{BBFBuilder, BBFMediaType};
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+
+const PAGE_SIZE: usize = 64 * 1024;
+const PAGES: usize = 256;
+
+/// `count` pages of `size` bytes, all different.
+fn distinct_pages(count: usize, size: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| {
+            let mut state = i as u64 ^ 0x9E37_79B9_7F4A_7C15;
+            (0..size)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn add_page(c: &mut Criterion) {
+    let distinct = distinct_pages(PAGES, PAGE_SIZE);
+    let repeated = vec![distinct[0].clone(); PAGES];
+
+    let mut group = c.benchmark_group("add_page");
+    group.throughput(Throughput::Bytes((PAGES * PAGE_SIZE) as u64));
+    for (name, pages) in [("distinct", &distinct), ("duplicates", &repeated)] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || BBFBuilder::new(io::sink()).unwrap(),
+                |mut builder| {
+                    for page in pages {
+                        builder.add_page(page, BBFMediaType::Png, 0).unwrap();
+                    }
+                    builder
+                },
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn small_pages(c: &mut Criterion) {
+    const SMALL_PAGES: usize = 20_000;
+    let pages = distinct_pages(SMALL_PAGES, 100);
+
+    c.bench_function("add_page/20k_padded", |b| {
+        b.iter_batched(
+            || BBFBuilder::new(io::sink()).unwrap(),
+            |mut builder| {
+                for page in &pages {
+                    builder.add_page(page, BBFMediaType::Png, 0).unwrap();
+                }
+                builder
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+fn finalize(c: &mut Criterion) {
+    const TABLE_PAGES: usize = 20_000;
+    let pages = distinct_pages(TABLE_PAGES, 16);
+
+    let filled = || {
+        let mut builder = BBFBuilder::new(io::sink()).unwrap();
+        for (i, page) in pages.iter().enumerate() {
+            builder.add_page(page, BBFMediaType::Jpg, 0).unwrap();
+            if i % 20 == 0 {
+                builder.add_section(&format!("Chapter {i}"), i as u32, None);
+            }
+            builder.set_page_name(i as u32, &format!("{i:05}.jpg"));
+        }
+        for i in 0..200 {
+            builder.add_metadata(&format!("Key{i}"), "Value");
+        }
+        builder
+    };
+
+    c.bench_function("finalize/20k_pages", |b| {
+        b.iter_batched(
+            filled,
+            |builder| black_box(builder.finish().unwrap()),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+criterion_group!(benches, add_page, small_pages, finalize);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+//! Reader hot paths: parsing the index, reading assets, and verifying a
+//! book's hashes.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::hint::black_box;
+use std::io::Cursor;
+
+use bbf::validate::{self, Options, Profile};
+use bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use xxhash_rust::xxh3::xxh3_64;
+
+const PAGE_SIZE: usize = 64 * 1024;
+const PAGES: usize = 512;
+
+/// An in-memory book of `PAGES` distinct pages with a section every ten.
+fn book() -> Vec<u8> {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    for i in 0..PAGES {
+        let mut page = vec![0u8; PAGE_SIZE];
+        page[..8].copy_from_slice(&(i as u64).to_le_bytes());
+        builder.add_page(&page, BBFMediaType::Png, 0).unwrap();
+        if i % 10 == 0 {
+            builder.add_section(&format!("Chapter {i}"), i as u32, None);
+        }
+    }
+    builder.add_metadata("Title", "Benchmark");
+    builder.finish().unwrap().into_inner()
+}
+
+fn open(c: &mut Criterion) {
+    let data = book();
+    c.bench_function("open", |b| {
+        b.iter(|| BBFReader::new(black_box(&data[..])).unwrap());
+    });
+}
+
+fn get_asset(c: &mut Criterion) {
+    let data = book();
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let count = reader.assets().len() as u32;
+
+    let mut group = c.benchmark_group("get_asset");
+    group.throughput(Throughput::Elements(u64::from(count)));
+    group.bench_function("slice", |b| {
+        b.iter(|| {
+            for i in 0..count {
+                black_box(reader.get_asset(i).unwrap());
+            }
+        });
+    });
+    group.bench_function("decoded", |b| {
+        b.iter(|| {
+            for i in 0..count {
+                black_box(reader.get_asset_decoded(i).unwrap());
+            }
+        });
+    });
+    group.finish();
+}
+
+fn verify(c: &mut Criterion) {
+    let data = book();
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let count = reader.assets().len() as u32;
+
+    let mut group = c.benchmark_group("verify");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("asset_hashes", |b| {
+        b.iter(|| {
+            (0..count).all(|i| {
+                let expected = reader.assets()[i as usize].xxh3_hash.get();
+                reader
+                    .get_asset(i)
+                    .is_ok_and(|asset| xxh3_64(asset) == expected)
+            })
+        });
+    });
+    group.bench_function("validate", |b| {
+        let options = Options::for_profile(Profile::Standard);
+        b.iter(|| validate::validate(&reader, &options));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, open, get_asset, verify);
+criterion_main!(benches);
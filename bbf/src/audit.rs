@@ -0,0 +1,236 @@
+//! Structural problems in a book's tables, found without hashing anything:
+//! `BBFReader::audit`. `rewrite_clean` writes a copy without them.
+//!
+//! `validate` reports the same problems and more as messages; this is for
+//! callers that want to act on them.
+
+#![allow(clippy::cast_possible_truncation, clippy::missing_errors_doc)]
+
+use std::io::{self, Write};
+use std::mem::size_of;
+
+use crate::builder::BBFBuilder;
+use crate::format::{AssetFlags, BBFHeader, BBFMediaType};
+use crate::reader::{BBFError, BBFReader, decode_asset};
+
+/// What `BBFReader::audit` found, as table indices in ascending order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Audit {
+    /// Assets that start inside the header or run into the index or past
+    /// the end of the file. External assets are never out of bounds.
+    pub out_of_bounds: Vec<u32>,
+    /// Pairs of in-bounds assets whose stored bytes overlap.
+    pub overlapping: Vec<(u32, u32)>,
+    /// Pages pointing at an asset that doesn't exist.
+    pub bad_pages: Vec<u32>,
+    /// Sections starting past the last page.
+    pub bad_sections: Vec<u32>,
+    /// Assets no page or thumbnail uses.
+    pub orphaned: Vec<u32>,
+}
+
+impl Audit {
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.out_of_bounds.is_empty()
+            && self.overlapping.is_empty()
+            && self.bad_pages.is_empty()
+            && self.bad_sections.is_empty()
+            && self.orphaned.is_empty()
+    }
+}
+
+impl<T: AsRef<[u8]>> BBFReader<T> {
+    /// Checks where assets lie and what points at them.
+    #[must_use]
+    pub fn audit(&self) -> Audit {
+        let mut audit = Audit::default();
+        let assets = self.assets();
+        let index_start = self.footer.string_pool_offset.get();
+
+        let mut spans = Vec::with_capacity(assets.len());
+        for (i, a) in assets.iter().enumerate() {
+            if a.asset_flags().contains(AssetFlags::EXTERNAL) {
+                continue;
+            }
+            let start = a.offset.get();
+            match start.checked_add(a.length.get()) {
+                Some(end) if start >= size_of::<BBFHeader>() as u64 && end <= index_start => {
+                    spans.push((start, end, i as u32));
+                }
+                _ => audit.out_of_bounds.push(i as u32),
+            }
+        }
+        spans.sort_unstable();
+        // The furthest-reaching asset so far overlaps anything starting
+        // before its end, adjacent or not.
+        let mut reach: Option<(u64, u32)> = None;
+        for &(start, end, i) in &spans {
+            if let Some((reach_end, j)) = reach
+                && start < reach_end
+            {
+                audit.overlapping.push((j.min(i), j.max(i)));
+            }
+            if reach.is_none_or(|(reach_end, _)| end > reach_end) {
+                reach = Some((end, i));
+            }
+        }
+        audit.overlapping.sort_unstable();
+
+        let mut used = vec![false; assets.len()];
+        let asset_indices = self.pages().iter().map(|p| p.asset_index.get()).enumerate();
+        for (page, asset) in asset_indices {
+            match used.get_mut(asset as usize) {
+                Some(u) => *u = true,
+                None => audit.bad_pages.push(page as u32),
+            }
+        }
+        for t in self.thumbnails() {
+            if let Some(u) = used.get_mut(t.asset_index.get() as usize) {
+                *u = true;
+            }
+        }
+        audit.orphaned = (0..assets.len() as u32)
+            .filter(|&i| !used[i as usize])
+            .collect();
+
+        let page_count = self.pages().len();
+        audit.bad_sections = (0..self.sections().len() as u32)
+            .filter(|&i| {
+                self.sections()[i as usize].section_start_index.get() as usize >= page_count
+            })
+            .collect();
+
+        audit
+    }
+}
+
+/// Adds a repaired copy of `reader`'s book to `builder`, which the caller
+/// then finishes.
+///
+/// Pages are stored again in order, so overlapping assets get their own
+/// bytes and orphaned ones are left behind. External assets are stored in
+/// the book if `reader`'s resolver finds them, and stay external otherwise.
+/// Pages whose asset is missing or can't be read are dropped, and so are
+/// sections starting past the last remaining page; the rest move to the
+/// page they started on, or the next one kept. Subsections of a dropped
+/// section move up to its parent. Metadata, page names and readable
+/// thumbnails are kept.
+///
+/// Assets are copied as stored, keeping their compression, unless
+/// `builder` has `set_compression` on; then they're decoded and compressed
+/// again as it says.
+pub fn rewrite_clean<T: AsRef<[u8]>, W: Write>(
+    reader: &BBFReader<T>,
+    builder: &mut BBFBuilder<W>,
+) -> io::Result<()> {
+    let assets = reader.assets();
+    let media_type = |asset: u32| {
+        assets
+            .get(asset as usize)
+            .map_or(BBFMediaType::Unknown, |a| BBFMediaType::from(a.type_))
+    };
+    #[cfg(feature = "zstd")]
+    let recompress = builder.compression().is_some();
+    #[cfg(not(feature = "zstd"))]
+    let recompress = false;
+
+    // New index of each old page, if kept.
+    let mut new_pages = Vec::with_capacity(reader.pages().len());
+    for (i, page) in reader.pages().iter().enumerate() {
+        let asset = page.asset_index.get();
+        let new_index = builder.page_count();
+        let entry = assets.get(asset as usize);
+        // Stored bytes are only copied if they decode, so broken frames are
+        // dropped like any other unreadable page.
+        let stored = entry.and_then(|entry| {
+            reader
+                .get_asset(asset)
+                .ok()
+                .filter(|data| !recompress && decode_asset(entry, data).is_ok())
+        });
+        if let (Some(entry), Some(data)) = (entry, stored) {
+            builder.add_stored_page(data, entry, page.flags.get())?;
+        } else {
+            match (
+                entry,
+                reader.get_asset_decoded(asset),
+                reader.external_name(asset),
+            ) {
+                (Some(entry), Ok(data), _) => {
+                    let new_asset = builder.add_page(&data, media_type(asset), page.flags.get())?;
+                    if let Some(t) = entry.mtime() {
+                        builder.set_asset_mtime(new_asset, t)?;
+                    }
+                }
+                (Some(entry), Err(BBFError::External), Some(name)) => {
+                    let new_asset = builder.add_external(name, entry);
+                    builder.push_page(new_asset, page.flags.get());
+                }
+                _ => {
+                    event!(warn, page = i, asset, "Dropping unreadable page");
+                    new_pages.push(None);
+                    continue;
+                }
+            }
+        }
+        if let Some(name) = reader.page_name(i as u32) {
+            builder.set_page_name(new_index, name);
+        }
+        new_pages.push(Some(new_index));
+    }
+    let page_count = builder.page_count();
+
+    for t in reader.thumbnails() {
+        let (page, asset) = (t.page_index.get(), t.asset_index.get());
+        let Some(&Some(new_page)) = new_pages.get(page as usize) else {
+            continue;
+        };
+        if let Ok(data) = reader.get_asset_decoded(asset) {
+            builder.add_thumbnail(new_page, &data, media_type(asset))?;
+        }
+    }
+
+    let sections = reader.sections();
+    let mut new_sections: Vec<Option<u32>> = Vec::with_capacity(sections.len());
+    let mut added = 0;
+    for (i, s) in sections.iter().enumerate() {
+        let start = s.section_start_index.get() as usize;
+        let new_start = new_pages
+            .get(start..)
+            .and_then(|rest| rest.iter().find_map(|&p| p))
+            .filter(|&p| p < page_count);
+        let Some(new_start) = new_start else {
+            new_sections.push(None);
+            continue;
+        };
+
+        // Parents come before their subsections; a parent that doesn't is
+        // broken and the walk stops there, so it always ends.
+        let (mut parent, mut bound) = (s.parent_section_index.get() as usize, i);
+        let new_parent = loop {
+            if parent >= bound {
+                break None;
+            }
+            if let Some(p) = new_sections[parent] {
+                break Some(p);
+            }
+            bound = parent;
+            parent = sections[parent].parent_section_index.get() as usize;
+        };
+        let title = reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("");
+        builder.add_section(title, new_start, new_parent);
+        new_sections.push(Some(added));
+        added += 1;
+    }
+
+    for m in reader.metadata() {
+        builder.add_metadata(
+            reader.get_string(m.key_offset.get()).unwrap_or(""),
+            reader.get_string(m.val_offset.get()).unwrap_or(""),
+        );
+    }
+    Ok(())
+}
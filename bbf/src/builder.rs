@@ -1,30 +1,129 @@
 #![allow(clippy::cast_possible_truncation, clippy::missing_errors_doc)]
 
-use std::collections::HashMap;
-use std::io::{self, Seek, Write};
-use xxhash_rust::xxh3::{Xxh3, xxh3_64};
-use zerocopy::{FromZeros, IntoBytes};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use unicode_normalization::{UnicodeNormalization, is_nfc};
+use xxhash_rust::xxh3::Xxh3;
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
 
 use crate::format::{
-    BBFAssetEntry, BBFFooter, BBFHeader, BBFMediaType, BBFMetadata, BBFPageEntry, BBFSection,
+    AssetFlags, BBFAssetEntry, BBFExpansionHeader, BBFFooter, BBFHeader, BBFMediaType, BBFMetadata,
+    BBFPageEntry, BBFPageName, BBFSection, BBFThumbnailEntry,
 };
+use crate::hash::{self, ContentHasher};
+use crate::reader::{BBFError, Limits, find_footer};
+use crate::remote;
 
-pub struct BBFBuilder<W: Write + Seek> {
+pub struct BBFBuilder<W: Write> {
     writer: W,
     current_offset: u64,
+    alignment: u64,
 
     assets: Vec<BBFAssetEntry>,
     pages: Vec<BBFPageEntry>,
     sections: Vec<BBFSection>,
     metadata: Vec<BBFMetadata>,
     string_pool: Vec<u8>,
+    thumbnails: Vec<BBFThumbnailEntry>,
+    page_names: Vec<BBFPageName>,
+    /// Extensions this builder doesn't understand, carried over verbatim by `from_existing`.
+    extensions: Vec<Extension>,
 
     dedupe_map: HashMap<u64, u32>,
     string_map: HashMap<String, u32>,
+
+    hasher: Box<dyn ContentHasher>,
+    /// Algorithm of the hashes in `assets`, which `hasher` has to match.
+    hash_algorithm: u32,
+    /// Assets from `reserve_page` still waiting for `fill_reserved`.
+    unfilled: HashSet<u32>,
+    /// Tells this builder's tickets from other builders'.
+    id: u64,
+    duplicate_keys: DuplicateKeys,
+    sort_sections: bool,
+    bad_sections: BadSections,
+    normalize_titles: bool,
+    /// Problems `BadSections::Warn` let through; see `diagnostics`.
+    diagnostics: Vec<String>,
+
+    #[cfg(feature = "zstd")]
+    compression: Option<Compression>,
 }
 
-impl<W: Write + Seek> BBFBuilder<W> {
-    pub fn new(mut writer: W) -> io::Result<Self> {
+/// A page whose data is written later, from `BBFBuilder::reserve_page`.
+#[derive(Debug)]
+#[must_use = "a reserved page has to be filled before the book is finished"]
+pub struct PageTicket {
+    builder: u64,
+    page_index: u32,
+    asset_index: u32,
+    offset: u64,
+    length: u64,
+}
+
+fn next_builder_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+impl PageTicket {
+    pub const fn page_index(&self) -> u32 {
+        self.page_index
+    }
+}
+
+/// What `add_metadata` does with a key that's already set. Readers use the
+/// first entry for a key; see `BBFReader::metadata_value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeys {
+    /// Record another entry for the key.
+    #[default]
+    Keep,
+    /// Replace the existing value, like `set_metadata`.
+    Replace,
+    /// Leave the existing value and drop the new one.
+    Skip,
+}
+
+/// What finishing a book does with a section that starts past the last
+/// page, or whose parent isn't another section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BadSections {
+    /// Write it anyway, noting the problem in `BBFBuilder::diagnostics`.
+    #[default]
+    Warn,
+    /// Fail with `InvalidInput`.
+    Error,
+}
+
+/// Settings for `BBFBuilder::set_compression`.
+#[cfg(feature = "zstd")]
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// zstd level, as accepted by `zstd::bulk::compress`.
+    pub level: i32,
+    /// Media types to compress; empty compresses every asset.
+    pub media_types: Vec<BBFMediaType>,
+}
+
+impl<W: Write> BBFBuilder<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_capacity(writer, 0, 0, 0)
+    }
+
+    /// Like `new`, with room reserved for `pages` pages, `assets` distinct
+    /// assets and `metadata` entries, so huge builds don't keep growing
+    /// their tables. Going past these numbers is fine.
+    pub fn with_capacity(
+        mut writer: W,
+        pages: usize,
+        assets: usize,
+        metadata: usize,
+    ) -> io::Result<Self> {
         let header = BBFHeader {
             magic: *b"BBF1",
             version: 2,
@@ -39,23 +138,107 @@ impl<W: Write + Seek> BBFBuilder<W> {
         Ok(Self {
             writer,
             current_offset,
-            assets: Vec::new(),
-            pages: Vec::new(),
+            alignment: 4096,
+            assets: Vec::with_capacity(assets),
+            pages: Vec::with_capacity(pages),
             sections: Vec::new(),
-            metadata: Vec::new(),
+            metadata: Vec::with_capacity(metadata),
             string_pool: Vec::new(),
-            dedupe_map: HashMap::new(),
+            thumbnails: Vec::new(),
+            page_names: Vec::new(),
+            extensions: Vec::new(),
+            dedupe_map: HashMap::with_capacity(assets),
             string_map: HashMap::new(),
+            hasher: Box::new(hash::Xxh3),
+            hash_algorithm: hash::XXH3,
+            unfilled: HashSet::new(),
+            id: next_builder_id(),
+            duplicate_keys: DuplicateKeys::Keep,
+            sort_sections: false,
+            bad_sections: BadSections::Warn,
+            normalize_titles: false,
+            diagnostics: Vec::new(),
+            #[cfg(feature = "zstd")]
+            compression: None,
         })
     }
 
+    /// Sets the boundary each newly written asset is padded to (default 4096).
+    /// An alignment of 0 or 1 stores assets back to back.
+    pub const fn set_alignment(&mut self, alignment: u64) {
+        self.alignment = alignment;
+    }
+
+    /// Compresses assets added from now on with zstd, or stores them as-is
+    /// for `None` (the default). An asset is only stored compressed when
+    /// that makes it smaller.
+    #[cfg(feature = "zstd")]
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    /// What `set_compression` last set.
+    #[cfg(feature = "zstd")]
+    pub const fn compression(&self) -> Option<&Compression> {
+        self.compression.as_ref()
+    }
+
+    /// Hashes assets with `hasher` instead of XXH3, recording its id in the
+    /// book. Fails once assets hashed another way are stored, including
+    /// ones loaded by `from_existing`.
+    pub fn set_hasher(&mut self, hasher: Box<dyn ContentHasher>) -> io::Result<()> {
+        let id = hasher.id();
+        if id != self.hash_algorithm && !self.assets.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Book already holds assets hashed with another algorithm",
+            ));
+        }
+        self.hasher = hasher;
+        self.hash_algorithm = id;
+        Ok(())
+    }
+
+    /// Sets the flags of a stored asset that the builder doesn't manage
+    /// itself: anything but `AssetFlags::COMPRESSED` and
+    /// `AssetFlags::THUMBNAIL`, which are ignored in `flags`.
+    pub fn set_asset_flags(&mut self, asset_index: u32, flags: AssetFlags) -> io::Result<()> {
+        let managed = AssetFlags::COMPRESSED | AssetFlags::THUMBNAIL;
+        let asset = self
+            .assets
+            .get_mut(asset_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No such asset"))?;
+        let kept = AssetFlags::from_bits_retain(asset.flags & managed.bits());
+        asset.flags = kept.union(flags.difference(managed)).bits();
+        Ok(())
+    }
+
+    /// Records when the file a stored asset was made from was last
+    /// modified, in unix seconds. Pages sharing an asset share its time, so
+    /// the earliest one given is kept.
+    pub fn set_asset_mtime(&mut self, asset_index: u32, secs: u64) -> io::Result<()> {
+        let asset = self
+            .assets
+            .get_mut(asset_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No such asset"))?;
+        if asset.mtime().is_none_or(|t| secs < t) {
+            asset.reserved[BBFAssetEntry::MTIME] = secs.into();
+        }
+        Ok(())
+    }
+
+    /// Hash of `data` as this builder stores it.
+    pub fn hash(&self, data: &[u8]) -> u64 {
+        self.hasher.hash(data)
+    }
+
     fn align_padding(&mut self) -> io::Result<()> {
-        let padding = (4096 - (self.current_offset % 4096)) % 4096;
-        if padding > 0 {
-            let zeroes = vec![0u8; padding as usize];
-            self.writer.write_all(&zeroes)?;
-            self.current_offset += padding;
+        if self.alignment <= 1 {
+            return Ok(());
         }
+        let padding = (self.alignment - (self.current_offset % self.alignment)) % self.alignment;
+        write_zeros(&mut self.writer, padding)?;
+        self.current_offset += padding;
         Ok(())
     }
 
@@ -65,40 +248,252 @@ impl<W: Write + Seek> BBFBuilder<W> {
         media_type: BBFMediaType,
         flags: u32,
     ) -> io::Result<u32> {
-        let hash = xxh3_64(data);
-        let asset_index;
+        self.add_page_with_hash(data, self.hash(data), media_type, flags)
+    }
 
-        if let Some(&idx) = self.dedupe_map.get(&hash) {
-            asset_index = idx;
-        } else {
-            self.align_padding()?;
-
-            let offset = self.current_offset;
-            let length = data.len() as u64;
-
-            self.writer.write_all(data)?;
-            self.current_offset += length;
-
-            let entry = BBFAssetEntry {
-                offset: offset.into(),
-                length: length.into(),
-                decoded_length: length.into(),
-                xxh3_hash: hash.into(),
-                type_: media_type as u8,
-                flags: 0,
-                padding: [0; 6],
-                reserved: [0.into(); 3],
-            };
+    /// Like `add_page`, for callers that already computed the builder's
+    /// `hash` of `data` (e.g. while reading inputs in parallel). A wrong
+    /// hash breaks both deduplication and verification of the written file.
+    pub fn add_page_with_hash(
+        &mut self,
+        data: &[u8],
+        hash: u64,
+        media_type: BBFMediaType,
+        flags: u32,
+    ) -> io::Result<u32> {
+        let asset_index = self.add_asset(data, hash, media_type)?;
+        self.push_page(asset_index, flags);
+        Ok(asset_index)
+    }
 
-            asset_index = self.assets.len() as u32;
-            self.assets.push(entry);
-            self.dedupe_map.insert(hash, asset_index);
+    /// Adds a page whose asset is copied as another book stored it: `data`
+    /// are the stored bytes and `entry` their asset entry, whose flags,
+    /// decoded length and modification time carry over. Compressed assets
+    /// stay compressed without being decoded, whatever `set_compression`
+    /// says.
+    pub fn add_stored_page(
+        &mut self,
+        data: &[u8],
+        entry: &BBFAssetEntry,
+        flags: u32,
+    ) -> io::Result<u32> {
+        self.check_hasher()?;
+        let hash = self.hash(data);
+        let asset_index = match self.dedupe_map.get(&hash) {
+            Some(&idx) => idx,
+            None => {
+                let asset_flags = entry
+                    .asset_flags()
+                    .difference(AssetFlags::EXTERNAL | AssetFlags::THUMBNAIL);
+                let decoded_length = if asset_flags.contains(AssetFlags::COMPRESSED) {
+                    entry.decoded_length.get()
+                } else {
+                    data.len() as u64
+                };
+                self.write_asset(
+                    data,
+                    hash,
+                    decoded_length,
+                    BBFMediaType::from(entry.type_),
+                    asset_flags,
+                )?
+            }
+        };
+        if let Some(secs) = entry.mtime() {
+            self.set_asset_mtime(asset_index, secs)?;
         }
+        self.push_page(asset_index, flags);
+        Ok(asset_index)
+    }
 
+    /// Adds a page whose data stays in a separate file, recorded as `name`:
+    /// a path relative to the book, for `reader::DirResolver`. `data` is
+    /// only hashed, and writing it to that file is up to the caller.
+    /// External assets are never shared with other pages.
+    pub fn add_external_page(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        media_type: BBFMediaType,
+        flags: u32,
+    ) -> io::Result<u32> {
+        self.check_hasher()?;
+        let length = data.len() as u64;
+        let entry = BBFAssetEntry {
+            offset: 0.into(),
+            length: length.into(),
+            decoded_length: length.into(),
+            xxh3_hash: self.hash(data).into(),
+            type_: media_type.into(),
+            flags: 0,
+            padding: [0; 6],
+            reserved: [0.into(); 3],
+        };
+        let asset_index = self.add_external(name, &entry);
+        self.push_page(asset_index, flags);
+        Ok(asset_index)
+    }
+
+    /// Stores a copy of `entry` for an external asset named `name`.
+    pub(crate) fn add_external(&mut self, name: &str, entry: &BBFAssetEntry) -> u32 {
+        let mut entry = *entry;
+        entry.offset = u64::from(self.get_or_add_str(name)).into();
+        entry.flags |= AssetFlags::EXTERNAL.bits();
+        self.assets.push(entry);
+        self.assets.len() as u32 - 1
+    }
+
+    pub(crate) fn push_page(&mut self, asset_index: u32, flags: u32) {
         self.pages.push(BBFPageEntry {
             asset_index: asset_index.into(),
             flags: flags.into(),
         });
+    }
+
+    /// Stores `data` as the preview image for `page_index`, replacing any
+    /// previous one. Thumbnails are ordinary (deduplicated) assets that no
+    /// page points at directly.
+    pub fn add_thumbnail(
+        &mut self,
+        page_index: u32,
+        data: &[u8],
+        media_type: BBFMediaType,
+    ) -> io::Result<u32> {
+        let asset_index = self.add_asset(data, self.hash(data), media_type)?;
+
+        self.thumbnails.retain(|t| t.page_index.get() != page_index);
+        self.thumbnails.push(BBFThumbnailEntry {
+            page_index: page_index.into(),
+            asset_index: asset_index.into(),
+        });
+
+        Ok(asset_index)
+    }
+
+    /// Records the file `page_index` was built from, replacing any earlier name.
+    pub fn set_page_name(&mut self, page_index: u32, name: &str) {
+        let name_offset = self.get_or_add_str(name);
+        self.page_names.retain(|n| n.page_index.get() != page_index);
+        self.page_names.push(BBFPageName {
+            page_index: page_index.into(),
+            name_offset: name_offset.into(),
+        });
+    }
+
+    /// Replaces the `BBFPageEntry` flags of a page added earlier (or loaded
+    /// by `from_existing`).
+    pub fn set_page_flags(&mut self, page_index: u32, flags: u32) -> io::Result<()> {
+        let page = self
+            .pages
+            .get_mut(page_index as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No such page"))?;
+        page.flags = flags.into();
+        Ok(())
+    }
+
+    /// Flags of page `page_index`, if it exists.
+    pub fn page_flags(&self, page_index: u32) -> Option<u32> {
+        self.pages.get(page_index as usize).map(|p| p.flags.get())
+    }
+
+    /// Drops every thumbnail added so far (or loaded by `from_existing`).
+    pub fn clear_thumbnails(&mut self) {
+        self.thumbnails.clear();
+    }
+
+    fn check_hasher(&self) -> io::Result<()> {
+        if self.hasher.id() != self.hash_algorithm {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Book's assets are hashed with another algorithm; call set_hasher first",
+            ));
+        }
+        Ok(())
+    }
+
+    fn add_asset(&mut self, data: &[u8], hash: u64, media_type: BBFMediaType) -> io::Result<u32> {
+        self.check_hasher()?;
+        if let Some(&idx) = self.dedupe_map.get(&hash) {
+            event!(trace, asset = idx, "Duplicate data, reusing asset");
+            return Ok(idx);
+        }
+
+        #[cfg(feature = "zstd")]
+        if let Some(compressed) = self.compress(data, media_type)? {
+            // Stored hashes cover the compressed bytes, so look those up too:
+            // that's all an index loaded by `from_existing` knows about.
+            let stored_hash = self.hash(&compressed);
+            event!(
+                trace,
+                from = data.len(),
+                to = compressed.len(),
+                "Compressed asset"
+            );
+            let idx = match self.dedupe_map.get(&stored_hash) {
+                Some(&idx) => idx,
+                None => self.write_asset(
+                    &compressed,
+                    stored_hash,
+                    data.len() as u64,
+                    media_type,
+                    AssetFlags::COMPRESSED,
+                )?,
+            };
+            self.dedupe_map.insert(hash, idx);
+            return Ok(idx);
+        }
+
+        self.write_asset(
+            data,
+            hash,
+            data.len() as u64,
+            media_type,
+            AssetFlags::empty(),
+        )
+    }
+
+    #[cfg(feature = "zstd")]
+    fn compress(&self, data: &[u8], media_type: BBFMediaType) -> io::Result<Option<Vec<u8>>> {
+        let Some(compression) = &self.compression else {
+            return Ok(None);
+        };
+        if !compression.media_types.is_empty() && !compression.media_types.contains(&media_type) {
+            return Ok(None);
+        }
+        let compressed = zstd::bulk::compress(data, compression.level)?;
+        Ok((compressed.len() < data.len()).then_some(compressed))
+    }
+
+    fn write_asset(
+        &mut self,
+        data: &[u8],
+        hash: u64,
+        decoded_length: u64,
+        media_type: BBFMediaType,
+        flags: AssetFlags,
+    ) -> io::Result<u32> {
+        self.align_padding()?;
+
+        let offset = self.current_offset;
+        let length = data.len() as u64;
+
+        self.writer.write_all(data)?;
+        self.current_offset += length;
+
+        let entry = BBFAssetEntry {
+            offset: offset.into(),
+            length: length.into(),
+            decoded_length: decoded_length.into(),
+            xxh3_hash: hash.into(),
+            type_: media_type.into(),
+            flags: flags.bits(),
+            padding: [0; 6],
+            reserved: [0.into(); 3],
+        };
+
+        let asset_index = self.assets.len() as u32;
+        self.assets.push(entry);
+        self.dedupe_map.insert(hash, asset_index);
 
         Ok(asset_index)
     }
@@ -116,15 +511,75 @@ impl<W: Write + Seek> BBFBuilder<W> {
     }
 
     pub fn add_section(&mut self, title: &str, start_page: u32, parent_idx: Option<u32>) {
+        let title = if self.normalize_titles && !is_nfc(title) {
+            Cow::Owned(title.nfc().collect())
+        } else {
+            Cow::Borrowed(title)
+        };
         let section = BBFSection {
-            section_title_offset: self.get_or_add_str(title).into(),
+            section_title_offset: self.get_or_add_str(&title).into(),
             section_start_index: start_page.into(),
             parent_section_index: parent_idx.unwrap_or(0xFFFF_FFFF).into(),
         };
         self.sections.push(section);
     }
 
+    /// Whether `add_section` stores titles in Unicode NFC form (default
+    /// off), so text from different sources, such as macOS file names,
+    /// compares equal byte for byte.
+    pub const fn set_normalize_titles(&mut self, normalize: bool) {
+        self.normalize_titles = normalize;
+    }
+
+    /// Whether finishing a book puts sections in order of their first page
+    /// (default off). Sections starting on the same page keep the order
+    /// they were added in, and parents are renumbered to match.
+    pub const fn set_sort_sections(&mut self, sort: bool) {
+        self.sort_sections = sort;
+    }
+
+    /// Sets what finishing a book does with sections pointing nowhere
+    /// (default `BadSections::Warn`).
+    pub const fn set_bad_sections(&mut self, policy: BadSections) {
+        self.bad_sections = policy;
+    }
+
+    /// Drops every section added so far (or loaded by `from_existing`).
+    pub fn clear_sections(&mut self) {
+        self.sections.clear();
+    }
+
+    /// Number of pages added so far, including any loaded by `from_existing`.
+    pub fn page_count(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Number of distinct assets stored so far.
+    pub fn asset_count(&self) -> u32 {
+        self.assets.len() as u32
+    }
+
+    /// Drops every metadata entry added so far (or loaded by `from_existing`).
+    pub fn clear_metadata(&mut self) {
+        self.metadata.clear();
+    }
+
+    /// Sets what `add_metadata` does with a key that's already set
+    /// (default `DuplicateKeys::Keep`).
+    pub const fn set_duplicate_keys(&mut self, policy: DuplicateKeys) {
+        self.duplicate_keys = policy;
+    }
+
     pub fn add_metadata(&mut self, key: &str, value: &str) {
+        match self.duplicate_keys {
+            DuplicateKeys::Keep => {}
+            DuplicateKeys::Replace => return self.set_metadata(key, value),
+            DuplicateKeys::Skip => {
+                if self.find_metadata(key).is_some() {
+                    return;
+                }
+            }
+        }
         let meta = BBFMetadata {
             key_offset: self.get_or_add_str(key).into(),
             val_offset: self.get_or_add_str(value).into(),
@@ -132,65 +587,705 @@ impl<W: Write + Seek> BBFBuilder<W> {
         self.metadata.push(meta);
     }
 
+    /// Sets `key` to `value`. The first existing entry for the key keeps
+    /// its place with the new value and any others are dropped; a new key
+    /// goes at the end.
+    pub fn set_metadata(&mut self, key: &str, value: &str) {
+        let val_offset = self.get_or_add_str(value).into();
+        let Some(first) = self.find_metadata(key) else {
+            let key_offset = self.get_or_add_str(key).into();
+            self.metadata.push(BBFMetadata {
+                key_offset,
+                val_offset,
+            });
+            return;
+        };
+        self.metadata[first].val_offset = val_offset;
+        let mut i = 0;
+        let pool = &self.string_pool;
+        self.metadata.retain(|m| {
+            let keep = i <= first || pool_str(pool, m.key_offset.get()) != key.as_bytes();
+            i += 1;
+            keep
+        });
+    }
+
+    /// Drops every entry for `key`, returning whether there were any.
+    pub fn remove_metadata(&mut self, key: &str) -> bool {
+        let before = self.metadata.len();
+        let pool = &self.string_pool;
+        self.metadata
+            .retain(|m| pool_str(pool, m.key_offset.get()) != key.as_bytes());
+        self.metadata.len() != before
+    }
+
+    fn find_metadata(&self, key: &str) -> Option<usize> {
+        self.metadata
+            .iter()
+            .position(|m| pool_str(&self.string_pool, m.key_offset.get()) == key.as_bytes())
+    }
+
     pub fn finalize(self) -> io::Result<()> {
-        let Self {
-            mut writer,
-            mut current_offset,
-            assets,
-            pages,
-            sections,
-            metadata,
-            string_pool,
-            ..
-        } = self;
+        self.finish().map(|_| ())
+    }
 
-        let mut hasher = Xxh3::new();
-        let mut footer = BBFFooter::new_zeroed();
+    /// Writes the index and footer like `finalize`, then hands the writer back
+    /// positioned just past the footer.
+    ///
+    /// When editing a file opened with `from_existing` the new index can be
+    /// shorter than the old one, so callers should truncate the file at the
+    /// returned writer's position.
+    pub fn finish(self) -> io::Result<W> {
+        self.finish_with_diagnostics().map(|(writer, _)| writer)
+    }
 
-        macro_rules! write_hash {
-            ($slice:expr) => {
-                if !$slice.is_empty() {
-                    writer.write_all($slice)?;
-                    hasher.update($slice);
-                    current_offset += $slice.len() as u64;
-                }
+    /// Like `finish`, also handing back the `diagnostics` gathered while
+    /// writing the book.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(pages = self.pages.len(), assets = self.assets.len()),
+        )
+    )]
+    pub fn finish_with_diagnostics(mut self) -> io::Result<(W, Vec<String>)> {
+        self.check_filled()?;
+        self.check_sections()?;
+        let assets = std::mem::take(&mut self.assets);
+        let pages = std::mem::take(&mut self.pages);
+        let thumbnails = std::mem::take(&mut self.thumbnails);
+        self.write_index(&assets, &pages, &thumbnails)?;
+        Ok((self.writer, self.diagnostics))
+    }
+
+    /// Problems found while finishing volumes that the builder's policies
+    /// let through, such as sections `BadSections::Warn` kept. `finish`
+    /// drops them; `finish_with_diagnostics` returns them.
+    pub fn diagnostics(&self) -> &[String] {
+        &self.diagnostics
+    }
+
+    /// Writes an index and footer for the pages, sections, metadata and
+    /// names added since the last call, then starts over with empty tables
+    /// for the next volume. Stored assets stay shared, so later volumes
+    /// deduplicate against earlier ones.
+    ///
+    /// The index only lists the assets this volume uses, and asset offsets
+    /// are absolute, so the output up to the returned offset reads as a
+    /// standalone book. `pack::PackBuilder` is built on this.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(pages = self.pages.len()))
+    )]
+    pub fn finish_volume(&mut self) -> io::Result<u64> {
+        self.check_filled()?;
+        self.check_sections()?;
+        // Renumber the assets this volume uses, keeping file order.
+        let mut local = vec![u32::MAX; self.assets.len()];
+        let used = self
+            .pages
+            .iter()
+            .map(|p| p.asset_index.get())
+            .chain(self.thumbnails.iter().map(|t| t.asset_index.get()));
+        for i in used {
+            if let Some(slot) = local.get_mut(i as usize) {
+                *slot = 0;
+            }
+        }
+        let mut assets = Vec::new();
+        for (i, slot) in local.iter_mut().enumerate() {
+            if *slot == 0 {
+                *slot = assets.len() as u32;
+                assets.push(self.assets[i]);
+            }
+        }
+        let remap = |i: u32| local.get(i as usize).copied().unwrap_or(i);
+
+        let mut pages = std::mem::take(&mut self.pages);
+        for p in &mut pages {
+            p.asset_index = remap(p.asset_index.get()).into();
+        }
+        let mut thumbnails = std::mem::take(&mut self.thumbnails);
+        for t in &mut thumbnails {
+            t.asset_index = remap(t.asset_index.get()).into();
+        }
+
+        self.write_index(&assets, &pages, &thumbnails)?;
+        self.sections.clear();
+        self.metadata.clear();
+        self.page_names.clear();
+        self.extensions.clear();
+        self.string_pool.clear();
+        self.string_map.clear();
+        Ok(self.current_offset)
+    }
+
+    fn check_filled(&self) -> io::Result<()> {
+        if !self.unfilled.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Reserved pages were never filled",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Applies `bad_sections` and `sort_sections` to the sections about to
+    /// be written.
+    fn check_sections(&mut self) -> io::Result<()> {
+        let count = self.sections.len() as u32;
+        let pages = self.pages.len() as u32;
+        for (i, s) in self.sections.iter().enumerate() {
+            let start = s.section_start_index.get();
+            let parent = s.parent_section_index.get();
+            let problem = if start >= pages {
+                format!("Section {i} starts at page {start}, but the book has {pages} pages")
+            } else if parent != u32::MAX && (parent >= count || parent == i as u32) {
+                format!("Section {i} has parent {parent}, which isn't another section")
+            } else {
+                continue;
             };
+            match self.bad_sections {
+                BadSections::Warn => {
+                    event!(debug, "{problem}");
+                    self.diagnostics.push(problem);
+                }
+                BadSections::Error => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, problem));
+                }
+            }
         }
 
-        footer.string_pool_offset = current_offset.into();
-        write_hash!(&string_pool);
+        if self.sort_sections
+            && !self
+                .sections
+                .is_sorted_by_key(|s| s.section_start_index.get())
+        {
+            let mut order: Vec<u32> = (0..count).collect();
+            order.sort_by_key(|&i| self.sections[i as usize].section_start_index.get());
+            let mut renumbered = vec![0; order.len()];
+            for (new, &old) in order.iter().enumerate() {
+                renumbered[old as usize] = new as u32;
+            }
+            let sorted = order
+                .iter()
+                .map(|&old| {
+                    let mut s = self.sections[old as usize];
+                    let parent = s.parent_section_index.get();
+                    if let Some(&new) = renumbered.get(parent as usize) {
+                        s.parent_section_index = new.into();
+                    }
+                    s
+                })
+                .collect();
+            self.sections = sorted;
+            event!(debug, sections = count, "Sorted sections by start page");
+        }
+        Ok(())
+    }
 
-        footer.asset_table_offset = current_offset.into();
+    /// The writer and how many bytes have gone through it, without writing
+    /// an index.
+    pub(crate) fn into_parts(self) -> (W, u64) {
+        (self.writer, self.current_offset)
+    }
+
+    fn write_index(
+        &mut self,
+        assets: &[BBFAssetEntry],
+        pages: &[BBFPageEntry],
+        thumbnails: &[BBFThumbnailEntry],
+    ) -> io::Result<()> {
+        let assets = &with_thumbnail_flags(assets, pages, thumbnails);
+        let start = self.current_offset;
+        let mut out = HashingWriter::new(&mut self.writer);
+        let mut footer = BBFFooter::new_zeroed();
+        let at = |out: &HashingWriter<_>| start + out.written;
+
+        footer.string_pool_offset = start.into();
+        out.write_all(&self.string_pool)?;
+
+        footer.asset_table_offset = at(&out).into();
         footer.asset_count = (assets.len() as u32).into();
-        for asset in &assets {
-            write_hash!(asset.as_bytes());
-        }
+        out.write_all(assets.as_bytes())?;
 
-        footer.page_table_offset = current_offset.into();
+        footer.page_table_offset = at(&out).into();
         footer.page_count = (pages.len() as u32).into();
-        for page in &pages {
-            write_hash!(page.as_bytes());
-        }
+        out.write_all(pages.as_bytes())?;
+
+        footer.section_table_offset = at(&out).into();
+        footer.section_count = (self.sections.len() as u32).into();
+        out.write_all(self.sections.as_bytes())?;
 
-        footer.section_table_offset = current_offset.into();
-        footer.section_count = (sections.len() as u32).into();
-        for section in &sections {
-            write_hash!(section.as_bytes());
+        footer.meta_table_offset = at(&out).into();
+        footer.key_count = (self.metadata.len() as u32).into();
+        out.write_all(self.metadata.as_bytes())?;
+
+        let mut expansions = Vec::new();
+        let mut expansion =
+            |out: &mut HashingWriter<_>, extension_type: u32, flags: u64, payload: &[u8]| {
+                let offset = at(out);
+                out.write_all(payload)?;
+                expansions.push(BBFExpansionHeader {
+                    extension_type: extension_type.into(),
+                    padding: 0.into(),
+                    offset: offset.into(),
+                    flags: flags.into(),
+                    length: (payload.len() as u64).into(),
+                });
+                io::Result::Ok(())
+            };
+
+        if !thumbnails.is_empty() {
+            expansion(
+                &mut out,
+                BBFExpansionHeader::THUMBNAILS,
+                0,
+                thumbnails.as_bytes(),
+            )?;
+        }
+        if !self.page_names.is_empty() {
+            expansion(
+                &mut out,
+                BBFExpansionHeader::PAGE_NAMES,
+                0,
+                self.page_names.as_bytes(),
+            )?;
+        }
+        if self.hash_algorithm != hash::XXH3 {
+            expansion(
+                &mut out,
+                BBFExpansionHeader::ASSET_HASH,
+                0,
+                &self.hash_algorithm.to_le_bytes(),
+            )?;
+        }
+        for ext in &self.extensions {
+            expansion(&mut out, ext.kind, ext.flags, &ext.payload)?;
         }
 
-        footer.meta_table_offset = current_offset.into();
-        footer.key_count = (metadata.len() as u32).into();
-        for meta in &metadata {
-            write_hash!(meta.as_bytes());
+        if !expansions.is_empty() {
+            footer.extra_offset = at(&out).into();
+            for header in &expansions {
+                out.write_all(header.as_bytes())?;
+            }
+            out.write_all(BBFExpansionHeader::new_zeroed().as_bytes())?;
         }
 
-        footer.index_hash = hasher.digest().into();
+        footer.index_hash = out.hasher.digest().into();
         footer.magic = *b"BBF1";
+        let index_len = out.written;
+
+        self.writer.write_all(footer.as_bytes())?;
+        self.current_offset = start + index_len + size_of::<BBFFooter>() as u64;
+        event!(
+            debug,
+            bytes = self.current_offset - start,
+            strings = self.string_pool.len(),
+            extensions = expansions.len(),
+            "Wrote index"
+        );
+        Ok(())
+    }
+}
 
-        writer.write_all(footer.as_bytes())?;
+/// Writes through to `inner`, hashing and counting the bytes on the way so
+/// the index is only touched once.
+///
+/// Only `write_index` uses it. Page data can't be hashed this way: the
+/// hash decides whether a page is written at all, reserved pages are
+/// zeroes until filled, external pages aren't written here, and a
+/// `ContentHasher` only hashes whole buffers. Callers that want a single
+/// pass over page data hash it while reading and use `add_page_with_hash`.
+struct HashingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: Xxh3,
+    written: u64,
+}
 
-        let _ = current_offset;
+impl<'a, W: Write> HashingWriter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner,
+            hasher: Xxh3::new(),
+            written: 0,
+        }
+    }
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.written += n as u64;
+        Ok(n)
+    }
 
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> BBFBuilder<W> {
+    /// Adds a page whose `expected_len` bytes are written later with
+    /// `fill_reserved`, e.g. a cover made once the rest of the book is
+    /// built. The space is zeroed until then, and the asset is stored
+    /// uncompressed and never shared with other pages.
+    ///
+    /// Every reserved page has to be filled before `finish`.
+    pub fn reserve_page(
+        &mut self,
+        expected_len: u64,
+        media_type: BBFMediaType,
+        flags: u32,
+    ) -> io::Result<PageTicket> {
+        self.check_hasher()?;
+        self.align_padding()?;
+        let offset = self.current_offset;
+        write_zeros(&mut self.writer, expected_len)?;
+        self.current_offset += expected_len;
+
+        let asset_index = self.assets.len() as u32;
+        self.assets.push(BBFAssetEntry {
+            offset: offset.into(),
+            length: expected_len.into(),
+            decoded_length: expected_len.into(),
+            xxh3_hash: 0.into(),
+            type_: media_type.into(),
+            flags: 0,
+            padding: [0; 6],
+            reserved: [0.into(); 3],
+        });
+        let page_index = self.pages.len() as u32;
+        self.push_page(asset_index, flags);
+        self.unfilled.insert(asset_index);
+
+        Ok(PageTicket {
+            builder: self.id,
+            page_index,
+            asset_index,
+            offset,
+            length: expected_len,
+        })
+    }
+
+    /// Writes a reserved page's data into its space. `data` has to be
+    /// exactly the length given to `reserve_page`, and `ticket` has to come
+    /// from this builder. Each page is filled once; after an error the
+    /// ticket can be used again.
+    pub fn fill_reserved(&mut self, ticket: &PageTicket, data: &[u8]) -> io::Result<()> {
+        if ticket.builder != self.id || !self.unfilled.contains(&ticket.asset_index) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Ticket wasn't issued by this builder, or its page is already filled",
+            ));
+        }
+        if data.len() as u64 != ticket.length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Data does not match the reserved length",
+            ));
+        }
+        let hash = self.hash(data);
+
+        self.writer.seek(SeekFrom::Start(ticket.offset))?;
+        self.writer.write_all(data)?;
+        self.writer.seek(SeekFrom::Start(self.current_offset))?;
+
+        self.assets[ticket.asset_index as usize].xxh3_hash = hash.into();
+        self.dedupe_map.entry(hash).or_insert(ticket.asset_index);
+        self.unfilled.remove(&ticket.asset_index);
+        event!(
+            trace,
+            page = ticket.page_index,
+            asset = ticket.asset_index,
+            "Filled reserved page"
+        );
         Ok(())
     }
 }
+
+impl<W: Read + Write + Seek> BBFBuilder<W> {
+    /// Reopens a finished BBF file for editing.
+    ///
+    /// The existing tables are loaded and the writer is positioned where the
+    /// old index began: asset data stays where it is, new pages are written
+    /// over the old index, and `finish` appends a fresh index and footer.
+    /// The footer is found like `BBFReader::new` finds it, so data appended
+    /// after it is overwritten too; truncate the file after `finish` if
+    /// the new book could be shorter.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err(level = "debug"))
+    )]
+    pub fn from_existing(mut inner: W) -> io::Result<Self> {
+        let total_len = inner.seek(SeekFrom::End(0))?;
+        if total_len < (size_of::<BBFHeader>() + size_of::<BBFFooter>()) as u64 {
+            return Err(invalid_data("File too short or corrupted header"));
+        }
+
+        let mut header = BBFHeader::new_zeroed();
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(header.as_mut_bytes())?;
+
+        if &header.magic != b"BBF1" {
+            return Err(invalid_data("Invalid BBF Magic"));
+        }
+
+        // Read like `RemoteReader`: the footer at the end, or failing that,
+        // the index of every footer in the trailing bytes, then let
+        // `find_footer` pick the one whose index hash matches.
+        let limits = Limits::default();
+        let to_io = |e: BBFError| io::Error::new(io::ErrorKind::InvalidData, e);
+        let read_range = |inner: &mut W, range: Range<u64>| -> io::Result<Vec<u8>> {
+            let mut buf = vec![0u8; (range.end - range.start) as usize];
+            inner.seek(SeekFrom::Start(range.start))?;
+            inner.read_exact(&mut buf)?;
+            Ok(buf)
+        };
+        let footer = read_range(&mut inner, remote::footer_range(total_len).map_err(to_io)?)?;
+        let range = match remote::index_range(total_len, &footer, &limits) {
+            Err(BBFError::InvalidMagic) => {
+                let tail = remote::trailing_range(total_len).map_err(to_io)?;
+                let tail = read_range(&mut inner, tail)?;
+                remote::trailing_index_range(total_len, &tail, &limits).map_err(to_io)?
+            }
+            range => range.map_err(to_io)?,
+        };
+        let data = read_range(&mut inner, range.clone())?;
+        let (footer, end) = find_footer(&data, range.start).map_err(to_io)?;
+        let footer_offset = range.start + (end - size_of::<BBFFooter>()) as u64;
+        limits.check(&footer, footer_offset).map_err(to_io)?;
+
+        let index_start = footer.string_pool_offset.get();
+        let pool_end = footer.asset_table_offset.get();
+        if index_start < range.start.max(size_of::<BBFHeader>() as u64)
+            || pool_end < index_start
+            || pool_end > footer_offset
+        {
+            return Err(invalid_data("Table error or invalid offsets"));
+        }
+
+        let index = &data[(index_start - range.start) as usize..end - size_of::<BBFFooter>()];
+
+        let table = |offset: u64, count: u32, elem_size: usize| {
+            index_slice(index, index_start, offset, count as usize * elem_size)
+        };
+
+        let string_pool = index[..(pool_end - index_start) as usize].to_vec();
+        let assets: Vec<BBFAssetEntry> = read_table(table(
+            footer.asset_table_offset.get(),
+            footer.asset_count.get(),
+            size_of::<BBFAssetEntry>(),
+        )?)?;
+        let pages = read_table(table(
+            footer.page_table_offset.get(),
+            footer.page_count.get(),
+            size_of::<BBFPageEntry>(),
+        )?)?;
+        let sections = read_table(table(
+            footer.section_table_offset.get(),
+            footer.section_count.get(),
+            size_of::<BBFSection>(),
+        )?)?;
+        let metadata = read_table(table(
+            footer.meta_table_offset.get(),
+            footer.key_count.get(),
+            size_of::<BBFMetadata>(),
+        )?)?;
+
+        let Extensions {
+            thumbnails,
+            page_names,
+            hash_algorithm,
+            opaque: extensions,
+        } = read_extensions(index, index_start, footer.extra_offset.get())?;
+
+        // External assets aren't in the file, so new pages can't share them.
+        let mut dedupe_map = HashMap::new();
+        for (i, asset) in assets.iter().enumerate() {
+            if !asset.asset_flags().contains(AssetFlags::EXTERNAL) {
+                dedupe_map.entry(asset.xxh3_hash.get()).or_insert(i as u32);
+            }
+        }
+
+        let mut string_map = HashMap::new();
+        let mut offset = 0;
+        for s in string_pool.split(|&b| b == 0) {
+            if let Ok(s) = std::str::from_utf8(s) {
+                string_map.entry(s.to_string()).or_insert(offset as u32);
+            }
+            offset += s.len() + 1;
+        }
+
+        inner.seek(SeekFrom::Start(index_start))?;
+        event!(
+            debug,
+            assets = assets.len(),
+            pages = pages.len(),
+            sections = sections.len(),
+            unknown_extensions = extensions.len(),
+            "Loaded existing index"
+        );
+
+        Ok(Self {
+            writer: inner,
+            current_offset: index_start,
+            alignment: 4096,
+            assets,
+            pages,
+            sections,
+            metadata,
+            string_pool,
+            thumbnails,
+            page_names,
+            extensions,
+            dedupe_map,
+            string_map,
+            hasher: Box::new(hash::Xxh3),
+            hash_algorithm,
+            unfilled: HashSet::new(),
+            id: next_builder_id(),
+            duplicate_keys: DuplicateKeys::Keep,
+            sort_sections: false,
+            bad_sections: BadSections::Warn,
+            normalize_titles: false,
+            diagnostics: Vec::new(),
+            #[cfg(feature = "zstd")]
+            compression: None,
+        })
+    }
+}
+
+struct Extension {
+    kind: u32,
+    flags: u64,
+    payload: Vec<u8>,
+}
+
+struct Extensions {
+    thumbnails: Vec<BBFThumbnailEntry>,
+    page_names: Vec<BBFPageName>,
+    hash_algorithm: u32,
+    opaque: Vec<Extension>,
+}
+
+/// Splits the expansion table found at `extra_offset` into the extensions the
+/// builder manages and opaque ones to carry over.
+fn read_extensions(index: &[u8], index_start: u64, extra_offset: u64) -> io::Result<Extensions> {
+    let mut out = Extensions {
+        thumbnails: Vec::new(),
+        page_names: Vec::new(),
+        hash_algorithm: hash::XXH3,
+        opaque: Vec::new(),
+    };
+    if extra_offset == 0 {
+        return Ok(out);
+    }
+
+    let start = extra_offset
+        .checked_sub(index_start)
+        .filter(|&s| s <= index.len() as u64)
+        .ok_or_else(|| invalid_data("Table error or invalid offsets"))? as usize;
+    let whole = (index.len() - start) / size_of::<BBFExpansionHeader>();
+    let headers: Vec<BBFExpansionHeader> = read_table(index_slice(
+        index,
+        index_start,
+        extra_offset,
+        whole * size_of::<BBFExpansionHeader>(),
+    )?)?;
+
+    for header in headers
+        .iter()
+        .take_while(|h| h.extension_type.get() != BBFExpansionHeader::END)
+    {
+        let payload = index_slice(
+            index,
+            index_start,
+            header.offset.get(),
+            header.length.get() as usize,
+        )?;
+        match header.extension_type.get() {
+            BBFExpansionHeader::THUMBNAILS => out.thumbnails = read_table(payload)?,
+            BBFExpansionHeader::PAGE_NAMES => out.page_names = read_table(payload)?,
+            BBFExpansionHeader::ASSET_HASH => {
+                let id = payload
+                    .get(..4)
+                    .ok_or_else(|| invalid_data("Table error or invalid offsets"))?;
+                out.hash_algorithm = u32::from_le_bytes([id[0], id[1], id[2], id[3]]);
+            }
+            kind => out.opaque.push(Extension {
+                kind,
+                flags: header.flags.get(),
+                payload: payload.to_vec(),
+            }),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Writes `len` zero bytes without allocating, a page of them at a time.
+fn write_zeros<W: Write>(writer: &mut W, mut len: u64) -> io::Result<()> {
+    static ZEROS: [u8; 4096] = [0; 4096];
+    while len > 0 {
+        let n = len.min(ZEROS.len() as u64) as usize;
+        writer.write_all(&ZEROS[..n])?;
+        len -= n as u64;
+    }
+    Ok(())
+}
+
+/// `assets` with `AssetFlags::THUMBNAIL` set on exactly the ones only
+/// thumbnails use.
+fn with_thumbnail_flags(
+    assets: &[BBFAssetEntry],
+    pages: &[BBFPageEntry],
+    thumbnails: &[BBFThumbnailEntry],
+) -> Vec<BBFAssetEntry> {
+    let mut assets = assets.to_vec();
+    for a in &mut assets {
+        a.flags = a.asset_flags().difference(AssetFlags::THUMBNAIL).bits();
+    }
+    for t in thumbnails {
+        if let Some(a) = assets.get_mut(t.asset_index.get() as usize) {
+            a.flags |= AssetFlags::THUMBNAIL.bits();
+        }
+    }
+    for p in pages {
+        if let Some(a) = assets.get_mut(p.asset_index.get() as usize) {
+            a.flags = a.asset_flags().difference(AssetFlags::THUMBNAIL).bits();
+        }
+    }
+    assets
+}
+
+/// The string at `offset` in `pool`, without its terminator.
+fn pool_str(pool: &[u8], offset: u32) -> &[u8] {
+    let rest = pool.get(offset as usize..).unwrap_or_default();
+    rest.split(|&b| b == 0).next().unwrap_or_default()
+}
+
+/// Bytes `offset..offset + len` of the file, where `index` holds everything
+/// from `index_start` up to the footer.
+fn index_slice(index: &[u8], index_start: u64, offset: u64, len: usize) -> io::Result<&[u8]> {
+    let start = offset
+        .checked_sub(index_start)
+        .ok_or_else(|| invalid_data("Table error or invalid offsets"))? as usize;
+    let end = start
+        .checked_add(len)
+        .filter(|&end| end <= index.len())
+        .ok_or_else(|| invalid_data("Table error or invalid offsets"))?;
+    Ok(&index[start..end])
+}
+
+fn read_table<T: FromBytes + Immutable + KnownLayout + Clone>(bytes: &[u8]) -> io::Result<Vec<T>> {
+    <[T]>::ref_from_bytes(bytes)
+        .map(<[T]>::to_vec)
+        .map_err(|_| invalid_data("Table error or invalid offsets"))
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,137 @@
+//! Books in object storage (S3, GCS, Azure, ...) read through `object_store`:
+//! the index is fetched once on open and assets on demand, as byte ranges.
+//!
+//! Needs the `cloud` feature. Stores come from the caller, so enable the
+//! `object_store` features for the services you use in your own manifest.
+
+#![allow(clippy::missing_errors_doc)]
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use object_store::path::Path;
+use object_store::{ObjectStore, ObjectStoreExt};
+
+use crate::reader::{AsyncPageSource, BBFError, BBFReader, Limits, decode_asset};
+use crate::remote;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CloudError {
+    #[error(transparent)]
+    Store(#[from] object_store::Error),
+    #[error(transparent)]
+    Bbf(#[from] BBFError),
+}
+
+/// A book stored at `path` in an object store.
+pub struct ObjectStoreReader {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    reader: BBFReader<Bytes>,
+}
+
+impl ObjectStoreReader {
+    /// Reads the header, footer and index, leaving the assets in the store.
+    pub async fn open(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self, CloudError> {
+        Self::open_with_limits(store, path, &Limits::default()).await
+    }
+
+    /// Like `open`, with `limits` instead of the defaults.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip(store, limits),
+            fields(path = %path),
+            err(level = "debug"),
+        )
+    )]
+    pub async fn open_with_limits(
+        store: Arc<dyn ObjectStore>,
+        path: Path,
+        limits: &Limits,
+    ) -> Result<Self, CloudError> {
+        let len = store.head(&path).await?.size;
+        let ranges = [remote::header_range(), remote::footer_range(len)?];
+        let [header, footer]: [Bytes; 2] = store
+            .get_ranges(&path, &ranges)
+            .await?
+            .try_into()
+            .map_err(|_| BBFError::FileTooShort)?;
+
+        let index = match remote::index_range(len, &footer, limits) {
+            Err(BBFError::InvalidMagic) => {
+                let tail = store.get_range(&path, remote::trailing_range(len)?).await?;
+                remote::trailing_index_range(len, &tail, limits)?
+            }
+            index => index?,
+        };
+        let index = store.get_range(&path, index).await?;
+        let reader = BBFReader::from_index_with_limits(&header, index, len, limits)?;
+
+        Ok(Self {
+            store,
+            path,
+            reader,
+        })
+    }
+
+    /// The book's index. Its `get_asset` returns `BBFError::NotLoaded`.
+    pub const fn reader(&self) -> &BBFReader<Bytes> {
+        &self.reader
+    }
+
+    pub const fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stored bytes of asset `index`, as `BBFReader::get_asset` would
+    /// return them.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "debug"))
+    )]
+    pub async fn get_asset(&self, index: u32) -> Result<Bytes, CloudError> {
+        let range = remote::asset_range(&self.reader, index)?;
+        let len = range.end - range.start;
+        let data = self.store.get_range(&self.path, range).await?;
+        if data.len() as u64 != len {
+            return Err(BBFError::FileTooShort.into());
+        }
+        Ok(data)
+    }
+
+    /// Asset `index` as the original file, decompressed if needed.
+    pub async fn get_asset_decoded(&self, index: u32) -> Result<Bytes, CloudError> {
+        let data = self.get_asset(index).await?;
+        let entry = &self.reader.assets()[index as usize];
+        if let Cow::Owned(decoded) = decode_asset(entry, &data)? {
+            return Ok(decoded.into());
+        }
+        Ok(data)
+    }
+
+    /// The image of page `page_index`, decompressed if needed. Also
+    /// available through `AsyncPageSource`, typed like `BBFReader::get_page`.
+    pub async fn get_page(&self, page_index: u32) -> Result<Bytes, CloudError> {
+        let page = self
+            .reader
+            .pages()
+            .get(page_index as usize)
+            .ok_or(BBFError::OutOfBounds)?;
+        self.get_asset_decoded(page.asset_index.get()).await
+    }
+}
+
+impl AsyncPageSource for ObjectStoreReader {
+    type Error = CloudError;
+
+    fn page_count(&self) -> usize {
+        self.reader.pages().len()
+    }
+
+    async fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, CloudError> {
+        Ok(Cow::Owned(Self::get_page(self, page_index).await?.into()))
+    }
+}
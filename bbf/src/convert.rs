@@ -0,0 +1,213 @@
+//! Conversion between books and other formats, shared by `bbfmux` and
+//! anything else that wants it without shelling out: PDF import through a
+//! `ConversionPlan`, and CBZ export.
+//!
+//! Needs the `convert` feature.
+
+#![allow(clippy::cast_possible_truncation, clippy::missing_errors_doc)]
+
+mod cbz;
+mod pdf;
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitStatus;
+
+use crate::builder::BBFBuilder;
+use crate::format::BBFMediaType;
+use crate::reader::BBFError;
+
+pub use cbz::write_cbz;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConvertError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Failed to parse PDF: {0}")]
+    Pdf(#[from] lopdf::Error),
+    #[error("Failed to encode page as PNG: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("Failed to write archive: {0}")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Page {page}: {source}")]
+    Page { page: usize, source: BBFError },
+    #[error("Pages have to be rasterized, which needs the PDF as a file")]
+    NeedsRasterizing,
+    #[error("Rasterizing requires `pdftoppm` (poppler-utils) on PATH")]
+    MissingRasterizer,
+    #[error("pdftoppm failed ({0})")]
+    Rasterizer(ExitStatus),
+    #[error("Source contains no pages")]
+    Empty,
+}
+
+/// What a conversion is doing, for callers that show progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// Page images are being taken out of the source as they are.
+    Extracting,
+    /// Pages are being rendered at `dpi`.
+    Rasterizing { dpi: u32 },
+    /// `done` of `total` pages have been written.
+    Pages { done: usize, total: usize },
+}
+
+/// How `ConversionPlan::from_pdf` gets page images.
+#[derive(Debug, Clone, Copy)]
+pub struct PdfOptions {
+    /// Resolution used when pages have to be rasterized.
+    pub dpi: u32,
+    /// Rasterize even if page images could be extracted losslessly.
+    pub rasterize: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 150,
+            rasterize: false,
+        }
+    }
+}
+
+pub struct PlannedPage {
+    pub data: Vec<u8>,
+    pub media_type: BBFMediaType,
+}
+
+pub struct PlannedSection {
+    pub title: String,
+    /// First page, counting from zero.
+    pub page: u32,
+    /// Index of the enclosing section in `ConversionPlan::sections`.
+    pub parent: Option<u32>,
+}
+
+/// Everything a converted book will hold, read out of the source before any
+/// of it is written, so callers can inspect or adjust it first.
+#[derive(Default)]
+pub struct ConversionPlan {
+    pub pages: Vec<PlannedPage>,
+    pub sections: Vec<PlannedSection>,
+    pub metadata: Vec<(String, String)>,
+}
+
+impl ConversionPlan {
+    /// Plans a book from the PDF at `path`: the embedded page images when the
+    /// PDF is a plain scan wrapper, rendered pages otherwise, and the outline
+    /// as sections.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "info",
+            skip(options, progress),
+            fields(path = %path.display(), dpi = options.dpi),
+            err,
+        )
+    )]
+    pub fn from_pdf(
+        path: &Path,
+        options: &PdfOptions,
+        mut progress: impl FnMut(Progress),
+    ) -> Result<Self, ConvertError> {
+        let doc = lopdf::Document::load(path)?;
+
+        let extracted = if options.rasterize {
+            None
+        } else {
+            pdf::extract_scan_images(&doc)?
+        };
+        let pages = if let Some(pages) = extracted {
+            progress(Progress::Extracting);
+            pages
+        } else {
+            event!(info, "Page images can't be kept as they are, rasterizing");
+            progress(Progress::Rasterizing { dpi: options.dpi });
+            pdf::rasterize(path, options.dpi)?
+        };
+
+        Self::from_pdf_pages(&doc, pages)
+    }
+
+    /// Like `from_pdf` for a PDF held in memory. Rendering pages needs a
+    /// file, so PDFs that aren't plain scan wrappers are refused with
+    /// `ConvertError::NeedsRasterizing`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "info", skip_all, fields(len = data.len()), err)
+    )]
+    pub fn from_pdf_bytes(
+        data: &[u8],
+        mut progress: impl FnMut(Progress),
+    ) -> Result<Self, ConvertError> {
+        let doc = lopdf::Document::load_mem(data)?;
+        let pages = pdf::extract_scan_images(&doc)?.ok_or(ConvertError::NeedsRasterizing)?;
+        progress(Progress::Extracting);
+        Self::from_pdf_pages(&doc, pages)
+    }
+
+    fn from_pdf_pages(
+        doc: &lopdf::Document,
+        pages: Vec<PlannedPage>,
+    ) -> Result<Self, ConvertError> {
+        if pages.is_empty() {
+            return Err(ConvertError::Empty);
+        }
+
+        // Outline entries arrive depth-first; keep the open ancestors on a
+        // stack so each bookmark can point at its parent section.
+        let last_page = pages.len() as u32 - 1;
+        let mut sections = Vec::new();
+        let mut open: Vec<(usize, u32)> = Vec::new();
+        for (i, b) in pdf::bookmarks(doc).into_iter().enumerate() {
+            while open.last().is_some_and(|&(level, _)| level >= b.level) {
+                open.pop();
+            }
+            if b.page > last_page {
+                event!(
+                    debug,
+                    title = %b.title,
+                    page = b.page,
+                    "Bookmark points past the last page, clamping"
+                );
+            }
+            sections.push(PlannedSection {
+                title: b.title,
+                page: b.page.min(last_page),
+                parent: open.last().map(|&(_, idx)| idx),
+            });
+            open.push((b.level, i as u32));
+        }
+
+        Ok(Self {
+            pages,
+            sections,
+            metadata: Vec::new(),
+        })
+    }
+
+    /// Adds the planned pages, sections, and metadata to `builder`. Finalizing
+    /// is left to the caller.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(pages = self.pages.len()))
+    )]
+    pub fn write<W: Write>(
+        &self,
+        builder: &mut BBFBuilder<W>,
+        mut progress: impl FnMut(Progress),
+    ) -> io::Result<()> {
+        let total = self.pages.len();
+        for (i, page) in self.pages.iter().enumerate() {
+            builder.add_page(&page.data, page.media_type, 0)?;
+            progress(Progress::Pages { done: i + 1, total });
+        }
+        for s in &self.sections {
+            builder.add_section(&s.title, s.page, s.parent);
+        }
+        for (key, value) in &self.metadata {
+            builder.add_metadata(key, value);
+        }
+        Ok(())
+    }
+}
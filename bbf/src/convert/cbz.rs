@@ -0,0 +1,172 @@
+use std::fmt::Write as _;
+use std::io::{Seek, Write};
+use zip::CompressionMethod;
+use zip::write::{SimpleFileOptions, ZipWriter};
+
+use super::{ConvertError, Progress};
+use crate::format::BBFMediaType;
+use crate::reader::BBFReader;
+
+/// ComicInfo.xml elements that BBF metadata keys are mapped onto verbatim.
+/// Anything else ends up as a `Key: Value` line in `<Notes>`.
+const COMIC_INFO_FIELDS: &[&str] = &[
+    "Title",
+    "Series",
+    "Number",
+    "Count",
+    "Volume",
+    "AlternateSeries",
+    "AlternateNumber",
+    "AlternateCount",
+    "Summary",
+    "Year",
+    "Month",
+    "Day",
+    "Writer",
+    "Penciller",
+    "Inker",
+    "Colorist",
+    "Letterer",
+    "CoverArtist",
+    "Editor",
+    "Translator",
+    "Publisher",
+    "Imprint",
+    "Genre",
+    "Tags",
+    "Web",
+    "LanguageISO",
+    "Format",
+    "BlackAndWhite",
+    "Manga",
+    "Characters",
+    "Teams",
+    "Locations",
+    "ScanInformation",
+    "StoryArc",
+    "SeriesGroup",
+    "AgeRating",
+    "CommunityRating",
+    "GTIN",
+];
+
+/// Writes every page in reading order plus a generated ComicInfo.xml.
+/// Returns the number of pages written.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "info", skip_all, fields(pages = reader.pages().len()), err)
+)]
+pub fn write_cbz<T: AsRef<[u8]>, W: Write + Seek>(
+    reader: &BBFReader<T>,
+    out: W,
+    mut progress: impl FnMut(Progress),
+) -> Result<usize, ConvertError> {
+    let mut zip = ZipWriter::new(out);
+
+    // Pages are already compressed images, deflating them again only costs time.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let pages = reader.pages();
+    let assets = reader.assets();
+    let width = pages.len().to_string().len().max(3);
+
+    for (i, page) in pages.iter().enumerate() {
+        let asset_index = page.asset_index.get();
+        let data = reader
+            .get_asset_decoded(asset_index)
+            .map_err(|source| ConvertError::Page {
+                page: i + 1,
+                source,
+            })?;
+        let ext = BBFMediaType::from(assets[asset_index as usize].type_).as_extension();
+
+        zip.start_file(format!("{:0width$}{ext}", i + 1), stored)?;
+        zip.write_all(&data)?;
+        progress(Progress::Pages {
+            done: i + 1,
+            total: pages.len(),
+        });
+    }
+
+    zip.start_file("ComicInfo.xml", deflated)?;
+    zip.write_all(comic_info(reader).as_bytes())?;
+    zip.finish()?;
+
+    Ok(pages.len())
+}
+
+fn comic_info<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+         xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n",
+    );
+
+    let mut notes = Vec::new();
+    for m in reader.metadata() {
+        let key = reader.get_string(m.key_offset.get()).unwrap_or("");
+        let val = reader.get_string(m.val_offset.get()).unwrap_or("");
+
+        match COMIC_INFO_FIELDS
+            .iter()
+            .find(|f| f.eq_ignore_ascii_case(key))
+        {
+            Some(field) => {
+                let _ = writeln!(xml, "  <{field}>{}</{field}>", escape_xml(val));
+            }
+            None => notes.push(format!("{key}: {val}")),
+        }
+    }
+
+    if !notes.is_empty() {
+        let _ = writeln!(xml, "  <Notes>{}</Notes>", escape_xml(&notes.join("\n")));
+    }
+
+    let page_count = reader.pages().len();
+    let _ = writeln!(xml, "  <PageCount>{page_count}</PageCount>");
+
+    // ComicInfo has one bookmark per page, so sections sharing a start page
+    // are joined into a single label.
+    let mut bookmarks: Vec<Vec<&str>> = vec![Vec::new(); page_count];
+    for s in reader.sections() {
+        let start = s.section_start_index.get() as usize;
+        if let Some(slot) = bookmarks.get_mut(start) {
+            slot.push(
+                reader
+                    .get_string(s.section_title_offset.get())
+                    .unwrap_or(""),
+            );
+        }
+    }
+
+    xml.push_str("  <Pages>\n");
+    for (i, titles) in bookmarks.iter().enumerate() {
+        let _ = write!(xml, "    <Page Image=\"{i}\"");
+        if i == 0 {
+            xml.push_str(" Type=\"FrontCover\"");
+        }
+        if !titles.is_empty() {
+            let _ = write!(xml, " Bookmark=\"{}\"", escape_xml(&titles.join(" / ")));
+        }
+        xml.push_str(" />\n");
+    }
+    xml.push_str("  </Pages>\n</ComicInfo>\n");
+
+    xml
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
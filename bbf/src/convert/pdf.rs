@@ -0,0 +1,165 @@
+use lopdf::Document;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use super::{ConvertError, PlannedPage};
+use crate::format::BBFMediaType;
+
+type Result<T> = std::result::Result<T, ConvertError>;
+
+pub struct Bookmark {
+    pub title: String,
+    pub level: usize,
+    pub page: u32, // 0-based
+}
+
+/// Pulls the embedded image out of every page when the PDF is a plain scan
+/// wrapper (exactly one image per page, stored in a format we can keep as-is).
+///
+/// Returns `None` if any page doesn't fit that shape, in which case the
+/// caller should fall back to rasterizing.
+pub fn extract_scan_images(doc: &Document) -> Result<Option<Vec<PlannedPage>>> {
+    let mut out = Vec::new();
+
+    for page_id in doc.get_pages().into_values() {
+        let images = doc.get_page_images(page_id).unwrap_or_default();
+        let [image] = images.as_slice() else {
+            event!(
+                debug,
+                page = ?page_id,
+                images = images.len(),
+                "Page isn't a single image"
+            );
+            return Ok(None);
+        };
+
+        if image.origin_dict.has(b"SMask") || image.origin_dict.has(b"Decode") {
+            event!(debug, page = ?page_id, "Page image has a mask or decode array");
+            return Ok(None);
+        }
+
+        let filters = image.filters.clone().unwrap_or_default();
+        let page = match filters.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["DCTDecode"] => PlannedPage {
+                data: image.content.to_vec(),
+                media_type: BBFMediaType::Jpg,
+            },
+            [] | ["FlateDecode"] => {
+                let Some(png) = encode_raw_image(doc, image)? else {
+                    return Ok(None);
+                };
+                PlannedPage {
+                    data: png,
+                    media_type: BBFMediaType::Png,
+                }
+            }
+            _ => {
+                event!(debug, page = ?page_id, ?filters, "Page image filters can't be kept");
+                return Ok(None);
+            }
+        };
+        out.push(page);
+    }
+
+    Ok(Some(out))
+}
+
+/// Re-wraps raw 8-bit gray/RGB samples as a PNG, which keeps them lossless.
+fn encode_raw_image(doc: &Document, image: &lopdf::xobject::PdfImage) -> Result<Option<Vec<u8>>> {
+    let color_type = match image.color_space.as_deref() {
+        Some("DeviceGray") => png::ColorType::Grayscale,
+        Some("DeviceRGB") => png::ColorType::Rgb,
+        _ => return Ok(None),
+    };
+    if image.bits_per_component != Some(8) {
+        return Ok(None);
+    }
+
+    let stream = doc.get_object(image.id)?.as_stream()?;
+    let samples = if image.filters.as_ref().is_some_and(|f| !f.is_empty()) {
+        stream.decompressed_content()?
+    } else {
+        stream.content.clone()
+    };
+
+    let (Ok(width), Ok(height)) = (u32::try_from(image.width), u32::try_from(image.height)) else {
+        return Ok(None);
+    };
+    let channels = if color_type == png::ColorType::Rgb {
+        3
+    } else {
+        1
+    };
+    if samples.len() != width as usize * height as usize * channels {
+        return Ok(None);
+    }
+
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&samples)?;
+
+    Ok(Some(buf))
+}
+
+/// Renders every page to PNG through poppler's `pdftoppm`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip(path), err)
+)]
+pub fn rasterize(path: &Path, dpi: u32) -> Result<Vec<PlannedPage>> {
+    let tmp = tempfile::tempdir()?;
+    let prefix = tmp.path().join("p");
+
+    let status = Command::new("pdftoppm")
+        .arg("-r")
+        .arg(dpi.to_string())
+        .arg("-png")
+        .arg(path)
+        .arg(&prefix)
+        .status()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => ConvertError::MissingRasterizer,
+            _ => ConvertError::Io(e),
+        })?;
+
+    if !status.success() {
+        return Err(ConvertError::Rasterizer(status));
+    }
+
+    // pdftoppm zero-pads page numbers to a fixed width, so a plain sort is enough.
+    let mut files: Vec<_> = fs::read_dir(tmp.path())?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|p| {
+            Ok(PlannedPage {
+                data: fs::read(&p)?,
+                media_type: BBFMediaType::Png,
+            })
+        })
+        .collect()
+}
+
+/// Flattens the document outline into (title, depth, page) triples in order.
+pub fn bookmarks(doc: &Document) -> Vec<Bookmark> {
+    let Ok(toc) = doc.get_toc() else {
+        event!(debug, "No usable outline, converting without sections");
+        return Vec::new();
+    };
+
+    toc.toc
+        .into_iter()
+        .map(|t| Bookmark {
+            title: t.title,
+            level: t.level,
+            page: (t.page as u32).saturating_sub(1),
+        })
+        .collect()
+}
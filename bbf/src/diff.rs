@@ -0,0 +1,181 @@
+//! Page, section, and metadata comparison between two books, for telling
+//! whether two files hold the same book and what changed if not.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::collections::BTreeMap;
+
+use crate::reader::BBFReader;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct BookDiff {
+    /// No page, section or metadata differs. Asset layout, compression and
+    /// thumbnails aren't compared.
+    pub identical: bool,
+    pub pages: Vec<PageDelta>,
+    pub sections: Vec<SectionDelta>,
+    pub metadata: Vec<MetaDelta>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PageDelta {
+    pub index: u32,
+    pub change: Change,
+    /// Hash of the page's asset in the first book.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "hex_opt"))]
+    pub old_hash: Option<u64>,
+    /// Hash of the page's asset in the second book.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "hex_opt"))]
+    pub new_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SectionDelta {
+    pub title: String,
+    pub change: Change,
+    pub old_start: Option<u32>,
+    pub new_start: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MetaDelta {
+    pub key: String,
+    pub change: Change,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// Compares pages positionally by asset hash, sections by title, and metadata
+/// by key. Repeated keys are compared as their values joined in table order.
+#[must_use]
+pub fn compare<A: AsRef<[u8]>, B: AsRef<[u8]>>(a: &BBFReader<A>, b: &BBFReader<B>) -> BookDiff {
+    let (old_pages, new_pages) = (page_hashes(a), page_hashes(b));
+    let mut pages = Vec::new();
+    for i in 0..old_pages.len().max(new_pages.len()) {
+        let (old, new) = (
+            old_pages.get(i).copied().flatten(),
+            new_pages.get(i).copied().flatten(),
+        );
+        let change = match (i < old_pages.len(), i < new_pages.len()) {
+            (true, false) => Change::Removed,
+            (false, true) => Change::Added,
+            _ if old != new => Change::Changed,
+            _ => continue,
+        };
+        pages.push(PageDelta {
+            index: i as u32,
+            change,
+            old_hash: old,
+            new_hash: new,
+        });
+    }
+
+    let sections = deltas(section_starts(a), section_starts(b))
+        .map(|(title, change, old_start, new_start)| SectionDelta {
+            title,
+            change,
+            old_start,
+            new_start,
+        })
+        .collect::<Vec<_>>();
+
+    let metadata = deltas(meta_values(a), meta_values(b))
+        .map(|(key, change, old, new)| MetaDelta {
+            key,
+            change,
+            old,
+            new,
+        })
+        .collect::<Vec<_>>();
+
+    BookDiff {
+        identical: pages.is_empty() && sections.is_empty() && metadata.is_empty(),
+        pages,
+        sections,
+        metadata,
+    }
+}
+
+/// Hash of each page's asset, or `None` if the page points past the asset table.
+fn page_hashes<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<Option<u64>> {
+    let assets = reader.assets();
+    reader
+        .pages()
+        .iter()
+        .map(|p| {
+            assets
+                .get(p.asset_index.get() as usize)
+                .map(|a| a.xxh3_hash.get())
+        })
+        .collect()
+}
+
+/// Start page per section title. Titles that repeat keep their first start.
+fn section_starts<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> BTreeMap<String, u32> {
+    let mut out = BTreeMap::new();
+    for s in reader.sections() {
+        let title = reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("")
+            .to_string();
+        out.entry(title).or_insert(s.section_start_index.get());
+    }
+    out
+}
+
+fn meta_values<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> BTreeMap<String, String> {
+    let mut out: BTreeMap<String, String> = BTreeMap::new();
+    for m in reader.metadata() {
+        let key = reader.get_string(m.key_offset.get()).unwrap_or("");
+        let value = reader.get_string(m.val_offset.get()).unwrap_or("");
+        out.entry(key.to_string())
+            .and_modify(|v| {
+                v.push_str("; ");
+                v.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    out
+}
+
+fn deltas<V: PartialEq>(
+    old: BTreeMap<String, V>,
+    mut new: BTreeMap<String, V>,
+) -> impl Iterator<Item = (String, Change, Option<V>, Option<V>)> {
+    let mut out = Vec::new();
+    for (key, old_val) in old {
+        match new.remove(&key) {
+            Some(new_val) if new_val == old_val => {}
+            Some(new_val) => out.push((key, Change::Changed, Some(old_val), Some(new_val))),
+            None => out.push((key, Change::Removed, Some(old_val), None)),
+        }
+    }
+    out.extend(
+        new.into_iter()
+            .map(|(key, new_val)| (key, Change::Added, None, Some(new_val))),
+    );
+    out.sort_by(|x, y| x.0.cmp(&y.0));
+    out.into_iter()
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::ref_option)]
+fn hex_opt<S: serde::Serializer>(v: &Option<u64>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(v) => s.serialize_str(&format!("{v:016x}")),
+        None => s.serialize_none(),
+    }
+}
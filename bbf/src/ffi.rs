@@ -47,7 +47,8 @@ pub extern "C" fn bbf_builder_new(path: *const c_char) -> *mut CBbfBuilder {
 /// * `builder` - Pointer to the builder instance.
 /// * `data` - Pointer to the raw image data.
 /// * `len` - Length of the image data in bytes.
-/// * `media_type` - The format of the image data (e.g., PNG, JPEG).
+/// * `media_type` - The format of the image data as its type byte (e.g. 0x02
+///   for PNG). Bytes this library doesn't know are stored as given.
 /// * `flags` - Optional flags for the page (usually 0).
 ///
 /// Returns the asset index on success, or 0xFFFFFFFF ((uint32_t)-1) on failure.
@@ -56,7 +57,7 @@ pub extern "C" fn bbf_builder_add_page(
     builder: *mut CBbfBuilder,
     data: *const u8,
     len: usize,
-    media_type: BBFMediaType,
+    media_type: u8,
     flags: u32,
 ) -> u32 {
     let result = panic::catch_unwind(|| {
@@ -68,7 +69,7 @@ pub extern "C" fn bbf_builder_add_page(
         let slice = unsafe { slice::from_raw_parts(data, len) };
 
         builder_ref
-            .add_page(slice, media_type, flags)
+            .add_page(slice, BBFMediaType::from(media_type), flags)
             .unwrap_or(0xFFFF_FFFF)
     });
 
@@ -2,23 +2,47 @@ use zerocopy::byteorder::LittleEndian;
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 use zerocopy::{U16, U32, U64};
 
-#[repr(u8)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, Default)]
 pub enum BBFMediaType {
     #[default]
-    Unknown = 0x00,
-    Avif = 0x01,
-    Png = 0x02,
-    Webp = 0x03,
-    Jxl = 0x04,
-    Bmp = 0x05,
-    Gif = 0x07,
-    Tiff = 0x08,
-    Jpg = 0x09,
+    Unknown,
+    Avif,
+    Png,
+    Webp,
+    Jxl,
+    Bmp,
+    Gif,
+    Tiff,
+    Jpg,
+    /// A type byte this version doesn't know, likely from a newer writer.
+    /// Kept so the asset is written back with the same byte; `From<u8>`
+    /// never produces it for a known value. Types compare by their byte,
+    /// so `Other(2)` still equals `Png`.
+    Other(u8),
 }
 
 impl From<u8> for BBFMediaType {
     fn from(v: u8) -> Self {
+        Self::from_byte(v)
+    }
+}
+
+impl From<BBFMediaType> for u8 {
+    fn from(t: BBFMediaType) -> Self {
+        t.byte()
+    }
+}
+
+impl PartialEq for BBFMediaType {
+    fn eq(&self, other: &Self) -> bool {
+        self.byte() == other.byte()
+    }
+}
+
+impl Eq for BBFMediaType {}
+
+impl BBFMediaType {
+    const fn from_byte(v: u8) -> Self {
         match v {
             0x01 => Self::Avif,
             0x02 => Self::Png,
@@ -28,12 +52,26 @@ impl From<u8> for BBFMediaType {
             0x07 => Self::Gif,
             0x08 => Self::Tiff,
             0x09 => Self::Jpg,
-            _ => Self::Unknown,
+            0x00 => Self::Unknown,
+            v => Self::Other(v),
+        }
+    }
+
+    const fn byte(self) -> u8 {
+        match self {
+            Self::Unknown => 0x00,
+            Self::Avif => 0x01,
+            Self::Png => 0x02,
+            Self::Webp => 0x03,
+            Self::Jxl => 0x04,
+            Self::Bmp => 0x05,
+            Self::Gif => 0x07,
+            Self::Tiff => 0x08,
+            Self::Jpg => 0x09,
+            Self::Other(v) => v,
         }
     }
-}
 
-impl BBFMediaType {
     #[must_use]
     pub fn from_extension(ext: &str) -> Self {
         match ext.to_lowercase().as_str() {
@@ -51,7 +89,7 @@ impl BBFMediaType {
 
     #[must_use]
     pub const fn as_extension(&self) -> &'static str {
-        match self {
+        match Self::from_byte(self.byte()) {
             Self::Png => ".png",
             Self::Jpg => ".jpg",
             Self::Avif => ".avif",
@@ -60,7 +98,7 @@ impl BBFMediaType {
             Self::Bmp => ".bmp",
             Self::Gif => ".gif",
             Self::Tiff => ".tiff",
-            Self::Unknown => ".bin",
+            Self::Unknown | Self::Other(_) => ".bin",
         }
     }
 }
@@ -88,6 +126,99 @@ pub struct BBFAssetEntry {
     pub reserved: [U64<LittleEndian>; 3],
 }
 
+impl BBFAssetEntry {
+    /// `AssetFlags::COMPRESSED` as a raw bit.
+    pub const ZSTD: u8 = AssetFlags::COMPRESSED.bits();
+    /// Index in `reserved` of the source file's modification time, in unix
+    /// seconds; 0 if unknown.
+    pub const MTIME: usize = 0;
+
+    /// When the file this asset was made from was last modified, in unix
+    /// seconds, if recorded.
+    #[must_use]
+    pub fn mtime(&self) -> Option<u64> {
+        Some(self.reserved[Self::MTIME].get()).filter(|&t| t != 0)
+    }
+
+    #[must_use]
+    pub const fn asset_flags(&self) -> AssetFlags {
+        AssetFlags::from_bits_retain(self.flags)
+    }
+
+    /// Bytes the asset takes up in the book: `length`, or 0 for external
+    /// assets, whose `offset` is a string instead.
+    #[must_use]
+    pub const fn stored_len(&self) -> u64 {
+        if self.asset_flags().contains(AssetFlags::EXTERNAL) {
+            0
+        } else {
+            self.length.get()
+        }
+    }
+}
+
+/// The bits of `BBFAssetEntry::flags`. Bits without a name are kept as they
+/// are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AssetFlags(u8);
+
+impl AssetFlags {
+    /// Stored bytes are a zstd frame; `decoded_length` is the original size.
+    /// `xxh3_hash` always covers the bytes as stored.
+    pub const COMPRESSED: Self = Self(1 << 0);
+    /// Stored bytes are encrypted by the application that wrote them.
+    /// Readers hand them out as stored but won't decode them.
+    pub const ENCRYPTED: Self = Self(1 << 1);
+    /// The payload lives outside the book.
+    pub const EXTERNAL: Self = Self(1 << 2);
+    /// Only thumbnails use this asset, never a page.
+    pub const THUMBNAIL: Self = Self(1 << 3);
+
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    #[must_use]
+    pub const fn from_bits_retain(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    #[must_use]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl core::ops::BitOr for AssetFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl core::ops::BitOrAssign for AssetFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
 #[repr(C, packed)]
 #[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
 pub struct BBFPageEntry {
@@ -95,6 +226,32 @@ pub struct BBFPageEntry {
     pub flags: U32<LittleEndian>,
 }
 
+impl BBFPageEntry {
+    /// Page is a two-page spread and should be shown on its own.
+    pub const SPREAD: u32 = 1 << 0;
+    /// Page is the book's cover.
+    pub const COVER: u32 = 1 << 1;
+    /// Bits 2-3 hold the clockwise quarter turns to apply when displaying.
+    pub const ROTATION_MASK: u32 = 0b11 << 2;
+    pub const ROTATION_SHIFT: u32 = 2;
+
+    /// Flag bits for a clockwise rotation, or `None` unless `degrees` is a
+    /// multiple of 90.
+    #[must_use]
+    pub const fn rotation_flags(degrees: u32) -> Option<u32> {
+        if !degrees.is_multiple_of(90) {
+            return None;
+        }
+        Some(((degrees / 90) % 4) << Self::ROTATION_SHIFT)
+    }
+
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270.
+    #[must_use]
+    pub fn rotation_degrees(&self) -> u32 {
+        ((self.flags.get() & Self::ROTATION_MASK) >> Self::ROTATION_SHIFT) * 90
+    }
+}
+
 #[repr(C, packed)]
 #[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
 pub struct BBFSection {
@@ -120,6 +277,34 @@ pub struct BBFExpansionHeader {
     pub length: U64<LittleEndian>,
 }
 
+impl BBFExpansionHeader {
+    /// Marks the end of the expansion table that `BBFFooter::extra_offset` points at.
+    pub const END: u32 = 0;
+    /// Payload is an array of `BBFThumbnailEntry`.
+    pub const THUMBNAILS: u32 = 1;
+    /// Payload is an array of `BBFPageName`.
+    pub const PAGE_NAMES: u32 = 2;
+    /// Payload is a little-endian `u32`: the `hash` algorithm id of every
+    /// asset hash. Absent means XXH3.
+    pub const ASSET_HASH: u32 = 3;
+}
+
+/// Links a page to a downscaled preview stored as a regular asset.
+#[repr(C, packed)]
+#[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
+pub struct BBFThumbnailEntry {
+    pub page_index: U32<LittleEndian>,
+    pub asset_index: U32<LittleEndian>,
+}
+
+/// Records the file a page was built from, as an offset into the string pool.
+#[repr(C, packed)]
+#[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
+pub struct BBFPageName {
+    pub page_index: U32<LittleEndian>,
+    pub name_offset: U32<LittleEndian>,
+}
+
 #[repr(C, packed)]
 #[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
 pub struct BBFFooter {
@@ -141,3 +326,67 @@ pub struct BBFFooter {
     pub index_hash: U64<LittleEndian>,
     pub magic: [u8; 4],
 }
+
+/// One volume of a `.bbfpack`. Its book is the pack's first `end_offset` bytes.
+#[repr(C, packed)]
+#[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
+pub struct BBFPackEntry {
+    pub end_offset: U64<LittleEndian>,
+    /// Offset into the pack's name pool, not a volume's string pool.
+    pub name_offset: U32<LittleEndian>,
+    pub reserved: U32<LittleEndian>,
+}
+
+/// Closes a `.bbfpack`: the name pool, then the volume table, then this.
+#[repr(C, packed)]
+#[derive(IntoBytes, FromBytes, Immutable, KnownLayout, Unaligned, Debug, Clone, Copy)]
+pub struct BBFPackFooter {
+    pub name_pool_offset: U64<LittleEndian>,
+    pub volume_table_offset: U64<LittleEndian>,
+    pub volume_count: U32<LittleEndian>,
+    pub magic: [u8; 4],
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for BBFMediaType {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(u8::arbitrary(u)?.into())
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        u8::size_hint(depth)
+    }
+}
+
+/// Builds each struct from raw bytes, the way the reader gets them from a
+/// file, so fuzzers see every bit pattern a damaged book could hold.
+#[cfg(feature = "arbitrary")]
+macro_rules! impl_arbitrary_from_bytes {
+    ($($ty:ty),* $(,)?) => {$(
+        impl<'a> arbitrary::Arbitrary<'a> for $ty {
+            fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+                let bytes = u.bytes(size_of::<Self>())?;
+                Self::read_from_bytes(bytes).map_err(|_| arbitrary::Error::NotEnoughData)
+            }
+
+            fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+                (size_of::<Self>(), Some(size_of::<Self>()))
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "arbitrary")]
+impl_arbitrary_from_bytes!(
+    BBFHeader,
+    BBFAssetEntry,
+    BBFPageEntry,
+    BBFSection,
+    BBFMetadata,
+    BBFExpansionHeader,
+    BBFThumbnailEntry,
+    BBFPageName,
+    BBFFooter,
+    BBFPackEntry,
+    BBFPackFooter,
+);
@@ -0,0 +1,51 @@
+//! The hash stored with each asset, used for deduplication and verification.
+//!
+//! Books use XXH3-64 unless built with another `ContentHasher`, whose `id`
+//! is then recorded in a `BBFExpansionHeader::ASSET_HASH` extension. The
+//! index hash in the footer is always XXH3.
+
+use core::panic::RefUnwindSafe;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// XXH3-64, the default.
+pub const XXH3: u32 = 0;
+/// SHA-256, truncated to its first 8 bytes read as little-endian.
+pub const SHA256: u32 = 1;
+/// BLAKE3, truncated to its first 8 bytes read as little-endian.
+pub const BLAKE3: u32 = 2;
+
+/// Computes the 64-bit digest stored in `BBFAssetEntry::xxh3_hash`.
+///
+/// Only `Xxh3` ships with this crate; implement this over a SHA-256 or
+/// BLAKE3 library (with the matching `id`) where those are required.
+pub trait ContentHasher: Send + Sync + RefUnwindSafe {
+    /// Which algorithm this is, as recorded in the book: `XXH3`, `SHA256`,
+    /// `BLAKE3`, or a private value of 256 or above.
+    fn id(&self) -> u32;
+
+    fn hash(&self, data: &[u8]) -> u64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xxh3;
+
+impl ContentHasher for Xxh3 {
+    fn id(&self) -> u32 {
+        XXH3
+    }
+
+    fn hash(&self, data: &[u8]) -> u64 {
+        xxh3_64(data)
+    }
+}
+
+/// Display name of a hash algorithm id, if it's one of the known ones.
+#[must_use]
+pub const fn name(id: u32) -> Option<&'static str> {
+    match id {
+        XXH3 => Some("XXH3"),
+        SHA256 => Some("SHA-256"),
+        BLAKE3 => Some("BLAKE3"),
+        _ => None,
+    }
+}
@@ -0,0 +1,102 @@
+//! Books on a web server, read with HTTP range requests so only the index
+//! and the pages actually viewed are downloaded.
+//!
+//! Needs the `http` feature.
+
+#![allow(clippy::missing_errors_doc)]
+
+use std::io;
+
+use reqwest::StatusCode;
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+
+use crate::reader::BBFReader;
+use crate::remote::{RandomAccess, RemoteError, RemoteReader};
+
+/// A file served over HTTP(S) by a server that honours `Range` requests.
+pub struct HttpSource {
+    client: Client,
+    url: String,
+    len: u64,
+}
+
+impl HttpSource {
+    /// Checks that `url` can be read in ranges and learns its size.
+    pub fn new(url: &str) -> io::Result<Self> {
+        Self::with_client(Client::new(), url)
+    }
+
+    /// Like `new`, sending requests through `client` (for proxies,
+    /// timeouts or authentication headers).
+    pub fn with_client(client: Client, url: &str) -> io::Result<Self> {
+        let response = client
+            .get(url)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .map_err(io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(no_ranges());
+        }
+
+        // "bytes 0-0/<len>"
+        let len = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit_once('/'))
+            .and_then(|(_, len)| len.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing file size"))?;
+
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            len,
+        })
+    }
+
+    #[must_use]
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl RandomAccess for HttpSource {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.len)
+    }
+
+    fn read(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        if start >= end {
+            return Ok(Vec::new());
+        }
+        let response = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={start}-{}", end - 1))
+            .send()
+            .and_then(reqwest::blocking::Response::error_for_status)
+            .map_err(io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(no_ranges());
+        }
+        let body = response.bytes().map_err(io::Error::other)?;
+        Ok(body.to_vec())
+    }
+}
+
+fn no_ranges() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Server does not support range requests",
+    )
+}
+
+impl BBFReader<Vec<u8>> {
+    /// Opens the book at `url`, downloading only its header, footer and
+    /// index. Pages are fetched by `RemoteReader::get_page`.
+    pub fn open_url(url: &str) -> Result<RemoteReader<HttpSource>, RemoteError> {
+        RemoteReader::open(HttpSource::new(url)?)
+    }
+}
@@ -0,0 +1,130 @@
+//! Pages as decoded images, for apps that would rather not pick a decoder
+//! per media type: `BBFReader::decode_page` and `BBFBuilder::add_image`.
+//!
+//! Needs the `image` feature. AVIF is only encoded, and JPEG XL isn't
+//! supported by the `image` crate at all.
+
+#![allow(clippy::missing_errors_doc)]
+
+use std::io::{self, Cursor, Write};
+
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageFormat};
+
+use crate::builder::BBFBuilder;
+use crate::format::BBFMediaType;
+use crate::reader::{BBFError, BBFReader};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ImagingError {
+    #[error(transparent)]
+    Bbf(#[from] BBFError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error("No codec for {0:?} images")]
+    Unsupported(BBFMediaType),
+}
+
+/// The `image` format for a media type, if it has one.
+#[must_use]
+pub const fn image_format(media_type: BBFMediaType) -> Option<ImageFormat> {
+    match media_type {
+        BBFMediaType::Png => Some(ImageFormat::Png),
+        BBFMediaType::Jpg => Some(ImageFormat::Jpeg),
+        BBFMediaType::Webp => Some(ImageFormat::WebP),
+        BBFMediaType::Avif => Some(ImageFormat::Avif),
+        BBFMediaType::Bmp => Some(ImageFormat::Bmp),
+        BBFMediaType::Gif => Some(ImageFormat::Gif),
+        BBFMediaType::Tiff => Some(ImageFormat::Tiff),
+        BBFMediaType::Jxl | BBFMediaType::Unknown | BBFMediaType::Other(_) => None,
+    }
+}
+
+/// Encodes `image` as `target`. `quality` is 1-100 and only applies to the
+/// lossy targets, AVIF and JPEG; WebP and PNG are lossless. Other media
+/// types are `ImagingError::Unsupported`.
+pub fn encode(
+    image: &DynamicImage,
+    target: BBFMediaType,
+    quality: u8,
+) -> Result<Vec<u8>, ImagingError> {
+    let mut out = Vec::new();
+
+    match target {
+        BBFMediaType::Avif => {
+            let encoder = AvifEncoder::new_with_speed_quality(&mut out, 6, quality);
+            without_needless_alpha(image).write_with_encoder(encoder)?;
+        }
+        BBFMediaType::Jpg => {
+            let encoder = JpegEncoder::new_with_quality(&mut out, quality);
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)?;
+        }
+        BBFMediaType::Webp => {
+            let encoder = WebPEncoder::new_lossless(&mut out);
+            without_needless_alpha(image).write_with_encoder(encoder)?;
+        }
+        BBFMediaType::Png => image.write_with_encoder(PngEncoder::new(&mut out))?,
+        _ => return Err(ImagingError::Unsupported(target)),
+    }
+
+    Ok(out)
+}
+
+/// Scans rarely use transparency; dropping an opaque alpha channel keeps
+/// encoders from spending bits on it. Also normalizes to 8-bit RGB(A).
+fn without_needless_alpha(image: &DynamicImage) -> DynamicImage {
+    if image.color().has_alpha() {
+        let rgba = image.to_rgba8();
+        if rgba.pixels().any(|p| p.0[3] != u8::MAX) {
+            return DynamicImage::ImageRgba8(rgba);
+        }
+    }
+    DynamicImage::ImageRgb8(image.to_rgb8())
+}
+
+impl<T: AsRef<[u8]>> BBFReader<T> {
+    /// Decodes the image of page `page_index`. For animated GIFs this is the
+    /// first frame. The page's rotation flags are not applied.
+    pub fn decode_page(&self, page_index: u32) -> Result<DynamicImage, ImagingError> {
+        let page = self
+            .pages()
+            .get(page_index as usize)
+            .ok_or(BBFError::OutOfBounds)?;
+        self.decode_image(page.asset_index.get())
+    }
+
+    /// Decodes asset `asset_index`, such as a thumbnail, as an image.
+    pub fn decode_image(&self, asset_index: u32) -> Result<DynamicImage, ImagingError> {
+        let data = self.get_asset_decoded(asset_index)?;
+        let media_type = BBFMediaType::from(self.assets()[asset_index as usize].type_);
+        let format = image_format(media_type).ok_or(ImagingError::Unsupported(media_type))?;
+        Ok(image::load_from_memory_with_format(&data, format)?)
+    }
+}
+
+impl<W: Write> BBFBuilder<W> {
+    /// Encodes `image` as `target_format` and adds it as a page. JPEG drops
+    /// the alpha channel; WebP is encoded losslessly.
+    pub fn add_image(
+        &mut self,
+        image: &DynamicImage,
+        target_format: BBFMediaType,
+    ) -> Result<u32, ImagingError> {
+        let format = image_format(target_format).ok_or(ImagingError::Unsupported(target_format))?;
+
+        let mut data = Vec::new();
+        if format == ImageFormat::Jpeg && image.color().has_alpha() {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_to(&mut Cursor::new(&mut data), format)?;
+        } else {
+            image.write_to(&mut Cursor::new(&mut data), format)?;
+        }
+
+        Ok(self.add_page(&data, target_format, 0)?)
+    }
+}
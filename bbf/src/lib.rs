@@ -1,8 +1,48 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// A `tracing` event, or nothing without the `tracing` feature.
+macro_rules! event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "std")]
 pub mod builder;
+#[cfg(feature = "cloud")]
+pub mod cloud;
+#[cfg(feature = "convert")]
+pub mod convert;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
 pub mod ffi;
 pub mod format;
+pub mod hash;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "image")]
+pub mod imaging;
+#[cfg(feature = "std")]
+pub mod pack;
 pub mod reader;
+#[cfg(feature = "std")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "image")]
+pub mod thumbs;
+#[cfg(feature = "std")]
+pub mod validate;
 
+#[cfg(feature = "std")]
 pub use builder::BBFBuilder;
+#[cfg(feature = "std")]
+pub use diff::compare;
 pub use format::BBFMediaType;
 pub use reader::BBFReader;
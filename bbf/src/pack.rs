@@ -0,0 +1,152 @@
+//! Experimental `.bbfpack` files: several books sharing one asset store, so
+//! pages repeated across a series (credits, ads) are stored once.
+//!
+//! A pack starts like a book. Each volume's index and footer follow the
+//! assets it introduced, and asset offsets are absolute, so every volume is
+//! a valid book made of the pack's first `end_offset` bytes. A name pool,
+//! a table of `BBFPackEntry` and a `BBFPackFooter` close the file.
+
+#![allow(clippy::cast_possible_truncation, clippy::missing_errors_doc)]
+
+use std::io::{self, Write};
+use std::mem::size_of;
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::builder::BBFBuilder;
+use crate::format::{BBFPackEntry, BBFPackFooter};
+use crate::reader::{BBFError, BBFReader};
+
+pub const PACK_MAGIC: [u8; 4] = *b"BBFP";
+
+pub struct PackBuilder<W: Write> {
+    builder: BBFBuilder<W>,
+    volumes: Vec<(String, u64)>,
+}
+
+impl<W: Write> PackBuilder<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        Ok(Self {
+            builder: BBFBuilder::new(writer)?,
+            volumes: Vec::new(),
+        })
+    }
+
+    /// The builder for the volume in progress. Pages, sections, and metadata
+    /// added here belong to it until `finish_volume`.
+    pub const fn volume(&mut self) -> &mut BBFBuilder<W> {
+        &mut self.builder
+    }
+
+    /// Writes the current volume's index under `name` and starts the next.
+    pub fn finish_volume(&mut self, name: &str) -> io::Result<()> {
+        let end = self.builder.finish_volume()?;
+        self.volumes.push((name.to_string(), end));
+        Ok(())
+    }
+
+    /// Number of distinct assets stored across all volumes so far.
+    pub fn asset_count(&self) -> u32 {
+        self.builder.asset_count()
+    }
+
+    /// Writes the volume table and pack footer. Pages added since the last
+    /// `finish_volume` are dropped.
+    pub fn finish(self) -> io::Result<W> {
+        let (mut writer, mut offset) = self.builder.into_parts();
+
+        let mut names = Vec::new();
+        let mut entries = Vec::with_capacity(self.volumes.len());
+        for (name, end) in &self.volumes {
+            entries.push(BBFPackEntry {
+                end_offset: (*end).into(),
+                name_offset: (names.len() as u32).into(),
+                reserved: 0.into(),
+            });
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+
+        let name_pool_offset = offset;
+        writer.write_all(&names)?;
+        offset += names.len() as u64;
+        writer.write_all(entries.as_bytes())?;
+
+        let footer = BBFPackFooter {
+            name_pool_offset: name_pool_offset.into(),
+            volume_table_offset: offset.into(),
+            volume_count: (entries.len() as u32).into(),
+            magic: PACK_MAGIC,
+        };
+        writer.write_all(footer.as_bytes())?;
+        Ok(writer)
+    }
+}
+
+pub struct PackReader<T: AsRef<[u8]>> {
+    data: T,
+    volumes: Vec<(String, usize)>,
+}
+
+impl<T: AsRef<[u8]>> PackReader<T> {
+    pub fn new(data: T) -> Result<Self, BBFError> {
+        let slice = data.as_ref();
+        let footer_start = slice
+            .len()
+            .checked_sub(size_of::<BBFPackFooter>())
+            .ok_or(BBFError::FileTooShort)?;
+        let footer = BBFPackFooter::read_from_bytes(&slice[footer_start..])
+            .map_err(|_| BBFError::FileTooShort)?;
+        if footer.magic != PACK_MAGIC {
+            return Err(BBFError::InvalidMagic);
+        }
+
+        let pool_start = footer.name_pool_offset.get() as usize;
+        let table_start = footer.volume_table_offset.get() as usize;
+        let table_len = footer.volume_count.get() as usize * size_of::<BBFPackEntry>();
+        if pool_start > table_start || table_start.saturating_add(table_len) != footer_start {
+            return Err(BBFError::TableError);
+        }
+        let pool = &slice[pool_start..table_start];
+        let entries = <[BBFPackEntry]>::ref_from_bytes(&slice[table_start..footer_start])
+            .map_err(|_| BBFError::TableError)?;
+
+        let volumes = entries
+            .iter()
+            .map(|e| {
+                let end = e.end_offset.get() as usize;
+                let name = pool
+                    .get(e.name_offset.get() as usize..)
+                    .and_then(|rest| rest.split(|&b| b == 0).next())
+                    .and_then(|n| std::str::from_utf8(n).ok())
+                    .ok_or(BBFError::TableError)?;
+                if end > pool_start {
+                    return Err(BBFError::TableError);
+                }
+                Ok((name.to_string(), end))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { data, volumes })
+    }
+
+    /// Volume names in the order they were packed.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.volumes.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+
+    /// Opens volume `index` as a book. Its pages resolve to the shared store.
+    pub fn volume(&self, index: usize) -> Result<BBFReader<&[u8]>, BBFError> {
+        let (_, end) = self.volumes.get(index).ok_or(BBFError::OutOfBounds)?;
+        BBFReader::new(&self.data.as_ref()[..*end])
+    }
+
+    /// Opens the first volume called `name`.
+    pub fn find(&self, name: &str) -> Option<Result<BBFReader<&[u8]>, BBFError>> {
+        let index = self.volumes.iter().position(|(n, _)| n == name)?;
+        Some(self.volume(index))
+    }
+}
@@ -4,10 +4,22 @@
     clippy::cast_possible_wrap
 )]
 
-use std::mem::size_of;
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::panic::RefUnwindSafe;
+use once_cell::race::OnceBox;
+use unicode_normalization::UnicodeNormalization;
+use xxhash_rust::xxh3::xxh3_64;
 use zerocopy::FromBytes;
 
-use crate::format::{BBFAssetEntry, BBFFooter, BBFHeader, BBFMetadata, BBFPageEntry, BBFSection};
+use crate::format::{
+    AssetFlags, BBFAssetEntry, BBFExpansionHeader, BBFFooter, BBFHeader, BBFMetadata, BBFPageEntry,
+    BBFPageName, BBFSection, BBFThumbnailEntry,
+};
 
 #[derive(Debug, thiserror::Error)]
 pub enum BBFError {
@@ -19,39 +31,266 @@ pub enum BBFError {
     TableError,
     #[error("Index out of bounds")]
     OutOfBounds,
+    #[error("Asset is compressed and zstd support is not enabled")]
+    UnsupportedCompression,
+    #[error("Asset data failed to decompress")]
+    Decompression,
+    #[error("Asset is encrypted")]
+    Encrypted,
+    #[error("Asset is stored outside the book and could not be resolved")]
+    External,
+    #[error("Asset lies outside the loaded part of the file")]
+    NotLoaded,
+    #[error("Book exceeds the reader's limits: {0}")]
+    LimitsExceeded(&'static str),
 }
 
+/// Caps on what a footer may declare, checked before any table is read.
+/// Tables already have to fit in the file; these keep a hostile or damaged
+/// book from making a reader fetch or walk far more than any real book
+/// needs. `Limits::default()` is what `BBFReader::new` applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Most entries in any one table: assets, pages, sections or metadata.
+    pub max_table_entries: u32,
+    /// Largest string pool, in bytes.
+    pub max_string_pool: u64,
+    /// Largest index (string pool, tables, extensions and footer), in
+    /// bytes. This is what `RemoteReader::open` downloads.
+    pub max_index: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_table_entries: 1 << 24,
+            max_string_pool: 64 << 20,
+            max_index: 256 << 20,
+        }
+    }
+}
+
+impl Limits {
+    /// No caps beyond the file's own length.
+    pub const NONE: Self = Self {
+        max_table_entries: u32::MAX,
+        max_string_pool: u64::MAX,
+        max_index: u64::MAX,
+    };
+
+    /// Checks what `footer` declares for a book of `file_len` bytes.
+    pub fn check(&self, footer: &BBFFooter, file_len: u64) -> Result<(), BBFError> {
+        let counts = [
+            footer.asset_count.get(),
+            footer.page_count.get(),
+            footer.section_count.get(),
+            footer.key_count.get(),
+        ];
+        if counts.iter().any(|&c| c > self.max_table_entries) {
+            return Err(BBFError::LimitsExceeded("too many table entries"));
+        }
+        let pool = footer
+            .asset_table_offset
+            .get()
+            .saturating_sub(footer.string_pool_offset.get());
+        if pool > self.max_string_pool {
+            return Err(BBFError::LimitsExceeded("string pool too large"));
+        }
+        if file_len.saturating_sub(footer.string_pool_offset.get()) > self.max_index {
+            return Err(BBFError::LimitsExceeded("index too large"));
+        }
+        Ok(())
+    }
+}
+
+/// Fetches the payload of an asset flagged `AssetFlags::EXTERNAL`, given
+/// the name it was recorded under.
+pub trait AssetResolver: Send + Sync + RefUnwindSafe {
+    /// The payload's bytes as stored, or `None` if it can't be found.
+    fn resolve(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+/// Something pages can be read from, so code serving them is written once
+/// for `BBFReader` and `remote::RemoteReader`.
+pub trait PageSource {
+    type Error: From<BBFError>;
+
+    fn page_count(&self) -> usize;
+
+    /// The image of page `page_index` as the original file.
+    fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, Self::Error>;
+}
+
+/// `PageSource` for readers that fetch pages asynchronously, such as
+/// `cloud::ObjectStoreReader`. Every `PageSource` is one as well.
+pub trait AsyncPageSource {
+    type Error: From<BBFError>;
+
+    fn page_count(&self) -> usize;
+
+    /// The image of page `page_index` as the original file.
+    fn get_page(
+        &self,
+        page_index: u32,
+    ) -> impl Future<Output = Result<Cow<'_, [u8]>, Self::Error>> + Send;
+}
+
+impl<S: PageSource + Sync> AsyncPageSource for S
+where
+    S::Error: Send,
+{
+    type Error = S::Error;
+
+    fn page_count(&self) -> usize {
+        PageSource::page_count(self)
+    }
+
+    fn get_page(
+        &self,
+        page_index: u32,
+    ) -> impl Future<Output = Result<Cow<'_, [u8]>, Self::Error>> + Send {
+        core::future::ready(PageSource::get_page(self, page_index))
+    }
+}
+
+/// Resolves external assets as files in a directory, usually the one the
+/// book is in. Names that would leave the directory aren't resolved.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct DirResolver {
+    pub dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl AssetResolver for DirResolver {
+    fn resolve(&self, name: &str) -> Option<Vec<u8>> {
+        let path = std::path::Path::new(name);
+        if !path
+            .components()
+            .all(|c| matches!(c, std::path::Component::Normal(_)))
+        {
+            return None;
+        }
+        std::fs::read(self.dir.join(path)).ok()
+    }
+}
+
+/// A parsed book over `data`.
+///
+/// Every accessor takes `&self` and nothing is mutated after opening apart
+/// from caches filled once, so a reader is `Send + Sync` whenever `T` is
+/// (resolvers must be too). Worker threads can serve pages from one book
+/// through an `Arc`; see `shared`.
 pub struct BBFReader<T: AsRef<[u8]>> {
     data: T,
+    resolver: Option<Box<dyn AssetResolver>>,
+    /// Built by the first `pages_for_asset` call.
+    asset_pages: OnceBox<AssetPages>,
+    /// File offset of `data[0]`: zero unless only the index is loaded.
+    base: u64,
+    /// Bytes of `data` up to the end of the footer; anything appended after
+    /// it is ignored.
+    len: usize,
     pub header: BBFHeader,
     pub footer: BBFFooter,
 }
 
 impl<T: AsRef<[u8]>> BBFReader<T> {
     pub fn new(data: T) -> Result<Self, BBFError> {
+        Self::with_limits(data, &Limits::default())
+    }
+
+    /// Like `new`, ready to hand out to several threads.
+    pub fn shared(data: T) -> Result<Arc<Self>, BBFError> {
+        Self::new(data).map(Arc::new)
+    }
+
+    /// Like `with_limits`, ready to hand out to several threads.
+    pub fn shared_with_limits(data: T, limits: &Limits) -> Result<Arc<Self>, BBFError> {
+        Self::with_limits(data, limits).map(Arc::new)
+    }
+
+    /// Like `new`, with `limits` instead of the defaults.
+    pub fn with_limits(data: T, limits: &Limits) -> Result<Self, BBFError> {
+        let slice = data.as_ref();
+        if slice.len() < size_of::<BBFHeader>() + size_of::<BBFFooter>() {
+            return Err(BBFError::FileTooShort);
+        }
+        let mut header = [0; size_of::<BBFHeader>()];
+        header.copy_from_slice(&slice[..size_of::<BBFHeader>()]);
+        Self::with_base(&header, data, 0, limits)
+    }
+
+    /// A reader over just the index of a book too large to hold in memory.
+    /// `header` is the start of the file and `index` its last bytes, from
+    /// the string pool (`footer.string_pool_offset`) to the end; `file_len`
+    /// is the size of the whole file.
+    ///
+    /// Tables, strings and extensions read as usual. Assets aren't loaded:
+    /// `get_asset` returns `BBFError::NotLoaded`, so fetch the byte range in
+    /// `assets()[i]` yourself and pass it to `decode_asset`.
+    pub fn from_index(header: &[u8], index: T, file_len: u64) -> Result<Self, BBFError> {
+        Self::from_index_with_limits(header, index, file_len, &Limits::default())
+    }
+
+    /// Like `from_index`, with `limits` instead of the defaults.
+    pub fn from_index_with_limits(
+        header: &[u8],
+        index: T,
+        file_len: u64,
+        limits: &Limits,
+    ) -> Result<Self, BBFError> {
+        let base = file_len
+            .checked_sub(index.as_ref().len() as u64)
+            .ok_or(BBFError::FileTooShort)?;
+        Self::with_base(header, index, base, limits)
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "open",
+            level = "debug",
+            skip_all,
+            fields(base, len = data.as_ref().len()),
+            err(level = "debug"),
+        )
+    )]
+    fn with_base(header: &[u8], data: T, base: u64, limits: &Limits) -> Result<Self, BBFError> {
         let slice = data.as_ref();
-        let total_len = slice.len() as u64;
+        let total_len = base + slice.len() as u64;
 
-        if total_len < (size_of::<BBFHeader>() + size_of::<BBFFooter>()) as u64 {
+        if total_len < (size_of::<BBFHeader>() + size_of::<BBFFooter>()) as u64
+            || slice.len() < size_of::<BBFFooter>()
+        {
             return Err(BBFError::FileTooShort);
         }
 
-        let header_slice = &slice[..size_of::<BBFHeader>()];
-        let header =
-            BBFHeader::read_from_bytes(header_slice).map_err(|_| BBFError::FileTooShort)?;
+        let header = BBFHeader::read_from_bytes(
+            header
+                .get(..size_of::<BBFHeader>())
+                .ok_or(BBFError::FileTooShort)?,
+        )
+        .map_err(|_| BBFError::FileTooShort)?;
 
         if &header.magic != b"BBF1" {
             return Err(BBFError::InvalidMagic);
         }
 
-        let footer_offset = (total_len as usize) - size_of::<BBFFooter>();
-        let footer_slice = &slice[footer_offset..];
-        let footer =
-            BBFFooter::read_from_bytes(footer_slice).map_err(|_| BBFError::FileTooShort)?;
+        let (footer, len) = find_footer(slice, base)?;
+        if len < slice.len() {
+            event!(
+                info,
+                trailing = slice.len() - len,
+                "Ignoring data after the footer"
+            );
+        }
+        let total_len = base + len as u64;
 
-        if &footer.magic != b"BBF1" {
-            return Err(BBFError::InvalidMagic);
+        if footer.string_pool_offset.get() < base {
+            return Err(BBFError::NotLoaded);
         }
+        limits.check(&footer, total_len)?;
 
         let check_range = |offset: u64, count: u32, elem_size: usize| -> Result<(), BBFError> {
             let start = offset;
@@ -63,6 +302,9 @@ impl<T: AsRef<[u8]>> BBFReader<T> {
             if end > total_len {
                 return Err(BBFError::FileTooShort);
             }
+            if start < base {
+                return Err(BBFError::NotLoaded);
+            }
             Ok(())
         };
 
@@ -93,21 +335,49 @@ impl<T: AsRef<[u8]>> BBFReader<T> {
             size_of::<BBFMetadata>(),
         )?;
 
+        event!(
+            debug,
+            assets = footer.asset_count.get(),
+            pages = footer.page_count.get(),
+            sections = footer.section_count.get(),
+            "Opened book"
+        );
         Ok(Self {
             data,
+            resolver: None,
+            asset_pages: OnceBox::new(),
+            base,
+            len,
             header,
             footer,
         })
     }
 
     fn get_table_slice<U: FromBytes + zerocopy::Immutable>(&self, offset: u64, count: u32) -> &[U] {
-        let start = offset as usize;
-        let elem_size = size_of::<U>();
-        let len = (count as usize) * elem_size;
+        (count as usize)
+            .checked_mul(size_of::<U>())
+            .and_then(|len| self.bytes(offset, offset.checked_add(len as u64)?))
+            .and_then(|b| <[U]>::ref_from_bytes(b).ok())
+            .unwrap_or(&[])
+    }
 
-        let byte_slice = &self.data.as_ref()[start..start + len];
+    /// The file's bytes from `start` to `end`, or `None` if they aren't all
+    /// loaded.
+    pub(crate) fn bytes(&self, start: u64, end: u64) -> Option<&[u8]> {
+        let start = usize::try_from(start.checked_sub(self.base)?).ok()?;
+        let end = usize::try_from(end.checked_sub(self.base)?).ok()?;
+        self.data.as_ref()[..self.len].get(start..end)
+    }
 
-        <[U]>::ref_from_bytes(byte_slice).unwrap_or(&[])
+    /// Size of the whole book in bytes, footer included. Data appended after
+    /// the footer doesn't count; see `trailing_len`.
+    pub fn file_len(&self) -> usize {
+        self.base as usize + self.len
+    }
+
+    /// Bytes found after the footer, such as padding or a signature.
+    pub fn trailing_len(&self) -> usize {
+        self.data.as_ref().len() - self.len
     }
 
     pub fn assets(&self) -> &[BBFAssetEntry] {
@@ -138,11 +408,128 @@ impl<T: AsRef<[u8]>> BBFReader<T> {
         )
     }
 
-    pub fn get_string(&self, offset: u32) -> Option<&str> {
-        let pool_start = self.footer.string_pool_offset.get() as usize;
-        let pool_end = self.footer.asset_table_offset.get() as usize;
+    /// Value of the first entry for `key`. Keys are compared exactly, and
+    /// later entries for the same key are ignored.
+    pub fn metadata_value(&self, key: &str) -> Option<&str> {
+        self.metadata()
+            .iter()
+            .find(|m| self.get_string(m.key_offset.get()) == Some(key))
+            .and_then(|m| self.get_string(m.val_offset.get()))
+    }
+
+    /// Entries of the expansion table, up to (not including) its terminator.
+    /// Files without extensions return an empty slice.
+    pub fn extensions(&self) -> &[BBFExpansionHeader] {
+        let start = self.footer.extra_offset.get() as usize;
+        let end = self.file_len() - size_of::<BBFFooter>();
+        if start == 0 || start > end {
+            return &[];
+        }
+
+        let count = (end - start) / size_of::<BBFExpansionHeader>();
+        let table: &[BBFExpansionHeader] = self.get_table_slice(start as u64, count as u32);
+        let len = table
+            .iter()
+            .position(|e| e.extension_type.get() == BBFExpansionHeader::END)
+            .unwrap_or(table.len());
+        &table[..len]
+    }
+
+    /// Raw payload of the first extension of `extension_type`, if present and in bounds.
+    pub fn extension_data(&self, extension_type: u32) -> Option<&[u8]> {
+        let ext = self
+            .extensions()
+            .iter()
+            .find(|e| e.extension_type.get() == extension_type)?;
+
+        let start = ext.offset.get();
+        let end = start.checked_add(ext.length.get())?;
+        if end > (self.file_len() - size_of::<BBFFooter>()) as u64 {
+            return None;
+        }
+        self.bytes(start, end)
+    }
 
-        let pool_slice = &self.data.as_ref()[pool_start..pool_end];
+    pub fn thumbnails(&self) -> &[BBFThumbnailEntry] {
+        let Some(bytes) = self.extension_data(BBFExpansionHeader::THUMBNAILS) else {
+            return &[];
+        };
+        let whole = bytes.len() - bytes.len() % size_of::<BBFThumbnailEntry>();
+        <[BBFThumbnailEntry]>::ref_from_bytes(&bytes[..whole]).unwrap_or(&[])
+    }
+
+    /// Asset index of the preview for `page_index`, if the book has one.
+    pub fn thumbnail(&self, page_index: u32) -> Option<u32> {
+        self.thumbnails()
+            .iter()
+            .find(|t| t.page_index.get() == page_index)
+            .map(|t| t.asset_index.get())
+    }
+
+    /// Index of the first section titled `title`, matching any Unicode
+    /// normalization of it; see `same_title`.
+    pub fn find_section(&self, title: &str) -> Option<u32> {
+        self.find_section_by(title, false)
+    }
+
+    /// Like `find_section`, ignoring case as well.
+    pub fn find_section_ignore_case(&self, title: &str) -> Option<u32> {
+        self.find_section_by(title, true)
+    }
+
+    fn find_section_by(&self, title: &str, ignore_case: bool) -> Option<u32> {
+        self.sections()
+            .iter()
+            .position(|s| {
+                self.get_string(s.section_title_offset.get())
+                    .is_some_and(|t| same_title(t, title, ignore_case))
+            })
+            .map(|i| i as u32)
+    }
+
+    /// Every page showing `asset_index`, in page order; empty for orphaned
+    /// or out-of-range assets. The reverse mapping is built on first use.
+    pub fn pages_for_asset(&self, asset_index: u32) -> &[u32] {
+        let map = self
+            .asset_pages
+            .get_or_init(|| Box::new(AssetPages::new(self.assets().len(), self.pages())));
+        let i = asset_index as usize;
+        match (map.starts.get(i), map.starts.get(i + 1)) {
+            (Some(&start), Some(&end)) => &map.pages[start as usize..end as usize],
+            _ => &[],
+        }
+    }
+
+    pub fn page_names(&self) -> &[BBFPageName] {
+        let Some(bytes) = self.extension_data(BBFExpansionHeader::PAGE_NAMES) else {
+            return &[];
+        };
+        let whole = bytes.len() - bytes.len() % size_of::<BBFPageName>();
+        <[BBFPageName]>::ref_from_bytes(&bytes[..whole]).unwrap_or(&[])
+    }
+
+    /// Original file name recorded for `page_index`, if the book has one.
+    pub fn page_name(&self, page_index: u32) -> Option<&str> {
+        self.page_names()
+            .iter()
+            .find(|n| n.page_index.get() == page_index)
+            .and_then(|n| self.get_string(n.name_offset.get()))
+    }
+
+    /// The `hash` algorithm id the asset hashes were computed with.
+    pub fn hash_algorithm(&self) -> u32 {
+        self.extension_data(BBFExpansionHeader::ASSET_HASH)
+            .and_then(|b| b.get(..4))
+            .map_or(crate::hash::XXH3, |b| {
+                u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+            })
+    }
+
+    pub fn get_string(&self, offset: u32) -> Option<&str> {
+        let pool_slice = self.bytes(
+            self.footer.string_pool_offset.get(),
+            self.footer.asset_table_offset.get(),
+        )?;
 
         let offset = offset as usize;
         if offset >= pool_slice.len() {
@@ -155,9 +542,26 @@ impl<T: AsRef<[u8]>> BBFReader<T> {
             .position(|&c| c == 0)
             .unwrap_or(slice_from_offset.len());
 
-        std::str::from_utf8(&slice_from_offset[..end]).ok()
+        core::str::from_utf8(&slice_from_offset[..end]).ok()
+    }
+
+    /// Lets `get_asset_decoded` read external assets through `resolver`.
+    pub fn set_resolver(&mut self, resolver: Box<dyn AssetResolver>) {
+        self.resolver = Some(resolver);
+    }
+
+    /// Name an external asset was recorded under, or `None` for assets
+    /// stored in the book.
+    pub fn external_name(&self, asset_index: u32) -> Option<&str> {
+        let asset = self.assets().get(asset_index as usize)?;
+        if !asset.asset_flags().contains(AssetFlags::EXTERNAL) {
+            return None;
+        }
+        self.get_string(u32::try_from(asset.offset.get()).ok()?)
     }
 
+    /// The asset's bytes as stored in the book. External assets aren't, so
+    /// they return `BBFError::External`; see `get_asset_decoded`.
     pub fn get_asset(&self, asset_index: u32) -> Result<&[u8], BBFError> {
         let assets = self.assets();
         if asset_index as usize >= assets.len() {
@@ -165,15 +569,211 @@ impl<T: AsRef<[u8]>> BBFReader<T> {
         }
 
         let asset = &assets[asset_index as usize];
-        let offset = asset.offset.get() as usize;
-        let length = asset.length.get() as usize;
-
-        let total_slice = self.data.as_ref();
+        if asset.asset_flags().contains(AssetFlags::EXTERNAL) {
+            return Err(BBFError::External);
+        }
+        let offset = asset.offset.get();
+        let end = offset
+            .checked_add(asset.length.get())
+            .ok_or(BBFError::OutOfBounds)?;
 
-        if offset.checked_add(length).ok_or(BBFError::OutOfBounds)? > total_slice.len() {
+        if end > self.file_len() as u64 {
             return Err(BBFError::FileTooShort);
         }
 
-        Ok(&total_slice[offset..offset + length])
+        self.bytes(offset, end).ok_or(BBFError::NotLoaded)
+    }
+
+    /// The asset as the original file: like `get_asset`, but compressed
+    /// assets are decompressed into an owned buffer, and external ones are
+    /// read through the resolver from `set_resolver`.
+    pub fn get_asset_decoded(&self, asset_index: u32) -> Result<Cow<'_, [u8]>, BBFError> {
+        match self.get_asset(asset_index) {
+            Ok(data) => decode_asset(&self.assets()[asset_index as usize], data),
+            Err(BBFError::External) => self
+                .resolve_external(asset_index)
+                .ok_or(BBFError::External)
+                .and_then(|(asset, data)| {
+                    decode_asset(asset, &data).map(|d| Cow::Owned(d.into_owned()))
+                }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The image of page `page_index` as the original file. Borrowed from
+    /// the book unless it had to be decompressed or read from outside it.
+    /// Also available through `PageSource`.
+    pub fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, BBFError> {
+        let page = self
+            .pages()
+            .get(page_index as usize)
+            .ok_or(BBFError::OutOfBounds)?;
+        self.get_asset_decoded(page.asset_index.get())
+    }
+
+    /// An external asset's entry and payload, if the resolver finds it
+    /// with the recorded length.
+    pub fn resolve_external(&self, asset_index: u32) -> Option<(&BBFAssetEntry, Vec<u8>)> {
+        let name = self.external_name(asset_index)?;
+        let data = self.resolver.as_ref()?.resolve(name)?;
+        let asset = &self.assets()[asset_index as usize];
+        (data.len() as u64 == asset.length.get()).then_some((asset, data))
+    }
+}
+
+impl<T: AsRef<[u8]>> PageSource for BBFReader<T> {
+    type Error = BBFError;
+
+    fn page_count(&self) -> usize {
+        self.pages().len()
+    }
+
+    fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, BBFError> {
+        Self::get_page(self, page_index)
+    }
+}
+
+/// Pages grouped by asset: those of asset `i` are
+/// `pages[starts[i]..starts[i + 1]]`.
+struct AssetPages {
+    starts: Vec<u32>,
+    pages: Vec<u32>,
+}
+
+impl AssetPages {
+    fn new(asset_count: usize, pages: &[BBFPageEntry]) -> Self {
+        let valid = |p: &BBFPageEntry| {
+            let asset = p.asset_index.get() as usize;
+            (asset < asset_count).then_some(asset)
+        };
+
+        let mut starts = vec![0u32; asset_count + 1];
+        for asset in pages.iter().filter_map(valid) {
+            starts[asset + 1] += 1;
+        }
+        for i in 1..starts.len() {
+            starts[i] += starts[i - 1];
+        }
+
+        let mut next = starts.clone();
+        let mut grouped = vec![0; starts[asset_count] as usize];
+        for (page, p) in pages.iter().enumerate() {
+            if let Some(asset) = valid(p) {
+                grouped[next[asset] as usize] = page as u32;
+                next[asset] += 1;
+            }
+        }
+        Self {
+            starts,
+            pages: grouped,
+        }
+    }
+}
+
+// Sharing a reader between threads is part of its contract.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BBFReader<&[u8]>>();
+    assert_send_sync::<BBFReader<Vec<u8>>>();
+};
+
+/// Whether two titles are the same text: canonically equivalent, so NFC
+/// and NFD spellings match, and with `ignore_case` also equal after simple
+/// lowercasing.
+#[must_use]
+pub fn same_title(a: &str, b: &str, ignore_case: bool) -> bool {
+    if a == b {
+        return true;
+    }
+    if ignore_case {
+        a.nfd()
+            .flat_map(char::to_lowercase)
+            .eq(b.nfd().flat_map(char::to_lowercase))
+    } else {
+        a.nfd().eq(b.nfd())
+    }
+}
+
+/// How far back from the end of the data `find_footer` looks for a footer
+/// when something was appended after it.
+pub const MAX_TRAILING: usize = 1 << 20;
+
+/// The footer and where it ends in `slice`, whose first byte is at file
+/// offset `base`. Normally that's the end of the data; failing that, the
+/// last `MAX_TRAILING` bytes are searched for a footer whose index hash
+/// matches the bytes before it.
+pub(crate) fn find_footer(slice: &[u8], base: u64) -> Result<(BBFFooter, usize), BBFError> {
+    if let Some(footer) = footer_ending_at(slice, slice.len()) {
+        return Ok((footer, slice.len()));
+    }
+
+    for (footer, end) in trailing_footers(slice) {
+        // The magic alone could be anywhere in an appended blob.
+        let index = footer
+            .string_pool_offset
+            .get()
+            .checked_sub(base)
+            .and_then(|start| usize::try_from(start).ok())
+            .and_then(|start| slice.get(start..end - size_of::<BBFFooter>()));
+        if index.is_some_and(|index| xxh3_64(index) == footer.index_hash.get()) {
+            return Ok((footer, end));
+        }
+    }
+    Err(BBFError::InvalidMagic)
+}
+
+/// Whatever could be a footer ending `MAX_TRAILING` bytes or less before
+/// the end of `slice`, but not at it, latest first, with where each ends.
+/// Only the magic is checked.
+pub(crate) fn trailing_footers(slice: &[u8]) -> impl Iterator<Item = (BBFFooter, usize)> + '_ {
+    let lowest = slice.len().saturating_sub(MAX_TRAILING);
+    (lowest.max(size_of::<BBFFooter>())..slice.len())
+        .rev()
+        .filter(|&end| &slice[end - 4..end] == b"BBF1")
+        .filter_map(|end| Some((footer_ending_at(slice, end)?, end)))
+}
+
+fn footer_ending_at(slice: &[u8], end: usize) -> Option<BBFFooter> {
+    let start = end.checked_sub(size_of::<BBFFooter>())?;
+    BBFFooter::read_from_bytes(slice.get(start..end)?)
+        .ok()
+        .filter(|f| &f.magic == b"BBF1")
+}
+
+/// The original file from `data`, the stored bytes of `asset`: borrowed as
+/// is, or decompressed into an owned buffer if the asset is compressed.
+/// Encrypted assets can't be decoded here.
+pub fn decode_asset<'a>(asset: &BBFAssetEntry, data: &'a [u8]) -> Result<Cow<'a, [u8]>, BBFError> {
+    let flags = asset.asset_flags();
+    if flags.contains(AssetFlags::ENCRYPTED) {
+        return Err(BBFError::Encrypted);
+    }
+    if !flags.contains(AssetFlags::COMPRESSED) {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    #[cfg(feature = "zstd")]
+    {
+        use std::io::Read;
+
+        // Stop one byte past the recorded size, so a small frame claiming to
+        // expand without end can't take all memory before the check below.
+        let expected = asset.decoded_length.get();
+        let mut decoded = Vec::new();
+        zstd::stream::read::Decoder::new(data)
+            .and_then(|d| d.take(expected.saturating_add(1)).read_to_end(&mut decoded))
+            .map_err(|_| BBFError::Decompression)?;
+        if decoded.len() as u64 != expected {
+            event!(
+                debug,
+                expected,
+                actual = decoded.len(),
+                "Decompressed asset has the wrong size"
+            );
+            return Err(BBFError::Decompression);
+        }
+        Ok(Cow::Owned(decoded))
     }
+    #[cfg(not(feature = "zstd"))]
+    Err(BBFError::UnsupportedCompression)
 }
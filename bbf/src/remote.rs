@@ -0,0 +1,220 @@
+//! Books read through byte ranges of a source that isn't in memory: only the
+//! header, footer and index are fetched on open, and each asset when asked
+//! for. Backends implement `RandomAccess`; async ones can build on the range
+//! helpers instead.
+
+#![allow(clippy::missing_errors_doc)]
+
+use std::borrow::Cow;
+use std::io;
+use std::mem::size_of;
+use std::ops::Range;
+
+use zerocopy::FromBytes;
+
+use crate::format::{AssetFlags, BBFFooter, BBFHeader};
+use crate::reader::{
+    BBFError, BBFReader, Limits, MAX_TRAILING, PageSource, decode_asset, trailing_footers,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Bbf(#[from] BBFError),
+}
+
+/// A source of byte ranges, such as a file on a server.
+pub trait RandomAccess {
+    /// Size of the whole file.
+    fn len(&self) -> io::Result<u64>;
+
+    /// Bytes `start..end` of the file.
+    fn read(&self, start: u64, end: u64) -> io::Result<Vec<u8>>;
+
+    fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+/// Where the header is: the first bytes of the file.
+#[must_use]
+pub const fn header_range() -> Range<u64> {
+    0..size_of::<BBFHeader>() as u64
+}
+
+/// Where the footer of a `len`-byte file is, unless something was appended
+/// after it; `index_range` then fails with `BBFError::InvalidMagic`, and
+/// `trailing_range` says where to look instead.
+pub fn footer_range(len: u64) -> Result<Range<u64>, BBFError> {
+    let start = len
+        .checked_sub(size_of::<BBFFooter>() as u64)
+        .filter(|&s| s >= size_of::<BBFHeader>() as u64)
+        .ok_or(BBFError::FileTooShort)?;
+    Ok(start..len)
+}
+
+/// Where the index is, given the footer read from `footer_range(len)`: the
+/// bytes `BBFReader::from_index` takes. Footers declaring more than `limits`
+/// allow are refused before anything that large is fetched.
+pub fn index_range(len: u64, footer: &[u8], limits: &Limits) -> Result<Range<u64>, BBFError> {
+    let footer_start = footer_range(len)?.start;
+    let footer = BBFFooter::read_from_bytes(footer).map_err(|_| BBFError::FileTooShort)?;
+    if &footer.magic != b"BBF1" {
+        return Err(BBFError::InvalidMagic);
+    }
+    limits.check(&footer, len)?;
+    Ok(footer.string_pool_offset.get().min(footer_start)..len)
+}
+
+/// Where to look for the footer of a `len`-byte file when `footer_range`
+/// doesn't hold one: the last `MAX_TRAILING` bytes, as `BBFReader::new`
+/// searches them, plus room for the footer itself.
+pub fn trailing_range(len: u64) -> Result<Range<u64>, BBFError> {
+    footer_range(len)?;
+    let start = len
+        .saturating_sub((MAX_TRAILING + size_of::<BBFFooter>()) as u64)
+        .max(size_of::<BBFHeader>() as u64);
+    Ok(start..len)
+}
+
+/// Like `index_range`, given `tail`, the bytes at `trailing_range(len)`.
+/// The range reaches the index of every footer `tail` could hold within
+/// `limits`, so `BBFReader::from_index` can pick the one whose index hash
+/// matches.
+pub fn trailing_index_range(
+    len: u64,
+    tail: &[u8],
+    limits: &Limits,
+) -> Result<Range<u64>, BBFError> {
+    let tail_start = len
+        .checked_sub(tail.len() as u64)
+        .ok_or(BBFError::FileTooShort)?;
+    let start = trailing_footers(tail)
+        .filter(|(footer, end)| limits.check(footer, tail_start + *end as u64).is_ok())
+        .map(|(footer, _)| footer.string_pool_offset.get())
+        .min()
+        .ok_or(BBFError::InvalidMagic)?;
+    Ok(start.min(tail_start)..len)
+}
+
+/// Where the stored bytes of asset `index` are. External assets have none,
+/// so they return `BBFError::External`.
+pub fn asset_range<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    index: u32,
+) -> Result<Range<u64>, BBFError> {
+    let entry = reader
+        .assets()
+        .get(index as usize)
+        .ok_or(BBFError::OutOfBounds)?;
+    if entry.asset_flags().contains(AssetFlags::EXTERNAL) {
+        return Err(BBFError::External);
+    }
+    let start = entry.offset.get();
+    let end = start
+        .checked_add(entry.length.get())
+        .ok_or(BBFError::OutOfBounds)?;
+    // Assets live before the index; anything claiming otherwise is corrupt.
+    if end > reader.footer.string_pool_offset.get() {
+        return Err(BBFError::OutOfBounds);
+    }
+    Ok(start..end)
+}
+
+/// A book opened through a `RandomAccess` source. `reader()` gives the
+/// tables, strings and extensions; assets are fetched on every call to
+/// `get_asset`, so cache them if pages are revisited.
+pub struct RemoteReader<R> {
+    source: R,
+    reader: BBFReader<Vec<u8>>,
+}
+
+impl<R: RandomAccess> RemoteReader<R> {
+    /// Reads the header, footer and index, leaving the assets behind.
+    pub fn open(source: R) -> Result<Self, RemoteError> {
+        Self::open_with_limits(source, &Limits::default())
+    }
+
+    /// Like `open`, with `limits` instead of the defaults.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err(level = "debug"))
+    )]
+    pub fn open_with_limits(source: R, limits: &Limits) -> Result<Self, RemoteError> {
+        let len = source.len()?;
+        let footer = footer_range(len)?;
+        let header = source.read(header_range().start, header_range().end)?;
+        let footer = source.read(footer.start, footer.end)?;
+        let index = match index_range(len, &footer, limits) {
+            Err(BBFError::InvalidMagic) => {
+                let tail = trailing_range(len)?;
+                let tail = source.read(tail.start, tail.end)?;
+                trailing_index_range(len, &tail, limits)?
+            }
+            index => index?,
+        };
+        let index = source.read(index.start, index.end)?;
+
+        let reader = BBFReader::from_index_with_limits(&header, index, len, limits)?;
+        Ok(Self { source, reader })
+    }
+
+    /// The book's index. Its `get_asset` returns `BBFError::NotLoaded`.
+    pub const fn reader(&self) -> &BBFReader<Vec<u8>> {
+        &self.reader
+    }
+
+    pub const fn source(&self) -> &R {
+        &self.source
+    }
+
+    /// Stored bytes of asset `index`, as `BBFReader::get_asset` would
+    /// return them, including `BBFError::External` for external assets.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self), err(level = "debug"))
+    )]
+    pub fn get_asset(&self, index: u32) -> Result<Vec<u8>, RemoteError> {
+        let range = asset_range(&self.reader, index)?;
+        let data = self.source.read(range.start, range.end)?;
+        if data.len() as u64 != range.end - range.start {
+            return Err(BBFError::FileTooShort.into());
+        }
+        Ok(data)
+    }
+
+    /// Asset `index` as the original file, decompressed if needed.
+    pub fn get_asset_decoded(&self, index: u32) -> Result<Vec<u8>, RemoteError> {
+        let data = self.get_asset(index)?;
+        let entry = &self.reader.assets()[index as usize];
+        if let Cow::Owned(decoded) = decode_asset(entry, &data)? {
+            return Ok(decoded);
+        }
+        Ok(data)
+    }
+
+    /// The image of page `page_index`, decompressed if needed. Always owned,
+    /// but typed like `BBFReader::get_page`; both are a `PageSource`.
+    pub fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, RemoteError> {
+        let page = self
+            .reader
+            .pages()
+            .get(page_index as usize)
+            .ok_or(BBFError::OutOfBounds)?;
+        Ok(Cow::Owned(self.get_asset_decoded(page.asset_index.get())?))
+    }
+}
+
+impl<R: RandomAccess> PageSource for RemoteReader<R> {
+    type Error = RemoteError;
+
+    fn page_count(&self) -> usize {
+        self.reader.pages().len()
+    }
+
+    fn get_page(&self, page_index: u32) -> Result<Cow<'_, [u8]>, RemoteError> {
+        Self::get_page(self, page_index)
+    }
+}
@@ -0,0 +1,270 @@
+#![allow(clippy::cast_possible_truncation)]
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+use crate::format::{BBFAssetEntry, BBFHeader, BBFMediaType};
+use crate::reader::BBFReader;
+
+/// Space accounting for a book, as reported by `bbfmux stats`.
+#[derive(Debug, Clone)]
+pub struct BookStats {
+    pub file_size: u64,
+    pub page_count: u32,
+    pub asset_count: u32,
+    /// Bytes of stored asset data, each asset counted once. External assets
+    /// take none.
+    pub asset_bytes: u64,
+    /// Bytes the pages would take if every page stored its own copy.
+    pub page_bytes: u64,
+    /// `page_bytes` minus the bytes of the distinct assets pages use.
+    pub dedupe_savings: u64,
+    /// Bytes not covered by the header, assets, or index: alignment padding
+    /// plus anything orphaned by in-place edits.
+    pub padding_bytes: u64,
+    /// Everything from the string pool to the end of the footer.
+    pub index_bytes: u64,
+    pub string_pool_bytes: u64,
+    /// One entry per media type present, in `BBFMediaType` order.
+    pub media_types: Vec<MediaTypeStats>,
+    /// Largest pages first, as many as were asked for.
+    pub largest_pages: Vec<PageSize>,
+    pub sections: Vec<SectionStats>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MediaTypeStats {
+    pub media_type: BBFMediaType,
+    pub assets: u32,
+    pub pages: u32,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PageSize {
+    pub page_index: u32,
+    pub asset_index: u32,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SectionStats {
+    pub section_index: u32,
+    pub start_index: u32,
+    /// Pages from the section's start up to the next section that starts
+    /// later and isn't nested inside it, so subsections count towards it.
+    pub page_count: u32,
+}
+
+impl BookStats {
+    /// Walks the tables of `reader`. `largest` caps `largest_pages`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub fn compute<T: AsRef<[u8]>>(reader: &BBFReader<T>, largest: usize) -> Self {
+        let assets = reader.assets();
+        let pages = reader.pages();
+        let file_size = reader.file_len() as u64;
+        let index_start = reader.footer.string_pool_offset.get();
+
+        let asset_len = |i: u32| assets.get(i as usize).map_or(0, BBFAssetEntry::stored_len);
+
+        let mut by_type: BTreeMap<u8, MediaTypeStats> = BTreeMap::new();
+        for a in assets {
+            let entry = by_type.entry(a.type_).or_insert(MediaTypeStats {
+                media_type: BBFMediaType::from(a.type_),
+                assets: 0,
+                pages: 0,
+                bytes: 0,
+            });
+            entry.assets += 1;
+            entry.bytes = entry.bytes.saturating_add(a.stored_len());
+        }
+
+        let mut used = vec![false; assets.len()];
+        let mut page_bytes: u64 = 0;
+        let mut page_sizes = Vec::with_capacity(pages.len());
+        for (i, p) in pages.iter().enumerate() {
+            let asset_index = p.asset_index.get();
+            let bytes = asset_len(asset_index);
+            page_bytes = page_bytes.saturating_add(bytes);
+            page_sizes.push(PageSize {
+                page_index: i as u32,
+                asset_index,
+                bytes,
+            });
+            if let Some(a) = assets.get(asset_index as usize) {
+                used[asset_index as usize] = true;
+                if let Some(entry) = by_type.get_mut(&a.type_) {
+                    entry.pages += 1;
+                }
+            }
+        }
+        let used_bytes: u64 = assets
+            .iter()
+            .zip(&used)
+            .filter(|(_, u)| **u)
+            .map(|(a, _)| a.stored_len())
+            .fold(0, u64::saturating_add);
+
+        page_sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.page_index.cmp(&b.page_index)));
+        page_sizes.truncate(largest);
+
+        let asset_bytes = assets
+            .iter()
+            .map(BBFAssetEntry::stored_len)
+            .fold(0, u64::saturating_add);
+        let index_bytes = file_size.saturating_sub(index_start);
+
+        Self {
+            file_size,
+            page_count: pages.len() as u32,
+            asset_count: assets.len() as u32,
+            asset_bytes,
+            page_bytes,
+            dedupe_savings: page_bytes.saturating_sub(used_bytes),
+            padding_bytes: file_size
+                .saturating_sub(size_of::<BBFHeader>() as u64)
+                .saturating_sub(asset_bytes)
+                .saturating_sub(index_bytes),
+            index_bytes,
+            string_pool_bytes: reader
+                .footer
+                .asset_table_offset
+                .get()
+                .saturating_sub(index_start),
+            media_types: by_type.into_values().collect(),
+            largest_pages: page_sizes,
+            sections: section_stats(reader),
+        }
+    }
+}
+
+/// Where each section starts and how many pages it spans, in table order.
+#[must_use]
+pub fn section_stats<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<SectionStats> {
+    let sections = reader.sections();
+    let count = sections.len();
+    let page_count = reader.pages().len() as u32;
+    let start = |i: usize| sections[i].section_start_index.get();
+
+    let mut children = vec![Vec::new(); count];
+    let mut roots = Vec::new();
+    for (i, s) in sections.iter().enumerate() {
+        let parent = s.parent_section_index.get() as usize;
+        if parent < count && parent != i {
+            children[parent].push(i);
+        } else {
+            roots.push(i);
+        }
+    }
+
+    // Lay sections out depth first, so everything nested in section `i`
+    // sits at `first[i] + 1..end[i]`.
+    let mut first = vec![usize::MAX; count];
+    let mut end = vec![0; count];
+    let mut laid_out = 0;
+    for root in roots {
+        lay_out(root, &children, &mut first, &mut end, &mut laid_out);
+    }
+    // What's left hangs off parent cycles. Every section on a cycle is
+    // nested in all the others, so each gets the block laid out from the
+    // first one found.
+    let parent = |i: usize| sections[i].parent_section_index.get() as usize;
+    let mut walked = vec![false; count];
+    for i in 0..count {
+        if first[i] != usize::MAX {
+            continue;
+        }
+        let mut on_cycle = i;
+        while !walked[on_cycle] {
+            walked[on_cycle] = true;
+            on_cycle = parent(on_cycle);
+        }
+        lay_out(on_cycle, &children, &mut first, &mut end, &mut laid_out);
+        let mut s = parent(on_cycle);
+        while s != on_cycle {
+            (first[s], end[s]) = (first[on_cycle], end[on_cycle]);
+            s = parent(s);
+        }
+    }
+
+    // A section ends where the earliest later section outside it starts.
+    // Going from the latest start down, every section starting later than
+    // the current one has been recorded at its position, so that's the
+    // smallest start before `first[i]` or from `end[i]` on.
+    let mut by_start: Vec<usize> = (0..count).collect();
+    by_start.sort_by_key(|&i| Reverse(start(i)));
+    let mut before = MinPrefix::new(count);
+    let mut after = MinPrefix::new(count);
+    let mut ends = vec![page_count; count];
+    let mut recorded = 0;
+    for &i in &by_start {
+        while recorded < count && start(by_start[recorded]) > start(i) {
+            let j = by_start[recorded];
+            before.lower(first[j], start(j));
+            after.lower(count - 1 - first[j], start(j));
+            recorded += 1;
+        }
+        ends[i] = before
+            .prefix_min(first[i])
+            .min(after.prefix_min(count - end[i]))
+            .min(page_count);
+    }
+
+    (0..count)
+        .map(|i| SectionStats {
+            section_index: i as u32,
+            start_index: start(i),
+            page_count: ends[i].saturating_sub(start(i)),
+        })
+        .collect()
+}
+
+/// Numbers `root` and the sections below it from `*laid_out` on, parents
+/// before children, recording where each one's block starts and ends.
+fn lay_out(
+    root: usize,
+    children: &[Vec<usize>],
+    first: &mut [usize],
+    end: &mut [usize],
+    laid_out: &mut usize,
+) {
+    let mut stack = vec![(root, false)];
+    while let Some((s, done)) = stack.pop() {
+        if done {
+            end[s] = *laid_out;
+        } else if first[s] == usize::MAX {
+            first[s] = *laid_out;
+            *laid_out += 1;
+            stack.push((s, true));
+            stack.extend(children[s].iter().rev().map(|&c| (c, false)));
+        }
+    }
+}
+
+/// Smallest value among the first `len` slots, with slots that only ever
+/// go down: a Fenwick tree over `min`.
+struct MinPrefix(Vec<u32>);
+
+impl MinPrefix {
+    fn new(len: usize) -> Self {
+        Self(vec![u32::MAX; len])
+    }
+
+    fn lower(&mut self, slot: usize, value: u32) {
+        let mut i = slot + 1;
+        while i <= self.0.len() {
+            self.0[i - 1] = self.0[i - 1].min(value);
+            i += i & i.wrapping_neg();
+        }
+    }
+
+    fn prefix_min(&self, len: usize) -> u32 {
+        let (mut i, mut min) = (len, u32::MAX);
+        while i > 0 {
+            min = min.min(self.0[i - 1]);
+            i &= i - 1;
+        }
+        min
+    }
+}
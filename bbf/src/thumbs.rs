@@ -0,0 +1,180 @@
+//! Page previews for the thumbnail extension: `generate` renders them,
+//! decoding pages in parallel, and `embed` stores them in a book. `cover`
+//! renders the one preview a file manager or catalog shows for a book.
+//!
+//! Needs the `image` feature.
+
+#![allow(clippy::cast_possible_truncation, clippy::missing_errors_doc)]
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use image::DynamicImage;
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::builder::BBFBuilder;
+use crate::format::{BBFMediaType, BBFPageEntry};
+use crate::imaging::{self, ImagingError};
+use crate::reader::{BBFError, BBFReader};
+
+/// How `generate_with` renders thumbnails.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Longest side of a thumbnail in pixels. Smaller pages keep their size.
+    pub max_dim: u32,
+    /// Encoding of the thumbnails; see `imaging::encode`.
+    pub format: BBFMediaType,
+    /// Encoder quality for lossy formats, 1-100.
+    pub quality: u8,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_dim: 320,
+            format: BBFMediaType::Jpg,
+            quality: 75,
+        }
+    }
+}
+
+/// JPEG thumbnails no larger than `max_dim` on either side, as
+/// `(page index, data)` in page order.
+pub fn generate<T: AsRef<[u8]> + Sync>(
+    reader: &BBFReader<T>,
+    max_dim: u32,
+) -> Result<Vec<(u32, Vec<u8>)>, ImagingError> {
+    generate_with(
+        reader,
+        Options {
+            max_dim,
+            ..Options::default()
+        },
+    )
+}
+
+/// Like `generate`, with the format and quality of `options`.
+///
+/// Pages sharing an asset share a thumbnail, so each asset is rendered once.
+/// Pages that can't be decoded (no codec for their media type, or broken
+/// image data) are left out; failing to read an asset is an error.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(max_dim = options.max_dim, format = ?options.format),
+    )
+)]
+pub fn generate_with<T: AsRef<[u8]> + Sync>(
+    reader: &BBFReader<T>,
+    options: Options,
+) -> Result<Vec<(u32, Vec<u8>)>, ImagingError> {
+    let pages = reader.pages();
+    let used: HashSet<u32> = pages.iter().map(|p| p.asset_index.get()).collect();
+
+    let rendered: HashMap<u32, Vec<u8>> = used
+        .into_par_iter()
+        .filter_map(|asset| match render(reader, asset, options) {
+            Ok(thumb) => Some(Ok((asset, thumb))),
+            Err(ImagingError::Bbf(e)) => Some(Err(e.into())),
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            Err(e) => {
+                event!(debug, asset, error = %e, "Can't decode asset, skipping its thumbnail");
+                None
+            }
+        })
+        .collect::<Result<_, ImagingError>>()?;
+
+    Ok(pages
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| {
+            rendered
+                .get(&p.asset_index.get())
+                .map(|thumb| (i as u32, thumb.clone()))
+        })
+        .collect())
+}
+
+fn render<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    asset_index: u32,
+    options: Options,
+) -> Result<Vec<u8>, ImagingError> {
+    let entry = reader
+        .assets()
+        .get(asset_index as usize)
+        .ok_or(BBFError::OutOfBounds)?;
+    let media_type = BBFMediaType::from(entry.type_);
+    let format = imaging::image_format(media_type).ok_or(ImagingError::Unsupported(media_type))?;
+    let data = reader.get_asset_decoded(asset_index)?;
+
+    let image = image::load_from_memory_with_format(&data, format)?;
+    let max = options.max_dim;
+    let image = if image.width() > max || image.height() > max {
+        image.thumbnail(max, max)
+    } else {
+        image
+    };
+    imaging::encode(&image, options.format, options.quality)
+}
+
+/// The page flagged as the cover, or else the first page.
+pub fn cover_page<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Option<u32> {
+    let pages = reader.pages();
+    pages
+        .iter()
+        .position(|p| p.flags.get() & BBFPageEntry::COVER != 0)
+        .or((!pages.is_empty()).then_some(0))
+        .map(|i| i as u32)
+}
+
+/// The cover page no larger than `max_dim` on either side and turned the
+/// way it's displayed, or `None` for a book without pages. The embedded
+/// thumbnail is used when it's at least that large, so only covers that
+/// need it are decoded in full.
+pub fn cover<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    max_dim: u32,
+) -> Result<Option<DynamicImage>, ImagingError> {
+    let Some(page) = cover_page(reader) else {
+        return Ok(None);
+    };
+    let embedded = reader
+        .thumbnail(page)
+        .and_then(|asset| reader.decode_image(asset).ok())
+        .filter(|thumb| thumb.width().max(thumb.height()) >= max_dim);
+    let image = match embedded {
+        Some(thumb) => thumb,
+        None => reader.decode_page(page)?,
+    };
+    let image = if image.width() > max_dim || image.height() > max_dim {
+        image.thumbnail(max_dim, max_dim)
+    } else {
+        image
+    };
+
+    Ok(Some(
+        match reader.pages()[page as usize].rotation_degrees() {
+            90 => image.rotate90(),
+            180 => image.rotate180(),
+            270 => image.rotate270(),
+            _ => image,
+        },
+    ))
+}
+
+/// Replaces the book's thumbnails with `thumbs`, all encoded as
+/// `media_type`.
+pub fn embed<W: Write>(
+    builder: &mut BBFBuilder<W>,
+    thumbs: &[(u32, Vec<u8>)],
+    media_type: BBFMediaType,
+) -> io::Result<()> {
+    builder.clear_thumbnails();
+    for (page, data) in thumbs {
+        builder.add_thumbnail(*page, data, media_type)?;
+    }
+    Ok(())
+}
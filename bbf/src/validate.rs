@@ -0,0 +1,488 @@
+#![allow(clippy::cast_possible_truncation)]
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::mem::size_of;
+use xxhash_rust::xxh3::xxh3_64;
+
+use Severity::{Error, Info, Warning};
+
+use crate::format::{AssetFlags, BBFFooter, BBFHeader};
+use crate::hash::{self, ContentHasher};
+use crate::reader::BBFReader;
+
+/// How demanding `validate` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Everything a reader relies on, plus asset and index hashes.
+    Standard,
+    /// `Standard`, plus 4096-byte asset alignment and a `Title` entry.
+    /// Warnings count as errors.
+    Archival,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+            Self::Info => "info",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Stable kebab-case identifier, e.g. `asset-overlap`.
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub profile: Profile,
+    /// Hash every asset and the index. The slowest check by far.
+    pub check_hashes: bool,
+    /// Required asset alignment; 0 or 1 disables the check.
+    pub alignment: u64,
+}
+
+impl Options {
+    #[must_use]
+    pub const fn for_profile(profile: Profile) -> Self {
+        Self {
+            profile,
+            check_hashes: true,
+            alignment: match profile {
+                Profile::Standard => 0,
+                Profile::Archival => 4096,
+            },
+        }
+    }
+}
+
+struct Checker<'a, T: AsRef<[u8]>> {
+    reader: &'a BBFReader<T>,
+    options: &'a Options,
+    hasher: &'a dyn ContentHasher,
+    issues: Vec<Issue>,
+}
+
+/// Checks `reader`'s book against `options`, returning issues sorted by
+/// severity, then in the order they were found.
+pub fn validate<T: AsRef<[u8]>>(reader: &BBFReader<T>, options: &Options) -> Vec<Issue> {
+    validate_with_hasher(reader, options, &hash::Xxh3)
+}
+
+/// Like `validate`, checking asset hashes with `hasher`. Books recording
+/// another algorithm get a warning instead of asset hash checks.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "info",
+        skip_all,
+        fields(profile = ?options.profile, hashes = options.check_hashes),
+    )
+)]
+pub fn validate_with_hasher<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    options: &Options,
+    hasher: &dyn ContentHasher,
+) -> Vec<Issue> {
+    let mut checker = Checker {
+        reader,
+        options,
+        hasher,
+        issues: Vec::new(),
+    };
+    checker.assets();
+    checker.references();
+    checker.sections();
+    checker.metadata();
+    checker.index();
+
+    let mut issues = checker.issues;
+    issues.sort_by_key(|i| i.severity);
+    event!(
+        info,
+        errors = issues
+            .iter()
+            .filter(|i| i.severity == Severity::Error)
+            .count(),
+        issues = issues.len(),
+        "Validated"
+    );
+    issues
+}
+
+impl<T: AsRef<[u8]>> Checker<'_, T> {
+    fn push(&mut self, severity: Severity, code: &'static str, message: String) {
+        let severity = match severity {
+            Severity::Warning if self.options.profile == Profile::Archival => Severity::Error,
+            s => s,
+        };
+        self.issues.push(Issue {
+            severity,
+            code,
+            message,
+        });
+    }
+
+    fn index_start(&self) -> u64 {
+        self.reader.footer.string_pool_offset.get()
+    }
+
+    fn footer_start(&self) -> u64 {
+        (self.reader.file_len() - size_of::<BBFFooter>()) as u64
+    }
+
+    /// Checks that the string at `offset` is inside the pool, terminated, and UTF-8.
+    fn string(&mut self, offset: u32, what: impl Fn() -> String) {
+        let pool_end = self.reader.footer.asset_table_offset.get();
+        let pool = self
+            .reader
+            .bytes(self.index_start(), pool_end)
+            .unwrap_or_default();
+
+        let Some(rest) = pool.get(offset as usize..) else {
+            self.push(
+                Error,
+                "string-out-of-bounds",
+                format!("{} points past the string pool", what()),
+            );
+            return;
+        };
+        let Some(end) = rest.iter().position(|&b| b == 0) else {
+            self.push(
+                Error,
+                "string-unterminated",
+                format!("{} runs off the end of the string pool", what()),
+            );
+            return;
+        };
+        if std::str::from_utf8(&rest[..end]).is_err() {
+            self.push(
+                Error,
+                "string-not-utf8",
+                format!("{} is not valid UTF-8", what()),
+            );
+        }
+    }
+
+    /// Bounds, overlaps, alignment, hashes and duplicates.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    fn assets(&mut self) {
+        let reader = self.reader;
+        let index_start = self.index_start();
+        let data_start = size_of::<BBFHeader>() as u64;
+        let alignment = self.options.alignment;
+
+        let algorithm = reader.hash_algorithm();
+        let check_hashes = self.options.check_hashes && algorithm == self.hasher.id();
+        if self.options.check_hashes && !check_hashes {
+            let name =
+                hash::name(algorithm).map_or_else(|| format!("#{algorithm}"), str::to_string);
+            self.push(
+                Warning,
+                "asset-hash-unverified",
+                format!("Assets are hashed with {name}, which this check can't compute"),
+            );
+        }
+
+        let mut spans = Vec::with_capacity(reader.assets().len());
+        for (i, a) in reader.assets().iter().enumerate() {
+            let (offset, length) = (a.offset.get(), a.length.get());
+            if a.asset_flags().contains(AssetFlags::EXTERNAL) {
+                // The offset names the file instead; its bytes aren't here.
+                match u32::try_from(offset) {
+                    Ok(name) => self.string(name, || format!("Name of external asset {i}")),
+                    Err(_) => self.push(
+                        Error,
+                        "string-out-of-bounds",
+                        format!("Name of external asset {i} points past the string pool"),
+                    ),
+                }
+                continue;
+            }
+            let Some(end) = offset.checked_add(length).filter(|&end| end <= index_start) else {
+                self.push(
+                    Error,
+                    "asset-out-of-bounds",
+                    format!(
+                        "Asset {i} ({offset}+{length}) extends into the index or past the file"
+                    ),
+                );
+                continue;
+            };
+            if offset < data_start {
+                self.push(
+                    Error,
+                    "asset-out-of-bounds",
+                    format!("Asset {i} starts inside the header"),
+                );
+                continue;
+            }
+            spans.push((offset, end, i));
+
+            if alignment > 1 && offset % alignment != 0 {
+                self.push(
+                    Warning,
+                    "asset-misaligned",
+                    format!("Asset {i} at offset {offset} is not aligned to {alignment}"),
+                );
+            }
+            if check_hashes
+                && reader
+                    .bytes(offset, end)
+                    .is_some_and(|data| self.hasher.hash(data) != a.xxh3_hash.get())
+            {
+                self.push(
+                    Error,
+                    "asset-hash-mismatch",
+                    format!("Asset {i} does not match its stored hash"),
+                );
+            }
+            if !a.asset_flags().contains(AssetFlags::COMPRESSED) && a.decoded_length.get() != length
+            {
+                self.push(
+                    Warning,
+                    "asset-decoded-length",
+                    format!("Asset {i} is stored uncompressed but its decoded length differs"),
+                );
+            }
+        }
+        spans.sort_unstable();
+        for pair in spans.windows(2) {
+            let ((_, end, a), (start, _, b)) = (pair[0], pair[1]);
+            if start < end {
+                self.push(
+                    Error,
+                    "asset-overlap",
+                    format!("Assets {a} and {b} overlap"),
+                );
+            }
+        }
+
+        let mut first_with_hash: HashMap<u64, usize> = HashMap::new();
+        for (i, a) in reader.assets().iter().enumerate() {
+            match first_with_hash.entry(a.xxh3_hash.get()) {
+                Entry::Occupied(first) => self.push(
+                    Info,
+                    "asset-duplicate",
+                    format!(
+                        "Asset {i} has the same hash as asset {}; it was not deduplicated",
+                        first.get()
+                    ),
+                ),
+                Entry::Vacant(slot) => {
+                    slot.insert(i);
+                }
+            }
+        }
+    }
+
+    /// Pages and thumbnails point at real assets, and every asset is used.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    fn references(&mut self) {
+        let reader = self.reader;
+        let pages = reader.pages();
+        let mut referenced = vec![false; reader.assets().len()];
+        for (i, p) in pages.iter().enumerate() {
+            let asset = p.asset_index.get() as usize;
+            match referenced.get_mut(asset) {
+                Some(r) => *r = true,
+                None => self.push(
+                    Error,
+                    "page-bad-asset",
+                    format!(
+                        "Page {} points at asset {asset}, which doesn't exist",
+                        i + 1
+                    ),
+                ),
+            }
+        }
+        for t in reader.thumbnails() {
+            let (page, asset) = (t.page_index.get(), t.asset_index.get() as usize);
+            if page as usize >= pages.len() {
+                self.push(
+                    Error,
+                    "thumbnail-bad-page",
+                    format!(
+                        "Thumbnail for page {} points past the last page",
+                        u64::from(page) + 1
+                    ),
+                );
+            }
+            match referenced.get_mut(asset) {
+                Some(r) => *r = true,
+                None => self.push(
+                    Error,
+                    "thumbnail-bad-asset",
+                    format!(
+                        "Thumbnail for page {} points at missing asset {asset}",
+                        u64::from(page) + 1
+                    ),
+                ),
+            }
+        }
+        for (i, _) in referenced.iter().enumerate().filter(|(_, r)| !**r) {
+            self.push(
+                Warning,
+                "asset-orphaned",
+                format!("Asset {i} is not used by any page or thumbnail"),
+            );
+        }
+    }
+
+    /// Titles, start pages, and parents that exist and don't loop.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    fn sections(&mut self) {
+        let reader = self.reader;
+        let pages = reader.pages();
+        let sections = reader.sections();
+        for (i, s) in sections.iter().enumerate() {
+            self.string(s.section_title_offset.get(), || {
+                format!("Title of section {i}")
+            });
+            let start = s.section_start_index.get();
+            if start as usize >= pages.len() {
+                self.push(
+                    Error,
+                    "section-bad-start",
+                    format!(
+                        "Section {i} starts at page {}, past the last page",
+                        u64::from(start) + 1
+                    ),
+                );
+            }
+            let parent = s.parent_section_index.get();
+            if parent != 0xFFFF_FFFF && parent as usize >= sections.len() {
+                self.push(
+                    Error,
+                    "section-bad-parent",
+                    format!("Section {i} has parent {parent}, which doesn't exist"),
+                );
+            }
+        }
+        for i in 0..sections.len() {
+            let mut cur = i;
+            for _ in 0..sections.len() {
+                let parent = sections[cur].parent_section_index.get() as usize;
+                if parent >= sections.len() {
+                    break;
+                }
+                if parent == i {
+                    self.push(
+                        Error,
+                        "section-parent-cycle",
+                        format!("Section {i} is its own ancestor"),
+                    );
+                    break;
+                }
+                cur = parent;
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    fn metadata(&mut self) {
+        let reader = self.reader;
+        let mut has_title = false;
+        let mut first_with_key: HashMap<&str, usize> = HashMap::new();
+        for (i, m) in reader.metadata().iter().enumerate() {
+            self.string(m.key_offset.get(), || format!("Key of metadata entry {i}"));
+            self.string(m.val_offset.get(), || {
+                format!("Value of metadata entry {i}")
+            });
+            match reader.get_string(m.key_offset.get()) {
+                Some("") => self.push(
+                    Warning,
+                    "metadata-empty-key",
+                    format!("Metadata entry {i} has an empty key"),
+                ),
+                Some("Title") => has_title = true,
+                _ => {}
+            }
+            let Some(key) = reader.get_string(m.key_offset.get()) else {
+                continue;
+            };
+            match first_with_key.entry(key) {
+                Entry::Occupied(first) => self.push(
+                    Info,
+                    "metadata-duplicate-key",
+                    format!(
+                        "Metadata entry {i} repeats the key of entry {}; readers use the first",
+                        first.get()
+                    ),
+                ),
+                Entry::Vacant(slot) => {
+                    slot.insert(i);
+                }
+            }
+        }
+        if self.options.profile == Profile::Archival && !has_title {
+            self.push(
+                Error,
+                "metadata-no-title",
+                "Book has no Title metadata".into(),
+            );
+        }
+    }
+
+    /// Extensions, page names, and the index hash.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    fn index(&mut self) {
+        let reader = self.reader;
+        let pages = reader.pages();
+        let (index_start, footer_start) = (self.index_start(), self.footer_start());
+        for (i, e) in reader.extensions().iter().enumerate() {
+            let end = e.offset.get().checked_add(e.length.get());
+            if e.offset.get() < index_start || end.is_none_or(|end| end > footer_start) {
+                self.push(
+                    Error,
+                    "extension-out-of-bounds",
+                    format!(
+                        "Extension {i} (type {}) lies outside the index",
+                        e.extension_type.get()
+                    ),
+                );
+            }
+        }
+        for n in reader.page_names() {
+            let page = n.page_index.get();
+            if page as usize >= pages.len() {
+                self.push(
+                    Error,
+                    "page-name-bad-page",
+                    format!(
+                        "Name recorded for page {}, past the last page",
+                        u64::from(page) + 1
+                    ),
+                );
+            }
+            self.string(n.name_offset.get(), || {
+                format!("Name of page {}", u64::from(page) + 1)
+            });
+        }
+        if self.options.check_hashes
+            && reader
+                .bytes(index_start, footer_start)
+                .is_some_and(|index| xxh3_64(index) != reader.footer.index_hash.get())
+        {
+            self.push(
+                Error,
+                "index-hash-mismatch",
+                "Index does not match the footer's hash".into(),
+            );
+        }
+    }
+}
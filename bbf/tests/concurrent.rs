@@ -0,0 +1,58 @@
+//! One shared `BBFReader` must serve every page correctly to many threads
+//! at once.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::thread;
+
+use bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use xxhash_rust::xxh3::xxh3_64;
+
+const PAGES: u32 = 200;
+const THREADS: u32 = 8;
+const ROUNDS: u32 = 50;
+
+fn page_data(i: u32) -> Vec<u8> {
+    (0..64 + i * 13)
+        .map(|b| (b ^ i.wrapping_mul(31)) as u8)
+        .collect()
+}
+
+#[test]
+fn concurrent_get_asset() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    for i in 0..PAGES {
+        builder
+            .add_page(&page_data(i), BBFMediaType::Png, 0)
+            .unwrap();
+    }
+    let data = builder.finish().unwrap().into_inner();
+    let reader = BBFReader::shared(data).unwrap();
+    let expected: Arc<Vec<u64>> = Arc::new((0..PAGES).map(|i| xxh3_64(&page_data(i))).collect());
+
+    let workers: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let reader = Arc::clone(&reader);
+            let expected = Arc::clone(&expected);
+            thread::spawn(move || {
+                for round in 0..ROUNDS {
+                    for step in 0..PAGES {
+                        // Each thread walks the pages in a different order.
+                        let page = (step * (2 * t + 1) + round) % PAGES;
+                        let asset = reader.pages()[page as usize].asset_index.get();
+                        let bytes = reader.get_asset(asset).unwrap();
+                        assert_eq!(xxh3_64(bytes), expected[page as usize]);
+                        let decoded = reader.get_asset_decoded(asset).unwrap();
+                        assert_eq!(&*decoded, bytes);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for worker in workers {
+        worker.join().unwrap();
+    }
+}
@@ -0,0 +1,632 @@
+//! Random books written with `BBFBuilder` must read back exactly, both from
+//! memory and through `RemoteReader`.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::sync::Mutex;
+
+use bbf::audit::rewrite_clean;
+use bbf::builder::BadSections;
+use bbf::format::{BBFHeader, BBFPageEntry};
+use bbf::reader::{BBFError, PageSource};
+use bbf::remote::{RandomAccess, RemoteError, RemoteReader, asset_range};
+use bbf::stats::{BookStats, section_stats};
+use bbf::validate::{Options, Profile, validate};
+use bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use proptest::prelude::*;
+use xxhash_rust::xxh3::xxh3_64;
+
+#[derive(Debug, Clone)]
+struct Page {
+    /// Index into `Book::blobs`, so several pages can share data.
+    blob: usize,
+    media_type: BBFMediaType,
+    flags: u32,
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    title: String,
+    start: u32,
+    /// Always an earlier section, the way the builder's callers nest them.
+    parent: Option<u32>,
+}
+
+#[derive(Debug, Clone)]
+struct Book {
+    blobs: Vec<Vec<u8>>,
+    pages: Vec<Page>,
+    sections: Vec<Section>,
+    metadata: Vec<(String, String)>,
+    alignment: u64,
+}
+
+/// Strings the pool can hold: any text without NULs, which end entries.
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        8 => "[^\0]{0,24}",
+        1 => "[^\0]{2000,6000}",
+    ]
+}
+
+/// Mostly known types, sometimes any byte, as a newer writer might store.
+fn media_type() -> impl Strategy<Value = BBFMediaType> {
+    prop_oneof![
+        4 => prop::sample::select(vec![
+            BBFMediaType::Unknown,
+            BBFMediaType::Avif,
+            BBFMediaType::Png,
+            BBFMediaType::Webp,
+            BBFMediaType::Jxl,
+            BBFMediaType::Bmp,
+            BBFMediaType::Gif,
+            BBFMediaType::Tiff,
+            BBFMediaType::Jpg,
+        ]),
+        1 => any::<u8>().prop_map(BBFMediaType::from),
+    ]
+}
+
+fn book() -> impl Strategy<Value = Book> {
+    let blobs = prop::collection::vec(prop::collection::vec(any::<u8>(), 0..2048), 1..8);
+    (blobs, 0usize..40)
+        .prop_flat_map(|(blobs, page_count)| {
+            let blob_count = blobs.len();
+            let page = (
+                0..blob_count,
+                media_type(),
+                any::<u32>(),
+                prop::option::of(text()),
+            )
+                .prop_map(|(blob, media_type, flags, name)| Page {
+                    blob,
+                    media_type,
+                    flags,
+                    name,
+                });
+            let pages = prop::collection::vec(page, page_count);
+            let sections = prop::collection::vec((text(), any::<u32>(), any::<u32>()), 0..12)
+                .prop_map(move |raw| {
+                    raw.into_iter()
+                        .enumerate()
+                        .map(|(i, (title, start, parent))| Section {
+                            title,
+                            start: start % (page_count as u32).max(1),
+                            parent: (i > 0 && parent % 3 != 0).then(|| parent % i as u32),
+                        })
+                        .collect()
+                });
+            let metadata = prop::collection::vec((text(), text()), 0..12);
+            let alignment = prop::sample::select(vec![0, 1, 8, 4096]);
+            (Just(blobs), pages, sections, metadata, alignment)
+        })
+        .prop_map(|(blobs, pages, sections, metadata, alignment)| Book {
+            blobs,
+            pages,
+            sections,
+            metadata,
+            alignment,
+        })
+}
+
+fn write(book: &Book) -> Vec<u8> {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.set_alignment(book.alignment);
+    for (i, page) in book.pages.iter().enumerate() {
+        builder
+            .add_page(&book.blobs[page.blob], page.media_type, page.flags)
+            .unwrap();
+        if let Some(name) = &page.name {
+            builder.set_page_name(i as u32, name);
+        }
+    }
+    for s in &book.sections {
+        builder.add_section(&s.title, s.start, s.parent);
+    }
+    for (key, value) in &book.metadata {
+        builder.add_metadata(key, value);
+    }
+    builder.finish().unwrap().into_inner()
+}
+
+/// Checks everything `reader` holds besides asset bytes against `book`.
+fn check_index<T: AsRef<[u8]>>(book: &Book, reader: &BBFReader<T>) {
+    // Duplicate data is stored once, as the media type it was first added with.
+    let mut distinct = Vec::new();
+    for page in &book.pages {
+        let data = &book.blobs[page.blob];
+        if !distinct.contains(&data) {
+            distinct.push(data);
+        }
+    }
+    assert_eq!(reader.assets().len(), distinct.len());
+    assert_eq!(reader.pages().len(), book.pages.len());
+
+    for (i, (page, entry)) in book.pages.iter().zip(reader.pages()).enumerate() {
+        let asset = &reader.assets()[entry.asset_index.get() as usize];
+        let data = &book.blobs[page.blob];
+        assert_eq!(entry.flags.get(), page.flags, "flags of page {i}");
+        assert_eq!(asset.length.get(), data.len() as u64, "length of page {i}");
+        assert_eq!(asset.xxh3_hash.get(), xxh3_64(data), "hash of page {i}");
+        let first = distinct.iter().position(|d| *d == data).unwrap();
+        assert_eq!(entry.asset_index.get(), first as u32, "asset of page {i}");
+        let stored_type = book
+            .pages
+            .iter()
+            .find(|p| book.blobs[p.blob] == *data)
+            .unwrap()
+            .media_type;
+        assert_eq!(BBFMediaType::from(asset.type_), stored_type);
+        assert_eq!(reader.page_name(i as u32), page.name.as_deref());
+    }
+
+    for (asset, d) in distinct.iter().enumerate() {
+        let expected: Vec<u32> = (0..book.pages.len() as u32)
+            .filter(|&p| book.blobs[book.pages[p as usize].blob] == **d)
+            .collect();
+        assert_eq!(reader.pages_for_asset(asset as u32), expected);
+    }
+    assert!(reader.pages_for_asset(distinct.len() as u32).is_empty());
+
+    assert_eq!(reader.sections().len(), book.sections.len());
+    for (s, entry) in book.sections.iter().zip(reader.sections()) {
+        assert_eq!(
+            reader.get_string(entry.section_title_offset.get()),
+            Some(s.title.as_str())
+        );
+        assert_eq!(entry.section_start_index.get(), s.start);
+        let parent = entry.parent_section_index.get();
+        assert_eq!((parent != u32::MAX).then_some(parent), s.parent);
+    }
+
+    assert_eq!(reader.metadata().len(), book.metadata.len());
+    for ((key, value), entry) in book.metadata.iter().zip(reader.metadata()) {
+        assert_eq!(
+            reader.get_string(entry.key_offset.get()),
+            Some(key.as_str())
+        );
+        assert_eq!(
+            reader.get_string(entry.val_offset.get()),
+            Some(value.as_str())
+        );
+    }
+
+    if book.alignment > 1 {
+        for asset in reader.assets().iter().filter(|a| a.length.get() > 0) {
+            assert_eq!(asset.offset.get() % book.alignment, 0);
+        }
+    }
+}
+
+/// A `RandomAccess` source over anything seekable, like a file would be.
+struct Seekable<R>(Mutex<R>);
+
+impl<R: Read + Seek> RandomAccess for Seekable<R> {
+    fn len(&self) -> io::Result<u64> {
+        self.0.lock().unwrap().seek(SeekFrom::End(0))
+    }
+
+    fn read(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let mut inner = self.0.lock().unwrap();
+        inner.seek(SeekFrom::Start(start))?;
+        let mut buf = vec![0; (end - start) as usize];
+        inner.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+proptest! {
+    #[test]
+    fn slice_reader_round_trips(book in book()) {
+        let data = write(&book);
+        let reader = BBFReader::new(&data[..]).unwrap();
+        check_index(&book, &reader);
+        for (i, page) in book.pages.iter().enumerate() {
+            let asset = reader.pages()[i].asset_index.get();
+            prop_assert_eq!(reader.get_asset(asset).unwrap(), &book.blobs[page.blob][..]);
+            prop_assert_eq!(&*reader.get_page(i as u32).unwrap(), &book.blobs[page.blob][..]);
+        }
+        prop_assert!(reader.get_page(book.pages.len() as u32).is_err());
+        prop_assert_eq!(all_pages(&reader), expected_pages(&book));
+    }
+
+    #[test]
+    fn remote_reader_round_trips(book in book()) {
+        let data = write(&book);
+        let remote = RemoteReader::open(Seekable(Mutex::new(Cursor::new(data)))).unwrap();
+        check_index(&book, remote.reader());
+        for (i, page) in book.pages.iter().enumerate() {
+            prop_assert_eq!(&*remote.get_page(i as u32).unwrap(), &book.blobs[page.blob][..]);
+        }
+        prop_assert_eq!(all_pages(&remote), expected_pages(&book));
+    }
+}
+
+/// Every page of `source`, through nothing but `PageSource`.
+fn all_pages<S: PageSource>(source: &S) -> Vec<Vec<u8>>
+where
+    S::Error: std::fmt::Debug,
+{
+    (0..source.page_count() as u32)
+        .map(|i| source.get_page(i).unwrap().into_owned())
+        .collect()
+}
+
+fn expected_pages(book: &Book) -> Vec<Vec<u8>> {
+    book.pages
+        .iter()
+        .map(|p| book.blobs[p.blob].clone())
+        .collect()
+}
+
+#[test]
+fn empty_book_round_trips() {
+    let book = Book {
+        blobs: Vec::new(),
+        pages: Vec::new(),
+        sections: Vec::new(),
+        metadata: Vec::new(),
+        alignment: 4096,
+    };
+    let data = write(&book);
+    check_index(&book, &BBFReader::new(&data[..]).unwrap());
+    let remote = RemoteReader::open(Seekable(Mutex::new(Cursor::new(data)))).unwrap();
+    check_index(&book, remote.reader());
+}
+
+#[test]
+fn huge_string_pool_round_trips() {
+    // Offsets far past 16 bits, and one string larger than the rest of the file.
+    let long = "ページ".repeat(400_000);
+    let book = Book {
+        blobs: vec![vec![1, 2, 3]],
+        pages: vec![Page {
+            blob: 0,
+            media_type: BBFMediaType::Png,
+            flags: 0,
+            name: Some(long.clone()),
+        }],
+        sections: (0..2000)
+            .map(|i| Section {
+                title: format!("Chapter {i} – {}", "é".repeat(i % 50)),
+                start: 0,
+                parent: (i > 0).then(|| (i as u32 - 1) / 2),
+            })
+            .collect(),
+        metadata: vec![("Title".into(), long)],
+        alignment: 4096,
+    };
+    let data = write(&book);
+    check_index(&book, &BBFReader::new(&data[..]).unwrap());
+}
+
+#[test]
+fn sections_sort_and_check_targets() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    for i in 0..4u8 {
+        builder.add_page(&[i], BBFMediaType::Png, 0).unwrap();
+    }
+    builder.add_section("Part 2", 2, None);
+    builder.add_section("Chapter 3", 3, Some(0));
+    builder.add_section("Part 1", 0, None);
+    builder.add_section("Chapter 1", 0, Some(2));
+    builder.set_sort_sections(true);
+    let data = builder.finish().unwrap().into_inner();
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let sections: Vec<_> = reader
+        .sections()
+        .iter()
+        .map(|s| {
+            let parent = s.parent_section_index.get();
+            (
+                reader.get_string(s.section_title_offset.get()).unwrap(),
+                s.section_start_index.get(),
+                (parent != u32::MAX).then_some(parent),
+            )
+        })
+        .collect();
+    assert_eq!(
+        sections,
+        [
+            ("Part 1", 0, None),
+            ("Chapter 1", 0, Some(0)),
+            ("Part 2", 2, None),
+            ("Chapter 3", 3, Some(2)),
+        ]
+    );
+
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.add_page(&[0], BBFMediaType::Png, 0).unwrap();
+    builder.add_section("Past the end", 1, None);
+    builder.set_bad_sections(BadSections::Error);
+    let err = builder.finish().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn bad_sections_are_kept_as_diagnostics() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.add_page(&[0], BBFMediaType::Png, 0).unwrap();
+    builder.add_section("Past the end", 1, None);
+    builder.add_section("Own parent", 0, Some(1));
+    builder.add_section("Fine", 0, None);
+    let (writer, diagnostics) = builder.finish_with_diagnostics().unwrap();
+    assert_eq!(
+        diagnostics,
+        [
+            "Section 0 starts at page 1, but the book has 1 pages",
+            "Section 1 has parent 1, which isn't another section",
+        ]
+    );
+
+    let data = writer.into_inner();
+    let reader = BBFReader::new(&data[..]).unwrap();
+    assert_eq!(reader.sections().len(), 3);
+}
+
+#[test]
+fn section_titles_match_across_normalization() {
+    let decomposed = "Cafe\u{301}";
+    let composed = "Caf\u{e9}";
+
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.add_page(&[0], BBFMediaType::Png, 0).unwrap();
+    builder.add_section(decomposed, 0, None);
+    builder.set_normalize_titles(true);
+    builder.add_section(decomposed, 0, None);
+    let data = builder.finish().unwrap().into_inner();
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let title = |i: usize| reader.get_string(reader.sections()[i].section_title_offset.get());
+    assert_eq!(title(0), Some(decomposed));
+    assert_eq!(title(1), Some(composed));
+    assert_eq!(reader.find_section(composed), Some(0));
+    assert_eq!(reader.find_section("CAF\u{c9}"), None);
+    assert_eq!(reader.find_section_ignore_case("CAFE\u{301}"), Some(0));
+}
+
+#[test]
+fn external_assets_are_not_read_from_the_book() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.add_page(&[1, 2, 3], BBFMediaType::Png, 0).unwrap();
+    let external = builder
+        .add_external_page("pages/p2.png", &[0; 1000], BBFMediaType::Png, 0)
+        .unwrap();
+    let data = builder.finish().unwrap().into_inner();
+
+    let stats = BookStats::compute(&BBFReader::new(&data[..]).unwrap(), 0);
+    assert_eq!(stats.asset_bytes, 3);
+    assert_eq!(stats.page_bytes, 3);
+    let header = size_of::<BBFHeader>() as u64;
+    assert_eq!(
+        header + stats.asset_bytes + stats.padding_bytes + stats.index_bytes,
+        stats.file_size
+    );
+
+    let remote = RemoteReader::open(Seekable(Mutex::new(Cursor::new(data)))).unwrap();
+    assert_eq!(&*remote.get_page(0).unwrap(), [1, 2, 3]);
+    assert!(matches!(
+        asset_range(remote.reader(), external),
+        Err(BBFError::External)
+    ));
+    assert!(matches!(
+        remote.get_page(1),
+        Err(RemoteError::Bbf(BBFError::External))
+    ));
+}
+
+#[test]
+fn appended_data_is_skipped_when_reading_in_ranges_or_reopening() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.add_page(&[1; 16], BBFMediaType::Png, 0).unwrap();
+    builder.add_page(&[2; 16], BBFMediaType::Png, 0).unwrap();
+    let mut data = builder.finish().unwrap().into_inner();
+    // A stray magic in the appended blob mustn't be taken for the footer.
+    data.extend_from_slice(b"signature BBF1 ");
+    data.extend_from_slice(&[0xAB; 300]);
+
+    let remote = RemoteReader::open(Seekable(Mutex::new(Cursor::new(data.clone())))).unwrap();
+    assert_eq!(&*remote.get_page(1).unwrap(), [2; 16]);
+
+    let mut builder = BBFBuilder::from_existing(Cursor::new(data)).unwrap();
+    assert_eq!(builder.page_count(), 2);
+    builder.add_page(&[3; 16], BBFMediaType::Png, 0).unwrap();
+    let file = builder.finish().unwrap();
+    let end = file.position() as usize;
+    let mut data = file.into_inner();
+    data.truncate(end);
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    assert_eq!(reader.trailing_len(), 0);
+    assert_eq!(&*reader.get_page(2).unwrap(), [3; 16]);
+    assert_eq!(&*reader.get_page(0).unwrap(), [1; 16]);
+}
+
+#[test]
+fn reopened_books_store_pages_matching_external_ones() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder
+        .add_external_page("pages/p1.png", &[7; 32], BBFMediaType::Png, 0)
+        .unwrap();
+    let file = builder.finish().unwrap();
+
+    let mut builder = BBFBuilder::from_existing(file).unwrap();
+    let stored = builder.add_page(&[7; 32], BBFMediaType::Png, 0).unwrap();
+    assert_eq!(stored, 1);
+    let data = builder.finish().unwrap().into_inner();
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    assert!(matches!(reader.get_page(0), Err(BBFError::External)));
+    assert_eq!(&*reader.get_page(1).unwrap(), [7; 32]);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn repack_keeps_or_redoes_compression() {
+    use bbf::builder::Compression;
+    use bbf::format::AssetFlags;
+
+    let pages: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 10_000]).collect();
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    builder.set_compression(Some(Compression {
+        level: 3,
+        media_types: Vec::new(),
+    }));
+    for page in &pages {
+        builder.add_page(page, BBFMediaType::Bmp, 0).unwrap();
+    }
+    let data = builder.finish().unwrap().into_inner();
+    let source = BBFReader::new(&data[..]).unwrap();
+
+    let repack = |compression: Option<Compression>| {
+        let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+        builder.set_compression(compression);
+        rewrite_clean(&source, &mut builder).unwrap();
+        builder.finish().unwrap().into_inner()
+    };
+    let check = |data: &[u8], compressed: bool| {
+        let reader = BBFReader::new(data).unwrap();
+        for (i, page) in pages.iter().enumerate() {
+            assert_eq!(&*reader.get_page(i as u32).unwrap(), &page[..]);
+        }
+        for asset in reader.assets() {
+            let flags = asset.asset_flags();
+            assert_eq!(flags.contains(AssetFlags::COMPRESSED), compressed);
+            assert_eq!(asset.decoded_length.get(), 10_000);
+        }
+    };
+
+    let kept = repack(None);
+    check(&kept, true);
+    assert_eq!(kept.len(), data.len());
+    check(
+        &repack(Some(Compression {
+            level: 19,
+            media_types: vec![BBFMediaType::Png],
+        })),
+        false,
+    );
+    check(
+        &repack(Some(Compression {
+            level: 19,
+            media_types: Vec::new(),
+        })),
+        true,
+    );
+}
+
+#[test]
+fn deep_section_chains_are_counted() {
+    const DEPTH: u32 = 50_000;
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    for _ in 0..DEPTH {
+        builder.add_page(&[0], BBFMediaType::Png, 0).unwrap();
+    }
+    for i in 0..DEPTH {
+        builder.add_section("Part", i, i.checked_sub(1));
+    }
+    let data = builder.finish().unwrap().into_inner();
+
+    let stats = section_stats(&BBFReader::new(&data[..]).unwrap());
+    for (i, s) in stats.iter().enumerate() {
+        assert_eq!(s.page_count, DEPTH - i as u32);
+    }
+}
+
+#[test]
+fn foreign_tickets_are_refused() {
+    let mut a = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    let mut b = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    let ticket = a.reserve_page(4, BBFMediaType::Png, 0).unwrap();
+    // Same page, offset and length as `a`'s, but not `a`'s ticket.
+    let other = b.reserve_page(4, BBFMediaType::Png, 0).unwrap();
+
+    let err = b.fill_reserved(&ticket, &[1; 4]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    b.fill_reserved(&other, &[2; 4]).unwrap();
+    assert!(b.finish().is_ok());
+
+    let later = a.reserve_page(4, BBFMediaType::Png, 0).unwrap();
+    a.fill_reserved(&later, &[3; 4]).unwrap();
+    // `ticket` was only offered to `b`, so its page is still empty.
+    let err = a.finish().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn reserved_pages_fill_once_in_any_order() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    let first = builder.reserve_page(8, BBFMediaType::Png, 0).unwrap();
+    builder.add_page(&[9; 8], BBFMediaType::Png, 0).unwrap();
+    let second = builder.reserve_page(4, BBFMediaType::Jpg, 0).unwrap();
+    assert_eq!((first.page_index(), second.page_index()), (0, 2));
+
+    let err = builder.fill_reserved(&second, &[2; 5]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    builder.fill_reserved(&second, &[2; 4]).unwrap();
+    let err = builder.fill_reserved(&second, &[2; 4]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    builder.fill_reserved(&first, &[1; 8]).unwrap();
+
+    // Filled reservations take part in deduplication like any other asset.
+    builder.add_page(&[1; 8], BBFMediaType::Png, 0).unwrap();
+
+    let data = builder.finish().unwrap().into_inner();
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let issues = validate(&reader, &Options::for_profile(Profile::Standard));
+    assert!(issues.is_empty(), "{issues:?}");
+    let pages: Vec<_> = (0..4).map(|i| reader.get_page(i).unwrap()).collect();
+    assert_eq!(pages, [&[1; 8][..], &[9; 8], &[2; 4], &[1; 8]]);
+    assert_eq!(reader.assets().len(), 3);
+    assert_eq!(reader.pages()[3].asset_index, reader.pages()[0].asset_index);
+}
+
+#[test]
+fn media_types_compare_by_byte() {
+    assert_eq!(BBFMediaType::Other(2), BBFMediaType::Png);
+    assert_eq!(BBFMediaType::Other(2).as_extension(), ".png");
+    assert_ne!(BBFMediaType::Other(0x42), BBFMediaType::Unknown);
+    assert_eq!(BBFMediaType::from(0x42), BBFMediaType::Other(0x42));
+}
+
+#[test]
+fn rewrite_clean_repairs_audited_problems() {
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    for i in 1..=3u8 {
+        builder.add_page(&[i; 16], BBFMediaType::Png, 0).unwrap();
+    }
+    builder.add_section("Intro", 0, None);
+    builder.add_section("Gone", 5, None);
+    let mut data = builder.finish().unwrap().into_inner();
+
+    // Point page 2 past the asset table, leaving its asset orphaned.
+    let table = BBFReader::new(&data[..])
+        .unwrap()
+        .footer
+        .page_table_offset
+        .get() as usize;
+    let entry = table + size_of::<BBFPageEntry>();
+    data[entry..entry + 4].copy_from_slice(&99u32.to_le_bytes());
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    let audit = reader.audit();
+    assert_eq!(audit.bad_pages, [1]);
+    assert_eq!(audit.orphaned, [1]);
+    assert_eq!(audit.bad_sections, [1]);
+    assert!(audit.out_of_bounds.is_empty() && audit.overlapping.is_empty());
+
+    let mut builder = BBFBuilder::new(Cursor::new(Vec::new())).unwrap();
+    rewrite_clean(&reader, &mut builder).unwrap();
+    let data = builder.finish().unwrap().into_inner();
+
+    let reader = BBFReader::new(&data[..]).unwrap();
+    assert!(reader.audit().is_clean());
+    assert_eq!(&*reader.get_page(0).unwrap(), [1; 16]);
+    assert_eq!(&*reader.get_page(1).unwrap(), [3; 16]);
+    assert_eq!(reader.pages().len(), 2);
+    assert_eq!(reader.sections().len(), 1);
+}
@@ -0,0 +1,117 @@
+//! `bbfmux batch`: one book per subdirectory of a library folder.
+
+use crate::{
+    BuildStats, Cli, HIDE_PROGRESS, HashCache, build_book, collect_inputs, input_problem, status,
+    template,
+};
+use anyhow::{Context, Result, bail};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic;
+
+pub fn run(cli: &Cli, dir: &Path, outdir: &Path, name: &str) -> Result<()> {
+    if !cli.inputs.is_empty() {
+        bail!("batch takes its books from DIR; drop the other inputs.");
+    }
+    if cli.order.is_some() || cli.sections.is_some() || !cli.section.is_empty() {
+        bail!("--order, --sections and --section describe a single book; use sidecars with batch.");
+    }
+    if cli.watch || cli.dry_run {
+        bail!("--watch and --dry-run work on a single book.");
+    }
+
+    fs::create_dir_all(outdir)
+        .with_context(|| format!("Cannot create output directory {}", outdir.display()))?;
+    let books = book_dirs(cli, dir, outdir)?;
+    if books.is_empty() {
+        bail!("No subdirectory of {} has any pages.", dir.display());
+    }
+
+    HIDE_PROGRESS.store(true, atomic::Ordering::Relaxed);
+    let results: Vec<(&PathBuf, Result<BuildStats>)> = books
+        .par_iter()
+        .map(|book| {
+            let output = output_template(outdir, name, book);
+            let result = build_book(
+                cli,
+                std::slice::from_ref(book),
+                Path::new(&output),
+                &mut HashCache::new(),
+            );
+            match &result {
+                Ok(stats) => status!("Built {} ({} pages)", stats.output.display(), stats.pages),
+                Err(e) => log::error!("{}: {e:#}", book.display()),
+            }
+            (book, result)
+        })
+        .collect();
+
+    // Metadata placeholders can send two books to the same file, and only
+    // now do we know the names.
+    let mut written: HashMap<&Path, &Path> = HashMap::new();
+    for (book, result) in &results {
+        if let Ok(stats) = result
+            && let Some(other) = written.insert(&stats.output, book)
+        {
+            log::warn!(
+                "{} and {} were both written to {}; it holds one of them",
+                other.display(),
+                book.display(),
+                stats.output.display()
+            );
+        }
+    }
+
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    if failed > 0 {
+        bail!("{failed} of {} books failed", books.len());
+    }
+    status!("Built {} books into {}", books.len(), outdir.display());
+    Ok(())
+}
+
+/// Immediate subdirectories of `dir` that hold pages, by name. Hidden folders
+/// and the output directory are skipped.
+fn book_dirs(cli: &Cli, dir: &Path, outdir: &Path) -> Result<Vec<PathBuf>> {
+    let outdir = fs::canonicalize(outdir)?;
+    let mut books = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Cannot read {}", dir.display()))? {
+        let path = entry?.path();
+        if !path.is_dir()
+            || path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+            || fs::canonicalize(&path).is_ok_and(|p| p == outdir)
+        {
+            continue;
+        }
+
+        let mut opts = cli.input_opts;
+        opts.recursive |= cli.sections_from_dirs;
+        let (pages, _) = collect_inputs(std::slice::from_ref(&path), opts)?;
+        if pages.is_empty() {
+            input_problem(
+                cli.input_opts.strict,
+                &format!("{} has no pages", path.display()),
+                "Skipping it.",
+            )?;
+            continue;
+        }
+        books.push(path);
+    }
+    books.sort();
+    Ok(books)
+}
+
+/// The output path for `book`, still carrying any metadata placeholders for
+/// `build_book` to fill. Literal braces in the fixed parts are escaped.
+fn output_template(outdir: &Path, name: &str, book: &Path) -> String {
+    let escape = |s: &str| s.replace('{', "{{").replace('}', "}}");
+    let dir_name = template::sanitize(&book.file_name().unwrap_or_default().to_string_lossy());
+    let mut path = escape(&outdir.to_string_lossy());
+    path.push(std::path::MAIN_SEPARATOR);
+    path.push_str(&name.replace("{dir}", &escape(&dir_name)));
+    path
+}
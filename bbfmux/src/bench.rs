@@ -0,0 +1,295 @@
+//! `bbfmux bench`: how quickly a book opens and reads from each kind of
+//! storage the library can sit on.
+//!
+//! Every backend after the first reads a file the OS has already cached, so
+//! the numbers compare the library and the backends, not the disk.
+
+use crate::report::{BackendBench, BenchReport};
+use crate::{open_book, require_xxh3};
+use anyhow::{Context, Result, bail};
+use bbf::BBFReader;
+use bbf::format::{AssetFlags, BBFAssetEntry, BBFFooter, BBFPageEntry};
+use bbf::reader::BBFError;
+use std::fs::{self, File};
+use std::hint::black_box;
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use xxhash_rust::xxh3::xxh3_64;
+use zerocopy::FromBytes;
+
+/// Somewhere pages can be read from.
+trait Source {
+    fn pages(&self) -> usize;
+    fn assets(&self) -> usize;
+    /// Stored bytes of page `i`, or `None` if its asset is external.
+    fn page(&mut self, i: usize) -> Result<Option<&[u8]>>;
+    /// Stored bytes of asset `i` and the hash the index records for them,
+    /// or `None` if the asset is external.
+    fn asset(&mut self, i: usize) -> Result<Option<(&[u8], u64)>>;
+}
+
+type Open = fn(&Path) -> Result<Box<dyn Source>>;
+
+/// A whole book in memory, as a `Vec` or a mapping.
+struct InMemory<T: AsRef<[u8]>>(BBFReader<T>);
+
+impl<T: AsRef<[u8]>> Source for InMemory<T> {
+    fn pages(&self) -> usize {
+        self.0.pages().len()
+    }
+
+    fn assets(&self) -> usize {
+        self.0.assets().len()
+    }
+
+    fn page(&mut self, i: usize) -> Result<Option<&[u8]>> {
+        let asset = self.0.pages()[i].asset_index.get();
+        match self.0.get_asset(asset) {
+            Ok(data) => Ok(Some(data)),
+            Err(BBFError::External) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn asset(&mut self, i: usize) -> Result<Option<(&[u8], u64)>> {
+        let hash = self.0.assets()[i].xxh3_hash.get();
+        match self.0.get_asset(i as u32) {
+            Ok(data) => Ok(Some((data, hash))),
+            Err(BBFError::External) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Only the tables are kept in memory; pages are read with a seek and a read
+/// each, as a reader on a slow or remote disk would.
+struct Io {
+    file: File,
+    assets: Vec<BBFAssetEntry>,
+    pages: Vec<BBFPageEntry>,
+    buf: Vec<u8>,
+}
+
+impl Io {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).context("Failed to open BBF")?;
+        let len = file.metadata()?.len();
+        if len < size_of::<BBFFooter>() as u64 {
+            bail!("File too short to be a BBF book");
+        }
+
+        let mut footer = [0; size_of::<BBFFooter>()];
+        file.seek(SeekFrom::Start(len - footer.len() as u64))?;
+        file.read_exact(&mut footer)?;
+        let footer = BBFFooter::read_from_bytes(&footer).expect("buffer is footer-sized");
+        if &footer.magic != b"BBF1" {
+            bail!("Invalid BBF magic in footer");
+        }
+
+        let assets = read_table(
+            &mut file,
+            footer.asset_table_offset.get(),
+            footer.asset_count.get(),
+        )?;
+        let pages = read_table(
+            &mut file,
+            footer.page_table_offset.get(),
+            footer.page_count.get(),
+        )?;
+        Ok(Self {
+            file,
+            assets,
+            pages,
+            buf: Vec::new(),
+        })
+    }
+
+    fn read(&mut self, i: usize) -> Result<Option<&[u8]>> {
+        let a = self.assets.get(i).context("Asset index out of range")?;
+        if a.asset_flags().contains(AssetFlags::EXTERNAL) {
+            return Ok(None);
+        }
+        self.buf.resize(usize::try_from(a.length.get())?, 0);
+        self.file.seek(SeekFrom::Start(a.offset.get()))?;
+        self.file.read_exact(&mut self.buf)?;
+        Ok(Some(&self.buf))
+    }
+}
+
+impl Source for Io {
+    fn pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn assets(&self) -> usize {
+        self.assets.len()
+    }
+
+    fn page(&mut self, i: usize) -> Result<Option<&[u8]>> {
+        self.read(self.pages[i].asset_index.get() as usize)
+    }
+
+    fn asset(&mut self, i: usize) -> Result<Option<(&[u8], u64)>> {
+        let hash = self.assets[i].xxh3_hash.get();
+        Ok(self.read(i)?.map(|data| (data, hash)))
+    }
+}
+
+fn read_table<U: FromBytes + Copy>(file: &mut File, offset: u64, count: u32) -> Result<Vec<U>> {
+    let mut bytes = vec![0; count as usize * size_of::<U>()];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)
+        .context("Table runs past the end of the file")?;
+    Ok(bytes
+        .chunks_exact(size_of::<U>())
+        .map(|c| U::read_from_bytes(c).expect("chunk is element-sized"))
+        .collect())
+}
+
+pub fn run(path: &Path, rounds: usize, samples: usize) -> Result<BenchReport> {
+    // `verify` checks hashes with XXH3, so other algorithms would only
+    // count mismatches.
+    require_xxh3(&BBFReader::new(open_book(path)?).context("Failed to parse BBF")?)?;
+    let rounds = rounds.max(1);
+    let backends: [(&str, Open); 3] = [
+        ("vec", |p| {
+            let data = fs::read(p).context("Failed to read BBF")?;
+            Ok(Box::new(InMemory(
+                BBFReader::new(data).context("Failed to parse BBF")?,
+            )))
+        }),
+        ("mmap", |p| {
+            Ok(Box::new(InMemory(
+                BBFReader::new(open_book(p)?).context("Failed to parse BBF")?,
+            )))
+        }),
+        ("io", |p| Ok(Box::new(Io::open(p)?))),
+    ];
+
+    let mut report = BenchReport {
+        file_size: fs::metadata(path)?.len(),
+        page_count: 0,
+        rounds,
+        backends: Vec::new(),
+    };
+    for (name, open) in backends {
+        let mut open_time = Duration::MAX;
+        let mut source = None;
+        for _ in 0..rounds {
+            let started = Instant::now();
+            let s = open(path)?;
+            open_time = open_time.min(started.elapsed());
+            source = Some(s);
+        }
+        let mut source = source.expect("at least one round");
+        report.page_count = source.pages();
+
+        let (sequential, page_bytes) = best_of(rounds, || sequential(source.as_mut()))?;
+        let latencies = random(source.as_mut(), samples)?;
+        let (verify, (asset_bytes, mismatches)) = best_of(rounds, || verify(source.as_mut()))?;
+
+        report.backends.push(BackendBench {
+            backend: name,
+            open_us: micros(open_time),
+            sequential_bytes_per_sec: per_sec(page_bytes, sequential),
+            random_p50_us: percentile(&latencies, 50),
+            random_p99_us: percentile(&latencies, 99),
+            verify_bytes_per_sec: per_sec(asset_bytes, verify),
+            hash_mismatches: mismatches,
+        });
+    }
+    Ok(report)
+}
+
+/// Runs `f` `rounds` times and keeps the fastest time with its result.
+fn best_of<R>(rounds: usize, mut f: impl FnMut() -> Result<R>) -> Result<(Duration, R)> {
+    let mut best = None;
+    for _ in 0..rounds {
+        let started = Instant::now();
+        let result = f()?;
+        let elapsed = started.elapsed();
+        if best.as_ref().is_none_or(|(b, _)| elapsed < *b) {
+            best = Some((elapsed, result));
+        }
+    }
+    Ok(best.expect("at least one round"))
+}
+
+/// Reads every stored page in order. Each page is copied out, as a caller
+/// handing it to a decoder would, so in-memory backends can't skip the work.
+fn sequential(source: &mut dyn Source) -> Result<u64> {
+    let mut bytes = 0;
+    let mut scratch = Vec::new();
+    for i in 0..source.pages() {
+        let Some(data) = source.page(i)? else {
+            continue;
+        };
+        scratch.clear();
+        scratch.extend_from_slice(data);
+        black_box(&scratch);
+        bytes += data.len() as u64;
+    }
+    Ok(bytes)
+}
+
+/// Latencies of `samples` reads of pseudo-random pages, sorted.
+fn random(source: &mut dyn Source, samples: usize) -> Result<Vec<Duration>> {
+    let pages = source.pages();
+    if pages == 0 {
+        return Ok(Vec::new());
+    }
+    // xorshift64 with a fixed seed: the same pages on every run.
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    let mut latencies = Vec::with_capacity(samples);
+    let mut scratch = Vec::new();
+    for _ in 0..samples {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        let i = usize::try_from(state % pages as u64)?;
+        let started = Instant::now();
+        let Some(data) = source.page(i)? else {
+            continue;
+        };
+        scratch.clear();
+        scratch.extend_from_slice(data);
+        black_box(&scratch);
+        latencies.push(started.elapsed());
+    }
+    latencies.sort_unstable();
+    Ok(latencies)
+}
+
+/// Hashes every stored asset as `verify` does. Returns bytes hashed and
+/// mismatches.
+fn verify(source: &mut dyn Source) -> Result<(u64, usize)> {
+    let (mut bytes, mut mismatches) = (0, 0);
+    for i in 0..source.assets() {
+        let Some((data, hash)) = source.asset(i)? else {
+            continue;
+        };
+        bytes += data.len() as u64;
+        if xxh3_64(data) != hash {
+            mismatches += 1;
+        }
+    }
+    Ok((bytes, mismatches))
+}
+
+fn percentile(sorted: &[Duration], p: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    micros(sorted[(sorted.len() - 1) * p / 100])
+}
+
+fn micros(d: Duration) -> f64 {
+    d.as_secs_f64() * 1e6
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn per_sec(bytes: u64, d: Duration) -> f64 {
+    bytes as f64 / d.as_secs_f64().max(1e-9)
+}
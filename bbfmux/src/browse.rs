@@ -0,0 +1,607 @@
+//! `bbfmux browse`: an interactive terminal viewer for a single book.
+
+use crate::preview::{self, Graphics, HalfBlocks};
+use crate::{report, transcode};
+use anyhow::Result;
+use bbf::{BBFMediaType, BBFReader};
+use image::RgbImage;
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Margin, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Clear, List, ListItem, ListState, Paragraph, Wrap};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashSet;
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Sections,
+    Pages,
+}
+
+/// One visible line of the section tree. Row 0 is always "All pages".
+struct SectionRow {
+    section: Option<u32>,
+    title: String,
+    depth: usize,
+    pages: Range<u32>,
+    has_children: bool,
+}
+
+struct Verification {
+    index_ok: bool,
+    bad_assets: HashSet<u32>,
+}
+
+/// A decoded preview, keyed by the page and the area it was fitted to.
+struct Cached {
+    page: u32,
+    area: Rect,
+    image: Option<(RgbImage, Rect)>,
+}
+
+struct App<'a, T: AsRef<[u8]>> {
+    reader: &'a BBFReader<T>,
+    data: &'a [u8],
+    title: String,
+    tree: Vec<report::SectionNode>,
+    owners: Vec<Option<usize>>,
+    collapsed: HashSet<u32>,
+    rows: Vec<SectionRow>,
+    focus: Pane,
+    sections_state: ListState,
+    pages_state: ListState,
+    verification: Option<Verification>,
+    show_metadata: bool,
+    graphics: Graphics,
+    preview_on: bool,
+    preview_area: Option<Rect>,
+    cached: Option<Cached>,
+    emitted: Option<(u32, Rect)>,
+    message: Option<String>,
+}
+
+pub fn run<T: AsRef<[u8]> + Sync>(
+    reader: &BBFReader<T>,
+    data: &[u8],
+    title: String,
+    graphics: Graphics,
+) -> Result<()> {
+    let mut app = App {
+        reader,
+        data,
+        title,
+        tree: report::section_tree(reader),
+        owners: crate::owning_sections(reader),
+        collapsed: HashSet::new(),
+        rows: Vec::new(),
+        focus: Pane::Pages,
+        sections_state: ListState::default().with_selected(Some(0)),
+        pages_state: ListState::default().with_selected(Some(0)),
+        verification: None,
+        show_metadata: false,
+        graphics: graphics.detect(),
+        preview_on: graphics != Graphics::None,
+        preview_area: None,
+        cached: None,
+        emitted: None,
+        message: None,
+    };
+    app.rebuild_rows();
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    if app.graphics == Graphics::Kitty {
+        let _ = preview::clear_kitty(terminal.backend_mut());
+    }
+    ratatui::restore();
+    result
+}
+
+impl<T: AsRef<[u8]> + Sync> App<'_, T> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.render(frame))?;
+            self.sync_escape_preview(terminal)?;
+
+            match event::read()? {
+                Event::Key(key)
+                    if key.kind == KeyEventKind::Press && !self.handle_key(key.code) =>
+                {
+                    return Ok(());
+                }
+                Event::Resize(..) => terminal.clear()?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns `false` when the user asked to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        self.message = None;
+        if self.show_metadata {
+            self.show_metadata = false;
+            return !matches!(code, KeyCode::Char('q'));
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Pane::Sections => Pane::Pages,
+                    Pane::Pages => Pane::Sections,
+                };
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-10),
+            KeyCode::PageDown => self.move_selection(10),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::Left | KeyCode::Char('h') => self.fold(true),
+            KeyCode::Right | KeyCode::Char('l') => self.fold(false),
+            KeyCode::Enter if self.focus == Pane::Sections => self.focus = Pane::Pages,
+            KeyCode::Char('v') => self.verify(),
+            KeyCode::Char('m') => self.show_metadata = true,
+            KeyCode::Char('p') => self.preview_on = !self.preview_on,
+            _ => {}
+        }
+        true
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let len = match self.focus {
+            Pane::Sections => self.rows.len(),
+            Pane::Pages => self.page_range().len(),
+        };
+        let state = match self.focus {
+            Pane::Sections => &mut self.sections_state,
+            Pane::Pages => &mut self.pages_state,
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0);
+        let next = current.saturating_add_signed(delta).min(len - 1);
+        state.select(Some(next));
+
+        if self.focus == Pane::Sections {
+            self.pages_state.select(Some(0));
+        }
+    }
+
+    /// Collapses or expands the selected section. Outside the section pane the
+    /// arrows page through the book instead.
+    fn fold(&mut self, collapse: bool) {
+        if self.focus == Pane::Pages {
+            self.move_selection(if collapse { -1 } else { 1 });
+            return;
+        }
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let (Some(section), true) = (row.section, row.has_children) else {
+            return;
+        };
+        let changed = if collapse {
+            self.collapsed.insert(section)
+        } else {
+            self.collapsed.remove(&section)
+        };
+        if changed {
+            self.rebuild_rows();
+        }
+    }
+
+    fn verify(&mut self) {
+        let index_ok = crate::index_hash_check(self.data, self.reader).is_ok_and(|c| c.ok);
+        let assets = self.reader.assets();
+        let bad_assets: HashSet<u32> = (0..assets.len())
+            .into_par_iter()
+            .map(|i| crate::asset_check(self.data, self.reader, i))
+            .filter(|c| !c.ok)
+            .map(|c| c.index)
+            .collect();
+
+        self.message = Some(match (index_ok, bad_assets.len()) {
+            (true, 0) => format!("All {} assets and the index hash verified.", assets.len()),
+            (true, n) => format!("{n} corrupt asset(s); corrupt pages are marked in red."),
+            (false, 0) => "Index hash CORRUPT; all assets verified.".to_string(),
+            (false, n) => format!("Index hash CORRUPT and {n} corrupt asset(s)."),
+        });
+        self.verification = Some(Verification {
+            index_ok,
+            bad_assets,
+        });
+    }
+
+    fn rebuild_rows(&mut self) {
+        let page_count = self.reader.pages().len() as u32;
+        let mut rows = vec![SectionRow {
+            section: None,
+            title: "All pages".to_string(),
+            depth: 0,
+            pages: 0..page_count,
+            has_children: false,
+        }];
+
+        let starts: Vec<u32> = self
+            .reader
+            .sections()
+            .iter()
+            .map(|s| s.section_start_index.get())
+            .collect();
+        for node in &self.tree {
+            push_rows(&mut rows, node, 0, &starts, page_count, &self.collapsed);
+        }
+
+        self.rows = rows;
+        let selected = self.sections_state.selected().unwrap_or(0);
+        self.sections_state
+            .select(Some(selected.min(self.rows.len() - 1)));
+    }
+
+    fn selected_row(&self) -> Option<&SectionRow> {
+        self.rows.get(self.sections_state.selected().unwrap_or(0))
+    }
+
+    fn page_range(&self) -> Range<usize> {
+        self.selected_row()
+            .map_or(0..0, |r| r.pages.start as usize..r.pages.end as usize)
+    }
+
+    fn selected_page(&self) -> Option<u32> {
+        let range = self.page_range();
+        let page = range.start + self.pages_state.selected().unwrap_or(0);
+        range.contains(&page).then_some(page as u32)
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+        let [sections, pages] =
+            Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(left);
+        let [details, preview] =
+            Layout::vertical([Constraint::Length(9), Constraint::Min(0)]).areas(right);
+
+        self.render_sections(frame, sections);
+        self.render_pages(frame, pages);
+        self.render_details(frame, details);
+        self.render_preview(frame, preview);
+        self.render_status(frame, status);
+
+        if self.show_metadata {
+            self.render_metadata(frame, main);
+        }
+    }
+
+    fn pane_block(&self, pane: Pane, title: String) -> Block<'static> {
+        let style = if self.focus == pane {
+            Style::new().fg(Color::Cyan)
+        } else {
+            Style::new().fg(Color::DarkGray)
+        };
+        Block::bordered().title(title).border_style(style)
+    }
+
+    fn render_sections(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let marker = match (row.has_children, row.section) {
+                    (true, Some(s)) if self.collapsed.contains(&s) => "▸ ",
+                    (true, _) => "▾ ",
+                    _ => "  ",
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}{marker}{}", "  ".repeat(row.depth), row.title)),
+                    Span::raw(format!("  {}-{}", row.pages.start + 1, row.pages.end)).dark_gray(),
+                ]))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(self.pane_block(Pane::Sections, format!(" {} ", self.title)))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.sections_state);
+    }
+
+    fn render_pages(&mut self, frame: &mut Frame, area: Rect) {
+        let assets = self.reader.assets();
+        let pages = self.reader.pages();
+        let items: Vec<ListItem> = self
+            .page_range()
+            .map(|i| {
+                let asset = pages[i].asset_index.get();
+                let entry = assets.get(asset as usize);
+                let bad = self
+                    .verification
+                    .as_ref()
+                    .is_some_and(|v| v.bad_assets.contains(&asset));
+                let line = format!(
+                    "{:>5}  {:<4} {:>10}  asset {asset}",
+                    i + 1,
+                    report::media_type_name(entry.map_or(0, |a| a.type_)),
+                    entry.map_or_else(String::new, |a| report::human_size(a.length.get())),
+                );
+                if bad {
+                    ListItem::new(line).red()
+                } else {
+                    ListItem::new(line)
+                }
+            })
+            .collect();
+
+        let title = format!(" Pages ({}) ", items.len());
+        let list = List::new(items)
+            .block(self.pane_block(Pane::Pages, title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.pages_state);
+    }
+
+    fn render_details(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered()
+            .title(" Page ")
+            .border_style(Style::new().fg(Color::DarkGray));
+        let Some(page) = self.selected_page() else {
+            frame.render_widget(Paragraph::new("No page selected").block(block), area);
+            return;
+        };
+
+        let entry = &self.reader.pages()[page as usize];
+        let asset_index = entry.asset_index.get();
+        let asset = self.reader.assets().get(asset_index as usize);
+        let field = |name: &'static str, value: String| {
+            Line::from(vec![
+                Span::raw(format!("{name:<10}")).bold(),
+                Span::raw(value),
+            ])
+        };
+
+        let integrity = match &self.verification {
+            None => "not checked (press v)".to_string(),
+            Some(v) if v.bad_assets.contains(&asset_index) => "CORRUPT".to_string(),
+            Some(_) => "OK".to_string(),
+        };
+        let lines = vec![
+            field(
+                "Page",
+                format!("{} of {}", page + 1, self.reader.pages().len()),
+            ),
+            field("Section", self.section_path(page)),
+            field(
+                "Asset",
+                asset.map_or_else(
+                    || format!("{asset_index} (out of range)"),
+                    |a| {
+                        format!(
+                            "{asset_index}  {}  {}",
+                            report::media_type_name(a.type_),
+                            report::human_size(a.length.get())
+                        )
+                    },
+                ),
+            ),
+            field(
+                "Hash",
+                asset.map_or_else(String::new, |a| format!("{:016x}", a.xxh3_hash.get())),
+            ),
+            field("Flags", format!("{:#x}", entry.flags.get())),
+            field(
+                "Thumbnail",
+                self.reader
+                    .thumbnail(page)
+                    .map_or_else(|| "none".to_string(), |t| format!("asset {t}")),
+            ),
+            field("Integrity", integrity),
+        ];
+        frame.render_widget(Paragraph::new(lines).block(block), area);
+    }
+
+    fn render_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::bordered()
+            .title(" Preview ")
+            .border_style(Style::new().fg(Color::DarkGray));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        self.preview_area = None;
+
+        let Some(page) = self.selected_page() else {
+            return;
+        };
+        if !self.preview_on || self.graphics == Graphics::None {
+            frame.render_widget(Paragraph::new("Preview off (press p)").dark_gray(), inner);
+            return;
+        }
+
+        let asset = self.preview_asset(page);
+        let media_type = self
+            .reader
+            .assets()
+            .get(asset as usize)
+            .map_or(BBFMediaType::Unknown, |a| BBFMediaType::from(a.type_));
+        if !transcode::is_decodable(media_type) {
+            let name = media_type.as_extension().trim_start_matches('.');
+            frame.render_widget(
+                Paragraph::new(format!("No preview for {name} pages")).dark_gray(),
+                inner,
+            );
+            return;
+        }
+
+        if self.graphics.is_escape_based() {
+            self.preview_area = Some(inner);
+        } else if let Some((img, cells)) = self.fitted(page, inner) {
+            frame.render_widget(HalfBlocks(img), *cells);
+        }
+    }
+
+    fn render_status(&self, frame: &mut Frame, area: Rect) {
+        let text = self.message.clone().unwrap_or_else(|| {
+            let index = match &self.verification {
+                Some(v) if !v.index_ok => "  [index hash CORRUPT]",
+                _ => "",
+            };
+            format!("Tab pane  ↑↓ move  ←→ fold  v verify  m metadata  p preview  q quit{index}")
+        });
+        frame.render_widget(Paragraph::new(text).reversed(), area);
+    }
+
+    fn render_metadata(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = report::metadata(self.reader)
+            .into_iter()
+            .map(|m| {
+                Line::from(vec![
+                    Span::raw(format!("{}: ", m.key)).bold(),
+                    Span::raw(m.value),
+                ])
+            })
+            .collect();
+        let lines = if lines.is_empty() {
+            vec![Line::from("No metadata").dark_gray()]
+        } else {
+            lines
+        };
+
+        let popup = area.inner(Margin::new(area.width / 6, area.height / 6));
+        frame.render_widget(Clear, popup);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(" Metadata (any key to close) ")),
+            popup,
+        );
+    }
+
+    /// Half-block previews are coarse, so an embedded thumbnail is just as
+    /// good and much cheaper to decode than the full page.
+    fn preview_asset(&self, page: u32) -> u32 {
+        let thumbnail = match self.graphics {
+            Graphics::Blocks => self.reader.thumbnail(page),
+            _ => None,
+        };
+        thumbnail.unwrap_or_else(|| self.reader.pages()[page as usize].asset_index.get())
+    }
+
+    fn fitted(&mut self, page: u32, area: Rect) -> Option<&(RgbImage, Rect)> {
+        let stale = self
+            .cached
+            .as_ref()
+            .is_none_or(|c| c.page != page || c.area != area);
+        if stale {
+            let image = self
+                .reader
+                .get_asset_decoded(self.preview_asset(page))
+                .ok()
+                .and_then(|data| preview::fit(&data, area, self.graphics));
+            self.cached = Some(Cached { page, area, image });
+        }
+        self.cached.as_ref()?.image.as_ref()
+    }
+
+    /// Kitty and sixel images live outside ratatui's buffer, so they are
+    /// (re)emitted after a frame whenever the page or the layout changed.
+    fn sync_escape_preview(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let wanted = match (self.preview_area, self.selected_page()) {
+            (Some(area), Some(page)) if !self.show_metadata => Some((page, area)),
+            _ => None,
+        };
+        if wanted == self.emitted {
+            return Ok(());
+        }
+
+        if self.emitted.take().is_some() {
+            if self.graphics == Graphics::Kitty {
+                preview::clear_kitty(terminal.backend_mut())?;
+            } else {
+                // Sixel pixels stay until the cells under them are redrawn.
+                terminal.clear()?;
+                terminal.draw(|frame| self.render(frame))?;
+            }
+        }
+
+        if let Some((page, area)) = wanted {
+            let graphics = self.graphics;
+            if let Some((img, cells)) = self.fitted(page, area) {
+                preview::emit(terminal.backend_mut(), graphics, img, *cells)?;
+            }
+            self.emitted = wanted;
+        }
+        Ok(())
+    }
+
+    fn section_path(&self, page: u32) -> String {
+        let sections = self.reader.sections();
+        let mut path = Vec::new();
+        let mut current = self.owners.get(page as usize).copied().flatten();
+        while let Some(idx) = current {
+            if path.len() > sections.len() {
+                break;
+            }
+            let s = &sections[idx];
+            path.push(
+                self.reader
+                    .get_string(s.section_title_offset.get())
+                    .unwrap_or(""),
+            );
+            let parent = s.parent_section_index.get() as usize;
+            current = (parent < sections.len() && parent != idx).then_some(parent);
+        }
+
+        if path.is_empty() {
+            return "-".to_string();
+        }
+        path.reverse();
+        path.join(" / ")
+    }
+}
+
+/// A section covers pages from its start up to the next section that starts
+/// later and isn't one of its own descendants.
+fn push_rows(
+    rows: &mut Vec<SectionRow>,
+    node: &report::SectionNode,
+    depth: usize,
+    starts: &[u32],
+    page_count: u32,
+    collapsed: &HashSet<u32>,
+) {
+    let mut own = HashSet::new();
+    collect_indices(node, &mut own);
+    let end = starts
+        .iter()
+        .enumerate()
+        .filter(|&(i, &s)| s > node.start_index && !own.contains(&(i as u32)))
+        .map(|(_, &s)| s)
+        .min()
+        .unwrap_or(page_count)
+        .min(page_count);
+
+    rows.push(SectionRow {
+        section: Some(node.index),
+        title: node.title.clone(),
+        depth,
+        pages: node.start_index.min(end)..end,
+        has_children: !node.children.is_empty(),
+    });
+
+    if !collapsed.contains(&node.index) {
+        for child in &node.children {
+            push_rows(rows, child, depth + 1, starts, page_count, collapsed);
+        }
+    }
+}
+
+fn collect_indices(node: &report::SectionNode, out: &mut HashSet<u32>) {
+    out.insert(node.index);
+    for child in &node.children {
+        collect_indices(child, out);
+    }
+}
@@ -0,0 +1,586 @@
+//! Command-line definitions. Parsing, `completions` and `man` all come
+//! from these types, so a flag added here shows up everywhere.
+
+use crate::{exit, preview, transcode};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None, after_help = exit::HELP)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cli {
+    /// Input files or directories
+    #[arg(value_name = "INPUTS")]
+    pub inputs: Vec<PathBuf>,
+
+    /// Output filename (default: output.bbf). `{Key}` placeholders are
+    /// filled from the book's metadata, e.g. `-o '{Series} v{Volume:02}.bbf'`
+    #[arg(short, long, default_value = "output.bbf")]
+    pub output: String,
+
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    // --- Muxing Flags ---
+    /// Use a text file to define page order (see `order-template`)
+    #[arg(long)]
+    pub order: Option<PathBuf>,
+
+    /// Use a text file to define multiple sections (Name:Target[:Parent])
+    #[arg(long)]
+    pub sections: Option<PathBuf>,
+
+    /// Add a single section marker (Name:Target[:Parent])
+    #[arg(long)]
+    pub section: Vec<String>,
+
+    /// Create nested sections from the folders inputs were found in
+    /// (implies --recursive)
+    #[arg(long)]
+    pub sections_from_dirs: bool,
+
+    /// Order sections by their first page instead of the order given
+    #[arg(long)]
+    pub sort_sections: bool,
+
+    /// Store section titles in Unicode NFC form, so titles taken from
+    /// decomposed (NFD) file names match ones typed elsewhere
+    #[arg(long)]
+    pub normalize_titles: bool,
+
+    /// Add archival metadata (Key:Value)
+    #[arg(long)]
+    pub meta: Vec<String>,
+
+    #[command(flatten)]
+    pub input_opts: InputOpts,
+
+    /// Store pages zstd-compressed, e.g. `zstd` or `zstd:19`. Pages that
+    /// don't shrink are stored as-is.
+    #[arg(long, value_name = "zstd[:LEVEL]", value_parser = parse_compress)]
+    pub compress: Option<i32>,
+
+    /// Only compress pages of these media types (e.g. bmp,tiff,png)
+    #[arg(long, value_delimiter = ',', requires = "compress")]
+    pub compress_only: Vec<String>,
+
+    /// Mark inputs matching a file name or glob as two-page spreads
+    #[arg(long, value_name = "PATTERN")]
+    pub spread: Vec<String>,
+
+    /// Rotate inputs matching a pattern clockwise by 90, 180 or 270 degrees
+    #[arg(long, num_args = 2, value_names = ["DEGREES", "PATTERN"])]
+    pub rotate: Vec<String>,
+
+    /// Mark the input with this file name as the cover
+    #[arg(long, value_name = "FILE")]
+    pub cover: Option<String>,
+
+    /// Keep running and rebuild the output whenever an input changes
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Don't import metadata from ComicInfo.xml or metadata.json files
+    /// found among the inputs
+    #[arg(long)]
+    pub no_sidecar: bool,
+
+    /// Print the page order, sections, dedupe and output size without
+    /// writing anything
+    #[arg(long, conflicts_with = "watch")]
+    pub dry_run: bool,
+
+    /// Worker threads for reading, hashing, encoding and extracting (default: one per core)
+    #[arg(long, global = true)]
+    pub threads: Option<usize>,
+
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print more detail; repeat for debug output
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+}
+
+#[derive(Args, Clone, Copy)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct InputOpts {
+    /// How to order input files that have no explicit --order entry
+    #[arg(long, value_enum, default_value = "natural")]
+    pub sort: SortMode,
+
+    /// Descend into subdirectories of input directories
+    #[arg(short, long)]
+    pub recursive: bool,
+
+    /// Skip hidden files and OS metadata files (desktop.ini, Thumbs.db, ...)
+    #[arg(long)]
+    pub skip_hidden: bool,
+
+    /// Fail on input problems that are otherwise warned about and worked
+    /// around: unsupported file types, unmatched patterns, section targets
+    /// or order entries that aren't inputs, and malformed sidecars
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Record each input's modification time with its page; `extract`
+    /// restores it
+    #[arg(long)]
+    pub preserve_times: bool,
+}
+
+/// What `extract` writes and how it names it.
+#[derive(Args)]
+pub struct ExtractOpts {
+    /// Write each distinct asset once, named by asset index, instead of
+    /// one file per page
+    #[arg(long)]
+    pub assets: bool,
+
+    /// File name pattern, e.g. "{section}/{page:04}{ext}". Variables:
+    /// {page} (1-based), {index} (0-based), {section}, {name} (original
+    /// file name without extension, or pN), {hash}, {asset}, {ext}
+    #[arg(long)]
+    pub name_template: Option<String>,
+
+    /// Also write metadata.json, sections.txt and order.txt, so that
+    /// `bbfmux DIR --order DIR/order.txt --sections DIR/sections.txt`
+    /// rebuilds the book with its metadata and table of contents
+    #[arg(long, conflicts_with = "assets")]
+    pub with_sidecars: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Print an order file listing inputs in their current sort order
+    OrderTemplate {
+        /// Input files or directories
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        #[command(flatten)]
+        input_opts: InputOpts,
+    },
+    /// Display book structure and metadata, or the volumes of a .bbfpack
+    Info {
+        file: PathBuf,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Perform integrity check on assets
+    Verify {
+        file: PathBuf,
+        /// Optional specific asset index to verify.
+        /// -1 verifies directory hash only.
+        /// Omission verifies everything.
+        index: Option<i32>,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+        /// Rewrite a stale index hash if every asset checks out
+        #[arg(long, conflicts_with_all = ["index", "json"])]
+        repair: bool,
+        /// Restore the footer from a backup copy of the same book
+        #[arg(long, requires = "repair")]
+        footer_from: Option<PathBuf>,
+        /// Cross-check asset hashes against a list written by `hashes`
+        #[arg(long, conflicts_with_all = ["index", "json", "repair"])]
+        manifest: Option<PathBuf>,
+    },
+    /// Check a book against a conformance profile
+    Validate {
+        file: PathBuf,
+        #[arg(long, value_enum, default_value = "standard")]
+        profile: ValidateProfile,
+        /// Require asset offsets to be multiples of this (0 disables;
+        /// default: 4096 for archival, off otherwise)
+        #[arg(long)]
+        alignment: Option<u64>,
+        /// Skip hashing assets and the index
+        #[arg(long)]
+        no_hashes: bool,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print every asset's XXH3 hash in a sha256sum-like format
+    Hashes { file: PathBuf },
+    /// Break down where a book's bytes go
+    Stats {
+        file: PathBuf,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+        /// How many of the largest pages to list
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+    /// Measure open time, page reads and verify speed with each storage backend
+    Bench {
+        file: PathBuf,
+        /// Keep the best of this many runs of each measurement
+        #[arg(long, default_value_t = 3)]
+        rounds: usize,
+        /// Number of random page reads to time
+        #[arg(long, default_value_t = 1000)]
+        samples: usize,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// List every page with its asset, type, size, and owning section
+    List {
+        file: PathBuf,
+        /// Only show pages inside this section (including its subsections)
+        #[arg(long)]
+        section: Option<String>,
+        /// Only show pages of this media type (e.g. jpg, png)
+        #[arg(long = "type")]
+        media_type: Option<String>,
+    },
+    /// Browse a book interactively in the terminal
+    Browse {
+        file: PathBuf,
+        /// How to draw page previews
+        #[arg(long, value_enum, default_value = "auto")]
+        graphics: preview::Graphics,
+    },
+    /// Build one book per subdirectory of DIR, several at a time. Mux
+    /// options given before `batch` apply to every book
+    Batch {
+        dir: PathBuf,
+        /// Directory to write the books to (created if missing)
+        #[arg(long)]
+        outdir: PathBuf,
+        /// Book file name: `{dir}` is the subdirectory's name, other `{Key}`
+        /// placeholders come from the book's metadata
+        #[arg(long, default_value = "{dir}.bbf")]
+        name: String,
+    },
+    /// Write an OPDS catalog (1.2 Atom and 2.0 JSON) of every book under
+    /// DIR, with cover images, for reading apps
+    Opds {
+        dir: PathBuf,
+        /// Directory to write the catalog to (created if missing)
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Title of the catalog
+        #[arg(long, default_value = "Library")]
+        title: String,
+        /// URL DIR is served at, for absolute book links (default: links
+        /// relative to the catalog)
+        #[arg(long)]
+        base_url: Option<String>,
+    },
+    /// Serve a book over HTTP with a minimal reading UI and page API
+    Serve {
+        file: PathBuf,
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Address to bind (use 0.0.0.0 to allow other machines)
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+    },
+    /// Compare two BBF files page by page
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Extract content from a BBF file
+    Extract {
+        file: PathBuf,
+        /// Output directory
+        #[arg(long, default_value = "./extracted")]
+        outdir: PathBuf,
+        /// Extract only a specific section
+        #[arg(long)]
+        section: Option<String>,
+        /// Stop extraction when next section title matches this string
+        #[arg(long)]
+        rangekey: Option<String>,
+        /// Extract only these pages, e.g. "1-10,15,20-" (1-based)
+        #[arg(long, conflicts_with_all = ["section", "rangekey"])]
+        pages: Option<String>,
+        #[command(flatten)]
+        output: ExtractOpts,
+    },
+    /// Convert a PDF into a BBF file
+    Convert {
+        file: PathBuf,
+        /// Output filename (default: input name with a .bbf extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Resolution used when pages have to be rasterized
+        #[arg(long, default_value_t = 150)]
+        dpi: u32,
+        /// Always rasterize, even if page images could be extracted losslessly
+        #[arg(long)]
+        rasterize: bool,
+    },
+    /// Export a BBF file to another container format
+    Export {
+        file: PathBuf,
+        /// Target format
+        #[arg(long, value_enum)]
+        format: ExportFormat,
+        /// Output filename (default: input name with the format's extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Rewrite a BBF file, dropping duplicate and orphaned assets
+    ///
+    /// Overlapping assets are separated, and pages or sections that point
+    /// nowhere are dropped. Pages keep their compression unless --compress
+    /// is given.
+    Repack {
+        file: PathBuf,
+        /// Output filename
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Byte boundary to align assets to (0 or 1 disables padding)
+        #[arg(long, default_value_t = 4096)]
+        align: u64,
+        /// Store external assets in the book, reading them from files next
+        /// to it
+        #[arg(long)]
+        internalize: bool,
+        /// Compress pages again with zstd, e.g. `zstd` or `zstd:19`. Without
+        /// it, pages keep the compression they're stored with.
+        #[arg(long, value_name = "zstd[:LEVEL]", value_parser = parse_compress)]
+        compress: Option<i32>,
+        /// Only compress pages of these media types (e.g. bmp,tiff,png)
+        #[arg(long, value_delimiter = ',', requires = "compress")]
+        compress_only: Vec<String>,
+    },
+    /// Re-encode every raster page into another image format
+    Transcode {
+        file: PathBuf,
+        /// Target image format
+        #[arg(long, value_enum)]
+        to: transcode::TargetFormat,
+        /// Encoder quality for lossy formats (1-100)
+        #[arg(long, default_value_t = 80, value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+        /// Keep a page's original encoding when it is already smaller
+        #[arg(long)]
+        skip_if_smaller: bool,
+        /// Output filename
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Generate page previews and embed them (or write them to a directory)
+    Thumbs {
+        file: PathBuf,
+        /// Longest side of a thumbnail in pixels
+        #[arg(long, default_value_t = 320)]
+        max: u32,
+        /// Image format of the thumbnails
+        #[arg(long, value_enum, default_value = "jpg")]
+        format: transcode::TargetFormat,
+        /// Encoder quality for lossy formats (1-100)
+        #[arg(long, default_value_t = 75, value_parser = clap::value_parser!(u8).range(1..=100))]
+        quality: u8,
+        /// Write thumbnails to this directory instead of embedding them
+        #[arg(long)]
+        outdir: Option<PathBuf>,
+    },
+    /// Append pages to an existing BBF file in place
+    Append {
+        file: PathBuf,
+        /// Input files or directories to append
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Start a new section at the first appended page
+        #[arg(long)]
+        section: Option<String>,
+        /// Title of an existing section to nest the new section under
+        #[arg(long, requires = "section")]
+        parent: Option<String>,
+        #[command(flatten)]
+        input_opts: InputOpts,
+    },
+    /// Concatenate several BBF files into one
+    Merge {
+        /// Books to merge, in reading order
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+        /// Output filename
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Nest each book's sections under a section named after its
+        /// Title metadata (or file name if it has none)
+        #[arg(long)]
+        nest: bool,
+    },
+    /// Experimental: store several books in one .bbfpack that shares
+    /// pages repeated across them
+    Pack {
+        /// Books to pack; each becomes a volume named after its file
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Output filename
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Remove pages from a BBF file, shifting sections to match
+    Rm {
+        file: PathBuf,
+        /// Pages to drop, 1-based (e.g. 5,7,10-12)
+        #[arg(long)]
+        pages: String,
+        /// Output filename
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Show which page is the cover, flag another one in place, or write
+    /// the cover image out. Books without a flagged cover use page 1
+    Cover {
+        file: PathBuf,
+        /// Flag this page (1-based) as the cover, clearing the flag elsewhere
+        #[arg(long)]
+        set: Option<u32>,
+        /// Write the cover image, as stored, to this file
+        #[arg(long, conflicts_with = "set")]
+        extract: Option<PathBuf>,
+    },
+    /// View or edit metadata in place
+    Meta {
+        #[command(subcommand)]
+        action: MetaAction,
+    },
+    /// Edit the table of contents in place
+    Section {
+        #[command(subcommand)]
+        action: SectionAction,
+    },
+    /// Print a shell completion script to stdout
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+    /// Print the manual page (roff) to stdout, or write one page per
+    /// subcommand into a directory
+    Man {
+        #[arg(long, value_name = "DIR")]
+        outdir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MetaAction {
+    /// Set a key, replacing any existing values
+    Set {
+        file: PathBuf,
+        key: String,
+        value: String,
+    },
+    /// Remove every entry with the given key
+    Del { file: PathBuf, key: String },
+    /// Print all metadata entries
+    List {
+        file: PathBuf,
+        /// Emit JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SectionAction {
+    /// Add a section starting at a page
+    Add {
+        file: PathBuf,
+        title: String,
+        /// First page of the section, 1-based
+        #[arg(long)]
+        page: u32,
+        /// Title of the parent section
+        #[arg(long)]
+        parent: Option<String>,
+    },
+    /// Change a section's title
+    Rename {
+        file: PathBuf,
+        title: String,
+        new_title: String,
+    },
+    /// Remove a section; its subsections move up to its parent
+    Remove { file: PathBuf, title: String },
+    /// Change where a section starts or which section it belongs to
+    Move {
+        file: PathBuf,
+        title: String,
+        /// New first page, 1-based
+        #[arg(long, required_unless_present_any = ["parent", "top_level"])]
+        page: Option<u32>,
+        /// Title of the new parent section
+        #[arg(long, conflicts_with = "top_level")]
+        parent: Option<String>,
+        /// Make it a top-level section
+        #[arg(long)]
+        top_level: bool,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ValidateProfile {
+    /// Structure, strings, references and hashes
+    Standard,
+    /// Standard, plus aligned assets and a Title; warnings fail
+    Archival,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// Comic book zip with a generated ComicInfo.xml
+    Cbz,
+    /// Single offline HTML file with the pages embedded and a small reader
+    Html,
+}
+
+impl ExportFormat {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Cbz => "cbz",
+            Self::Html => "html",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortMode {
+    /// Compare digit runs by value, so page2 comes before page10
+    Natural,
+    /// Plain byte-wise file name order
+    Lexical,
+    /// Oldest modification time first
+    Mtime,
+    /// Keep the order inputs were given in
+    None,
+}
+
+/// Parses `zstd` or `zstd:LEVEL` into a zstd level.
+fn parse_compress(s: &str) -> Result<i32, String> {
+    let (method, level) = s.split_once(':').unwrap_or((s, ""));
+    if method != "zstd" {
+        return Err(format!(
+            "unsupported compression '{method}' (expected zstd)"
+        ));
+    }
+    if level.is_empty() {
+        return Ok(zstd_default_level());
+    }
+    match level.parse() {
+        Ok(level @ 1..=22) => Ok(level),
+        _ => Err(format!("zstd level must be 1-22, not '{level}'")),
+    }
+}
+
+const fn zstd_default_level() -> i32 {
+    3
+}
@@ -0,0 +1,78 @@
+//! Process exit codes. These are part of the CLI's contract: scripts match on
+//! them, so add new codes rather than renumbering.
+
+use bbf::reader::BBFError;
+use std::fmt;
+use std::io;
+use std::process::ExitCode;
+
+/// Shown at the end of `--help`.
+pub const HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Any other failure
+  2  Invalid command-line arguments
+  3  A file could not be found or opened
+  4  A file is not a valid BBF book, or its structure is damaged
+  5  An integrity or conformance check failed (`verify`, `validate`)
+  6  `diff` found differences between the books";
+
+pub const NOT_FOUND: u8 = 3;
+pub const INVALID_BOOK: u8 = 4;
+pub const INTEGRITY: u8 = 5;
+pub const DIFFERS: u8 = 6;
+
+/// Returned by `verify` and `validate` when they could read the book but
+/// found problems, so scripts can tell a corrupt asset apart from an
+/// unreadable file.
+#[derive(Debug)]
+pub struct IntegrityFailure(pub &'static str);
+
+impl fmt::Display for IntegrityFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl std::error::Error for IntegrityFailure {}
+
+/// Returned by `diff` when the books differ, like diff(1)'s non-zero exit.
+/// It has already said how, so it isn't logged as an error.
+#[derive(Debug)]
+pub struct Differs;
+
+impl fmt::Display for Differs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The books differ")
+    }
+}
+
+impl std::error::Error for Differs {}
+
+/// Picks the exit code for `err` from the first cause that has a class.
+pub fn code(err: &anyhow::Error) -> ExitCode {
+    for cause in err.chain() {
+        if cause.is::<IntegrityFailure>() {
+            return ExitCode::from(INTEGRITY);
+        }
+        if cause.is::<Differs>() {
+            return ExitCode::from(DIFFERS);
+        }
+        if cause.is::<BBFError>() {
+            return ExitCode::from(INVALID_BOOK);
+        }
+        if let Some(e) = cause.downcast_ref::<io::Error>() {
+            match e.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => {
+                    return ExitCode::from(NOT_FOUND);
+                }
+                // What `BBFBuilder::from_existing` reports for a bad index.
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                    return ExitCode::from(INVALID_BOOK);
+                }
+                _ => {}
+            }
+        }
+    }
+    ExitCode::FAILURE
+}
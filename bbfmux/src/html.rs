@@ -0,0 +1,57 @@
+//! `bbfmux export --format html`: a single offline HTML file with every page
+//! embedded as a data URI and the `serve` reading UI driven from it.
+
+use crate::{report, serve};
+use anyhow::{Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::write::EncoderWriter;
+use bbf::{BBFMediaType, BBFReader};
+use std::io::Write;
+
+const VIEWER_HTML: &str = include_str!("html.html");
+const BOOK_MARKER: &str = "<!-- book -->";
+
+/// Writes the viewer with the book inlined. Each asset a page uses is
+/// embedded once, so deduplicated pages stay deduplicated. Returns the
+/// number of pages written.
+pub fn write_html<T: AsRef<[u8]>, W: Write>(reader: &BBFReader<T>, mut out: W) -> Result<usize> {
+    let (head, tail) = VIEWER_HTML
+        .split_once(BOOK_MARKER)
+        .expect("viewer template has a book marker");
+
+    // "</" inside a string would end the script element early; "<\/" is the
+    // same JSON string.
+    let book_json = serde_json::to_string(&report::book_info(reader))?.replace("</", "<\\/");
+
+    let mut used = vec![false; reader.assets().len()];
+    for p in reader.pages() {
+        if let Some(u) = used.get_mut(p.asset_index.get() as usize) {
+            *u = true;
+        }
+    }
+
+    out.write_all(head.as_bytes())?;
+    write!(out, "<script>\nconst BOOK = {book_json};\nconst ASSETS = [")?;
+    for (i, asset) in reader.assets().iter().enumerate() {
+        if !used[i] {
+            out.write_all(b"null,")?;
+            continue;
+        }
+        let data = reader
+            .get_asset_decoded(i as u32)
+            .with_context(|| format!("Failed to read asset {i}"))?;
+        write!(
+            out,
+            "\n\"data:{};base64,",
+            serve::mime_type(BBFMediaType::from(asset.type_))
+        )?;
+        let mut encoder = EncoderWriter::new(&mut out, &STANDARD);
+        encoder.write_all(&data)?;
+        encoder.finish()?.write_all(b"\",")?;
+    }
+    out.write_all(b"];\n</script>")?;
+    out.write_all(tail.as_bytes())?;
+    out.flush()?;
+
+    Ok(reader.pages().len())
+}
@@ -1,85 +1,105 @@
-#![allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+#![allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+
+mod batch;
+mod bench;
+mod browse;
+mod cli;
+mod exit;
+mod html;
+mod opds;
+mod preview;
+mod report;
+mod serve;
+mod sidecar;
+mod template;
+mod transcode;
+mod watch;
 
 use anyhow::{Context, Result, bail};
-use bbf::{BBFBuilder, BBFMediaType, BBFReader, format::BBFFooter};
-use clap::{Parser, Subcommand};
+use bbf::audit;
+use bbf::builder::{BadSections, Compression};
+use bbf::convert::{self, ConversionPlan, PdfOptions, Progress};
+use bbf::diff;
+use bbf::format::{AssetFlags, BBFAssetEntry, BBFFooter, BBFPageEntry};
+use bbf::hash;
+use bbf::pack::{PackBuilder, PackReader};
+use bbf::reader::{DirResolver, decode_asset, same_title};
+use bbf::thumbs;
+use bbf::validate::{self, Severity};
+use bbf::{BBFBuilder, BBFMediaType, BBFReader};
+use clap::{CommandFactory, Parser};
+use cli::{
+    Cli, Commands, ExportFormat, ExtractOpts, InputOpts, MetaAction, SectionAction, SortMode,
+    ValidateProfile,
+};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{Level, LevelFilter};
 use memmap2::Mmap;
-use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, IsTerminal, Seek, SeekFrom, Write};
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{self, AtomicBool};
+use std::time::{Duration, Instant, SystemTime};
+use tracing_subscriber::filter::LevelFilter as TraceLevel;
+use tracing_subscriber::fmt::format::FmtSpan;
 use xxhash_rust::xxh3::xxh3_64;
-
-#[derive(Parser)]
-#[command(version, about, long_about = None)]
-struct Cli {
-    /// Input files or directories
-    #[arg(value_name = "INPUTS")]
-    inputs: Vec<PathBuf>,
-
-    /// Output filename (default: output.bbf)
-    #[arg(short, long, default_value = "output.bbf")]
-    output: String,
-
-    #[command(subcommand)]
-    command: Option<Commands>,
-
-    // --- Muxing Flags ---
-    /// Use a text file to define page order (filename:index)
-    #[arg(long)]
-    order: Option<PathBuf>,
-
-    /// Use a text file to define multiple sections (Name:Target[:Parent])
-    #[arg(long)]
-    sections: Option<PathBuf>,
-
-    /// Add a single section marker (Name:Target[:Parent])
-    #[arg(long)]
-    section: Vec<String>,
-
-    /// Add archival metadata (Key:Value)
-    #[arg(long)]
-    meta: Vec<String>,
-}
-
-#[derive(Subcommand)]
-enum Commands {
-    /// Display book structure and metadata
-    Info { file: PathBuf },
-    /// Perform integrity check on assets
-    Verify {
-        file: PathBuf,
-        /// Optional specific asset index to verify.
-        /// -1 verifies directory hash only.
-        /// Omission verifies everything.
-        index: Option<i32>,
-    },
-    /// Extract content from a BBF file
-    Extract {
-        file: PathBuf,
-        /// Output directory
-        #[arg(long, default_value = "./extracted")]
-        outdir: PathBuf,
-        /// Extract only a specific section
-        #[arg(long)]
-        section: Option<String>,
-        /// Stop extraction when next section title matches this string
-        #[arg(long)]
-        rangekey: Option<String>,
-    },
+use zerocopy::IntoBytes;
+
+/// Like `println!`, but moves to stderr while a book is written to stdout
+/// and is silenced by `--quiet`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+        } else if $crate::STATUS_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
 }
+pub(crate) use status;
 
 #[derive(Clone, Debug)]
 struct PagePlan {
     path: PathBuf,
     filename: String,
+    /// Path relative to the input it came from; what unordered pages sort by.
+    sort_key: String,
     order: i32, // 0 = unspecified, >0 = start, <0 = end
+    mtime: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct OrderFile {
+    /// (name, position) in file order. Ranges are expanded by `apply_order`.
+    entries: Vec<OrderEntry>,
+    /// Names after `!`, with the line each came from.
+    excluded: HashMap<String, usize>,
+}
+
+enum OrderEntry {
+    Single(String, i32),
+    Range(String, String, i32),
+}
+
+struct PlannedSection {
+    title: String,
+    page: u32,
+    parent: Option<u32>,
 }
 
 struct SectionReq {
+    /// Where the request came from, for diagnostics.
+    origin: String,
     name: String,
     target: String,
     parent: String,
@@ -91,79 +111,400 @@ struct MetaReq {
     value: String,
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Cli::parse();
+    init_logging(&cli);
+
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            if !e.is::<exit::Differs>() {
+                log::error!("{e:#}");
+            }
+            exit::code(&e)
+        }
+    }
+}
+
+fn init_logging(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, _) => LevelFilter::Debug,
+    };
+    QUIET.store(cli.quiet, atomic::Ordering::Relaxed);
+
+    // Dependencies only get to warn; -v is about what bbfmux is doing.
+    env_logger::Builder::new()
+        .filter_level(level.min(LevelFilter::Warn))
+        .filter_module("bbfmux", level)
+        .format(|buf, record| {
+            let prefix = match record.level() {
+                Level::Error => "Error: ",
+                Level::Warn => "Warning: ",
+                _ => "",
+            };
+            writeln!(buf, "{prefix}{}", record.args())
+        })
+        .init();
+
+    // The library reports through `tracing`: what it recovered or skipped at
+    // -v, and at -vv how long finalizing, validating or converting took.
+    if cli.verbose > 0 {
+        let (level, spans) = if cli.verbose == 1 {
+            (TraceLevel::INFO, FmtSpan::NONE)
+        } else {
+            (TraceLevel::DEBUG, FmtSpan::CLOSE)
+        };
+        tracing_subscriber::fmt()
+            .with_writer(io::stderr)
+            .with_max_level(level)
+            .with_span_events(spans)
+            // No timestamps; `without_time` would drop the span timings too.
+            .with_timer(())
+            .init();
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn run(cli: &Cli) -> Result<()> {
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .context("Failed to configure thread pool")?;
+    }
 
     match &cli.command {
-        Some(Commands::Info { file }) => cmd_info(file),
-        Some(Commands::Verify { file, index }) => cmd_verify(file, *index),
+        Some(Commands::OrderTemplate { inputs, input_opts }) => {
+            cmd_order_template(inputs, *input_opts)
+        }
+        Some(Commands::Info { file, json }) => cmd_info(file, *json),
+        Some(Commands::Verify {
+            file,
+            repair: true,
+            footer_from,
+            ..
+        }) => cmd_repair(file, footer_from.as_deref()),
+        Some(Commands::Verify {
+            file,
+            manifest: Some(manifest),
+            ..
+        }) => cmd_verify_manifest(file, manifest),
+        Some(Commands::Verify {
+            file, index, json, ..
+        }) => cmd_verify(file, *index, *json),
+        Some(Commands::Validate {
+            file,
+            profile,
+            alignment,
+            no_hashes,
+            json,
+        }) => cmd_validate(file, *profile, *alignment, !no_hashes, *json),
+        Some(Commands::Hashes { file }) => cmd_hashes(file),
+        Some(Commands::Stats { file, json, top }) => cmd_stats(file, *json, *top),
+        Some(Commands::Bench {
+            file,
+            rounds,
+            samples,
+            json,
+        }) => cmd_bench(file, *rounds, *samples, *json),
+        Some(Commands::List {
+            file,
+            section,
+            media_type,
+        }) => cmd_list(file, section.as_deref(), media_type.as_deref()),
+        Some(Commands::Browse { file, graphics }) => cmd_browse(file, *graphics),
+        Some(Commands::Batch { dir, outdir, name }) => batch::run(cli, dir, outdir, name),
+        Some(Commands::Opds {
+            dir,
+            output,
+            title,
+            base_url,
+        }) => opds::run(dir, output, title, base_url.as_deref()),
+        Some(Commands::Serve { file, port, bind }) => cmd_serve(file, bind, *port),
+        Some(Commands::Diff { old, new, json }) => cmd_diff(old, new, *json),
         Some(Commands::Extract {
             file,
             outdir,
             section,
             rangekey,
-        }) => cmd_extract(file, outdir, section.as_deref(), rangekey.as_deref()),
-        None => cmd_mux(&cli),
+            pages,
+            output,
+        }) => cmd_extract(
+            file,
+            outdir,
+            section.as_deref(),
+            rangekey.as_deref(),
+            pages.as_deref(),
+            output,
+        ),
+        Some(Commands::Convert {
+            file,
+            output,
+            dpi,
+            rasterize,
+        }) => cmd_convert(file, output.as_deref(), *dpi, *rasterize),
+        Some(Commands::Export {
+            file,
+            format,
+            output,
+        }) => cmd_export(file, *format, output.as_deref()),
+        Some(Commands::Repack {
+            file,
+            output,
+            align,
+            internalize,
+            compress,
+            compress_only,
+        }) => cmd_repack(
+            file,
+            output,
+            *align,
+            *internalize,
+            compression(*compress, compress_only)?,
+        ),
+        Some(Commands::Transcode {
+            file,
+            to,
+            quality,
+            skip_if_smaller,
+            output,
+        }) => cmd_transcode(file, *to, *quality, *skip_if_smaller, output),
+        Some(Commands::Thumbs {
+            file,
+            max,
+            format,
+            quality,
+            outdir,
+        }) => cmd_thumbs(file, *max, *format, *quality, outdir.as_deref()),
+        Some(Commands::Append {
+            file,
+            inputs,
+            section,
+            parent,
+            input_opts,
+        }) => cmd_append(
+            file,
+            inputs,
+            section.as_deref(),
+            parent.as_deref(),
+            *input_opts,
+        ),
+        Some(Commands::Merge {
+            files,
+            output,
+            nest,
+        }) => cmd_merge(files, output, *nest),
+        Some(Commands::Pack { files, output }) => cmd_pack(files, output),
+        Some(Commands::Rm {
+            file,
+            pages,
+            output,
+        }) => cmd_rm(file, pages, output),
+        Some(Commands::Cover { file, set, extract }) => cmd_cover(file, *set, extract.as_deref()),
+        Some(Commands::Meta { action }) => cmd_meta(action),
+        Some(Commands::Section { action }) => cmd_section(action),
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(*shell, &mut Cli::command(), "bbfmux", &mut io::stdout());
+            Ok(())
+        }
+        Some(Commands::Man { outdir }) => cmd_man(outdir.as_deref()),
+        None => cmd_mux(cli),
     }
 }
 
-#[allow(clippy::too_many_lines)]
+fn cmd_man(outdir: Option<&Path>) -> Result<()> {
+    let command = Cli::command();
+    let Some(outdir) = outdir else {
+        clap_mangen::Man::new(command).render(&mut io::stdout())?;
+        return Ok(());
+    };
+
+    fs::create_dir_all(outdir).with_context(|| format!("Cannot create {}", outdir.display()))?;
+    clap_mangen::generate_to(command, outdir)
+        .with_context(|| format!("Failed to write manual pages to {}", outdir.display()))?;
+    status!("Wrote manual pages to {}", outdir.display());
+    Ok(())
+}
+
 fn cmd_mux(cli: &Cli) -> Result<()> {
     if cli.inputs.is_empty() {
-        bail!("Error: No .bbf input specified.");
+        bail!("No .bbf input specified.");
     }
 
-    let mut manifest = Vec::new();
-    let mut order_map = HashMap::new();
+    let output = Path::new(&cli.output);
+    if cli.watch {
+        if is_output_template(&cli.output) {
+            bail!(
+                "--watch needs a fixed output name; metadata placeholders could rename it mid-session."
+            );
+        }
+        return watch::run(cli, output);
+    }
+
+    let stats = build_book(cli, &cli.inputs, output, &mut HashCache::new())?;
+    if cli.dry_run {
+        return Ok(());
+    }
+    status!(
+        "Successfully created {} ({} pages)",
+        output_name(&stats.output),
+        stats.pages
+    );
+    Ok(())
+}
+
+struct BuildStats {
+    /// Where the book went, after expanding any metadata placeholders.
+    output: PathBuf,
+    pages: usize,
+    /// Inputs that had to be hashed because the cache had no current entry.
+    hashed: usize,
+}
+
+/// Builds `inputs` into `output` as the rest of `cli` describes. `output` may contain
+/// `{Key}` metadata placeholders. `cache` carries input hashes between builds
+/// so watch mode only re-reads files that changed.
+fn build_book(
+    cli: &Cli,
+    inputs: &[PathBuf],
+    output: &Path,
+    cache: &mut HashCache,
+) -> Result<BuildStats> {
+    let mut input_opts = cli.input_opts;
+    input_opts.recursive |= cli.sections_from_dirs;
+    let (mut manifest, sidecars) = collect_inputs(inputs, input_opts)?;
 
     if let Some(order_path) = &cli.order {
         let content = fs::read_to_string(order_path).context("Failed to read order file")?;
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-            if let Some((fname, idx_str)) = line.rsplit_once(':') {
-                let fname = trim_quotes(fname);
-                let idx = idx_str.parse::<i32>().unwrap_or(0);
-                order_map.insert(fname, idx);
-            } else {
-                order_map.insert(trim_quotes(line), 0);
-            }
-        }
+        let order = parse_order_file(&content)
+            .with_context(|| format!("Invalid order file {}", order_path.display()))?;
+        apply_order(&mut manifest, &order, input_opts)
+            .with_context(|| format!("Order file {}", order_path.display()))?;
     }
 
-    for input_path in &cli.inputs {
-        if input_path.is_dir() {
-            for entry in fs::read_dir(input_path)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_file() {
-                    add_to_manifest(&mut manifest, path, &order_map);
+    let sec_reqs = section_requests(cli)?;
+    let mut meta_reqs = meta_requests(cli, input_opts.strict)?;
+
+    // Sidecar metadata has to be in before the output name can be expanded;
+    // their bookmarks wait until the page order is final.
+    let mut loaded = Vec::new();
+    if !cli.no_sidecar {
+        for path in sidecars {
+            match sidecar::load(&path) {
+                Ok(mut sidecar) => {
+                    let imported = import_sidecar_metadata(&mut sidecar, &mut meta_reqs);
+                    loaded.push((path, sidecar, imported));
                 }
+                Err(e) => input_problem(input_opts.strict, &format!("{e:#}"), "")?,
             }
-        } else {
-            add_to_manifest(&mut manifest, input_path.clone(), &order_map);
         }
     }
 
-    manifest.sort_by(compare_pages);
+    let output = if is_output_template(&output.to_string_lossy()) {
+        expand_output(&output.to_string_lossy(), &meta_reqs)?
+    } else {
+        output.to_path_buf()
+    };
+    log::info!("Writing {}", output_name(&output));
+
+    // A previous build's output sitting among the inputs must not become a page.
+    if let Ok(existing) = fs::canonicalize(&output) {
+        manifest.retain(|p| fs::canonicalize(&p.path).map_or(true, |p| p != existing));
+    }
+
+    // Everything `--strict` can reject is settled before the output is created.
+    let flags = page_flags(cli, &manifest)?;
+    let mut sections = resolve_sections(&sec_reqs, &manifest, input_opts.strict)?;
+
+    for (path, sidecar, imported) in &loaded {
+        let added =
+            import_sidecar_sections(path, sidecar, &manifest, &mut sections, input_opts.strict)?;
+        status!(
+            "Imported {imported} metadata entries and {added} sections from {}",
+            path.display()
+        );
+    }
+
+    if cli.sections_from_dirs {
+        sections.extend(dir_sections(&manifest, sections.len() as u32));
+    }
+
+    let writer = if cli.dry_run {
+        let sink: Box<dyn Write> = Box::new(io::sink());
+        CountingWriter {
+            inner: sink,
+            written: 0,
+        }
+    } else {
+        create_output(&output)?
+    };
+    let mut builder =
+        BBFBuilder::with_capacity(writer, manifest.len(), manifest.len(), meta_reqs.len())?;
+    builder.set_sort_sections(cli.sort_sections);
+    builder.set_normalize_titles(cli.normalize_titles);
+    if input_opts.strict {
+        builder.set_bad_sections(BadSections::Error);
+    }
+    builder.set_compression(compression(cli.compress, &cli.compress_only)?);
+
+    let hashed = add_input_pages(
+        &mut builder,
+        &manifest,
+        &flags,
+        cache,
+        input_opts.preserve_times,
+    )?;
+    log::info!(
+        "Stored {} pages as {} assets ({hashed} inputs hashed)",
+        manifest.len(),
+        builder.asset_count()
+    );
+
+    for s in &sections {
+        builder.add_section(&s.title, s.page, s.parent);
+    }
+
+    for m in meta_reqs {
+        builder.add_metadata(&m.key, &m.value);
+    }
+
+    let asset_count = builder.asset_count();
+    let size = finish_output(builder)?;
+    if cli.dry_run {
+        print_plan(&manifest, &flags, cache, &sections, asset_count, size);
+    }
+    Ok(BuildStats {
+        output,
+        pages: manifest.len(),
+        hashed,
+    })
+}
 
+/// `--sections` lines followed by `--section` flags, each tagged with where
+/// it came from for error messages.
+fn section_requests(cli: &Cli) -> Result<Vec<SectionReq>> {
     let mut sec_reqs = Vec::new();
 
     if let Some(sec_path) = &cli.sections {
         let content = fs::read_to_string(sec_path).context("Failed to read sections file")?;
-        for line in content.lines() {
+        for (line_no, line) in content.lines().enumerate() {
             if !line.trim().is_empty() {
-                sec_reqs.push(parse_section_string(line));
+                let origin = format!("{}:{}", sec_path.display(), line_no + 1);
+                sec_reqs.push(parse_section_string(line, origin));
             }
         }
     }
 
     for s_str in &cli.section {
-        sec_reqs.push(parse_section_string(s_str));
+        sec_reqs.push(parse_section_string(s_str, format!("--section '{s_str}'")));
     }
+    Ok(sec_reqs)
+}
 
+fn meta_requests(cli: &Cli, strict: bool) -> Result<Vec<MetaReq>> {
     let mut meta_reqs = Vec::new();
     for m_str in &cli.meta {
         if let Some((k, v)) = m_str.split_once(':') {
@@ -171,109 +512,125 @@ fn cmd_mux(cli: &Cli) -> Result<()> {
                 key: trim_quotes(k),
                 value: trim_quotes(v),
             });
-        }
-    }
-
-    let file = File::create(&cli.output).context("Cannot create output file")?;
-    let mut builder = BBFBuilder::new(file)?;
-
-    let mut file_to_page_idx = HashMap::new();
-
-    for (i, p) in manifest.iter().enumerate() {
-        let input_file =
-            File::open(&p.path).with_context(|| format!("Failed to open {}", p.path.display()))?;
-
-        let file_len = input_file.metadata()?.len();
-
-        let ext = p
-            .path
-            .extension()
-            .and_then(|e| e.to_str())
-            .unwrap_or("")
-            .to_string();
-
-        let media_type = BBFMediaType::from_extension(&format!(".{ext}"));
-
-        if file_len == 0 {
-            builder.add_page(&[], media_type, 0)?;
         } else {
-            let mmap = unsafe { Mmap::map(&input_file)? };
-            builder.add_page(&mmap, media_type, 0)?;
+            input_problem(
+                strict,
+                &format!("--meta '{m_str}' is not Key:Value"),
+                "Ignoring it.",
+            )?;
         }
-
-        file_to_page_idx.insert(p.filename.clone(), i as u32);
     }
+    Ok(meta_reqs)
+}
 
-    let mut section_name_to_idx = HashMap::new();
-
-    for (i, req) in sec_reqs.iter().enumerate() {
-        let page_idx = if req.is_filename {
-            if let Some(&idx) = file_to_page_idx.get(&req.target) {
-                idx
-            } else {
-                eprintln!(
-                    "Warning: Section target file '{}' not found. Defaulting to page 1.",
-                    req.target
-                );
-                0
-            }
-        } else {
-            req.target.parse::<u32>().unwrap_or(1).saturating_sub(1)
-        };
-
-        let parent_idx = if req.parent.is_empty() {
-            None
-        } else {
-            section_name_to_idx.get(&req.parent).copied()
-        };
+fn cmd_order_template(inputs: &[PathBuf], input_opts: InputOpts) -> Result<()> {
+    let (manifest, _) = collect_inputs(inputs, input_opts)?;
 
-        builder.add_section(&req.name, page_idx, parent_idx);
-        section_name_to_idx.insert(req.name.clone(), i as u32);
+    println!("# bbfmux order file");
+    println!("#   name:N          place at position N (negative counts from the end)");
+    println!("#   first..last:N   place an inclusive run of inputs starting at N");
+    println!("#   !name           leave the input out");
+    println!("#   name            no fixed position, sorted with the rest");
+    for (i, plan) in manifest.iter().enumerate() {
+        println!("{}:{}", plan.sort_key, i + 1);
     }
+    Ok(())
+}
 
-    for m in meta_reqs {
-        builder.add_metadata(&m.key, &m.value);
+fn pack_info(pack: &PackReader<&[u8]>, json: bool) -> Result<()> {
+    let volumes = pack
+        .names()
+        .enumerate()
+        .map(|(i, name)| {
+            let reader = pack
+                .volume(i)
+                .with_context(|| format!("Failed to parse volume '{name}'"))?;
+            Ok(report::VolumeInfo {
+                name: name.to_string(),
+                book: report::book_info(&reader),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&volumes)?);
+        return Ok(());
     }
 
-    builder.finalize()?;
-    println!(
-        "Successfully created {} ({} pages)",
-        cli.output,
-        manifest.len()
-    );
+    println!("Bound Book Pack (.bbfpack) Info");
+    println!("-------------------------------");
+    println!("Volumes:     {}", volumes.len());
+    for v in &volumes {
+        println!(
+            " - {:<14} {} pages, {} assets",
+            v.name, v.book.page_count, v.book.asset_count
+        );
+    }
     Ok(())
 }
 
-fn cmd_info(path: &Path) -> Result<()> {
-    let file = File::open(path).context("Failed to open BBF")?;
-    let mmap = unsafe { Mmap::map(&file).context("Failed to mmap BBF")? };
+fn cmd_info(path: &Path, json: bool) -> Result<()> {
+    let mmap = open_book(path)?;
+    if let Ok(pack) = PackReader::new(&mmap[..]) {
+        return pack_info(&pack, json);
+    }
+
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
 
-    let reader = BBFReader::new(&mmap[..])
-        .map_err(|e| anyhow::anyhow!("Error: Failed to parse BBF. {e:?}"))?;
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report::book_info(&reader))?
+        );
+        return Ok(());
+    }
 
     println!("Bound Book Format (.bbf) Info");
     println!("------------------------------");
     println!("BBF Version: {}", reader.header.version);
     println!("Pages:       {}", reader.footer.page_count.get());
+    let asset_bytes = reader
+        .assets()
+        .iter()
+        .map(BBFAssetEntry::stored_len)
+        .fold(0, u64::saturating_add);
     println!(
         "Assets:      {} (Deduplicated)",
         reader.footer.asset_count.get()
     );
+    println!("Asset data:  {}", report::human_size(asset_bytes));
+    let thumbnails = reader.thumbnails().len();
+    if thumbnails > 0 {
+        println!("Thumbnails:  {thumbnails}");
+    }
+    let algorithm = reader.hash_algorithm();
+    if algorithm != hash::XXH3 {
+        println!("Asset hash:  {}", hash_name(algorithm));
+    }
+    if reader.trailing_len() > 0 {
+        println!(
+            "Trailing:    {} bytes after the footer (ignored)",
+            reader.trailing_len()
+        );
+    }
 
     println!("\n[Sections]");
-    let sections = reader.sections();
-    if sections.is_empty() {
+    let tree = report::section_tree(&reader);
+    if tree.is_empty() {
         println!(" No sections defined.");
     } else {
-        for s in sections {
-            let title = reader
-                .get_string(s.section_title_offset.get())
-                .unwrap_or("???");
-            println!(
-                " - {:<20} (Starting Page: {})",
-                title,
-                s.section_start_index.get() + 1
-            );
+        let mut lines = Vec::new();
+        for node in &tree {
+            toc_lines(node, 0, &mut lines);
+        }
+        let width = lines
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max(20);
+        for (label, pages) in lines {
+            println!(" {label:<width$}  {pages}");
         }
     }
 
@@ -292,33 +649,73 @@ fn cmd_info(path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn cmd_verify(path: &Path, user_index: Option<i32>) -> Result<()> {
+/// One line per section for `info`, indented by depth: the title, and the
+/// pages it spans counting its subsections.
+fn toc_lines(node: &report::SectionNode, depth: usize, out: &mut Vec<(String, String)>) {
+    let first = node.start_index + 1;
+    let pages = match node.page_count {
+        0 => format!("Page {first} (no pages)"),
+        1 => format!("Page {first} (1 page)"),
+        n => format!("Pages {first}-{} ({n} pages)", node.start_index + n),
+    };
+    out.push((format!("{}- {}", "  ".repeat(depth), node.title), pages));
+    for child in &node.children {
+        toc_lines(child, depth + 1, out);
+    }
+}
+
+fn cmd_verify(path: &Path, user_index: Option<i32>, json: bool) -> Result<()> {
     let target_index = user_index.unwrap_or(-2);
 
-    let file = File::open(path).context("Failed to open BBF")?;
-    let mmap = unsafe { Mmap::map(&file).context("Failed to mmap BBF")? };
+    let mmap = open_book(path)?;
 
-    let reader = BBFReader::new(&mmap[..])
-        .map_err(|e| anyhow::anyhow!("Error: Failed to parse BBF. {e:?}"))?;
+    let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    resolve_beside(&mut reader, path);
 
     let data = &mmap[..];
 
-    let meta_start = reader.footer.string_pool_offset.get() as usize;
-    let meta_size = data.len() - size_of::<BBFFooter>() - meta_start;
-
-    if meta_start + meta_size > data.len() {
-        bail!("File corrupted: Table offsets invalid");
+    let index_hash = index_hash_check(data, &reader)?;
+    let dir_ok = index_hash.ok;
+    if target_index != -1 {
+        require_xxh3(&reader)?;
     }
 
-    let calc_index_hash = xxh3_64(&data[meta_start..meta_start + meta_size]);
-    let dir_ok = calc_index_hash == reader.footer.index_hash.get();
+    let assets = reader.assets();
+    let check_asset = |idx: usize| asset_check(data, &reader, idx);
+
+    let checks: Vec<_> = match target_index {
+        -1 => Vec::new(),
+        i if i >= 0 => {
+            if i as usize >= assets.len() {
+                bail!("Asset index {i} out of range ({} assets)", assets.len());
+            }
+            vec![check_asset(i as usize)]
+        }
+        _ => (0..assets.len()).into_par_iter().map(check_asset).collect(),
+    };
+
+    let all_assets_ok = checks.iter().all(|c| c.ok);
+
+    if json {
+        let result = report::VerifyReport {
+            ok: all_assets_ok && dir_ok,
+            index_hash,
+            assets: checks,
+        };
+        println!("{}", serde_json::to_string_pretty(&result)?);
+        return if result.ok {
+            Ok(())
+        } else {
+            Err(exit::IntegrityFailure("Integrity checks failed.").into())
+        };
+    }
 
     if target_index == -1 {
         println!("Directory Hash: {}", if dir_ok { "OK" } else { "CORRUPT" });
         return if dir_ok {
             Ok(())
         } else {
-            bail!("Directory hash mismatch")
+            Err(exit::IntegrityFailure("Directory hash mismatch").into())
         };
     }
 
@@ -326,170 +723,2576 @@ fn cmd_verify(path: &Path, user_index: Option<i32>) -> Result<()> {
     if !dir_ok {
         eprintln!(
             " [!!] Directory Hash CORRUPT (Wanted: {}, Got: {})",
-            reader.footer.index_hash.get(),
-            calc_index_hash
+            index_hash.expected, index_hash.actual
         );
     }
 
-    let assets = reader.assets();
-    let check_asset = |idx: usize| -> bool {
-        let asset = &assets[idx];
-        let start = asset.offset.get() as usize;
-        let len = asset.length.get() as usize;
-
-        if start + len > data.len() {
-            eprintln!(" [!!] Asset {idx} CORRUPT (Out of bounds)");
-            return false;
-        }
-
-        let slice = &data[start..start + len];
-        let hash = xxh3_64(slice);
-        if hash != asset.xxh3_hash.get() {
-            eprintln!(" [!!] Asset {idx} CORRUPT");
-            return false;
+    for c in checks.iter().filter(|c| !c.ok) {
+        if let Some(name) = reader.external_name(c.index)
+            && c.actual.is_none()
+        {
+            eprintln!(" [!!] Asset {} MISSING (external file {name})", c.index);
+        } else if c.actual.is_none() {
+            eprintln!(" [!!] Asset {} CORRUPT (Out of bounds)", c.index);
+        } else {
+            eprintln!(" [!!] Asset {} CORRUPT", c.index);
         }
-        true
-    };
-
-    let all_assets_ok = if target_index >= 0 {
-        check_asset(target_index as usize)
-    } else {
-        (0..assets.len())
-            .into_par_iter()
-            .map(check_asset)
-            .reduce(|| true, |a, b| a && b)
-    };
+    }
 
     if all_assets_ok && dir_ok {
         println!("All integrity checks passed.");
         Ok(())
     } else {
-        bail!("Integrity checks failed.");
+        Err(exit::IntegrityFailure("Integrity checks failed.").into())
     }
 }
 
-fn cmd_extract(
-    path: &Path,
-    outdir: &Path,
-    section_filter: Option<&str>,
+/// Pages `start..end` of the section titled `filter`. The section ends where
+/// the next one starts later, or, with `range_key`, at the next section whose
+/// title contains it.
+fn section_range<'a, T: AsRef<[u8]>>(
+    reader: &'a BBFReader<T>,
+    filter: &str,
     range_key: Option<&str>,
-) -> Result<()> {
-    let file = File::open(path).context("Failed to open BBF")?;
-    let mmap = unsafe { Mmap::map(&file).context("Failed to mmap BBF")? };
-
-    let reader = BBFReader::new(&mmap[..])
-        .map_err(|e| anyhow::anyhow!("Error: Failed to parse BBF. {e:?}"))?;
-
-    fs::create_dir_all(outdir)?;
-
+) -> Result<(u32, u32, &'a str)> {
     let pages = reader.pages();
     let sections = reader.sections();
 
-    let mut start_idx = 0;
-    let mut end_idx = pages.len() as u32;
-    let mut section_name_found = "Full Book";
+    for (i, s) in sections.iter().enumerate() {
+        let title = reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("");
+        if !same_title(title, filter, false) {
+            continue;
+        }
+        let start_idx = s.section_start_index.get();
+        let mut end_idx = pages.len() as u32;
 
-    if let Some(filter) = section_filter {
-        let mut found = false;
-        for (i, s) in sections.iter().enumerate() {
-            let title = reader
-                .get_string(s.section_title_offset.get())
+        for next_s in sections.iter().skip(i + 1) {
+            let next_title = reader
+                .get_string(next_s.section_title_offset.get())
                 .unwrap_or("");
-            if title == filter {
-                start_idx = s.section_start_index.get();
-                section_name_found = title;
 
-                end_idx = pages.len() as u32;
-
-                for next_s in sections.iter().skip(i + 1) {
-                    let next_title = reader
-                        .get_string(next_s.section_title_offset.get())
-                        .unwrap_or("");
-
-                    if let Some(rk) = range_key {
-                        if !rk.is_empty() && next_title.contains(rk) {
-                            end_idx = next_s.section_start_index.get();
-                            break;
-                        }
-                        if rk.is_empty() && next_s.section_start_index.get() > start_idx {
-                            end_idx = next_s.section_start_index.get();
-                            break;
-                        }
-                    } else if next_s.section_start_index.get() > start_idx {
-                        end_idx = next_s.section_start_index.get();
-                        break;
-                    }
+            if let Some(rk) = range_key {
+                if !rk.is_empty() && next_title.contains(rk) {
+                    end_idx = next_s.section_start_index.get();
+                    break;
+                }
+                if rk.is_empty() && next_s.section_start_index.get() > start_idx {
+                    end_idx = next_s.section_start_index.get();
+                    break;
                 }
-                found = true;
+            } else if next_s.section_start_index.get() > start_idx {
+                end_idx = next_s.section_start_index.get();
                 break;
             }
         }
-        if !found {
-            bail!("Section '{filter}' not found.");
-        }
+        return Ok((start_idx, end_idx, title));
     }
+    bail!("Section '{filter}' not found.");
+}
 
-    println!(
-        "Extracting: {} (Pages {} to {})",
-        section_name_found,
-        start_idx + 1,
-        end_idx
-    );
+/// Turns an expanded name template into a path below the output directory.
+/// Empty components (say, `{section}/` for a page without one) are dropped.
+fn relative_output_path(name: &str) -> Result<PathBuf> {
+    let rel: PathBuf = name
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    if rel.as_os_str().is_empty() || rel.components().any(|c| c.as_os_str() == "..") {
+        bail!("'{name}' is not a usable file name");
+    }
+    Ok(rel)
+}
 
-    let data = &mmap[..];
+fn cmd_validate(
+    path: &Path,
+    profile: ValidateProfile,
+    alignment: Option<u64>,
+    check_hashes: bool,
+    json: bool,
+) -> Result<()> {
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
 
-    for i in start_idx..end_idx {
-        if i as usize >= pages.len() {
-            break;
-        }
+    let (profile, name) = match profile {
+        ValidateProfile::Standard => (validate::Profile::Standard, "standard"),
+        ValidateProfile::Archival => (validate::Profile::Archival, "archival"),
+    };
+    let mut options = validate::Options::for_profile(profile);
+    options.check_hashes = check_hashes;
+    if let Some(alignment) = alignment {
+        options.alignment = alignment;
+    }
 
-        let page = &pages[i as usize];
-        let asset = &reader.assets()[page.asset_index.get() as usize];
+    let issues = validate::validate(&reader, &options);
+    let result = report::validation(name, &issues);
 
-        let ext = BBFMediaType::from(asset.type_).as_extension();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else {
+        for severity in [Severity::Error, Severity::Warning, Severity::Info] {
+            let group: Vec<_> = issues.iter().filter(|i| i.severity == severity).collect();
+            if group.is_empty() {
+                continue;
+            }
+            println!("[{severity}s: {}]", group.len());
+            for issue in group {
+                println!(" {:<24} {}", issue.code, issue.message);
+            }
+        }
+        if result.ok {
+            println!(
+                "Book conforms to the {name} profile ({} warning(s)).",
+                result.warnings
+            );
+        }
+    }
 
-        let out_name = format!("p{}{}", i + 1, ext);
-        let out_path = outdir.join(out_name);
+    if result.ok {
+        Ok(())
+    } else {
+        Err(exit::IntegrityFailure("Validation failed.").into())
+    }
+}
 
-        let file_offset = asset.offset.get() as usize;
-        let file_len = asset.length.get() as usize;
+/// One line per asset, `<hash>  asset<N><ext>`, matching the names
+/// `extract --assets` writes.
+fn cmd_hashes(path: &Path) -> Result<()> {
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    require_xxh3(&reader)?;
 
-        if file_offset + file_len > data.len() {
-            eprintln!("Warning: Page {i} out of bounds, skipping.");
-            continue;
-        }
+    let mut out = BufWriter::new(io::stdout().lock());
+    for (i, asset) in reader.assets().iter().enumerate() {
+        let ext = BBFMediaType::from(asset.type_).as_extension();
+        writeln!(out, "{:016x}  asset{i}{ext}", asset.xxh3_hash.get())?;
+    }
+    out.flush()?;
+    Ok(())
+}
 
-        let mut f = File::create(out_path)?;
-        f.write_all(&data[file_offset..file_offset + file_len])?;
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn cmd_bench(path: &Path, rounds: usize, samples: usize, json: bool) -> Result<()> {
+    let report = bench::run(path, rounds, samples)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
     }
 
-    println!("Done.");
+    let rate = |bytes_per_sec: f64| format!("{}/s", report::human_size(bytes_per_sec as u64));
+    println!(
+        "{}: {} pages, {} (best of {} runs, warm cache)",
+        path.display(),
+        report.page_count,
+        report::human_size(report.file_size),
+        report.rounds
+    );
+    println!(
+        "{:<8} {:>12} {:>14} {:>20} {:>14}",
+        "Backend", "Open", "Sequential", "Random p50 / p99", "Verify"
+    );
+    for b in &report.backends {
+        println!(
+            "{:<8} {:>9.1} us {:>14} {:>8.1} / {:>6.1} us {:>14}",
+            b.backend,
+            b.open_us,
+            rate(b.sequential_bytes_per_sec),
+            b.random_p50_us,
+            b.random_p99_us,
+            rate(b.verify_bytes_per_sec)
+        );
+    }
+    if let Some(b) = report.backends.first()
+        && b.hash_mismatches > 0
+    {
+        log::warn!(
+            "{} assets did not match their hash; run `verify` for details",
+            b.hash_mismatches
+        );
+    }
     Ok(())
 }
 
-fn add_to_manifest(manifest: &mut Vec<PagePlan>, path: PathBuf, order_map: &HashMap<String, i32>) {
-    let filename = path.file_name().unwrap().to_string_lossy().to_string();
-    let order = *order_map.get(&filename).unwrap_or(&0);
-    manifest.push(PagePlan {
-        path,
-        filename,
-        order,
-    });
-}
+fn cmd_stats(path: &Path, json: bool, top: usize) -> Result<()> {
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    let stats = report::stats(&reader, top);
 
-fn parse_section_string(s: &str) -> SectionReq {
-    let mut parts: Vec<&str> = Vec::new();
-    for part in s.split(':') {
-        parts.push(part);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
     }
 
-    let name = trim_quotes(parts.first().unwrap_or(&""));
+    let size = report::human_size;
+    let share = |bytes: u64| {
+        if stats.file_size == 0 {
+            0.0
+        } else {
+            bytes as f64 * 100.0 / stats.file_size as f64
+        }
+    };
+
+    println!("File size:      {}", size(stats.file_size));
+    println!(
+        "Asset data:     {} ({:.1}%) in {} assets for {} pages",
+        size(stats.asset_bytes),
+        share(stats.asset_bytes),
+        stats.asset_count,
+        stats.page_count
+    );
+    println!(
+        "Dedupe savings: {} ({} without dedupe)",
+        size(stats.dedupe_savings),
+        size(stats.page_bytes)
+    );
+    println!(
+        "Padding:        {} ({:.1}%)",
+        size(stats.padding_bytes),
+        share(stats.padding_bytes)
+    );
+    println!(
+        "Index:          {} (string pool {})",
+        size(stats.index_bytes),
+        size(stats.string_pool_bytes)
+    );
+
+    println!("\n[By Media Type]");
+    for m in &stats.media_types {
+        println!(
+            " {:<6} {:>10}  {:>5.1}%  {} assets, {} pages",
+            m.media_type,
+            size(m.bytes),
+            share(m.bytes),
+            m.assets,
+            m.pages
+        );
+    }
+
+    if !stats.largest_pages.is_empty() {
+        println!("\n[Largest Pages]");
+        for p in &stats.largest_pages {
+            println!(
+                " Page {:<5} {:>10}  (asset {})",
+                p.index + 1,
+                size(p.bytes),
+                p.asset
+            );
+        }
+    }
+
+    if !stats.sections.is_empty() {
+        println!("\n[Sections]");
+        for s in &stats.sections {
+            println!(
+                " - {:<20} {:>5} pages (from page {})",
+                s.title,
+                s.page_count,
+                s.start_index + 1
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every asset listed in `manifest_path` exists with the published
+/// hash, both in the asset table and in the bytes actually stored.
+fn cmd_verify_manifest(path: &Path, manifest_path: &Path) -> Result<()> {
+    let mmap = open_book(path)?;
+    let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    resolve_beside(&mut reader, path);
+    let content = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Failed to read {}", manifest_path.display()))?;
+    require_xxh3(&reader)?;
+
+    let assets = reader.assets();
+    let mut listed = vec![false; assets.len()];
+    let mut failures = 0;
+
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hash, index) = parse_manifest_line(line).with_context(|| {
+            format!(
+                "{}:{}: malformed line",
+                manifest_path.display(),
+                line_no + 1
+            )
+        })?;
+
+        if index >= assets.len() {
+            eprintln!(" [!!] asset{index}: not in book ({} assets)", assets.len());
+            failures += 1;
+            continue;
+        }
+        listed[index] = true;
+
+        let check = asset_check(&mmap, &reader, index);
+        if check.expected != hash {
+            eprintln!(
+                " [!!] asset{index}: book records {:016x}, manifest has {hash:016x}",
+                check.expected
+            );
+            failures += 1;
+        } else if !check.ok {
+            eprintln!(" [!!] asset{index}: stored data does not match its hash");
+            failures += 1;
+        }
+    }
+
+    let unlisted = listed.iter().filter(|&&l| !l).count();
+    if unlisted > 0 {
+        eprintln!(" [!!] {unlisted} asset(s) in the book are missing from the manifest");
+        failures += 1;
+    }
+
+    if failures == 0 {
+        println!("All {} assets match the manifest.", assets.len());
+        Ok(())
+    } else {
+        Err(exit::IntegrityFailure("Manifest check failed.").into())
+    }
+}
+
+/// Parses `<16 hex digits>  asset<N>[.ext]`; a leading `*` on the name, as
+/// binary-mode sha256sum writes, is accepted.
+fn parse_manifest_line(line: &str) -> Option<(u64, usize)> {
+    let (hash, name) = line.split_once(char::is_whitespace)?;
+    let hash = u64::from_str_radix(hash, 16).ok()?;
+    let name = name.trim_start().trim_start_matches('*');
+    let digits = name.strip_prefix("asset")?;
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    Some((hash, digits[..end].parse().ok()?))
+}
+
+/// Asset hashes are only checked with XXH3; the index hash always is one.
+fn require_xxh3<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Result<()> {
+    let algorithm = reader.hash_algorithm();
+    if algorithm != hash::XXH3 {
+        bail!(
+            "Assets are hashed with {}; bbfmux can only check XXH3 hashes",
+            hash_name(algorithm)
+        );
+    }
+    Ok(())
+}
+
+fn hash_name(algorithm: u32) -> String {
+    hash::name(algorithm).map_or_else(|| format!("#{algorithm}"), str::to_string)
+}
+
+/// Recomputes the hash over everything from the string pool to the footer.
+fn index_hash_check<T: AsRef<[u8]>>(
+    data: &[u8],
+    reader: &BBFReader<T>,
+) -> Result<report::HashCheck> {
+    let meta_start = reader.footer.string_pool_offset.get() as usize;
+    let meta_end = reader.file_len() - size_of::<BBFFooter>();
+
+    if meta_start > meta_end {
+        bail!("File corrupted: Table offsets invalid");
+    }
+
+    let actual = xxh3_64(&data[meta_start..meta_end]);
+    Ok(report::HashCheck {
+        ok: actual == reader.footer.index_hash.get(),
+        expected: reader.footer.index_hash.get(),
+        actual,
+    })
+}
+
+/// Hashes asset `idx` as stored: in `data`, or through the reader's
+/// resolver for external assets.
+fn asset_check<T: AsRef<[u8]>>(
+    data: &[u8],
+    reader: &BBFReader<T>,
+    idx: usize,
+) -> report::AssetCheck {
+    let asset = &reader.assets()[idx];
+    let start = asset.offset.get() as usize;
+    let len = asset.length.get() as usize;
+
+    let actual = if asset.asset_flags().contains(AssetFlags::EXTERNAL) {
+        reader
+            .resolve_external(idx as u32)
+            .map(|(_, data)| xxh3_64(&data))
+    } else {
+        start
+            .checked_add(len)
+            .filter(|&end| end <= data.len())
+            .map(|end| xxh3_64(&data[start..end]))
+    };
+
+    report::AssetCheck {
+        index: idx as u32,
+        ok: actual == Some(asset.xxh3_hash.get()),
+        expected: asset.xxh3_hash.get(),
+        actual,
+    }
+}
+
+fn cmd_repair(path: &Path, footer_from: Option<&Path>) -> Result<()> {
+    let footer_start;
+    let footer = {
+        let mmap = open_book(path)?;
+        let data = &mmap[..];
+        let backup = footer_from.map(open_book).transpose()?;
+
+        // With a backup the tables are read from the copy, which is only
+        // trusted if its whole index region is byte-identical to ours.
+        let reader = if let Some(backup) = &backup {
+            let reader = BBFReader::new(&backup[..]).context("Failed to parse backup")?;
+            let start = reader.footer.string_pool_offset.get() as usize;
+            let end = reader.file_len() - size_of::<BBFFooter>();
+            if backup.len() != data.len() || backup[start..end] != data[start..end] {
+                bail!("Backup index does not match this file; it is not a copy of the same book.");
+            }
+            reader
+        } else {
+            BBFReader::new(data).context(
+                "Failed to parse BBF (a damaged footer can be restored with --footer-from)",
+            )?
+        };
+
+        require_xxh3(&reader)?;
+        let assets = reader.assets();
+        let damaged = (0..assets.len())
+            .into_par_iter()
+            .filter(|&i| {
+                let asset = &assets[i];
+                if asset.asset_flags().contains(AssetFlags::EXTERNAL) {
+                    return false;
+                }
+                let start = asset.offset.get() as usize;
+                let end = start.saturating_add(asset.length.get() as usize);
+                end > data.len() || xxh3_64(&data[start..end]) != asset.xxh3_hash.get()
+            })
+            .count();
+        if damaged > 0 {
+            bail!(
+                "{damaged} asset(s) are damaged; only the index hash and footer can be repaired."
+            );
+        }
+
+        footer_start = reader.file_len() - size_of::<BBFFooter>();
+        let index_start = reader.footer.string_pool_offset.get() as usize;
+        let mut footer = reader.footer;
+        footer.index_hash = xxh3_64(&data[index_start..footer_start]).into();
+
+        if footer.as_bytes() == &data[footer_start..footer_start + size_of::<BBFFooter>()] {
+            println!("Nothing to repair; all integrity checks passed.");
+            return Ok(());
+        }
+        footer
+    };
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .context("Failed to open BBF")?;
+    file.seek(SeekFrom::Start(footer_start as u64))?;
+    file.write_all(footer.as_bytes())?;
+
+    println!(
+        "Repaired {}: {} rewritten (index hash {:016x}).",
+        path.display(),
+        if footer_from.is_some() {
+            "footer"
+        } else {
+            "index hash"
+        },
+        footer.index_hash.get()
+    );
+    Ok(())
+}
+
+/// Parses a media type name such as `png` or `.jpg`; `bin` means unknown.
+fn parse_media_type(t: &str) -> Result<BBFMediaType> {
+    let ext = format!(".{}", t.trim_start_matches('.'));
+    match BBFMediaType::from_extension(&ext) {
+        BBFMediaType::Unknown if ext != ".bin" => bail!("Unknown media type '{t}'"),
+        m => Ok(m),
+    }
+}
+
+/// Builder settings for `--compress` and `--compress-only`.
+fn compression(level: Option<i32>, only: &[String]) -> Result<Option<Compression>> {
+    let Some(level) = level else {
+        return Ok(None);
+    };
+    let media_types = only
+        .iter()
+        .map(|t| parse_media_type(t))
+        .collect::<Result<_>>()?;
+    Ok(Some(Compression { level, media_types }))
+}
+
+fn cmd_list(path: &Path, section_filter: Option<&str>, type_filter: Option<&str>) -> Result<()> {
+    let mmap = open_book(path)?;
+
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+
+    let type_filter = type_filter.map(parse_media_type).transpose()?;
+
+    let sections = reader.sections();
+    let section_title = |idx: usize| {
+        reader
+            .get_string(sections[idx].section_title_offset.get())
+            .unwrap_or("???")
+    };
+
+    // A page matches the section filter if its owning section, or any ancestor
+    // of it, carries the requested title.
+    let in_section = |owner: Option<usize>, filter: &str| {
+        let mut cur = owner;
+        let mut steps = 0;
+        while let Some(idx) = cur {
+            if same_title(section_title(idx), filter, false) {
+                return true;
+            }
+            let parent = sections[idx].parent_section_index.get() as usize;
+            steps += 1;
+            cur = (parent < sections.len() && steps < sections.len()).then_some(parent);
+        }
+        false
+    };
+
+    if let Some(filter) = section_filter
+        && reader.find_section(filter).is_none()
+    {
+        bail!("Section '{filter}' not found.");
+    }
+
+    let owners = owning_sections(&reader);
+    let assets = reader.assets();
+
+    println!(
+        "{:>6}  {:>6}  {:<5} {:>10}  {:>8}  {:<16}  Section",
+        "Page", "Asset", "Type", "Size", "Flags", "Hash"
+    );
+
+    let mut shown = 0;
+    for (i, page) in reader.pages().iter().enumerate() {
+        let asset_index = page.asset_index.get();
+        let Some(asset) = assets.get(asset_index as usize) else {
+            log::warn!("Page {} references missing asset {asset_index}", i + 1);
+            continue;
+        };
+
+        if type_filter.is_some_and(|t| u8::from(t) != asset.type_) {
+            continue;
+        }
+        if let Some(filter) = section_filter
+            && !in_section(owners[i], filter)
+        {
+            continue;
+        }
+
+        println!(
+            "{:>6}  {:>6}  {:<5} {:>10}  {:08x}  {:016x}  {}",
+            i + 1,
+            asset_index,
+            report::media_type_name(asset.type_),
+            asset.length.get(),
+            page.flags.get(),
+            asset.xxh3_hash.get(),
+            owners[i].map_or("-", section_title)
+        );
+        shown += 1;
+    }
+
+    println!("\n{shown} of {} pages listed.", reader.pages().len());
+    Ok(())
+}
+
+fn cmd_browse(path: &Path, graphics: preview::Graphics) -> Result<()> {
+    if !io::stdout().is_terminal() {
+        bail!("browse needs an interactive terminal");
+    }
+    let mmap = open_book(path)?;
+    let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    resolve_beside(&mut reader, path);
+
+    let title = report::metadata(&reader)
+        .into_iter()
+        .find(|m| m.key.eq_ignore_ascii_case("title"))
+        .map_or_else(|| output_name(path).into_owned(), |m| m.value);
+
+    browse::run(&reader, &mmap, title, graphics)
+}
+
+fn cmd_serve(path: &Path, bind: &str, port: u16) -> Result<()> {
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+
+    serve::run(&reader, &format!("{bind}:{port}"))
+}
+
+fn cmd_diff(old_path: &Path, new_path: &Path, json: bool) -> Result<()> {
+    let old_mmap = open_book(old_path)?;
+    let new_mmap = open_book(new_path)?;
+
+    let old = BBFReader::new(&old_mmap[..])
+        .with_context(|| format!("Failed to parse {}", old_path.display()))?;
+    let new = BBFReader::new(&new_mmap[..])
+        .with_context(|| format!("Failed to parse {}", new_path.display()))?;
+
+    let result = bbf::compare(&old, &new);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&result)?);
+    } else if result.identical {
+        println!("Books are identical.");
+    } else {
+        let sign = |c: diff::Change| match c {
+            diff::Change::Added => '+',
+            diff::Change::Removed => '-',
+            diff::Change::Changed => '~',
+        };
+        let opt = |v: Option<u32>| v.map_or_else(|| "-".to_string(), |v| (v + 1).to_string());
+
+        if !result.pages.is_empty() {
+            println!("[Pages]");
+            for p in &result.pages {
+                println!(" {} Page {}", sign(p.change), p.index + 1);
+            }
+        }
+        if !result.sections.is_empty() {
+            println!("[Sections]");
+            for s in &result.sections {
+                println!(
+                    " {} {} (Starting Page: {} -> {})",
+                    sign(s.change),
+                    s.title,
+                    opt(s.old_start),
+                    opt(s.new_start)
+                );
+            }
+        }
+        if !result.metadata.is_empty() {
+            println!("[Metadata]");
+            for m in &result.metadata {
+                println!(
+                    " {} {}: {} -> {}",
+                    sign(m.change),
+                    m.key,
+                    m.old.as_deref().unwrap_or("-"),
+                    m.new.as_deref().unwrap_or("-")
+                );
+            }
+        }
+        println!(
+            "\n{} page, {} section, {} metadata difference(s).",
+            result.pages.len(),
+            result.sections.len(),
+            result.metadata.len()
+        );
+    }
+
+    if !result.identical {
+        return Err(exit::Differs.into());
+    }
+    Ok(())
+}
+
+fn cmd_extract(
+    path: &Path,
+    outdir: &Path,
+    section_filter: Option<&str>,
+    range_key: Option<&str>,
+    pages_spec: Option<&str>,
+    opts: &ExtractOpts,
+) -> Result<()> {
+    let assets_only = opts.assets;
+    let mmap = open_book(path)?;
+
+    let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    resolve_beside(&mut reader, path);
+
+    fs::create_dir_all(outdir)?;
+
+    let pages = reader.pages();
+    let sections = reader.sections();
+
+    let (start_idx, end_idx, section_name_found) = match section_filter {
+        Some(filter) => section_range(&reader, filter, range_key)?,
+        None => (0, pages.len() as u32, "Full Book"),
+    };
+
+    let selected: Vec<u32> = if let Some(spec) = pages_spec {
+        let selected = parse_page_ranges(spec, pages.len() as u32)?;
+        println!("Extracting: Pages {spec} ({} pages)", selected.len());
+        selected
+    } else {
+        println!(
+            "Extracting: {} (Pages {} to {})",
+            section_name_found,
+            start_idx + 1,
+            end_idx
+        );
+        (start_idx..end_idx.min(pages.len() as u32)).collect()
+    };
+
+    let template = opts.name_template.as_deref().unwrap_or(if assets_only {
+        "asset{asset}{ext}"
+    } else {
+        "p{page}{ext}"
+    });
+    let owners = owning_sections(&reader);
+    let names = report::page_names(&reader);
+
+    // Name everything up front so a template that collides fails before any
+    // file is written.
+    let mut planned = Vec::new();
+    let mut claimed: HashMap<PathBuf, u32> = HashMap::new();
+    let mut written = HashSet::new();
+    for i in selected {
+        let asset_index = pages[i as usize].asset_index.get();
+        if assets_only && !written.insert(asset_index) {
+            continue;
+        }
+        let Some(asset) = reader.assets().get(asset_index as usize) else {
+            log::warn!("Page {} points past the asset table, skipping.", i + 1);
+            continue;
+        };
+
+        let section = owners[i as usize].map_or("", |s| {
+            reader
+                .get_string(sections[s].section_title_offset.get())
+                .unwrap_or("")
+        });
+        let stem = names[i as usize].map_or_else(
+            || format!("p{}", i + 1),
+            |n| {
+                Path::new(n)
+                    .file_stem()
+                    .map_or(n.into(), |s| s.to_string_lossy().into_owned())
+            },
+        );
+        let out_name = template::expand(template, |var| {
+            use template::Value::{Number, Text};
+            Some(match var {
+                "page" => Number(u64::from(i) + 1),
+                "index" => Number(u64::from(i)),
+                "asset" => Number(u64::from(asset_index)),
+                "section" => Text(section.to_string()),
+                "name" => Text(stem.clone()),
+                "hash" => Text(format!("{:016x}", asset.xxh3_hash.get())),
+                "ext" => Text(BBFMediaType::from(asset.type_).as_extension().to_string()),
+                _ => return None,
+            })
+        })?;
+
+        let rel = relative_output_path(&out_name)?;
+        if let Some(first) = claimed.insert(rel.clone(), i) {
+            bail!(
+                "Template '{template}' names both page {} and page {} '{}'; include {{page}} to make names unique",
+                first + 1,
+                i + 1,
+                rel.display()
+            );
+        }
+        planned.push((i, asset_index, rel));
+    }
+    if opts.with_sidecars
+        && let Some(name) = SIDECAR_FILES
+            .iter()
+            .find(|name| claimed.contains_key(Path::new(name)))
+    {
+        bail!("Template '{template}' names a page '{name}', which --with-sidecars writes");
+    }
+
+    // Each worker holds at most one decoded page; stored pages are borrowed
+    // straight from the map, so memory stays bounded by the thread count.
+    let started = Instant::now();
+    let progress = page_progress(planned.len() as u64);
+    let results = planned
+        .par_iter()
+        .map(|(i, asset_index, rel)| -> Result<Option<u64>> {
+            let data = match reader.get_asset_decoded(*asset_index) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Page {i}: {e}, skipping.");
+                    progress.inc(1);
+                    return Ok(None);
+                }
+            };
+
+            let out_path = outdir.join(rel);
+            if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut f = File::create(&out_path)
+                .with_context(|| format!("Failed to create {}", out_path.display()))?;
+            f.write_all(&data)?;
+            if let Some(secs) = reader.assets()[*asset_index as usize].mtime() {
+                f.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))?;
+            }
+            progress.inc(1);
+            Ok(Some(data.len() as u64))
+        })
+        .collect::<Result<Vec<_>>>();
+    progress.finish_and_clear();
+
+    let mut extracted = Vec::new();
+    let mut bytes = 0;
+    for ((i, _, rel), written) in planned.into_iter().zip(results?) {
+        if let Some(len) = written {
+            bytes += len;
+            extracted.push((i, rel));
+        }
+    }
+    let secs = started.elapsed().as_secs_f64();
+    status!(
+        "Extracted {} pages ({}) in {secs:.2}s, {}/s",
+        extracted.len(),
+        report::human_size(bytes),
+        report::human_size((bytes as f64 / secs.max(1e-3)) as u64)
+    );
+
+    if opts.with_sidecars {
+        write_sidecars(&reader, outdir, &extracted)?;
+    }
+    println!("Done.");
+    Ok(())
+}
+
+/// What `extract --with-sidecars` writes next to the pages.
+const SIDECAR_FILES: [&str; 3] = ["metadata.json", "sections.txt", "order.txt"];
+
+/// Writes the book's metadata, table of contents and page order in the
+/// formats mux reads. `extracted` holds each written page and its path
+/// under `outdir`, in page order.
+fn write_sidecars<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    outdir: &Path,
+    extracted: &[(u32, PathBuf)],
+) -> Result<()> {
+    use std::fmt::Write as _;
+
+    let [meta_name, sections_name, order_name] = SIDECAR_FILES;
+
+    // Repeated keys become arrays, which the sidecar reader splits again.
+    // Keys stay in book order, as mux reads them back in file order.
+    let mut metadata = serde_json::Map::new();
+    for m in report::metadata(reader) {
+        if m.key == "sections" {
+            log::warn!("metadata.json can't hold the key 'sections', leaving it out.");
+            continue;
+        }
+        match metadata.entry(m.key) {
+            serde_json::map::Entry::Vacant(e) => {
+                e.insert(m.value.into());
+            }
+            serde_json::map::Entry::Occupied(mut e) => match e.get_mut() {
+                serde_json::Value::Array(values) => values.push(m.value.into()),
+                first => *first = vec![first.take(), m.value.into()].into(),
+            },
+        }
+    }
+    fs::write(
+        outdir.join(meta_name),
+        serde_json::to_string_pretty(&metadata)? + "\n",
+    )?;
+
+    let file_names: HashMap<u32, String> = extracted
+        .iter()
+        .map(|(page, rel)| (*page, file_name(rel)))
+        .collect();
+
+    // Titles are referenced by name, and a colon would end the field early.
+    let field = |title: &str| {
+        if title.contains(':') {
+            log::warn!(
+                "Section title '{title}' contains ':', written with '-' in {sections_name}."
+            );
+        }
+        title.replace(':', "-")
+    };
+    let sections = reader.sections();
+    let mut lines = String::new();
+    let mut kept = vec![false; sections.len()];
+    for (i, s) in sections.iter().enumerate() {
+        let Some(target) = file_names.get(&s.section_start_index.get()) else {
+            continue;
+        };
+        kept[i] = true;
+        let title = reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("");
+        let parent = s.parent_section_index.get() as usize;
+        let parent = kept
+            .get(parent)
+            .filter(|&&k| k && parent != i)
+            .and_then(|_| reader.get_string(sections[parent].section_title_offset.get()));
+        match parent {
+            Some(parent) => writeln!(lines, "{}:{target}:{}", field(title), field(parent))?,
+            None => writeln!(lines, "{}:{target}", field(title))?,
+        }
+    }
+    fs::write(outdir.join(sections_name), lines)?;
+
+    // metadata.json is picked up as a sidecar; the text files would be pages.
+    let mut order = String::from("# bbfmux order file, written by extract --with-sidecars\n");
+    for name in [sections_name, order_name] {
+        writeln!(order, "!{name}")?;
+    }
+    for (n, (_, rel)) in extracted.iter().enumerate() {
+        writeln!(order, "{}:{}", file_name(rel), n + 1)?;
+    }
+    fs::write(outdir.join(order_name), order)?;
+    Ok(())
+}
+
+fn cmd_convert(path: &Path, output: Option<&Path>, dpi: u32, rasterize: bool) -> Result<()> {
+    let from_stdin = path == Path::new("-");
+    let out_path = match output {
+        Some(output) => output.to_path_buf(),
+        None if from_stdin => PathBuf::from("-"),
+        None => path.with_extension("bbf"),
+    };
+    if out_path == Path::new("-") {
+        STATUS_TO_STDERR.store(true, atomic::Ordering::Relaxed);
+    }
+
+    // pdftoppm needs a real file, so piped input is spooled to a temp file.
+    let spooled = if from_stdin {
+        let mut tmp = tempfile::NamedTempFile::new()?;
+        io::copy(&mut io::stdin().lock(), &mut tmp).context("Failed to read PDF from stdin")?;
+        Some(tmp)
+    } else {
+        None
+    };
+    let path = spooled.as_ref().map_or(path, tempfile::NamedTempFile::path);
+
+    let options = PdfOptions { dpi, rasterize };
+    let plan = ConversionPlan::from_pdf(path, &options, |p| match p {
+        Progress::Extracting => status!("Extracting embedded page images (lossless)..."),
+        Progress::Rasterizing { dpi } => status!("Rasterizing pages at {dpi} DPI..."),
+        Progress::Pages { .. } => {}
+    })?;
+
+    let mut builder = BBFBuilder::new(create_output(&out_path)?)?;
+    let progress = page_progress(plan.pages.len() as u64);
+    plan.write(&mut builder, |p| {
+        if let Progress::Pages { done, .. } = p {
+            progress.set_position(done as u64);
+        }
+    })?;
+    progress.finish_and_clear();
+
+    finish_output(builder)?;
+    status!(
+        "Successfully created {} ({} pages)",
+        output_name(&out_path),
+        plan.pages.len()
+    );
+    Ok(())
+}
+
+fn cmd_export(path: &Path, format: ExportFormat, output: Option<&Path>) -> Result<()> {
+    let mmap = open_book(path)?;
+
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+
+    let out_path = output.map_or_else(
+        || path.with_extension(format.extension()),
+        Path::to_path_buf,
+    );
+    let out = File::create(&out_path).context("Cannot create output file")?;
+
+    let count = match format {
+        ExportFormat::Cbz => {
+            let progress = page_progress(reader.pages().len() as u64);
+            let count = convert::write_cbz(&reader, out, |p| {
+                if let Progress::Pages { done, .. } = p {
+                    progress.set_position(done as u64);
+                }
+            })?;
+            progress.finish_and_clear();
+            count
+        }
+        ExportFormat::Html => html::write_html(&reader, BufWriter::new(out))?,
+    };
+
+    println!("Exported {} ({count} pages)", out_path.display());
+    Ok(())
+}
+
+fn cmd_repack(
+    path: &Path,
+    output: &Path,
+    align: u64,
+    internalize: bool,
+    compression: Option<Compression>,
+) -> Result<()> {
+    if output.exists() && fs::canonicalize(output)? == fs::canonicalize(path)? {
+        bail!("Output must differ from the input file.");
+    }
+
+    let mmap = open_book(path)?;
+    let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    if internalize {
+        resolve_beside(&mut reader, path);
+        let missing: Vec<_> = (0..reader.assets().len() as u32)
+            .filter_map(|i| reader.external_name(i).map(|name| (i, name)))
+            .filter(|&(i, _)| reader.resolve_external(i).is_none())
+            .map(|(_, name)| name)
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Can't read {} external asset(s): {}",
+                missing.len(),
+                missing.join(", ")
+            );
+        }
+    }
+
+    let mut builder = BBFBuilder::with_capacity(
+        create_output(output)?,
+        reader.pages().len(),
+        reader.assets().len(),
+        reader.metadata().len(),
+    )?;
+    builder.set_alignment(align);
+    builder.set_compression(compression);
+
+    // Re-adding pages in order lets the builder redo deduplication and
+    // leaves out any asset no page points at.
+    audit::rewrite_clean(&reader, &mut builder)?;
+    let (pages, assets) = (builder.page_count(), builder.asset_count());
+
+    let after = finish_output(builder)?;
+
+    let before = mmap.len() as u64;
+    status!("Repacked {}", output_name(output));
+    status!("  Assets: {} -> {assets}", reader.assets().len());
+    if pages as usize != reader.pages().len() {
+        status!(
+            "  Pages:  {} -> {pages} (unreadable pages dropped)",
+            reader.pages().len()
+        );
+    }
+    status!(
+        "  Size:   {before} -> {after} bytes ({:+.1}%)",
+        (after as f64 - before as f64) / before as f64 * 100.0
+    );
+    Ok(())
+}
+
+fn cmd_transcode(
+    path: &Path,
+    target: transcode::TargetFormat,
+    quality: u8,
+    skip_if_smaller: bool,
+    output: &Path,
+) -> Result<()> {
+    if output.exists() && fs::canonicalize(output)? == fs::canonicalize(path)? {
+        bail!("Output must differ from the input file.");
+    }
+
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+
+    let assets = reader.assets();
+    let used: HashSet<u32> = reader.pages().iter().map(|p| p.asset_index.get()).collect();
+
+    // Each asset is encoded once no matter how many pages share it. `None`
+    // means the original bytes are kept.
+    let encoded: HashMap<u32, Option<Vec<u8>>> = used
+        .into_par_iter()
+        .map(|idx| {
+            let data = reader
+                .get_asset_decoded(idx)
+                .with_context(|| format!("Asset {idx}"))?;
+            let media_type = BBFMediaType::from(assets[idx as usize].type_);
+
+            if media_type == target.media_type() || !transcode::is_decodable(media_type) {
+                return Ok((idx, None));
+            }
+
+            match transcode::encode(&data, target, quality) {
+                Ok(out) if skip_if_smaller && out.len() >= data.len() => Ok((idx, None)),
+                Ok(out) => Ok((idx, Some(out))),
+                Err(e) => {
+                    log::warn!("Asset {idx} could not be transcoded, keeping it: {e}");
+                    Ok((idx, None))
+                }
+            }
+        })
+        .collect::<Result<_>>()?;
+
+    let mut builder = BBFBuilder::new(create_output(output)?)?;
+
+    for (i, page) in reader.pages().iter().enumerate() {
+        match &encoded[&page.asset_index.get()] {
+            Some(data) => {
+                builder.add_page(data, target.media_type(), page.flags.get())?;
+            }
+            None => {
+                copy_page(&reader, &mut builder, i)?;
+            }
+        }
+    }
+
+    copy_sections(&reader, &mut builder, |start| start);
+    copy_metadata(&reader, &mut builder);
+    let after = finish_output(builder)?;
+
+    let converted = encoded.values().filter(|e| e.is_some()).count();
+    let before = mmap.len() as u64;
+    status!(
+        "Transcoded {} ({converted} of {} assets re-encoded)",
+        output_name(output),
+        encoded.len()
+    );
+    status!(
+        "  Size:   {before} -> {after} bytes ({:+.1}%)",
+        (after as f64 - before as f64) / before as f64 * 100.0
+    );
+    Ok(())
+}
+
+fn cmd_thumbs(
+    path: &Path,
+    max: u32,
+    format: transcode::TargetFormat,
+    quality: u8,
+    outdir: Option<&Path>,
+) -> Result<()> {
+    if max == 0 {
+        bail!("--max must be at least 1");
+    }
+
+    let options = thumbs::Options {
+        max_dim: max,
+        format: format.media_type(),
+        quality,
+    };
+    let thumbs = {
+        let mmap = open_book(path)?;
+        let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+        let thumbs = thumbs::generate_with(&reader, options).context("Failed to read BBF")?;
+
+        let skipped = reader.pages().len() - thumbs.len();
+        if skipped > 0 {
+            log::warn!("{skipped} pages could not be decoded and have no thumbnail.");
+        }
+        thumbs
+    };
+
+    let ext = format.media_type().as_extension();
+
+    if let Some(outdir) = outdir {
+        fs::create_dir_all(outdir)?;
+        for (page, data) in &thumbs {
+            fs::write(outdir.join(format!("p{}{ext}", page + 1)), data)?;
+        }
+        println!("Wrote {} thumbnails to {}", thumbs.len(), outdir.display());
+        return Ok(());
+    }
+
+    let handle = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("Failed to open BBF")?;
+    let mut builder = BBFBuilder::from_existing(handle).context("Failed to load BBF index")?;
+
+    thumbs::embed(&mut builder, &thumbs, format.media_type())?;
+
+    finish_in_place(builder)?;
+    println!("Embedded {} thumbnails in {}", thumbs.len(), path.display());
+    Ok(())
+}
+
+fn cmd_append(
+    path: &Path,
+    inputs: &[PathBuf],
+    section: Option<&str>,
+    parent: Option<&str>,
+    input_opts: InputOpts,
+) -> Result<()> {
+    // Resolve the parent before touching the file so a typo can't leave it half-edited.
+    let parent_idx = match parent {
+        Some(title) => {
+            let mmap = open_book(path)?;
+            let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+            let idx = reader
+                .find_section(title)
+                .with_context(|| format!("Section '{title}' not found."))?;
+            Some(idx)
+        }
+        None => None,
+    };
+
+    let (manifest, _) = collect_inputs(inputs, input_opts)?;
+    if manifest.is_empty() {
+        bail!("No input files found.");
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("Failed to open BBF")?;
+    let mut builder = BBFBuilder::from_existing(file).context("Failed to load BBF index")?;
+
+    let first_page = builder.page_count();
+    add_input_pages(
+        &mut builder,
+        &manifest,
+        &[],
+        &mut HashCache::new(),
+        input_opts.preserve_times,
+    )?;
+
+    if let Some(title) = section {
+        builder.add_section(title, first_page, parent_idx);
+    }
+
+    finish_in_place(builder)?;
+    println!(
+        "Appended {} pages to {} (now {} pages)",
+        manifest.len(),
+        path.display(),
+        first_page as usize + manifest.len()
+    );
+    Ok(())
+}
+
+fn cmd_merge(paths: &[PathBuf], output: &Path, nest: bool) -> Result<()> {
+    if output.exists() {
+        let out = fs::canonicalize(output)?;
+        for path in paths {
+            if fs::canonicalize(path)? == out {
+                bail!("Output must differ from the input files.");
+            }
+        }
+    }
+
+    let maps = paths
+        .iter()
+        .map(|p| open_book(p))
+        .collect::<Result<Vec<_>>>()?;
+    let readers = maps
+        .iter()
+        .zip(paths)
+        .map(|(m, p)| {
+            BBFReader::new(&m[..]).with_context(|| format!("Failed to parse {}", p.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut builder = BBFBuilder::new(create_output(output)?)?;
+
+    let mut section_count = 0u32;
+    let mut seen_keys = HashSet::new();
+    let mut total_assets = 0;
+
+    for (reader, path) in readers.iter().zip(paths) {
+        let page_base = builder.page_count();
+        for i in 0..reader.pages().len() {
+            copy_page(reader, &mut builder, i)?;
+        }
+        total_assets += reader.assets().len();
+
+        let metadata = report::metadata(reader);
+
+        let root = nest.then(|| {
+            let title = metadata
+                .iter()
+                .find(|m| m.key.eq_ignore_ascii_case("Title"))
+                .map_or_else(
+                    || path.file_stem().unwrap_or_default().to_string_lossy(),
+                    |m| m.value.as_str().into(),
+                );
+            builder.add_section(&title, page_base, None);
+            section_count += 1;
+            section_count - 1
+        });
+
+        let section_base = section_count;
+        let sections = reader.sections();
+        for s in sections {
+            let title = reader
+                .get_string(s.section_title_offset.get())
+                .unwrap_or("");
+            let parent = s.parent_section_index.get();
+            let parent = if (parent as usize) < sections.len() {
+                Some(parent + section_base)
+            } else {
+                root
+            };
+            builder.add_section(title, s.section_start_index.get() + page_base, parent);
+        }
+        section_count += sections.len() as u32;
+
+        // The first book to define a key wins, so the omnibus keeps vol. 1's Title.
+        let mut new_keys = HashSet::new();
+        for m in metadata {
+            if !seen_keys.contains(&m.key) {
+                builder.add_metadata(&m.key, &m.value);
+                new_keys.insert(m.key);
+            }
+        }
+        seen_keys.extend(new_keys);
+    }
+
+    let pages = builder.page_count();
+    let assets = builder.asset_count();
+    finish_output(builder)?;
+
+    status!(
+        "Merged {} books into {} ({pages} pages)",
+        paths.len(),
+        output_name(output)
+    );
+    status!("  Assets: {total_assets} -> {assets}");
+    Ok(())
+}
+
+fn cmd_pack(paths: &[PathBuf], output: &Path) -> Result<()> {
+    if output.exists() {
+        let out = fs::canonicalize(output)?;
+        for path in paths {
+            if fs::canonicalize(path)? == out {
+                bail!("Output must differ from the input files.");
+            }
+        }
+    }
+
+    let mut names = HashSet::new();
+    let mut pack = PackBuilder::new(create_output(output)?)?;
+    let mut separate = 0;
+    let mut total_assets = 0;
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        if !names.insert(name.to_string()) {
+            bail!("Two inputs would both be volume '{name}'; rename one.");
+        }
+
+        let mmap = open_book(path)?;
+        let reader = BBFReader::new(&mmap[..])
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        separate += mmap.len() as u64;
+        total_assets += reader.assets().len();
+
+        let volume = pack.volume();
+        for i in 0..reader.pages().len() {
+            copy_page(&reader, volume, i)?;
+        }
+        copy_sections(&reader, volume, |p| p);
+        for m in report::metadata(&reader) {
+            volume.add_metadata(&m.key, &m.value);
+        }
+        pack.finish_volume(&name)?;
+    }
+
+    warn_all(pack.volume().diagnostics());
+    let assets = pack.asset_count();
+    let mut out = pack.finish()?;
+    out.flush()?;
+
+    status!(
+        "Packed {} volumes into {} ({} vs {} as separate books)",
+        paths.len(),
+        output_name(output),
+        report::human_size(out.written),
+        report::human_size(separate)
+    );
+    status!("  Assets: {total_assets} -> {assets}");
+    Ok(())
+}
+
+fn cmd_rm(path: &Path, pages_spec: &str, output: &Path) -> Result<()> {
+    if output.exists() && fs::canonicalize(output)? == fs::canonicalize(path)? {
+        bail!("Output must differ from the input file.");
+    }
+
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+
+    let pages = reader.pages();
+    let page_count = pages.len() as u32;
+    let removed: HashSet<u32> = parse_page_ranges(pages_spec, page_count)?
+        .into_iter()
+        .collect();
+
+    if removed.len() == pages.len() {
+        bail!("Refusing to remove every page.");
+    }
+
+    let mut builder = BBFBuilder::new(create_output(output)?)?;
+
+    // new_start[i] is the index old page i (or the next surviving page) ends up at.
+    let mut new_start = Vec::with_capacity(pages.len());
+    for i in 0..pages.len() {
+        new_start.push(builder.page_count());
+        if !removed.contains(&(i as u32)) {
+            copy_page(&reader, &mut builder, i)?;
+        }
+    }
+
+    // Sections whose pages all went away collapse onto the last page.
+    let last_page = builder.page_count() - 1;
+    copy_sections(&reader, &mut builder, |start| {
+        new_start
+            .get(start as usize)
+            .map_or(last_page, |&s| s.min(last_page))
+    });
+    copy_metadata(&reader, &mut builder);
+
+    let remaining = builder.page_count();
+    finish_output(builder)?;
+    status!(
+        "Removed {} pages, wrote {} ({remaining} pages)",
+        removed.len(),
+        output_name(output)
+    );
+    Ok(())
+}
+
+fn cmd_cover(path: &Path, set: Option<u32>, extract: Option<&Path>) -> Result<()> {
+    let Some(page) = set else {
+        let mmap = open_book(path)?;
+        let mut reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+        resolve_beside(&mut reader, path);
+        let Some(cover) = thumbs::cover_page(&reader) else {
+            bail!("The book has no pages.");
+        };
+        let flagged = reader.pages()[cover as usize].flags.get() & BBFPageEntry::COVER != 0;
+
+        if let Some(out) = extract {
+            let data = reader
+                .get_page(cover)
+                .with_context(|| format!("Failed to read page {}", cover + 1))?;
+            fs::write(out, &data).with_context(|| format!("Failed to write {}", out.display()))?;
+            let asset = reader.pages()[cover as usize].asset_index.get();
+            let ext = BBFMediaType::from(reader.assets()[asset as usize].type_).as_extension();
+            status!("Wrote page {} ({ext}) to {}", cover + 1, out.display());
+        } else if flagged {
+            println!("Cover: page {}", cover + 1);
+        } else {
+            println!(
+                "Cover: page {} (no page is flagged as the cover)",
+                cover + 1
+            );
+        }
+        return Ok(());
+    };
+
+    let handle = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .context("Failed to open BBF")?;
+    let mut builder = BBFBuilder::from_existing(handle).context("Failed to load BBF index")?;
+
+    let page_count = builder.page_count();
+    if page == 0 || page > page_count {
+        bail!("Page {page} is out of range (1-{page_count}).");
+    }
+    for i in 0..page_count {
+        let flags = builder.page_flags(i).unwrap_or(0) & !BBFPageEntry::COVER;
+        let flags = if i == page - 1 {
+            flags | BBFPageEntry::COVER
+        } else {
+            flags
+        };
+        builder.set_page_flags(i, flags)?;
+    }
+
+    finish_in_place(builder)?;
+    println!("Set the cover of {} to page {page}", path.display());
+    Ok(())
+}
+
+fn cmd_meta(action: &MetaAction) -> Result<()> {
+    let (file, key) = match action {
+        MetaAction::List { file, json } => return list_metadata(file, *json),
+        MetaAction::Set { file, key, .. } | MetaAction::Del { file, key } => (file, key),
+    };
+
+    let handle = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file)
+        .context("Failed to open BBF")?;
+    let mut builder = BBFBuilder::from_existing(handle).context("Failed to load BBF index")?;
+
+    if let MetaAction::Set { value, .. } = action {
+        builder.set_metadata(key, value);
+    } else if !builder.remove_metadata(key) {
+        bail!("Key '{key}' not found.");
+    }
+
+    finish_in_place(builder)?;
+    println!("Updated metadata in {}", file.display());
+    Ok(())
+}
+
+fn list_metadata(file: &Path, json: bool) -> Result<()> {
+    let mmap = open_book(file)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    let entries = report::metadata(&reader);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        for m in &entries {
+            println!("{}: {}", m.key, m.value);
+        }
+    }
+    Ok(())
+}
+
+struct SectionEntry {
+    title: String,
+    start: u32,
+    parent: Option<u32>,
+}
+
+fn section_entries<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<SectionEntry> {
+    let count = reader.sections().len() as u32;
+    reader
+        .sections()
+        .iter()
+        .map(|s| SectionEntry {
+            title: reader
+                .get_string(s.section_title_offset.get())
+                .unwrap_or("???")
+                .to_string(),
+            start: s.section_start_index.get(),
+            parent: Some(s.parent_section_index.get()).filter(|&p| p < count),
+        })
+        .collect()
+}
+
+fn cmd_section(action: &SectionAction) -> Result<()> {
+    let (SectionAction::Add { file, .. }
+    | SectionAction::Rename { file, .. }
+    | SectionAction::Remove { file, .. }
+    | SectionAction::Move { file, .. }) = action;
+
+    let (mut entries, page_count) = {
+        let mmap = open_book(file)?;
+        let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+        (section_entries(&reader), reader.pages().len() as u32)
+    };
+
+    let find = |entries: &[SectionEntry], title: &str| {
+        entries
+            .iter()
+            .position(|s| same_title(&s.title, title, false))
+            .map(|i| i as u32)
+            .with_context(|| format!("Section '{title}' not found."))
+    };
+    let start_index = |page: u32| {
+        if page == 0 || page > page_count {
+            bail!("Page {page} is out of range (1-{page_count}).");
+        }
+        Ok(page - 1)
+    };
+
+    let summary = match action {
+        SectionAction::Add {
+            title,
+            page,
+            parent,
+            ..
+        } => {
+            let parent = parent.as_deref().map(|p| find(&entries, p)).transpose()?;
+            entries.push(SectionEntry {
+                title: title.clone(),
+                start: start_index(*page)?,
+                parent,
+            });
+            format!("Added section '{title}' at page {page}")
+        }
+        SectionAction::Rename {
+            title, new_title, ..
+        } => {
+            let idx = find(&entries, title)?;
+            entries[idx as usize].title.clone_from(new_title);
+            format!("Renamed section '{title}' to '{new_title}'")
+        }
+        SectionAction::Remove { title, .. } => {
+            let idx = find(&entries, title)?;
+            let removed = entries.remove(idx as usize);
+            for s in &mut entries {
+                s.parent = match s.parent {
+                    Some(p) if p == idx => removed.parent,
+                    p => p,
+                };
+                s.parent = s.parent.map(|p| if p > idx { p - 1 } else { p });
+            }
+            format!("Removed section '{title}'")
+        }
+        SectionAction::Move {
+            title,
+            page,
+            parent,
+            top_level,
+            ..
+        } => {
+            let idx = find(&entries, title)?;
+            if let Some(page) = page {
+                entries[idx as usize].start = start_index(*page)?;
+            }
+            if let Some(parent) = parent {
+                let new_parent = find(&entries, parent)?;
+                // Walk up from the new parent; reaching the moved section
+                // means it would end up inside itself.
+                let mut cur = Some(new_parent);
+                for _ in 0..=entries.len() {
+                    match cur {
+                        Some(p) if p == idx => {
+                            bail!("Section '{parent}' is '{title}' or one of its subsections.")
+                        }
+                        Some(p) => cur = entries[p as usize].parent,
+                        None => break,
+                    }
+                }
+                entries[idx as usize].parent = Some(new_parent);
+            } else if *top_level {
+                entries[idx as usize].parent = None;
+            }
+            format!("Moved section '{title}'")
+        }
+    };
+
+    let handle = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(file)
+        .context("Failed to open BBF")?;
+    let mut builder = BBFBuilder::from_existing(handle).context("Failed to load BBF index")?;
+
+    builder.clear_sections();
+    for s in &entries {
+        builder.add_section(&s.title, s.start, s.parent);
+    }
+
+    finish_in_place(builder)?;
+    println!("{summary} in {}", file.display());
+    Ok(())
+}
+
+/// Re-adds page `index` of `reader` to `builder`, keeping its media type,
+/// flags and compression. Returns the asset index the builder stored it
+/// under.
+fn copy_page<T: AsRef<[u8]>, W: Write>(
+    reader: &BBFReader<T>,
+    builder: &mut BBFBuilder<W>,
+    index: usize,
+) -> Result<u32> {
+    let page = &reader.pages()[index];
+    let asset_index = page.asset_index.get();
+    let entry = reader
+        .assets()
+        .get(asset_index as usize)
+        .with_context(|| format!("Page {} points past the asset table", index + 1))?;
+    let new_index = builder.page_count();
+    // Stored bytes that decode are copied as they are; anything else (such
+    // as an external asset) goes through the decoded page.
+    let new_asset = match reader.get_asset(asset_index) {
+        Ok(data) if decode_asset(entry, data).is_ok() => {
+            builder.add_stored_page(data, entry, page.flags.get())?
+        }
+        _ => {
+            let data = reader
+                .get_asset_decoded(asset_index)
+                .with_context(|| format!("Page {}", index + 1))?;
+            let media_type = BBFMediaType::from(entry.type_);
+            let new_asset = builder.add_page(&data, media_type, page.flags.get())?;
+            if let Some(secs) = entry.mtime() {
+                builder.set_asset_mtime(new_asset, secs)?;
+            }
+            new_asset
+        }
+    };
+    if let Some(name) = reader.page_name(index as u32) {
+        builder.set_page_name(new_index, name);
+    }
+    Ok(new_asset)
+}
+
+/// Re-adds every section of `reader` to `builder`, passing start pages through `map_start`.
+fn copy_sections<T: AsRef<[u8]>, W: Write>(
+    reader: &BBFReader<T>,
+    builder: &mut BBFBuilder<W>,
+    map_start: impl Fn(u32) -> u32,
+) {
+    for s in reader.sections() {
+        let title = reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("");
+        let parent = s.parent_section_index.get();
+        builder.add_section(
+            title,
+            map_start(s.section_start_index.get()),
+            (parent != 0xFFFF_FFFF).then_some(parent),
+        );
+    }
+}
+
+fn copy_metadata<T: AsRef<[u8]>, W: Write>(reader: &BBFReader<T>, builder: &mut BBFBuilder<W>) {
+    for m in reader.metadata() {
+        builder.add_metadata(
+            reader.get_string(m.key_offset.get()).unwrap_or(""),
+            reader.get_string(m.val_offset.get()).unwrap_or(""),
+        );
+    }
+}
+
+/// For each page, the section it falls under: the one with the greatest start
+/// index at or before the page. On ties the later table entry wins, since
+/// subsections are written after their parents.
+fn owning_sections<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<Option<usize>> {
+    let page_count = reader.pages().len();
+    let mut starts: Vec<(u32, usize)> = reader
+        .sections()
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (s.section_start_index.get(), i))
+        .collect();
+    starts.sort_unstable();
+
+    let mut owners = vec![None; page_count];
+    let mut next = 0;
+    let mut current = None;
+    for (page, owner) in owners.iter_mut().enumerate() {
+        while next < starts.len() && starts[next].0 as usize <= page {
+            current = Some(starts[next].1);
+            next += 1;
+        }
+        *owner = current;
+    }
+    owners
+}
+
+/// Parses a 1-based page list such as `5,7,10-12,20-` into sorted, unique
+/// zero-based indices. Open ranges run to the end of the book.
+fn parse_page_ranges(spec: &str, page_count: u32) -> Result<Vec<u32>> {
+    let parse_page = |s: &str| -> Result<u32> {
+        let n = s
+            .trim()
+            .parse::<u32>()
+            .with_context(|| format!("Invalid page number '{}'", s.trim()))?;
+        if n == 0 || n > page_count {
+            bail!("Page {n} is out of range (book has {page_count} pages)");
+        }
+        Ok(n)
+    };
+
+    let mut out = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (first, last) = if let Some((a, b)) = part.split_once('-') {
+            let first = if a.trim().is_empty() {
+                1
+            } else {
+                parse_page(a)?
+            };
+            let last = if b.trim().is_empty() {
+                page_count
+            } else {
+                parse_page(b)?
+            };
+            (first, last)
+        } else {
+            let n = parse_page(part)?;
+            (n, n)
+        };
+        if first > last {
+            bail!("Invalid page range '{part}'");
+        }
+        out.extend(first - 1..last);
+    }
+
+    out.sort_unstable();
+    out.dedup();
+    Ok(out)
+}
+
+/// Set once a book is being streamed to stdout, so `status!` stays out of its way.
+static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--quiet`; `status!` prints nothing.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by `batch`, whose books build side by side and would fight over a
+/// single progress bar.
+static HIDE_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Opens `path` for writing a book, or stdout when it is `-`.
+fn create_output(path: &Path) -> Result<CountingWriter<Box<dyn Write>>> {
+    let inner: Box<dyn Write> = if path == Path::new("-") {
+        let stdout = io::stdout();
+        if stdout.is_terminal() {
+            bail!("Refusing to write a BBF file to a terminal; redirect stdout or use -o FILE.");
+        }
+        STATUS_TO_STDERR.store(true, atomic::Ordering::Relaxed);
+        Box::new(BufWriter::new(stdout.lock()))
+    } else {
+        Box::new(BufWriter::new(
+            File::create(path).context("Cannot create output file")?,
+        ))
+    };
+    Ok(CountingWriter { inner, written: 0 })
+}
+
+/// Finalizes a book opened with `create_output` and returns its size in bytes.
+fn finish_output(builder: BBFBuilder<CountingWriter<Box<dyn Write>>>) -> Result<u64> {
+    let (mut out, diagnostics) = builder.finish_with_diagnostics()?;
+    warn_all(&diagnostics);
+    out.flush()?;
+    Ok(out.written)
+}
+
+/// Reports what a builder wrote despite its problems.
+fn warn_all(diagnostics: &[String]) {
+    for d in diagnostics {
+        log::warn!("{d}.");
+    }
+}
+
+fn output_name(path: &Path) -> std::borrow::Cow<'_, str> {
+    if path == Path::new("-") {
+        "<stdout>".into()
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Finishes an edit opened with `BBFBuilder::from_existing`, trimming any
+/// leftover bytes of the previous index.
+fn finish_in_place(builder: BBFBuilder<File>) -> Result<()> {
+    let (mut file, diagnostics) = builder.finish_with_diagnostics()?;
+    warn_all(&diagnostics);
+    let end = file.stream_position()?;
+    file.set_len(end)?;
+    Ok(())
+}
+
+/// Lets `reader` read external assets from files next to the book at `path`.
+fn resolve_beside<T: AsRef<[u8]>>(reader: &mut BBFReader<T>, path: &Path) {
+    let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+    reader.set_resolver(Box::new(DirResolver { dir }));
+}
+
+fn open_book(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).context("Failed to open BBF")?;
+    unsafe { Mmap::map(&file).context("Failed to mmap BBF") }
+}
+
+/// Returns the pages to mux and, separately, any metadata sidecars found
+/// among the inputs; those are never pages.
+fn collect_inputs(inputs: &[PathBuf], opts: InputOpts) -> Result<(Vec<PagePlan>, Vec<PathBuf>)> {
+    let mut manifest = Vec::new();
+
+    for input_path in inputs {
+        if input_path.is_dir() {
+            // Keys keep the input folder's own name so several folders with
+            // the same layout (Vol1/Ch1, Vol2/Ch1) don't interleave.
+            let base = match input_path.file_name() {
+                Some(_) => input_path.parent().unwrap_or(input_path),
+                None => input_path,
+            };
+            collect_dir(&mut manifest, base, input_path, opts)?;
+        } else if !input_path.exists() && is_glob(input_path) {
+            let pattern = input_path.to_string_lossy();
+            let mut matched = false;
+            for path in glob::glob(&pattern).with_context(|| format!("Invalid glob '{pattern}'"))? {
+                let path = path?;
+                if path.is_file() && !(opts.skip_hidden && is_hidden(&path)) {
+                    // Keep the matched directories in the key so `a/**/*.png`
+                    // groups pages by folder instead of interleaving them.
+                    let key = path.to_string_lossy().into_owned();
+                    add_to_manifest(&mut manifest, path, key);
+                    matched = true;
+                }
+            }
+            if !matched {
+                input_problem(
+                    opts.strict,
+                    &format!("Pattern '{pattern}' matched no files"),
+                    "",
+                )?;
+            }
+        } else {
+            let key = file_name(input_path);
+            add_to_manifest(&mut manifest, input_path.clone(), key);
+        }
+    }
+
+    let (sidecars, mut manifest): (Vec<_>, Vec<_>) = manifest
+        .into_iter()
+        .partition(|p| sidecar::is_sidecar(&p.path));
+
+    if opts.strict
+        && let Some(p) = manifest
+            .iter()
+            .find(|p| input_media_type(&p.path) == BBFMediaType::Unknown)
+    {
+        bail!("{}: unsupported file type", p.path.display());
+    }
+
+    // sort_by is stable, so `SortMode::None` leaves unordered inputs as given.
+    manifest.sort_by(|a, b| compare_pages(a, b, opts.sort));
+    Ok((manifest, sidecars.into_iter().map(|p| p.path).collect()))
+}
+
+/// Adds the files in `dir` (and, with `--recursive`, below it) in name order,
+/// keyed by their path relative to `base`.
+fn collect_dir(
+    manifest: &mut Vec<PagePlan>,
+    base: &Path,
+    dir: &Path,
+    opts: InputOpts,
+) -> Result<()> {
+    // read_dir order is unspecified, so even `--sort none` lists a directory by name.
+    let mut entries = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        if opts.skip_hidden && is_hidden(&path) {
+            continue;
+        }
+        if path.is_file() {
+            let key = path
+                .strip_prefix(base)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .into_owned();
+            add_to_manifest(manifest, path, key);
+        } else if opts.recursive && path.is_dir() {
+            collect_dir(manifest, base, &path, opts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens a section whenever a page's folder differs from the previous page's,
+/// nesting each folder under its parent. `first_index` is the number of
+/// sections already in the builder.
+/// Sections for the folders pages came from. `first_index` is the table index
+/// the first returned section will get, so parents can be referenced.
+fn dir_sections(manifest: &[PagePlan], first_index: u32) -> Vec<PlannedSection> {
+    let mut sections = Vec::new();
+    let mut next_index = first_index;
+    // (folder name, section index) for each level of the current page's path.
+    let mut open: Vec<(String, u32)> = Vec::new();
+
+    for (page, plan) in manifest.iter().enumerate() {
+        let dirs: Vec<String> = Path::new(&plan.sort_key)
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+            .filter_map(|c| match c {
+                std::path::Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                _ => None,
+            })
+            .collect();
+
+        let shared = open
+            .iter()
+            .zip(&dirs)
+            .take_while(|((open_name, _), name)| open_name == *name)
+            .count();
+        open.truncate(shared);
+
+        for name in &dirs[shared..] {
+            let parent = open.last().map(|&(_, idx)| idx);
+            sections.push(PlannedSection {
+                title: name.clone(),
+                page: page as u32,
+                parent,
+            });
+            open.push((name.clone(), next_index));
+            next_index += 1;
+        }
+    }
+    sections
+}
+
+/// Turns `--section`/`--sections` requests into sections, resolving file
+/// name targets against the final page order.
+fn resolve_sections(
+    sec_reqs: &[SectionReq],
+    manifest: &[PagePlan],
+    strict: bool,
+) -> Result<Vec<PlannedSection>> {
+    let mut file_to_page_idx = HashMap::new();
+    for (i, p) in manifest.iter().enumerate() {
+        file_to_page_idx.insert(p.filename.clone(), i as u32);
+    }
+
+    let mut section_name_to_idx = HashMap::new();
+    let mut sections = Vec::new();
+
+    for (i, req) in sec_reqs.iter().enumerate() {
+        let page_idx = if req.is_filename {
+            if let Some(&idx) = file_to_page_idx.get(&req.target) {
+                idx
+            } else {
+                input_problem(
+                    strict,
+                    &format!(
+                        "{}: section target file '{}' not found",
+                        req.origin, req.target
+                    ),
+                    "Defaulting to page 1.",
+                )?;
+                0
+            }
+        } else {
+            let page = req.target.parse::<u32>().unwrap_or(1);
+            if strict && (page == 0 || page as usize > manifest.len()) {
+                bail!(
+                    "{}: page {page} is out of range (1-{})",
+                    req.origin,
+                    manifest.len()
+                );
+            }
+            page.saturating_sub(1)
+        };
+
+        let parent_idx = if req.parent.is_empty() {
+            None
+        } else {
+            let idx = section_name_to_idx.get(&req.parent).copied();
+            if idx.is_none() {
+                input_problem(
+                    strict,
+                    &format!(
+                        "{}: parent section '{}' is not defined before it",
+                        req.origin, req.parent
+                    ),
+                    "Adding it at the top level.",
+                )?;
+            }
+            idx
+        };
+
+        sections.push(PlannedSection {
+            title: req.name.clone(),
+            page: page_idx,
+            parent: parent_idx,
+        });
+        section_name_to_idx.insert(req.name.clone(), i as u32);
+    }
+    Ok(sections)
+}
+
+/// An input problem `--strict` refuses: an error in strict mode, otherwise a
+/// warning followed by what happens instead.
+fn input_problem(strict: bool, problem: &str, fallback: &str) -> Result<()> {
+    if strict {
+        bail!("{problem}");
+    }
+    if fallback.is_empty() {
+        log::warn!("{problem}.");
+    } else {
+        log::warn!("{problem}. {fallback}");
+    }
+    Ok(())
+}
+
+/// Adds a sidecar's bookmarks as top-level sections and returns how many.
+fn import_sidecar_sections(
+    path: &Path,
+    sidecar: &sidecar::Sidecar,
+    manifest: &[PagePlan],
+    sections: &mut Vec<PlannedSection>,
+    strict: bool,
+) -> Result<usize> {
+    // Bookmarks count images next to (or below) the sidecar, in page order.
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let local: Vec<u32> = manifest
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.path.starts_with(dir))
+        .map(|(i, _)| i as u32)
+        .collect();
+
+    let mut added = 0;
+    for (title, image) in &sidecar.sections {
+        match local.get(*image as usize) {
+            Some(&page) => {
+                sections.push(PlannedSection {
+                    title: title.clone(),
+                    page,
+                    parent: None,
+                });
+                added += 1;
+            }
+            None => input_problem(
+                strict,
+                &format!(
+                    "{} bookmarks image {image}, which isn't among the inputs",
+                    path.display()
+                ),
+                "Skipping it.",
+            )?,
+        }
+    }
+    Ok(added)
+}
+
+/// Moves the sidecar's metadata into `meta`, skipping keys already set by
+/// `--meta` or an earlier sidecar. Returns how many entries were taken.
+fn import_sidecar_metadata(sidecar: &mut sidecar::Sidecar, meta: &mut Vec<MetaReq>) -> usize {
+    let taken: HashSet<String> = meta.iter().map(|m| m.key.to_lowercase()).collect();
+    let before = meta.len();
+    meta.extend(
+        std::mem::take(&mut sidecar.metadata)
+            .into_iter()
+            .filter(|(key, _)| !taken.contains(&key.to_lowercase()))
+            .map(|(key, value)| MetaReq { key, value }),
+    );
+    meta.len() - before
+}
+
+fn is_output_template(output: &str) -> bool {
+    output != "-" && output.contains('{')
+}
+
+/// Fills `{Key}` placeholders in an output name from the book's metadata.
+/// Keys match case-insensitively; values are sanitized for the filesystem,
+/// and plain numbers can be zero-padded with `{Volume:02}`.
+fn expand_output(template: &str, meta: &[MetaReq]) -> Result<PathBuf> {
+    let name = template::expand(template, |key| {
+        let m = meta
+            .iter()
+            .find(|m| m.key == key)
+            .or_else(|| meta.iter().find(|m| m.key.eq_ignore_ascii_case(key)))?;
+        Some(match m.value.parse::<u64>() {
+            Ok(n) if n.to_string() == m.value => template::Value::Number(n),
+            _ => template::Value::Text(m.value.clone()),
+        })
+    })
+    .context(
+        "Output name needs metadata the book doesn't have (set it with --meta or a sidecar)",
+    )?;
+    if name.trim().is_empty() {
+        bail!("Output name '{template}' expanded to nothing");
+    }
+    Ok(PathBuf::from(name))
+}
+
+/// Prints what `build_book` would have written for `--dry-run`.
+fn print_plan(
+    manifest: &[PagePlan],
+    flags: &[u32],
+    cache: &HashCache,
+    sections: &[PlannedSection],
+    asset_count: u32,
+    size: u64,
+) {
+    println!("Page order:");
+    let mut first_seen = HashMap::new();
+    let mut saved = 0;
+    for (i, plan) in manifest.iter().enumerate() {
+        let note = match cache.get(&plan.path) {
+            Some(c) => match first_seen.entry(c.hash) {
+                std::collections::hash_map::Entry::Occupied(e) => {
+                    saved += c.len;
+                    format!("  (duplicate of page {})", e.get() + 1)
+                }
+                std::collections::hash_map::Entry::Vacant(e) => {
+                    e.insert(i);
+                    String::new()
+                }
+            },
+            None => String::new(),
+        };
+        let marks = flag_labels(flags[i]);
+        let marks = if marks.is_empty() {
+            marks
+        } else {
+            format!("  [{marks}]")
+        };
+        println!("  {:>5}  {}{marks}{note}", i + 1, plan.path.display());
+    }
+
+    if !sections.is_empty() {
+        println!("Sections:");
+        let mut depths: Vec<usize> = Vec::with_capacity(sections.len());
+        for s in sections {
+            let depth = s
+                .parent
+                .and_then(|p| depths.get(p as usize))
+                .map_or(0, |d| d + 1);
+            depths.push(depth);
+            println!("  {}{} -> page {}", "  ".repeat(depth), s.title, s.page + 1);
+        }
+    }
+
+    let pages = manifest.len();
+    println!(
+        "Dedupe: {pages} pages, {asset_count} unique assets ({} duplicates, {saved} bytes saved)",
+        pages.saturating_sub(asset_count as usize)
+    );
+    println!("Output size: {size} bytes (nothing written)");
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+fn is_hidden(path: &Path) -> bool {
+    let name = file_name(path);
+    name.starts_with('.')
+        || name.eq_ignore_ascii_case("Thumbs.db")
+        || name.eq_ignore_ascii_case("desktop.ini")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Reads and hashes inputs on the rayon pool a chunk at a time, then writes
+/// each chunk in order. Chunking keeps only a handful of files mapped at once.
+/// Adds every planned input as a page and returns how many had to be hashed.
+/// Page flags for each entry of `manifest` from `--spread`, `--rotate` and
+/// `--cover`. Patterns match a page's file name or its path relative to the
+/// input it came from.
+fn page_flags(cli: &Cli, manifest: &[PagePlan]) -> Result<Vec<u32>> {
+    let mut flags = vec![0; manifest.len()];
+
+    let mut apply = |pattern: &str, set: u32, clear: u32| -> Result<()> {
+        let glob =
+            glob::Pattern::new(pattern).with_context(|| format!("Invalid pattern '{pattern}'"))?;
+        let mut matched = false;
+        for (plan, f) in manifest.iter().zip(&mut flags) {
+            if glob.matches(&plan.filename) || glob.matches(&plan.sort_key) {
+                *f = (*f & !clear) | set;
+                matched = true;
+            }
+        }
+        if !matched {
+            input_problem(
+                cli.input_opts.strict,
+                &format!("Pattern '{pattern}' matched no pages"),
+                "",
+            )?;
+        }
+        Ok(())
+    };
+
+    for pattern in &cli.spread {
+        apply(pattern, BBFPageEntry::SPREAD, 0)?;
+    }
+    for pair in cli.rotate.chunks(2) {
+        let [degrees, pattern] = pair else {
+            unreachable!("clap requires two values per --rotate");
+        };
+        let rotation = degrees
+            .parse()
+            .ok()
+            .and_then(BBFPageEntry::rotation_flags)
+            .with_context(|| format!("Rotation must be 0, 90, 180 or 270, not '{degrees}'"))?;
+        apply(pattern, rotation, BBFPageEntry::ROTATION_MASK)?;
+    }
+    if let Some(cover) = &cli.cover {
+        apply(&glob::Pattern::escape(cover), BBFPageEntry::COVER, 0)?;
+    }
+    Ok(flags)
+}
+
+/// Comma-separated names of the page flags set in `flags`.
+fn flag_labels(flags: u32) -> String {
+    let mut labels = Vec::new();
+    if flags & BBFPageEntry::COVER != 0 {
+        labels.push("cover".to_string());
+    }
+    if flags & BBFPageEntry::SPREAD != 0 {
+        labels.push("spread".to_string());
+    }
+    let rotation = ((flags & BBFPageEntry::ROTATION_MASK) >> BBFPageEntry::ROTATION_SHIFT) * 90;
+    if rotation != 0 {
+        labels.push(format!("rotate {rotation}"));
+    }
+    labels.join(", ")
+}
+
+/// Pages beyond the end of `flags` get none.
+/// A bar counting `len` pages, hidden under `--quiet` or when progress is
+/// turned off.
+fn page_progress(len: u64) -> ProgressBar {
+    let progress =
+        if QUIET.load(atomic::Ordering::Relaxed) || HIDE_PROGRESS.load(atomic::Ordering::Relaxed) {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(len)
+        };
+    progress.with_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} pages ({per_sec}, {eta} left)")
+            .expect("valid progress template"),
+    )
+}
+
+fn add_input_pages<W: Write>(
+    builder: &mut BBFBuilder<W>,
+    plans: &[PagePlan],
+    flags: &[u32],
+    cache: &mut HashCache,
+    preserve_times: bool,
+) -> Result<usize> {
+    let progress = page_progress(plans.len() as u64);
+
+    let mut hashed = 0;
+    let chunk_size = rayon::current_num_threads() * 4;
+    for (chunk_index, chunk) in plans.chunks(chunk_size).enumerate() {
+        let loaded = chunk
+            .par_iter()
+            .map(|plan| load_input(&plan.path, cache.get(&plan.path)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (i, (plan, input)) in chunk.iter().zip(loaded).enumerate() {
+            let data = input.mmap.as_deref().unwrap_or(&[]);
+            let page = builder.page_count();
+            let page_flags = flags
+                .get(chunk_index * chunk_size + i)
+                .copied()
+                .unwrap_or(0);
+            let asset =
+                builder.add_page_with_hash(data, input.hash.hash, input.media_type, page_flags)?;
+            builder.set_page_name(page, &plan.filename);
+            if preserve_times
+                && let Some(secs) = input
+                    .hash
+                    .mtime
+                    .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            {
+                builder.set_asset_mtime(asset, secs.as_secs())?;
+            }
+            log::debug!(
+                "Page {}: {} ({} bytes, {})",
+                page + 1,
+                plan.path.display(),
+                data.len(),
+                if input.rehashed {
+                    "hashed"
+                } else {
+                    "cached hash"
+                }
+            );
+            hashed += usize::from(input.rehashed);
+            cache.insert(plan.path.clone(), input.hash);
+            progress.inc(1);
+        }
+    }
+
+    progress.finish_and_clear();
+    Ok(hashed)
+}
+
+struct LoadedInput {
+    /// `None` for empty files, which can't be mapped.
+    mmap: Option<Mmap>,
+    hash: CachedHash,
+    /// `false` when the hash came from the cache.
+    rehashed: bool,
+    media_type: BBFMediaType,
+}
+
+/// An input's hash along with the size and mtime it was computed for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct CachedHash {
+    len: u64,
+    mtime: Option<SystemTime>,
+    hash: u64,
+}
+
+type HashCache = HashMap<PathBuf, CachedHash>;
+
+fn input_media_type(path: &Path) -> BBFMediaType {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    BBFMediaType::from_extension(&format!(".{ext}"))
+}
+
+fn load_input(path: &Path, cached: Option<&CachedHash>) -> Result<LoadedInput> {
+    let input_file =
+        File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let media_type = input_media_type(path);
+
+    let metadata = input_file.metadata()?;
+    let (len, mtime) = (metadata.len(), metadata.modified().ok());
+    let mmap = if len == 0 {
+        None
+    } else {
+        Some(unsafe { Mmap::map(&input_file)? })
+    };
+
+    let fresh = cached.filter(|c| c.len == len && c.mtime.is_some() && c.mtime == mtime);
+    let hash = CachedHash {
+        len,
+        mtime,
+        hash: fresh.map_or_else(|| xxh3_64(mmap.as_deref().unwrap_or(&[])), |c| c.hash),
+    };
+
+    Ok(LoadedInput {
+        mmap,
+        hash,
+        rehashed: fresh.is_none(),
+        media_type,
+    })
+}
+
+fn add_to_manifest(manifest: &mut Vec<PagePlan>, path: PathBuf, sort_key: String) {
+    log::debug!("Input: {}", path.display());
+    let filename = file_name(&path);
+    let mtime = fs::metadata(&path).and_then(|m| m.modified()).ok();
+    manifest.push(PagePlan {
+        path,
+        filename,
+        sort_key,
+        order: 0,
+        mtime,
+    });
+}
+
+/// Parses an order file. Each non-blank line is one of `name`, `name:N`,
+/// `first..last:N`, or `!name`; lines starting with `#` are comments. Names
+/// match either a file name or the path shown by `order-template`.
+fn parse_order_file(content: &str) -> Result<OrderFile> {
+    let mut order = OrderFile::default();
+
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| anyhow::anyhow!("line {}: {msg}", line_no + 1);
+
+        if let Some(name) = line.strip_prefix('!') {
+            let name = trim_quotes(name.trim());
+            if name.is_empty() {
+                return Err(err("'!' must be followed by a file name".into()));
+            }
+            order.excluded.insert(name, line_no + 1);
+            continue;
+        }
+
+        let (target, position) = match line.rsplit_once(':') {
+            Some((target, idx)) => {
+                let position = idx
+                    .trim()
+                    .parse::<i32>()
+                    .map_err(|_| err(format!("invalid position '{}'", idx.trim())))?;
+                (target, position)
+            }
+            None => (line, 0),
+        };
+
+        if let Some((first, last)) = target.split_once("..") {
+            let (first, last) = (trim_quotes(first.trim()), trim_quotes(last.trim()));
+            if first.is_empty() || last.is_empty() {
+                return Err(err(format!("incomplete range '{target}'")));
+            }
+            if position == 0 {
+                return Err(err("a range needs a position (first..last:N)".into()));
+            }
+            order.entries.push(OrderEntry::Range(first, last, position));
+        } else {
+            let name = trim_quotes(target.trim());
+            if name.is_empty() {
+                return Err(err("missing file name".into()));
+            }
+            order.entries.push(OrderEntry::Single(name, position));
+        }
+    }
+
+    Ok(order)
+}
+
+/// Applies explicit positions and exclusions to an already sorted manifest,
+/// then re-sorts it. Ranges are resolved against the incoming order.
+fn apply_order(manifest: &mut Vec<PagePlan>, order: &OrderFile, opts: InputOpts) -> Result<()> {
+    let matches = |plan: &PagePlan, name: &str| plan.filename == name || plan.sort_key == name;
+    let find = |name: &str| {
+        manifest
+            .iter()
+            .position(|p| matches(p, name))
+            .with_context(|| format!("Order file names '{name}', which is not an input"))
+    };
+
+    let mut positions = vec![0; manifest.len()];
+    for entry in &order.entries {
+        match entry {
+            OrderEntry::Single(name, position) => positions[find(name)?] = *position,
+            OrderEntry::Range(first, last, position) => {
+                let (start, end) = (find(first)?, find(last)?);
+                if start > end {
+                    bail!("Order range '{first}..{last}' runs backwards in the current sort");
+                }
+                for (slot, pos) in positions[start..=end].iter_mut().zip(*position..) {
+                    *slot = pos;
+                }
+            }
+        }
+    }
+
+    for (plan, position) in manifest.iter_mut().zip(positions) {
+        plan.order = position;
+    }
+    for (name, line) in &order.excluded {
+        if !manifest.iter().any(|p| matches(p, name)) {
+            input_problem(
+                opts.strict,
+                &format!("line {line}: excluded file '{name}' is not an input"),
+                "",
+            )?;
+        }
+    }
+    manifest.retain(|p| {
+        !order.excluded.contains_key(&p.filename) && !order.excluded.contains_key(&p.sort_key)
+    });
+    manifest.sort_by(|a, b| compare_pages(a, b, opts.sort));
+    Ok(())
+}
+
+fn parse_section_string(s: &str, origin: String) -> SectionReq {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in s.split(':') {
+        parts.push(part);
+    }
+
+    let name = trim_quotes(parts.first().unwrap_or(&""));
     let target = trim_quotes(parts.get(1).unwrap_or(&"1"));
     let parent = trim_quotes(parts.get(2).unwrap_or(&""));
 
     let is_filename = !target.chars().all(char::is_numeric);
 
     SectionReq {
+        origin,
         name,
         target,
         parent,
@@ -505,14 +3308,22 @@ fn trim_quotes(s: &str) -> String {
     }
 }
 
-fn compare_pages(a: &PagePlan, b: &PagePlan) -> Ordering {
+fn compare_pages(a: &PagePlan, b: &PagePlan, sort: SortMode) -> Ordering {
     match (a.order, b.order) {
         (x, y) if x > 0 && y > 0 => x.cmp(&y),
 
         (x, y) if x > 0 && y <= 0 => Ordering::Less,
         (x, y) if x <= 0 && y > 0 => Ordering::Greater,
 
-        (0, 0) => a.filename.cmp(&b.filename),
+        (0, 0) => match sort {
+            SortMode::Natural => natural_cmp(&a.sort_key, &b.sort_key),
+            SortMode::Lexical => a.sort_key.cmp(&b.sort_key),
+            SortMode::Mtime => a
+                .mtime
+                .cmp(&b.mtime)
+                .then_with(|| natural_cmp(&a.sort_key, &b.sort_key)),
+            SortMode::None => Ordering::Equal,
+        },
 
         (0, y) if y < 0 => Ordering::Less,
         (x, 0) if x < 0 => Ordering::Greater,
@@ -520,3 +3331,41 @@ fn compare_pages(a: &PagePlan, b: &PagePlan) -> Ordering {
         (x, y) => x.cmp(&y),
     }
 }
+
+/// Orders strings so that runs of digits compare by numeric value
+/// (`page2` < `page10`) and letters ignore ASCII case. Names that only differ
+/// in case or zero padding fall back to plain ordering so the result is total.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.as_bytes(), b.as_bytes());
+
+    while let (Some(&cx), Some(&cy)) = (x.first(), y.first()) {
+        if cx.is_ascii_digit() && cy.is_ascii_digit() {
+            let split = |s: &[u8]| {
+                s.iter()
+                    .position(|c| !c.is_ascii_digit())
+                    .unwrap_or(s.len())
+            };
+            let (nx, rx) = x.split_at(split(x));
+            let (ny, ry) = y.split_at(split(y));
+
+            let trim = |n: &[u8]| n.iter().take_while(|&&c| c == b'0').count();
+            let (tx, ty) = (&nx[trim(nx)..], &ny[trim(ny)..]);
+
+            let ord = tx.len().cmp(&ty.len()).then_with(|| tx.cmp(ty));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            x = rx;
+            y = ry;
+        } else {
+            let (lx, ly) = (cx.to_ascii_lowercase(), cy.to_ascii_lowercase());
+            if lx != ly {
+                return lx.cmp(&ly);
+            }
+            x = &x[1..];
+            y = &y[1..];
+        }
+    }
+
+    x.len().cmp(&y.len()).then_with(|| a.cmp(b))
+}
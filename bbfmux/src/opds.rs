@@ -0,0 +1,421 @@
+//! `bbfmux opds`: a static catalog of a directory of books for reading apps,
+//! as an OPDS 1.2 Atom feed (`catalog.xml`) and an OPDS 2.0 feed
+//! (`catalog.json`), with each book's cover and thumbnail in `covers/`.
+
+use crate::report::{self, MetaEntry};
+use crate::{open_book, serve, status};
+use anyhow::{Context, Result, bail};
+use bbf::{BBFMediaType, BBFReader, imaging, thumbs};
+use rayon::prelude::{IntoParallelRefIterator, ParallelIterator};
+use serde_json::json;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+const BOOK_TYPE: &str = "application/x-bbf";
+const THUMB_MAX: u32 = 320;
+
+struct Entry {
+    id: String,
+    title: String,
+    authors: Vec<String>,
+    summary: Option<String>,
+    publisher: Option<String>,
+    language: Option<String>,
+    pages: usize,
+    updated: String,
+    href: String,
+    cover: Option<Image>,
+    thumbnail: Option<Image>,
+}
+
+struct Image {
+    href: String,
+    mime: &'static str,
+}
+
+pub fn run(dir: &Path, outdir: &Path, title: &str, base_url: Option<&str>) -> Result<()> {
+    let mut books = Vec::new();
+    find_books(dir, &mut books)
+        .with_context(|| format!("Cannot read library directory {}", dir.display()))?;
+    if books.is_empty() {
+        bail!("No .bbf files found in {}.", dir.display());
+    }
+    books.sort();
+
+    let covers = outdir.join("covers");
+    fs::create_dir_all(&covers)
+        .with_context(|| format!("Cannot create output directory {}", outdir.display()))?;
+
+    // Without a base URL, books are linked relative to the catalog, which
+    // works as long as both are served from the same tree.
+    let link_base = match base_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => relative_path(&fs::canonicalize(outdir)?, &fs::canonicalize(dir)?),
+    };
+
+    let entries: Vec<Entry> = books
+        .par_iter()
+        .filter_map(|path| {
+            let rel = path.strip_prefix(dir).unwrap_or(path);
+            let href = join_url(&link_base, &url_path(rel));
+            match entry(path, href, &covers) {
+                Ok(entry) => Some(entry),
+                Err(e) => {
+                    log::warn!("Skipping {}: {e:#}", path.display());
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let updated = rfc3339(SystemTime::now());
+    fs::write(
+        outdir.join("catalog.xml"),
+        atom_feed(title, &updated, &entries),
+    )?;
+    fs::write(
+        outdir.join("catalog.json"),
+        serde_json::to_string_pretty(&opds2_feed(title, &updated, &entries))?,
+    )?;
+
+    status!(
+        "Wrote a catalog of {} books to {}",
+        entries.len(),
+        outdir.display()
+    );
+    Ok(())
+}
+
+fn find_books(dir: &Path, books: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_books(&path, books)?;
+        } else if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("bbf"))
+        {
+            books.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reads `path`'s metadata and writes its cover images into `covers`.
+fn entry(path: &Path, href: String, covers: &Path) -> Result<Entry> {
+    let mmap = open_book(path)?;
+    let reader = BBFReader::new(&mmap[..]).context("Failed to parse BBF")?;
+    let metadata = report::metadata(&reader);
+    let id = format!("{:016x}", reader.footer.index_hash.get());
+
+    let title = meta(&metadata, "Title").map_or_else(
+        || {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        },
+        str::to_string,
+    );
+    let authors = meta(&metadata, "Author")
+        .or_else(|| meta(&metadata, "Writer"))
+        .map(|v| v.split(',').map(|a| a.trim().to_string()).collect())
+        .unwrap_or_default();
+
+    let (cover, thumbnail) = match thumbs::cover_page(&reader) {
+        Some(page) => write_covers(&reader, page, &id, covers).unwrap_or_else(|e| {
+            log::warn!("No cover for {}: {e:#}", path.display());
+            (None, None)
+        }),
+        None => (None, None),
+    };
+
+    let modified = fs::metadata(path)?.modified()?;
+
+    Ok(Entry {
+        id: format!("urn:bbf:{id}"),
+        title,
+        authors,
+        summary: meta(&metadata, "Summary").map(str::to_string),
+        publisher: meta(&metadata, "Publisher").map(str::to_string),
+        language: meta(&metadata, "LanguageISO")
+            .or_else(|| meta(&metadata, "Language"))
+            .map(str::to_string),
+        pages: reader.pages().len(),
+        updated: rfc3339(modified),
+        href,
+        cover,
+        thumbnail,
+    })
+}
+
+fn meta<'a>(metadata: &'a [MetaEntry], key: &str) -> Option<&'a str> {
+    metadata
+        .iter()
+        .find(|m| m.key.eq_ignore_ascii_case(key))
+        .map(|m| m.value.as_str())
+}
+
+/// Writes the cover page as is, and a thumbnail: the embedded one if the
+/// book has it, otherwise a downscaled JPEG when the page can be decoded.
+/// Fails if the cover page can't be read.
+fn write_covers<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    page: u32,
+    id: &str,
+    covers: &Path,
+) -> Result<(Option<Image>, Option<Image>)> {
+    let asset = reader.pages()[page as usize].asset_index.get();
+    let entry = reader
+        .assets()
+        .get(asset as usize)
+        .with_context(|| format!("Cover page {} points past the asset table", page + 1))?;
+    let media_type = BBFMediaType::from(entry.type_);
+    let cover = write_image(covers, id, media_type, &reader.get_asset_decoded(asset)?)?;
+
+    let thumb = reader
+        .thumbnail(page)
+        .and_then(|t| Some((t, reader.assets().get(t as usize)?)));
+    let thumbnail = if let Some((thumb, entry)) = thumb {
+        let media_type = BBFMediaType::from(entry.type_);
+        let name = format!("{id}-thumb");
+        Some(write_image(
+            covers,
+            &name,
+            media_type,
+            &reader.get_asset_decoded(thumb)?,
+        )?)
+    } else {
+        match reader.decode_page(page) {
+            Ok(image) => {
+                let image = image.thumbnail(THUMB_MAX, THUMB_MAX);
+                let data = imaging::encode(&image, BBFMediaType::Jpg, 75)?;
+                let name = format!("{id}-thumb");
+                Some(write_image(covers, &name, BBFMediaType::Jpg, &data)?)
+            }
+            Err(e) => {
+                log::debug!("No thumbnail for cover page {}: {e}", page + 1);
+                None
+            }
+        }
+    };
+
+    Ok((Some(cover), thumbnail))
+}
+
+fn write_image(covers: &Path, name: &str, media_type: BBFMediaType, data: &[u8]) -> Result<Image> {
+    let file = format!("{name}{}", media_type.as_extension());
+    fs::write(covers.join(&file), data)?;
+    Ok(Image {
+        href: format!("covers/{file}"),
+        mime: serve::mime_type(media_type),
+    })
+}
+
+fn atom_feed(title: &str, updated: &str, entries: &[Entry]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\" \
+         xmlns:dc=\"http://purl.org/dc/terms/\" xmlns:opds=\"http://opds-spec.org/2010/catalog\">\n",
+    );
+    let kind = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+    let _ = writeln!(xml, "  <id>urn:bbf:catalog</id>");
+    let _ = writeln!(xml, "  <title>{}</title>", escape_xml(title));
+    let _ = writeln!(xml, "  <updated>{updated}</updated>");
+    let _ = writeln!(
+        xml,
+        "  <link rel=\"self\" href=\"catalog.xml\" type=\"{kind}\"/>"
+    );
+    let _ = writeln!(
+        xml,
+        "  <link rel=\"start\" href=\"catalog.xml\" type=\"{kind}\"/>"
+    );
+
+    for e in entries {
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape_xml(&e.title));
+        let _ = writeln!(xml, "    <id>{}</id>", e.id);
+        let _ = writeln!(xml, "    <updated>{}</updated>", e.updated);
+        for author in &e.authors {
+            let _ = writeln!(
+                xml,
+                "    <author><name>{}</name></author>",
+                escape_xml(author)
+            );
+        }
+        if let Some(language) = &e.language {
+            let _ = writeln!(
+                xml,
+                "    <dc:language>{}</dc:language>",
+                escape_xml(language)
+            );
+        }
+        if let Some(publisher) = &e.publisher {
+            let _ = writeln!(
+                xml,
+                "    <dc:publisher>{}</dc:publisher>",
+                escape_xml(publisher)
+            );
+        }
+        if let Some(summary) = &e.summary {
+            let _ = writeln!(xml, "    <summary>{}</summary>", escape_xml(summary));
+        }
+        for (rel, image) in [
+            ("http://opds-spec.org/image", &e.cover),
+            ("http://opds-spec.org/image/thumbnail", &e.thumbnail),
+        ] {
+            if let Some(image) = image {
+                let _ = writeln!(
+                    xml,
+                    "    <link rel=\"{rel}\" href=\"{}\" type=\"{}\"/>",
+                    escape_xml(&image.href),
+                    image.mime
+                );
+            }
+        }
+        let _ = writeln!(
+            xml,
+            "    <link rel=\"http://opds-spec.org/acquisition\" href=\"{}\" type=\"{BOOK_TYPE}\"/>",
+            escape_xml(&e.href)
+        );
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+fn opds2_feed(title: &str, updated: &str, entries: &[Entry]) -> serde_json::Value {
+    let publications: Vec<_> = entries
+        .iter()
+        .map(|e| {
+            let mut metadata = json!({
+                "@type": "http://schema.org/Book",
+                "identifier": e.id,
+                "title": e.title,
+                "modified": e.updated,
+                "numberOfPages": e.pages,
+            });
+            if !e.authors.is_empty() {
+                metadata["author"] = json!(e.authors);
+            }
+            if let Some(language) = &e.language {
+                metadata["language"] = json!(language);
+            }
+            if let Some(publisher) = &e.publisher {
+                metadata["publisher"] = json!(publisher);
+            }
+            if let Some(summary) = &e.summary {
+                metadata["description"] = json!(summary);
+            }
+
+            let images: Vec<_> = [&e.cover, &e.thumbnail]
+                .into_iter()
+                .flatten()
+                .map(|i| json!({ "href": i.href, "type": i.mime }))
+                .collect();
+
+            json!({
+                "metadata": metadata,
+                "links": [{
+                    "rel": "http://opds-spec.org/acquisition",
+                    "href": e.href,
+                    "type": BOOK_TYPE,
+                }],
+                "images": images,
+            })
+        })
+        .collect();
+
+    json!({
+        "metadata": { "title": title, "modified": updated },
+        "links": [{ "rel": "self", "href": "catalog.json", "type": "application/opds+json" }],
+        "publications": publications,
+    })
+}
+
+/// A relative URL from directory `from` to directory `to`, both absolute.
+fn relative_path(from: &Path, to: &Path) -> String {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut parts = vec!["..".to_string(); from.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| percent_encode(&c.as_os_str().to_string_lossy())),
+    );
+    parts.join("/")
+}
+
+fn join_url(base: &str, path: &str) -> String {
+    if base.is_empty() {
+        path.to_string()
+    } else {
+        format!("{base}/{path}")
+    }
+}
+
+fn url_path(path: &Path) -> String {
+    path.components()
+        .map(|c| percent_encode(&c.as_os_str().to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+    out
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// `time` as an RFC 3339 UTC timestamp, e.g. `2024-05-01T12:00:00Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let days = (secs / 86_400).cast_signed();
+    let rem = secs % 86_400;
+
+    // Days since the epoch to a proleptic Gregorian date (Howard Hinnant's
+    // `civil_from_days`).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
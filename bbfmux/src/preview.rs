@@ -0,0 +1,242 @@
+//! Page previews for `bbfmux browse`.
+//!
+//! Kitty and sixel images are written straight to the terminal after ratatui
+//! has drawn a frame; the half-block fallback renders into the frame buffer
+//! like any other widget and works on every truecolor terminal.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::{cursor::MoveTo, queue, terminal};
+use ratatui::layout::Rect;
+use ratatui::style::Color;
+use ratatui::widgets::Widget;
+use std::io::{self, Write};
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Graphics {
+    /// Pick the best protocol the terminal advertises
+    Auto,
+    /// Kitty graphics protocol, also spoken by Ghostty and `WezTerm`
+    Kitty,
+    /// DEC sixel (foot, mlterm, xterm -ti vt340, ...)
+    Sixel,
+    /// Unicode half blocks in 24-bit color
+    Blocks,
+    /// No preview
+    None,
+}
+
+impl Graphics {
+    /// Resolves `Auto` from environment hints. Terminals don't reliably
+    /// answer capability queries, so this errs towards half blocks.
+    pub fn detect(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || program == "WezTerm"
+            || program == "ghostty"
+        {
+            Self::Kitty
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || program == "iTerm.app"
+        {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+
+    /// Whether the image is emitted outside of ratatui's buffer.
+    pub const fn is_escape_based(self) -> bool {
+        matches!(self, Self::Kitty | Self::Sixel)
+    }
+}
+
+/// Pixel size of one terminal cell, falling back to a common 10x20 when the
+/// terminal doesn't report its pixel dimensions.
+fn cell_size() -> (u32, u32) {
+    terminal::window_size()
+        .ok()
+        .filter(|w| w.width > 0 && w.height > 0 && w.columns > 0 && w.rows > 0)
+        .map_or((10, 20), |w| {
+            (
+                u32::from(w.width / w.columns).max(1),
+                u32::from(w.height / w.rows).max(1),
+            )
+        })
+}
+
+/// Decodes `data` and scales it to fit `area`, keeping its aspect ratio.
+/// Returns the image and the number of cells it covers.
+pub fn fit(data: &[u8], area: Rect, graphics: Graphics) -> Option<(RgbImage, Rect)> {
+    let img = image::load_from_memory(data).ok()?;
+    let (cell_w, cell_h) = match graphics {
+        Graphics::Blocks => (1, 2),
+        _ => cell_size(),
+    };
+    let max_w = u32::from(area.width) * cell_w;
+    let max_h = u32::from(area.height) * cell_h;
+    if max_w == 0 || max_h == 0 {
+        return None;
+    }
+
+    let img = img.resize(max_w, max_h, FilterType::Triangle);
+    let cells = Rect {
+        x: area.x,
+        y: area.y,
+        width: (img.width().div_ceil(cell_w) as u16).min(area.width),
+        height: (img.height().div_ceil(cell_h) as u16).min(area.height),
+    };
+    Some((DynamicImage::to_rgb8(&img), cells))
+}
+
+/// Draws `img` with the upper pixel of each cell as foreground and the
+/// lower one as background.
+pub struct HalfBlocks<'a>(pub &'a RgbImage);
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let img = self.0;
+        let rows = (img.height().div_ceil(2)).min(u32::from(area.height));
+        let cols = img.width().min(u32::from(area.width));
+
+        for row in 0..rows {
+            for col in 0..cols {
+                let top = img.get_pixel(col, row * 2).0;
+                let bottom = img
+                    .get_pixel_checked(col, row * 2 + 1)
+                    .map_or(Color::Reset, |p| Color::Rgb(p.0[0], p.0[1], p.0[2]));
+                if let Some(cell) = buf.cell_mut((area.x + col as u16, area.y + row as u16)) {
+                    cell.set_char('▀')
+                        .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                        .set_bg(bottom);
+                }
+            }
+        }
+    }
+}
+
+/// Writes `img` at the top-left of `area` using an escape-based protocol.
+pub fn emit(
+    out: &mut impl Write,
+    graphics: Graphics,
+    img: &RgbImage,
+    area: Rect,
+) -> io::Result<()> {
+    queue!(out, MoveTo(area.x, area.y))?;
+    match graphics {
+        Graphics::Kitty => emit_kitty(out, img, area),
+        Graphics::Sixel => emit_sixel(out, img),
+        _ => Ok(()),
+    }?;
+    out.flush()
+}
+
+/// Removes every image placed with the kitty protocol.
+pub fn clear_kitty(out: &mut impl Write) -> io::Result<()> {
+    write!(out, "\x1b_Ga=d,d=A,q=2\x1b\\")?;
+    out.flush()
+}
+
+fn emit_kitty(out: &mut impl Write, img: &RgbImage, area: Rect) -> io::Result<()> {
+    // Raw 24-bit pixels (f=24) avoid a PNG round trip; the payload is sent in
+    // 4 KiB chunks as the protocol requires.
+    let payload = STANDARD.encode(img.as_raw());
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,m={more};",
+                img.width(),
+                img.height(),
+                area.width,
+                area.height,
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={more};")?;
+        }
+        out.write_all(chunk)?;
+        write!(out, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Encodes `img` as sixel against a fixed 6x6x6 color cube, which is good
+/// enough for a preview and needs no quantization pass.
+fn emit_sixel(out: &mut impl Write, img: &RgbImage) -> io::Result<()> {
+    const LEVELS: u32 = 6;
+    let level = |v: u8| (u32::from(v) * (LEVELS - 1) + 127) / 255;
+    let index =
+        |p: &image::Rgb<u8>| (level(p.0[0]) * LEVELS + level(p.0[1])) * LEVELS + level(p.0[2]);
+
+    let (width, height) = img.dimensions();
+    let mut body = Vec::new();
+    write!(body, "\x1bPq\"1;1;{width};{height}")?;
+    for color in 0..LEVELS.pow(3) {
+        let (r, g, b) = (
+            color / LEVELS / LEVELS,
+            color / LEVELS % LEVELS,
+            color % LEVELS,
+        );
+        let pct = |c: u32| c * 100 / (LEVELS - 1);
+        write!(body, "#{color};2;{};{};{}", pct(r), pct(g), pct(b))?;
+    }
+
+    let indices: Vec<u32> = img.pixels().map(index).collect();
+    let (w, h) = (width as usize, height as usize);
+    let mut used = vec![false; LEVELS.pow(3) as usize];
+    let mut bits = vec![0u8; w];
+    for band in (0..h).step_by(6) {
+        let rows = &indices[band * w..(band + 6).min(h) * w];
+        used.fill(false);
+        for &c in rows {
+            used[c as usize] = true;
+        }
+
+        for color in (0..LEVELS.pow(3)).filter(|&c| used[c as usize]) {
+            bits.fill(0);
+            for (y, row) in rows.chunks(w).enumerate() {
+                for (x, _) in row.iter().enumerate().filter(|&(_, &c)| c == color) {
+                    bits[x] |= 1 << y;
+                }
+            }
+            write!(body, "#{color}")?;
+            write_sixel_row(&mut body, &bits)?;
+            body.push(b'$');
+        }
+        body.push(b'-');
+    }
+    body.extend_from_slice(b"\x1b\\");
+    out.write_all(&body)
+}
+
+/// Writes one color's pass over a band, run-length encoding repeats.
+fn write_sixel_row(out: &mut Vec<u8>, bits: &[u8]) -> io::Result<()> {
+    let mut i = 0;
+    while i < bits.len() {
+        let run = bits[i..].iter().take_while(|&&b| b == bits[i]).count();
+        let ch = b'?' + bits[i];
+        if run > 3 {
+            write!(out, "!{run}{}", ch as char)?;
+        } else {
+            out.extend(std::iter::repeat_n(ch, run));
+        }
+        i += run;
+    }
+    Ok(())
+}
@@ -0,0 +1,420 @@
+//! Serializable views of a book, used for `--json` output.
+//!
+//! Field names here are part of the CLI's output contract; add fields rather
+//! than renaming or removing them. All indices are zero-based.
+
+use bbf::format::BBFAssetEntry;
+use bbf::stats::{self, BookStats, SectionStats};
+use bbf::validate::{Issue, Severity};
+use bbf::{BBFMediaType, BBFReader};
+use serde::{Serialize, Serializer};
+
+#[derive(Serialize)]
+pub struct BookInfo {
+    pub version: u8,
+    pub page_count: u32,
+    pub asset_count: u32,
+    /// Stored bytes of all assets, each counted once. External assets take
+    /// none.
+    pub asset_bytes: u64,
+    pub pages: Vec<PageInfo>,
+    pub sections: Vec<SectionNode>,
+    pub metadata: Vec<MetaEntry>,
+}
+
+/// One volume of a `.bbfpack`.
+#[derive(Serialize)]
+pub struct VolumeInfo {
+    pub name: String,
+    pub book: BookInfo,
+}
+
+#[derive(Serialize)]
+pub struct PageInfo {
+    pub index: u32,
+    pub asset: u32,
+    pub media_type: &'static str,
+    pub length: u64,
+    pub flags: u32,
+    /// Asset index of the embedded preview, if any.
+    pub thumbnail: Option<u32>,
+    /// File the page was built from, if the book records it.
+    pub name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SectionNode {
+    pub index: u32,
+    pub title: String,
+    pub start_index: u32,
+    /// Pages up to the next section that isn't nested inside this one.
+    pub page_count: u32,
+    pub children: Vec<SectionNode>,
+}
+
+#[derive(Serialize)]
+pub struct MetaEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct VerifyReport {
+    pub ok: bool,
+    pub index_hash: HashCheck,
+    pub assets: Vec<AssetCheck>,
+}
+
+#[derive(Serialize)]
+pub struct HashCheck {
+    pub ok: bool,
+    #[serde(serialize_with = "hex")]
+    pub expected: u64,
+    #[serde(serialize_with = "hex")]
+    pub actual: u64,
+}
+
+#[derive(Serialize)]
+pub struct AssetCheck {
+    pub index: u32,
+    pub ok: bool,
+    #[serde(serialize_with = "hex")]
+    pub expected: u64,
+    /// `None` when the asset's byte range lies outside the file.
+    #[serde(serialize_with = "hex_opt")]
+    pub actual: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct StatsReport {
+    pub file_size: u64,
+    pub page_count: u32,
+    pub asset_count: u32,
+    pub asset_bytes: u64,
+    pub page_bytes: u64,
+    pub dedupe_savings: u64,
+    pub padding_bytes: u64,
+    pub index_bytes: u64,
+    pub string_pool_bytes: u64,
+    pub media_types: Vec<MediaTypeUsage>,
+    pub largest_pages: Vec<PageUsage>,
+    pub sections: Vec<SectionUsage>,
+}
+
+#[derive(Serialize)]
+pub struct MediaTypeUsage {
+    pub media_type: &'static str,
+    pub assets: u32,
+    pub pages: u32,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct PageUsage {
+    pub index: u32,
+    pub asset: u32,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct SectionUsage {
+    pub index: u32,
+    pub title: String,
+    pub start_index: u32,
+    pub page_count: u32,
+}
+
+#[derive(Serialize)]
+pub struct ValidationReport {
+    pub ok: bool,
+    pub profile: &'static str,
+    pub errors: usize,
+    pub warnings: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Serialize)]
+pub struct ValidationIssue {
+    pub severity: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+pub struct BenchReport {
+    pub file_size: u64,
+    pub page_count: usize,
+    /// Each measurement is the best of this many runs.
+    pub rounds: usize,
+    pub backends: Vec<BackendBench>,
+}
+
+#[derive(Serialize)]
+pub struct BackendBench {
+    /// `vec`, `mmap` or `io`.
+    pub backend: &'static str,
+    pub open_us: f64,
+    pub sequential_bytes_per_sec: f64,
+    pub random_p50_us: f64,
+    pub random_p99_us: f64,
+    pub verify_bytes_per_sec: f64,
+    pub hash_mismatches: usize,
+}
+
+/// Hashes are emitted as 16-digit hex strings; JSON numbers can't hold a u64 exactly.
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn hex<S: Serializer>(v: &u64, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format!("{v:016x}"))
+}
+
+#[allow(clippy::ref_option)]
+pub fn hex_opt<S: Serializer>(v: &Option<u64>, s: S) -> Result<S::Ok, S::Error> {
+    match v {
+        Some(v) => hex(v, s),
+        None => s.serialize_none(),
+    }
+}
+
+/// Binary-prefixed size for human output, e.g. `1.5 MiB`.
+#[allow(clippy::cast_precision_loss)]
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+pub fn media_type_name(type_: u8) -> &'static str {
+    BBFMediaType::from(type_)
+        .as_extension()
+        .trim_start_matches('.')
+}
+
+pub fn book_info<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> BookInfo {
+    let assets = reader.assets();
+    let names = page_names(reader);
+    let pages = reader
+        .pages()
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let asset = p.asset_index.get();
+            let entry = assets.get(asset as usize);
+            PageInfo {
+                index: i as u32,
+                asset,
+                media_type: media_type_name(entry.map_or(0, |a| a.type_)),
+                length: entry.map_or(0, |a| a.length.get()),
+                flags: p.flags.get(),
+                thumbnail: reader.thumbnail(i as u32),
+                name: names[i].map(str::to_string),
+            }
+        })
+        .collect();
+
+    BookInfo {
+        version: reader.header.version,
+        page_count: reader.footer.page_count.get(),
+        asset_count: reader.footer.asset_count.get(),
+        asset_bytes: assets
+            .iter()
+            .map(BBFAssetEntry::stored_len)
+            .fold(0, u64::saturating_add),
+        pages,
+        sections: section_tree(reader),
+        metadata: metadata(reader),
+    }
+}
+
+/// Recorded file name per page, looked up once instead of per page.
+pub fn page_names<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<Option<&str>> {
+    let mut names = vec![None; reader.pages().len()];
+    for n in reader.page_names() {
+        if let Some(slot) = names.get_mut(n.page_index.get() as usize) {
+            *slot = reader.get_string(n.name_offset.get());
+        }
+    }
+    names
+}
+
+pub fn metadata<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<MetaEntry> {
+    reader
+        .metadata()
+        .iter()
+        .map(|m| MetaEntry {
+            key: reader
+                .get_string(m.key_offset.get())
+                .unwrap_or("")
+                .to_string(),
+            value: reader
+                .get_string(m.val_offset.get())
+                .unwrap_or("")
+                .to_string(),
+        })
+        .collect()
+}
+
+/// How deep `section_tree` nests sections before treating the rest of a
+/// chain as malformed.
+pub const MAX_NESTING: usize = 64;
+
+/// Builds the section hierarchy from parent indices. Sections with a missing
+/// or invalid parent, caught in a parent cycle, or nested deeper than
+/// `MAX_NESTING`, are promoted to roots so nothing silently disappears.
+pub fn section_tree<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<SectionNode> {
+    let sections = reader.sections();
+    let count = sections.len();
+
+    let mut children = vec![Vec::new(); count];
+    let mut roots = Vec::new();
+    for (i, s) in sections.iter().enumerate() {
+        let parent = s.parent_section_index.get() as usize;
+        if parent < count && parent != i {
+            children[parent].push(i);
+        } else {
+            roots.push(i);
+        }
+    }
+
+    let spans = stats::section_stats(reader);
+    let mut visited = vec![false; count];
+    let mut too_deep = false;
+    let mut tree = Vec::new();
+    for root in roots.into_iter().chain(0..count) {
+        if !visited[root] {
+            tree.push(build_node(
+                reader,
+                root,
+                &children,
+                &spans,
+                &mut visited,
+                &mut too_deep,
+                0,
+            ));
+        }
+    }
+    if too_deep {
+        log::warn!(
+            "Sections are nested more than {MAX_NESTING} deep; showing the rest as top-level sections."
+        );
+    }
+    tree
+}
+
+fn build_node<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    idx: usize,
+    children: &[Vec<usize>],
+    spans: &[SectionStats],
+    visited: &mut [bool],
+    too_deep: &mut bool,
+    depth: usize,
+) -> SectionNode {
+    visited[idx] = true;
+    let s = &reader.sections()[idx];
+
+    let mut node = SectionNode {
+        index: idx as u32,
+        title: reader
+            .get_string(s.section_title_offset.get())
+            .unwrap_or("")
+            .to_string(),
+        start_index: s.section_start_index.get(),
+        page_count: spans[idx].page_count,
+        children: Vec::new(),
+    };
+
+    if depth + 1 >= MAX_NESTING {
+        *too_deep |= children[idx].iter().any(|&c| !visited[c]);
+        return node;
+    }
+    for &child in &children[idx] {
+        if !visited[child] {
+            node.children.push(build_node(
+                reader,
+                child,
+                children,
+                spans,
+                visited,
+                too_deep,
+                depth + 1,
+            ));
+        }
+    }
+    node
+}
+
+pub fn stats<T: AsRef<[u8]>>(reader: &BBFReader<T>, largest: usize) -> StatsReport {
+    let s = BookStats::compute(reader, largest);
+    let sections = reader.sections();
+    StatsReport {
+        file_size: s.file_size,
+        page_count: s.page_count,
+        asset_count: s.asset_count,
+        asset_bytes: s.asset_bytes,
+        page_bytes: s.page_bytes,
+        dedupe_savings: s.dedupe_savings,
+        padding_bytes: s.padding_bytes,
+        index_bytes: s.index_bytes,
+        string_pool_bytes: s.string_pool_bytes,
+        media_types: s
+            .media_types
+            .iter()
+            .map(|m| MediaTypeUsage {
+                media_type: m.media_type.as_extension().trim_start_matches('.'),
+                assets: m.assets,
+                pages: m.pages,
+                bytes: m.bytes,
+            })
+            .collect(),
+        largest_pages: s
+            .largest_pages
+            .iter()
+            .map(|p| PageUsage {
+                index: p.page_index,
+                asset: p.asset_index,
+                bytes: p.bytes,
+            })
+            .collect(),
+        sections: s
+            .sections
+            .iter()
+            .map(|sec| SectionUsage {
+                index: sec.section_index,
+                title: sections
+                    .get(sec.section_index as usize)
+                    .and_then(|t| reader.get_string(t.section_title_offset.get()))
+                    .unwrap_or("???")
+                    .to_string(),
+                start_index: sec.start_index,
+                page_count: sec.page_count,
+            })
+            .collect(),
+    }
+}
+
+pub fn validation(profile: &'static str, issues: &[Issue]) -> ValidationReport {
+    let count = |severity| issues.iter().filter(|i| i.severity == severity).count();
+    ValidationReport {
+        ok: count(Severity::Error) == 0,
+        profile,
+        errors: count(Severity::Error),
+        warnings: count(Severity::Warning),
+        issues: issues
+            .iter()
+            .map(|i| ValidationIssue {
+                severity: i.severity.to_string(),
+                code: i.code,
+                message: i.message.clone(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,194 @@
+//! `bbfmux serve`: a small HTTP server that reads pages straight out of the
+//! mapped book.
+//!
+//! Routes (page indices are zero-based, like the JSON output):
+//! - `GET /`                the reading UI
+//! - `GET /api/book`        `report::BookInfo` as JSON
+//! - `GET /api/pages/N`     page image, with `Range` and `ETag` support
+//! - `GET /api/thumbs/N`    embedded thumbnail for page N, if any
+
+use crate::report;
+use anyhow::{Context, Result};
+use bbf::{BBFMediaType, BBFReader};
+use std::io::Cursor;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+const INDEX_HTML: &str = include_str!("serve.html");
+const WORKERS: usize = 4;
+
+pub fn run<T: AsRef<[u8]> + Sync>(reader: &BBFReader<T>, addr: &str) -> Result<()> {
+    let server = Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("{e}"))
+        .with_context(|| format!("Failed to listen on {addr}"))?;
+    let book_json = serde_json::to_string(&report::book_info(reader))?;
+
+    println!("Serving on http://{addr}/ (Ctrl+C to stop)");
+
+    std::thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| {
+                while let Ok(request) = server.recv() {
+                    if let Err(e) = handle(reader, &book_json, request) {
+                        log::warn!("Failed to send response: {e}");
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    book_json: &str,
+    request: Request,
+) -> std::io::Result<()> {
+    if *request.method() != Method::Get && *request.method() != Method::Head {
+        return request.respond(text(405, "Method not allowed"));
+    }
+
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match segments.as_slice() {
+        [""] => request.respond(
+            Response::from_string(INDEX_HTML).with_header(header("Content-Type", "text/html")),
+        ),
+        ["api", "book"] => request.respond(
+            Response::from_string(book_json)
+                .with_header(header("Content-Type", "application/json")),
+        ),
+        ["api", "pages", n] => {
+            let asset = n
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| reader.pages().get(n))
+                .map(|p| p.asset_index.get());
+            serve_asset(reader, asset, request)
+        }
+        ["api", "thumbs", n] => {
+            let asset = n.parse::<u32>().ok().and_then(|n| reader.thumbnail(n));
+            serve_asset(reader, asset, request)
+        }
+        _ => request.respond(text(404, "Not found")),
+    }
+}
+
+fn serve_asset<T: AsRef<[u8]>>(
+    reader: &BBFReader<T>,
+    asset_index: Option<u32>,
+    request: Request,
+) -> std::io::Result<()> {
+    let Some(asset_index) = asset_index else {
+        return request.respond(text(404, "No such page"));
+    };
+    let (Ok(data), Some(entry)) = (
+        reader.get_asset_decoded(asset_index),
+        reader.assets().get(asset_index as usize),
+    ) else {
+        return request.respond(text(500, "Asset out of bounds"));
+    };
+
+    // Assets are content-addressed, so the stored hash is a perfect ETag.
+    let etag = format!("\"{:016x}\"", entry.xxh3_hash.get());
+    let mime = mime_type(BBFMediaType::from(entry.type_));
+    let common = [
+        header("Content-Type", mime),
+        header("ETag", &etag),
+        header("Accept-Ranges", "bytes"),
+        header("Cache-Control", "public, max-age=3600"),
+    ];
+
+    if request_header(&request, "If-None-Match").is_some_and(|v| v == etag) {
+        let mut response = Response::empty(304);
+        for h in common {
+            response.add_header(h);
+        }
+        return request.respond(response);
+    }
+
+    let total = data.len();
+    let (status, body, range) = match request_header(&request, "Range") {
+        Some(spec) => {
+            let Some((start, end)) = parse_range(spec, total) else {
+                let response = text(416, "Range not satisfiable")
+                    .with_header(header("Content-Range", &format!("bytes */{total}")));
+                return request.respond(response);
+            };
+            (206, &data[start..=end], Some((start, end)))
+        }
+        None => (200, &data[..], None),
+    };
+
+    let mut response = Response::new(
+        StatusCode(status),
+        common.to_vec(),
+        Cursor::new(body),
+        Some(body.len()),
+        None,
+    );
+    if let Some((start, end)) = range {
+        response.add_header(header(
+            "Content-Range",
+            &format!("bytes {start}-{end}/{total}"),
+        ));
+    }
+    request.respond(response)
+}
+
+/// Parses a single `bytes=` range into inclusive bounds. Multi-range
+/// requests and anything outside the body yield `None`.
+fn parse_range(spec: &str, total: usize) -> Option<(usize, usize)> {
+    let spec = spec.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || total == 0 {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let len = suffix.parse::<usize>().ok()?.min(total);
+            if len == 0 {
+                return None;
+            }
+            (total - len, total - 1)
+        }
+        (start, "") => (start.parse().ok()?, total - 1),
+        (start, end) => (
+            start.parse().ok()?,
+            end.parse::<usize>().ok()?.min(total - 1),
+        ),
+    };
+    (start <= end && start < total).then_some((start, end))
+}
+
+pub const fn mime_type(media_type: BBFMediaType) -> &'static str {
+    match media_type {
+        BBFMediaType::Png => "image/png",
+        BBFMediaType::Jpg => "image/jpeg",
+        BBFMediaType::Avif => "image/avif",
+        BBFMediaType::Webp => "image/webp",
+        BBFMediaType::Jxl => "image/jxl",
+        BBFMediaType::Bmp => "image/bmp",
+        BBFMediaType::Gif => "image/gif",
+        BBFMediaType::Tiff => "image/tiff",
+        BBFMediaType::Unknown | BBFMediaType::Other(_) => "application/octet-stream",
+    }
+}
+
+fn request_header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str())
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("valid header")
+}
+
+fn text(status: u16, body: &str) -> Response<Cursor<Vec<u8>>> {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
@@ -0,0 +1,136 @@
+//! Metadata sidecars found next to muxed images: `ComicInfo.xml` as written by
+//! comic managers (and by `bbfmux export --format cbz`) and a flat
+//! `metadata.json`.
+
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::path::Path;
+
+/// Metadata entries and page bookmarks read from one sidecar. Section pages
+/// are zero-based and relative to the images the sidecar sits next to.
+#[derive(Default)]
+pub struct Sidecar {
+    pub metadata: Vec<(String, String)>,
+    pub sections: Vec<(String, u32)>,
+}
+
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+        n.eq_ignore_ascii_case("ComicInfo.xml") || n.eq_ignore_ascii_case("metadata.json")
+    })
+}
+
+pub fn load(path: &Path) -> Result<Sidecar> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let parsed = if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"))
+    {
+        parse_metadata_json(&content)
+    } else {
+        parse_comic_info(&content)
+    };
+    parsed.with_context(|| format!("Invalid sidecar {}", path.display()))
+}
+
+/// Maps each `ComicInfo` element onto a metadata key of the same name and each
+/// `<Page Bookmark="...">` onto a section. `<Notes>` made of `Key: Value`
+/// lines, which is how `export` stores keys `ComicInfo` has no element for, is
+/// split back into separate keys.
+fn parse_comic_info(xml: &str) -> Result<Sidecar> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("ComicInfo") {
+        bail!(
+            "Root element is <{}>, not <ComicInfo>",
+            root.tag_name().name()
+        );
+    }
+
+    let mut sidecar = Sidecar::default();
+    for node in root.children().filter(roxmltree::Node::is_element) {
+        match node.tag_name().name() {
+            "Pages" => {
+                for page in node.children().filter(|n| n.has_tag_name("Page")) {
+                    let image = page.attribute("Image").and_then(|i| i.parse().ok());
+                    if let (Some(image), Some(title)) = (image, page.attribute("Bookmark")) {
+                        sidecar.sections.push((title.to_string(), image));
+                    }
+                }
+            }
+            // Derived from the pages themselves.
+            "PageCount" => {}
+            "Notes" => {
+                let notes = node.text().unwrap_or("").trim();
+                let pairs: Option<Vec<_>> = notes.lines().map(|l| l.split_once(": ")).collect();
+                match pairs {
+                    Some(pairs) if !notes.is_empty() => sidecar.metadata.extend(
+                        pairs
+                            .into_iter()
+                            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string())),
+                    ),
+                    _ => push_value(&mut sidecar.metadata, "Notes", notes),
+                }
+            }
+            name => push_value(&mut sidecar.metadata, name, node.text().unwrap_or("")),
+        }
+    }
+    Ok(sidecar)
+}
+
+/// A flat JSON object of keys to strings, numbers, booleans or arrays of
+/// those (one metadata entry per element). An optional `sections` array of
+/// `{"title": ..., "page": N}` objects adds bookmarks, with `page` one-based.
+fn parse_metadata_json(json: &str) -> Result<Sidecar> {
+    let Value::Object(object) = serde_json::from_str(json)? else {
+        bail!("Expected a JSON object at the top level");
+    };
+
+    let mut sidecar = Sidecar::default();
+    for (key, value) in object {
+        if key == "sections" {
+            let Value::Array(sections) = value else {
+                bail!("\"sections\" must be an array");
+            };
+            for s in sections {
+                let title = s.get("title").and_then(Value::as_str);
+                let page = s.get("page").and_then(Value::as_u64);
+                let (Some(title), Some(page)) = (title, page) else {
+                    bail!("Each section needs a string \"title\" and a numeric \"page\"");
+                };
+                let page = u32::try_from(page.saturating_sub(1)).unwrap_or(u32::MAX);
+                sidecar.sections.push((title.to_string(), page));
+            }
+            continue;
+        }
+
+        let values = match value {
+            Value::Array(items) => items,
+            other => vec![other],
+        };
+        for v in values {
+            match scalar(&v) {
+                Some(text) => push_value(&mut sidecar.metadata, &key, &text),
+                None => log::warn!("Skipping non-scalar metadata value for '{key}'."),
+            }
+        }
+    }
+    Ok(sidecar)
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn push_value(metadata: &mut Vec<(String, String)>, key: &str, value: &str) {
+    let value = value.trim();
+    if !value.is_empty() {
+        metadata.push((key.to_string(), value.to_string()));
+    }
+}
@@ -0,0 +1,80 @@
+//! `{name}` / `{name:04}` placeholder expansion for user-supplied file names.
+
+use anyhow::{Result, bail};
+use std::fmt::Write as _;
+
+pub enum Value {
+    Text(String),
+    Number(u64),
+}
+
+/// Expands every `{name}` or `{name:spec}` in `template` with `lookup`, which
+/// returns `None` for unknown names. `{{` and `}}` produce literal braces.
+///
+/// `spec` is a width as in `format!`: `{page:4}` pads to four columns and
+/// `{page:04}` pads numbers with zeros. Substituted text is made safe to use
+/// as a single path component; the template itself may still contain `/`.
+pub fn expand(template: &str, lookup: impl Fn(&str) -> Option<Value>) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        let brace = rest.as_bytes()[pos];
+        rest = &rest[pos + 1..];
+
+        if rest.as_bytes().first() == Some(&brace) {
+            out.push(brace as char);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == b'}' {
+            bail!("Unmatched '}}' in template '{template}'");
+        }
+
+        let Some(end) = rest.find('}') else {
+            bail!("Unclosed '{{' in template '{template}'");
+        };
+        let (name, spec) = rest[..end].split_once(':').unwrap_or((&rest[..end], ""));
+        rest = &rest[end + 1..];
+
+        let Some(value) = lookup(name.trim()) else {
+            bail!("Unknown variable '{{{name}}}' in template '{template}'");
+        };
+        let zero = spec.starts_with('0');
+        let width: usize = if spec.is_empty() {
+            0
+        } else {
+            spec.parse()
+                .map_err(|_| anyhow::anyhow!("Invalid width '{spec}' for '{{{name}}}'"))?
+        };
+
+        match value {
+            Value::Number(n) if zero => write!(out, "{n:0width$}")?,
+            Value::Number(n) => write!(out, "{n:>width$}")?,
+            Value::Text(s) => write!(out, "{:<width$}", sanitize(&s))?,
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Replaces characters that are reserved on common filesystems, and names
+/// that would walk the directory tree, so book data can't escape the
+/// output directory.
+pub fn sanitize(s: &str) -> String {
+    let cleaned: String = s
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).trim_start();
+    if cleaned.is_empty() && !s.is_empty() {
+        "_".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+use bbf::BBFMediaType;
+use clap::ValueEnum;
+use image::DynamicImage;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TargetFormat {
+    /// Lossy AV1 still images, usually the smallest output
+    Avif,
+    /// Lossy JPEG
+    Jpg,
+    /// Lossless WebP (quality is ignored)
+    Webp,
+    /// Lossless PNG (quality is ignored)
+    Png,
+}
+
+impl TargetFormat {
+    pub const fn media_type(self) -> BBFMediaType {
+        match self {
+            Self::Avif => BBFMediaType::Avif,
+            Self::Jpg => BBFMediaType::Jpg,
+            Self::Webp => BBFMediaType::Webp,
+            Self::Png => BBFMediaType::Png,
+        }
+    }
+}
+
+/// Media types `image` can decode with the features this crate enables.
+pub const fn is_decodable(media_type: BBFMediaType) -> bool {
+    matches!(
+        media_type,
+        BBFMediaType::Png
+            | BBFMediaType::Jpg
+            | BBFMediaType::Webp
+            | BBFMediaType::Bmp
+            | BBFMediaType::Gif
+            | BBFMediaType::Tiff
+    )
+}
+
+/// Decodes `data` and re-encodes it as `target`. `quality` is 1-100 and only
+/// applies to lossy targets.
+pub fn encode(data: &[u8], target: TargetFormat, quality: u8) -> Result<Vec<u8>> {
+    encode_image(&image::load_from_memory(data)?, target, quality)
+}
+
+fn encode_image(img: &DynamicImage, target: TargetFormat, quality: u8) -> Result<Vec<u8>> {
+    Ok(bbf::imaging::encode(img, target.media_type(), quality)?)
+}
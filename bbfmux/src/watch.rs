@@ -0,0 +1,134 @@
+//! `bbfmux --watch`: rebuild the output whenever an input changes.
+
+use crate::{Cli, HashCache, build_book, is_glob};
+use anyhow::{Context, Result, bail};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Editors and scanners tend to touch a file several times in a row; wait for
+/// this much quiet before rebuilding.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+pub fn run(cli: &Cli, output: &Path) -> Result<()> {
+    if output == Path::new("-") {
+        bail!("--watch can't write to stdout; use -o FILE.");
+    }
+
+    // Build next to the output and rename over it, so anything reading the
+    // book never sees a half-written file.
+    let staging = staging_path(output);
+    let mut cache = HashCache::new();
+    rebuild(cli, output, &staging, &mut cache);
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start file watcher")?;
+    for (path, mode) in watch_targets(cli) {
+        watcher
+            .watch(&path, mode)
+            .with_context(|| format!("Failed to watch {}", path.display()))?;
+    }
+
+    println!("Watching for changes (Ctrl+C to stop)...");
+    while let Ok(event) = rx.recv() {
+        if !is_relevant(&event?, output, &staging) {
+            continue;
+        }
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            event?;
+        }
+        rebuild(cli, output, &staging, &mut cache);
+    }
+    Ok(())
+}
+
+/// Reports build errors instead of returning them; a file that is still being
+/// copied shouldn't end the session.
+fn rebuild(cli: &Cli, output: &Path, staging: &Path, cache: &mut HashCache) {
+    let started = Instant::now();
+    let result = build_book(cli, &cli.inputs, staging, cache).and_then(|stats| {
+        std::fs::rename(staging, output)
+            .with_context(|| format!("Failed to replace {}", output.display()))?;
+        Ok(stats)
+    });
+
+    match result {
+        Ok(stats) => println!(
+            "Rebuilt {} ({} pages, {} re-read) in {:.2?}",
+            output.display(),
+            stats.pages,
+            stats.hashed,
+            started.elapsed()
+        ),
+        Err(e) => {
+            let _ = std::fs::remove_file(staging);
+            log::error!("Build failed: {e:#}");
+        }
+    }
+
+    // Inputs that disappeared shouldn't keep their hashes around forever.
+    cache.retain(|path, _| path.exists());
+}
+
+fn staging_path(output: &Path) -> PathBuf {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!(".{name}.tmp"))
+}
+
+/// Directories are watched directly. Files are watched through their parent
+/// because many tools save by writing a new file and renaming it into place.
+fn watch_targets(cli: &Cli) -> Vec<(PathBuf, RecursiveMode)> {
+    let recursive = cli.input_opts.recursive || cli.sections_from_dirs;
+    let mut targets = Vec::new();
+
+    let files = cli.order.iter().chain(&cli.sections);
+    for input in cli.inputs.iter().chain(files) {
+        let target = if input.is_dir() {
+            let mode = if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            (input.clone(), mode)
+        } else if !input.exists() && is_glob(input) {
+            (glob_root(input), RecursiveMode::Recursive)
+        } else {
+            (parent_dir(input), RecursiveMode::NonRecursive)
+        };
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+/// The longest leading part of a glob pattern without wildcards.
+fn glob_root(pattern: &Path) -> PathBuf {
+    let root: PathBuf = pattern
+        .components()
+        .take_while(|c| !is_glob(Path::new(c.as_os_str())))
+        .collect();
+    if root.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        root
+    }
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Ignores reads and our own writes to the output and its staging file.
+fn is_relevant(event: &Event, output: &Path, staging: &Path) -> bool {
+    if matches!(event.kind, EventKind::Access(_)) {
+        return false;
+    }
+    let own =
+        |p: &PathBuf| p.file_name() == output.file_name() || p.file_name() == staging.file_name();
+    !event.paths.iter().all(own)
+}
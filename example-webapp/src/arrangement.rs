@@ -0,0 +1,189 @@
+//! The order and sections text files `bbfmux` reads with `--order` and
+//! `--sections`, so a project can move between the builder and the CLI.
+
+use std::cmp::Ordering;
+
+/// Where an order file puts its named files. Positions are 1-based;
+/// negative ones count from the end and zero means "with the rest".
+#[derive(Default)]
+pub struct Order {
+    entries: Vec<(OrderTarget, i32)>,
+    excluded: Vec<String>,
+}
+
+enum OrderTarget {
+    Single(String),
+    Range(String, String),
+}
+
+/// An order file placing `names` in the order given.
+pub fn order_file(names: &[String]) -> String {
+    let mut out = String::from("# bbfmux order file\n");
+    for (i, name) in names.iter().enumerate() {
+        out.push_str(&format!("{name}:{}\n", i + 1));
+    }
+    out
+}
+
+/// Parses an order file as `bbfmux` does.
+pub fn parse_order(content: &str) -> Result<Order, String> {
+    let mut order = Order::default();
+
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: &str| format!("line {}: {msg}", line_no + 1);
+
+        if let Some(name) = line.strip_prefix('!') {
+            let name = trim_quotes(name.trim());
+            if name.is_empty() {
+                return Err(err("'!' must be followed by a file name"));
+            }
+            order.excluded.push(name);
+            continue;
+        }
+
+        let (target, position) = match line.rsplit_once(':') {
+            Some((target, idx)) => {
+                let position = idx
+                    .trim()
+                    .parse::<i32>()
+                    .map_err(|_| err(&format!("invalid position '{}'", idx.trim())))?;
+                (target, position)
+            }
+            None => (line, 0),
+        };
+
+        if let Some((first, last)) = target.split_once("..") {
+            let (first, last) = (trim_quotes(first.trim()), trim_quotes(last.trim()));
+            if first.is_empty() || last.is_empty() {
+                return Err(err(&format!("incomplete range '{target}'")));
+            }
+            if position == 0 {
+                return Err(err("a range needs a position (first..last:N)"));
+            }
+            order
+                .entries
+                .push((OrderTarget::Range(first, last), position));
+        } else {
+            let name = trim_quotes(target.trim());
+            if name.is_empty() {
+                return Err(err("missing file name"));
+            }
+            order.entries.push((OrderTarget::Single(name), position));
+        }
+    }
+
+    Ok(order)
+}
+
+/// Indices into `names` in the order `order` gives them, without the
+/// excluded ones. Files the order doesn't mention keep their relative order.
+pub fn apply_order(names: &[String], order: &Order) -> Result<Vec<usize>, String> {
+    let find = |name: &str| {
+        names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| format!("Order file names '{name}', which is not in the list"))
+    };
+
+    let mut positions = vec![0; names.len()];
+    for (target, position) in &order.entries {
+        match target {
+            OrderTarget::Single(name) => positions[find(name)?] = *position,
+            OrderTarget::Range(first, last) => {
+                let (start, end) = (find(first)?, find(last)?);
+                if start > end {
+                    return Err(format!(
+                        "Order range '{first}..{last}' runs backwards in the current list"
+                    ));
+                }
+                for (slot, pos) in positions[start..=end].iter_mut().zip(*position..) {
+                    *slot = pos;
+                }
+            }
+        }
+    }
+
+    let mut indices: Vec<usize> = (0..names.len())
+        .filter(|&i| !order.excluded.contains(&names[i]))
+        .collect();
+    // Same ranking as bbfmux: positive positions first, then the rest, then
+    // positions counted from the end.
+    indices.sort_by(|&a, &b| match (positions[a], positions[b]) {
+        (x, y) if x > 0 && y > 0 => x.cmp(&y),
+        (x, y) if x > 0 && y <= 0 => Ordering::Less,
+        (x, y) if x <= 0 && y > 0 => Ordering::Greater,
+        (0, 0) => Ordering::Equal,
+        (0, y) if y < 0 => Ordering::Less,
+        (x, 0) if x < 0 => Ordering::Greater,
+        (x, y) => x.cmp(&y),
+    });
+    Ok(indices)
+}
+
+/// Where a section line starts its section.
+pub enum SectionTarget {
+    /// 1-based page number.
+    Page(u32),
+    File(String),
+}
+
+/// One line of a sections file: `Name:Target:Parent`.
+pub struct SectionLine {
+    pub name: String,
+    pub target: SectionTarget,
+    /// Name of a section defined earlier, or empty.
+    pub parent: String,
+}
+
+/// A sections file with one line per section.
+pub fn sections_file(lines: &[SectionLine]) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let target = match &line.target {
+            SectionTarget::Page(page) => page.to_string(),
+            SectionTarget::File(name) => name.clone(),
+        };
+        if line.parent.is_empty() {
+            out.push_str(&format!("{}:{target}\n", line.name));
+        } else {
+            out.push_str(&format!("{}:{target}:{}\n", line.name, line.parent));
+        }
+    }
+    out
+}
+
+/// Parses a sections file as `bbfmux` does.
+pub fn parse_sections(content: &str) -> Vec<SectionLine> {
+    content
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            let mut parts = l.split(':');
+            let name = trim_quotes(parts.next().unwrap_or(""));
+            let target = trim_quotes(parts.next().unwrap_or("1"));
+            let parent = trim_quotes(parts.next().unwrap_or(""));
+            let target = if target.chars().all(char::is_numeric) {
+                SectionTarget::Page(target.parse().unwrap_or(1))
+            } else {
+                SectionTarget::File(target)
+            };
+            SectionLine {
+                name,
+                target,
+                parent,
+            }
+        })
+        .collect()
+}
+
+fn trim_quotes(s: &str) -> String {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        s[1..s.len() - 1].to_string()
+    } else {
+        s.to_string()
+    }
+}
@@ -1,11 +1,15 @@
-use crate::utils::{download_blob, read_file_to_vec};
+use crate::arrangement::{self, SectionLine, SectionTarget};
+use crate::presets;
+use crate::transcode::{self, Target};
+use crate::utils::{download_blob, dropped_entries, entry_files, human_size, read_file_to_vec};
 use bbf::{BBFBuilder, BBFMediaType};
 use leptos::prelude::*;
 use leptos::task::spawn_local;
 use leptos_styling::inline_style_sheet;
+use std::collections::HashMap;
 use std::io::Cursor;
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlInputElement, KeyboardEvent};
+use web_sys::{HtmlInputElement, KeyboardEvent, Url};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SendFile(pub web_sys::File);
@@ -26,11 +30,17 @@ enum BuilderEntry {
         id: usize,
         file: SendFile,
         name: String,
+        /// Object URL of the file itself, for its thumbnail.
+        preview: String,
     },
     Section {
         id: usize,
         name: RwSignal<String>,
-        parent: Option<String>,
+        /// File the section starts at. Without one it starts at the next
+        /// file below it.
+        anchor: RwSignal<Option<usize>>,
+        /// Id of the enclosing section.
+        parent: RwSignal<Option<usize>>,
     },
 }
 
@@ -51,6 +61,71 @@ impl BuilderEntry {
     const fn is_section(&self) -> bool {
         matches!(self, Self::Section { .. })
     }
+
+    fn parent(&self) -> Option<usize> {
+        match self {
+            Self::File { .. } => None,
+            Self::Section { parent, .. } => parent.get(),
+        }
+    }
+}
+
+/// Media type a page is stored as, going by its file name.
+fn media_type(name: &str) -> BBFMediaType {
+    let ext = std::path::Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{e}"))
+        .unwrap_or_default();
+    BBFMediaType::from_extension(&ext)
+}
+
+/// Text of the file picked in `input`. The input is cleared so picking the
+/// same file again fires another change.
+async fn read_text(input: HtmlInputElement) -> Option<String> {
+    let file = input.files()?.get(0)?;
+    input.set_value("");
+    let data = read_file_to_vec(&file).await.ok()?;
+    Some(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Page number `file_id` will get, counting from zero.
+fn page_index(list: &[BuilderEntry], file_id: usize) -> Option<u32> {
+    list.iter()
+        .filter(|e| !e.is_section())
+        .position(|e| e.id() == file_id)
+        .map(|p| p as u32)
+}
+
+/// First page of section `id`: its anchor's, or that of the next file below it.
+fn section_start(list: &[BuilderEntry], id: usize) -> u32 {
+    let Some(pos) = list.iter().position(|e| e.id() == id) else {
+        return 0;
+    };
+    if let BuilderEntry::Section { anchor, .. } = &list[pos]
+        && let Some(page) = anchor.get().and_then(|a| page_index(list, a))
+    {
+        return page;
+    }
+    list[..pos].iter().filter(|e| !e.is_section()).count() as u32
+}
+
+/// Ancestors of `id`, nearest first. Stops at a cycle or a missing section.
+fn ancestors(list: &[BuilderEntry], id: usize) -> Vec<usize> {
+    let mut chain = Vec::new();
+    let mut current = id;
+    while let Some(parent) = list
+        .iter()
+        .find(|e| e.id() == current)
+        .and_then(BuilderEntry::parent)
+    {
+        if parent == id || chain.contains(&parent) {
+            break;
+        }
+        chain.push(parent);
+        current = parent;
+    }
+    chain
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -69,6 +144,13 @@ pub fn Builder() -> impl IntoView {
 
     let (editing_id, set_editing_id) = signal(Option::<usize>::None);
     let (drag_id, set_drag_id) = signal(Option::<usize>::None);
+    // Section waiting for a file to be clicked to anchor it.
+    let (anchoring, set_anchoring) = signal(Option::<usize>::None);
+    // Re-encoding applied to pages on compile, and what it did to each file
+    // last time as (before, after) sizes.
+    let (target, set_target) = signal(Target::Original);
+    let (quality, set_quality) = signal(80u32);
+    let (savings, set_savings) = signal(HashMap::<usize, (f64, f64)>::new());
 
     let (floating_entry, set_floating_entry) = signal(Option::<BuilderEntry>::None);
     let (mouse_pos, set_mouse_pos) = signal((0.0, 0.0));
@@ -191,6 +273,11 @@ pub fn Builder() -> impl IntoView {
             padding-bottom: 0.5rem;
         }
 
+        .list-container[data-dropping="true"] {
+            outline: 2px dashed #6366f1;
+            outline-offset: 4px;
+        }
+
         /* List Items (Draggable) */
         .list-container {
             display: flex;
@@ -236,6 +323,16 @@ pub fn Builder() -> impl IntoView {
         }
 
         .item-icon { font-size: 1.25rem; flex-shrink: 0; }
+        .item-thumb {
+            width: 2.5rem;
+            height: 2.5rem;
+            object-fit: cover;
+            border-radius: 0.25rem;
+            background-color: #0f172a;
+            flex-shrink: 0;
+        }
+        .file-info { display: block; font-size: 0.75rem; color: #64748b; }
+        .file-info[data-unknown="true"] { color: #fbbf24; /* amber-400 */ }
         .item-text {
             color: #cbd5e1;
             display: block;
@@ -255,6 +352,32 @@ pub fn Builder() -> impl IntoView {
             width: 100%;
         }
 
+        .list-item[data-anchoring="true"] {
+            border-color: #34d399; /* emerald-400 */
+            cursor: pointer;
+        }
+
+        .section-info { display: block; font-size: 0.75rem; color: #64748b; }
+        .section-select {
+            margin-top: 0.25rem;
+            background-color: #0f172a;
+            color: #cbd5e1;
+            border: 1px solid #475569;
+            border-radius: 0.25rem;
+            font-size: 0.75rem;
+            padding: 0.125rem 0.25rem;
+        }
+
+        .anchor-btn {
+            padding: 0.5rem;
+            background: none;
+            border: none;
+            cursor: pointer;
+            opacity: 0.5;
+            transition: opacity 0.2s;
+        }
+        .anchor-btn:hover, .anchor-btn[data-active="true"] { opacity: 1; }
+
         /* Remove Button */
         .remove-btn {
             color: #64748b;
@@ -356,30 +479,77 @@ pub fn Builder() -> impl IntoView {
 
     on_cleanup(move || handle_click.remove());
 
+    on_cleanup(move || {
+        for entry in entries.try_get_untracked().unwrap_or_default() {
+            if let BuilderEntry::File { preview, .. } = entry {
+                let _ = Url::revoke_object_url(&preview);
+            }
+        }
+    });
+
+    let add_files = move |files: Vec<web_sys::File>| {
+        let new_entries: Vec<BuilderEntry> = files
+            .into_iter()
+            .map(|file| BuilderEntry::File {
+                id: get_id(),
+                name: file.name(),
+                preview: Url::create_object_url_with_blob(&file).unwrap_or_default(),
+                file: SendFile(file),
+            })
+            .collect();
+        set_entries.update(move |e: &mut Vec<BuilderEntry>| e.extend(new_entries));
+    };
+
     let handle_files = move |ev: web_sys::Event| {
         let target: HtmlInputElement = ev.target().unwrap().unchecked_into();
         if let Some(files) = target.files() {
-            let mut new_entries = Vec::new();
-            for i in 0..files.length() {
-                if let Some(file) = files.get(i) {
-                    new_entries.push(BuilderEntry::File {
-                        id: get_id(),
-                        name: file.name(),
-                        file: SendFile(file),
-                    });
-                }
-            }
-            set_entries.update(move |e: &mut Vec<BuilderEntry>| e.extend(new_entries));
+            add_files((0..files.length()).filter_map(|i| files.get(i)).collect());
         }
     };
 
+    // Files and folders dragged in from outside, as opposed to a row being
+    // moved within the list.
+    let (file_drag, set_file_drag) = signal(false);
+    let carries_files = |ev: &web_sys::DragEvent| {
+        ev.data_transfer()
+            .is_some_and(|d| d.types().includes(&"Files".into(), 0))
+    };
+
+    let handle_file_drop = move |ev: web_sys::DragEvent| {
+        set_file_drag.set(false);
+        if drag_id.get_untracked().is_some() || !carries_files(&ev) {
+            return;
+        }
+        ev.prevent_default();
+        let Some(data) = ev.data_transfer() else {
+            return;
+        };
+        let entries = dropped_entries(&data);
+        spawn_local(async move {
+            set_status.set("Reading dropped files...".to_string());
+            // Folders bring along whatever else is in them, so only their
+            // images are kept; files dropped on their own are taken as is.
+            let files = entry_files(entries)
+                .await
+                .into_iter()
+                .filter(|(path, file)| {
+                    !path.contains('/') || media_type(&file.name()) != BBFMediaType::Unknown
+                })
+                .map(|(_, file)| file)
+                .collect::<Vec<_>>();
+            set_status.set(format!("Added {} files", files.len()));
+            add_files(files);
+        });
+    };
+
     let add_section = move |ev: web_sys::MouseEvent| {
         ev.stop_propagation();
         let id = get_id();
         let entry = BuilderEntry::Section {
             id,
             name: RwSignal::new("New Section".to_string()),
-            parent: None,
+            anchor: RwSignal::new(None),
+            parent: RwSignal::new(None),
         };
         set_floating_entry.set(Some(entry));
     };
@@ -395,8 +565,257 @@ pub fn Builder() -> impl IntoView {
         });
     };
 
+    let (has_custom_preset, set_has_custom_preset) = signal(presets::load_custom().is_some());
+
+    // Adds the preset's keys that aren't there yet; existing values are kept.
+    let apply_preset = move |choice: String| {
+        let pairs: Vec<(String, String)> = if choice == "custom" {
+            presets::load_custom().unwrap_or_default()
+        } else {
+            presets::PRESETS
+                .iter()
+                .find(|(name, _)| *name == choice)
+                .map(|(_, keys)| {
+                    keys.iter()
+                        .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        for (key, value) in pairs {
+            if metadata
+                .get_untracked()
+                .iter()
+                .any(|m| m.key.eq_ignore_ascii_case(&key))
+            {
+                continue;
+            }
+            let id = get_id();
+            set_metadata.update(move |m| m.push(MetaEntry { id, key, value }));
+        }
+    };
+
+    let save_preset = move |_| {
+        let pairs: Vec<(String, String)> = metadata
+            .get_untracked()
+            .into_iter()
+            .filter(|m| !m.key.is_empty())
+            .map(|m| (m.key, m.value))
+            .collect();
+        match presets::save_custom(&pairs) {
+            Ok(()) => {
+                set_has_custom_preset.set(true);
+                set_status.set(format!("Saved {} keys as My preset", pairs.len()));
+            }
+            Err(err) => set_status.set(format!("Couldn't save preset: {err:?}")),
+        }
+    };
+
+    let export_order = move |_| {
+        let names: Vec<String> = entries
+            .get_untracked()
+            .iter()
+            .filter(|e| !e.is_section())
+            .map(BuilderEntry::name)
+            .collect();
+        let _ = download_blob(
+            arrangement::order_file(&names).as_bytes(),
+            "order.txt",
+            "text/plain",
+        );
+    };
+
+    let export_sections = move |_| {
+        let list = entries.get_untracked();
+        let name_of = |id: usize| list.iter().find(|e| e.id() == id).map(BuilderEntry::name);
+        let lines: Vec<SectionLine> = list
+            .iter()
+            .filter_map(|e| match e {
+                BuilderEntry::Section {
+                    id,
+                    name,
+                    anchor,
+                    parent,
+                } => Some(SectionLine {
+                    name: name.get_untracked(),
+                    target: match anchor.get_untracked().and_then(name_of) {
+                        Some(file) => SectionTarget::File(file),
+                        None => SectionTarget::Page(section_start(&list, *id) + 1),
+                    },
+                    parent: parent.get_untracked().and_then(name_of).unwrap_or_default(),
+                }),
+                BuilderEntry::File { .. } => None,
+            })
+            .collect();
+        let _ = download_blob(
+            arrangement::sections_file(&lines).as_bytes(),
+            "sections.txt",
+            "text/plain",
+        );
+        set_status.set(format!("Exported {} sections", lines.len()));
+    };
+
+    // Re-sorts the files; each section keeps its place in front of the file
+    // that followed it.
+    let import_order = move |content: String| {
+        let list = entries.get_untracked();
+        let files: Vec<&BuilderEntry> = list.iter().filter(|e| !e.is_section()).collect();
+        let names: Vec<String> = files.iter().map(|e| e.name()).collect();
+        let order = match arrangement::parse_order(&content)
+            .and_then(|o| arrangement::apply_order(&names, &o))
+        {
+            Ok(order) => order,
+            Err(err) => {
+                set_status.set(format!("Invalid order file: {err}"));
+                return;
+            }
+        };
+
+        let kept: Vec<usize> = order.iter().map(|&i| files[i].id()).collect();
+        let mut before: HashMap<usize, Vec<BuilderEntry>> = HashMap::new();
+        let mut pending = Vec::new();
+        for entry in &list {
+            match entry {
+                BuilderEntry::Section { .. } => pending.push(entry.clone()),
+                BuilderEntry::File { id, preview, .. } => {
+                    if kept.contains(id) {
+                        before.entry(*id).or_default().append(&mut pending);
+                    } else {
+                        let _ = Url::revoke_object_url(preview);
+                    }
+                }
+            }
+        }
+
+        let mut new_list = Vec::with_capacity(list.len());
+        for &i in &order {
+            new_list.extend(before.remove(&files[i].id()).unwrap_or_default());
+            new_list.push(files[i].clone());
+        }
+        new_list.extend(pending);
+        for entry in &new_list {
+            if let BuilderEntry::Section { anchor, .. } = entry
+                && anchor.get_untracked().is_some_and(|a| !kept.contains(&a))
+            {
+                anchor.set(None);
+            }
+        }
+
+        let dropped = files.len() - order.len();
+        set_entries.set(new_list);
+        set_status.set(if dropped == 0 {
+            format!("Applied order to {} files", order.len())
+        } else {
+            format!("Applied order to {} files, left out {dropped}", order.len())
+        });
+    };
+
+    // Replaces every section with the file's.
+    let import_sections = move |content: String| {
+        let mut list: Vec<BuilderEntry> = entries
+            .get_untracked()
+            .into_iter()
+            .filter(|e| !e.is_section())
+            .collect();
+        let mut imported: Vec<(String, usize)> = Vec::new();
+        let mut missing = 0;
+
+        for line in arrangement::parse_sections(&content) {
+            let file_pos = |list: &[BuilderEntry], page: usize| {
+                list.iter()
+                    .enumerate()
+                    .filter(|(_, e)| !e.is_section())
+                    .nth(page)
+                    .map_or(list.len(), |(i, _)| i)
+            };
+            let (pos, anchor) = match &line.target {
+                SectionTarget::Page(page) => {
+                    (file_pos(&list, page.saturating_sub(1) as usize), None)
+                }
+                SectionTarget::File(name) => match list
+                    .iter()
+                    .position(|e| !e.is_section() && e.name() == *name)
+                {
+                    Some(pos) => (pos, Some(list[pos].id())),
+                    None => {
+                        // Like bbfmux, fall back to the first page.
+                        missing += 1;
+                        (file_pos(&list, 0), None)
+                    }
+                },
+            };
+            let parent = imported
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == line.parent)
+                .map(|(_, id)| *id);
+
+            let id = get_id();
+            imported.push((line.name.clone(), id));
+            list.insert(
+                pos,
+                BuilderEntry::Section {
+                    id,
+                    name: RwSignal::new(line.name),
+                    anchor: RwSignal::new(anchor),
+                    parent: RwSignal::new(parent),
+                },
+            );
+        }
+
+        set_entries.set(list);
+        set_status.set(if missing == 0 {
+            format!("Imported {} sections", imported.len())
+        } else {
+            format!(
+                "Imported {} sections; {missing} named files that aren't in the list and start at page 1",
+                imported.len()
+            )
+        });
+    };
+
     let remove_entry = move |id: usize| {
-        set_entries.update(|e| e.retain(|x| x.id() != id));
+        set_entries.update(|e| {
+            e.retain(|x| match x {
+                BuilderEntry::File {
+                    id: file_id,
+                    preview,
+                    ..
+                } if *file_id == id => {
+                    let _ = Url::revoke_object_url(preview);
+                    false
+                }
+                x => x.id() != id,
+            });
+        });
+        // Nothing may point at the entry any more.
+        for entry in entries.get_untracked() {
+            if let BuilderEntry::Section { anchor, parent, .. } = entry {
+                if anchor.get_untracked() == Some(id) {
+                    anchor.set(None);
+                }
+                if parent.get_untracked() == Some(id) {
+                    parent.set(None);
+                }
+            }
+        }
+        if anchoring.get_untracked() == Some(id) {
+            set_anchoring.set(None);
+        }
+    };
+
+    let anchor_to = move |file_id: usize| {
+        let Some(section_id) = anchoring.get_untracked() else {
+            return;
+        };
+        for entry in entries.get_untracked() {
+            if let BuilderEntry::Section { id, anchor, .. } = entry
+                && id == section_id
+            {
+                anchor.set(Some(file_id));
+            }
+        }
+        set_anchoring.set(None);
     };
 
     let handle_drag_start = move |id: usize| {
@@ -461,6 +880,11 @@ pub fn Builder() -> impl IntoView {
             set_status.set("Reading files...".to_string());
             let current_entries = entries.get();
             let current_meta = metadata.get();
+            let target = target.get_untracked();
+            let quality = quality.get_untracked();
+            set_savings.set(HashMap::new());
+            let mut total_before = 0.0;
+            let mut total_after = 0.0;
 
             let mut cursor = Cursor::new(Vec::new());
 
@@ -472,31 +896,57 @@ pub fn Builder() -> impl IntoView {
                 }
             };
 
-            let mut page_count = 0;
+            let section_index: Vec<usize> = current_entries
+                .iter()
+                .filter(|e| e.is_section())
+                .map(BuilderEntry::id)
+                .collect();
 
-            for entry in current_entries {
+            for entry in current_entries.clone() {
                 match entry {
-                    BuilderEntry::File { file, name, .. } => {
-                        if let Ok(data) = read_file_to_vec(&file).await {
-                            let ext = std::path::Path::new(&name)
-                                .extension()
-                                .and_then(|e| e.to_str())
-                                .map(|e| format!(".{e}"))
-                                .unwrap_or_default();
-
-                            let media_type = BBFMediaType::from_extension(&ext);
-                            if let Err(err) = builder.add_page(&data, media_type, 0) {
-                                set_status.set(format!("Error adding page: {err:?}"));
-                                return;
-                            }
-                            page_count += 1;
-                        } else {
+                    BuilderEntry::File { id, file, name, .. } => {
+                        let Ok(mut data) = read_file_to_vec(&file).await else {
                             set_status.set("Failed to read file".to_string());
                             return;
+                        };
+                        let mut media = media_type(&name);
+                        if let Some(to) = target.media_type()
+                            && transcode::can_transcode(media)
+                        {
+                            set_status.set(format!("Transcoding {name}..."));
+                            let quality = f64::from(quality) / 100.0;
+                            if let Some(out) = transcode::transcode(&file, target, quality).await {
+                                // A page that doesn't shrink is kept as it was.
+                                let before = data.len() as f64;
+                                if out.len() < data.len() {
+                                    data = out;
+                                    media = to;
+                                }
+                                let after = data.len() as f64;
+                                total_before += before;
+                                total_after += after;
+                                set_savings.update(|s| {
+                                    s.insert(id, (before, after));
+                                });
+                            }
+                        }
+                        if let Err(err) = builder.add_page(&data, media, 0) {
+                            set_status.set(format!("Error adding page: {err:?}"));
+                            return;
                         }
                     }
-                    BuilderEntry::Section { name, .. } => {
-                        builder.add_section(&name.get(), page_count, None);
+                    BuilderEntry::Section {
+                        id, name, parent, ..
+                    } => {
+                        let parent = parent
+                            .get()
+                            .and_then(|p| section_index.iter().position(|&s| s == p))
+                            .map(|p| p as u32);
+                        builder.add_section(
+                            &name.get(),
+                            section_start(&current_entries, id),
+                            parent,
+                        );
                     }
                 }
             }
@@ -516,7 +966,15 @@ pub fn Builder() -> impl IntoView {
                 "web_generated.bbf",
                 "application/octet-stream",
             );
-            set_status.set("Done!".to_string());
+            if total_before > 0.0 {
+                set_status.set(format!(
+                    "Done! Transcoding saved {} ({:.0}%)",
+                    human_size(total_before - total_after),
+                    (1.0 - total_after / total_before) * 100.0
+                ));
+            } else {
+                set_status.set("Done!".to_string());
+            }
         });
     };
 
@@ -558,6 +1016,91 @@ pub fn Builder() -> impl IntoView {
                     >
                          <span class=builder_css::TEXT_EMERALD>"Add Metadata"</span>
                     </button>
+                    <select
+                        class=builder_css::ACTION_BTN
+                        on:change=move |ev| {
+                            let target: web_sys::HtmlSelectElement = event_target(&ev);
+                            apply_preset(target.value());
+                            target.set_value("");
+                        }
+                    >
+                        <option value="" selected>"Metadata Preset..."</option>
+                        {presets::PRESETS.iter().map(|(name, _)| view! {
+                            <option value=*name>{*name}</option>
+                        }).collect_view()}
+                        <Show when=move || has_custom_preset.get()>
+                            <option value="custom">"My preset"</option>
+                        </Show>
+                    </select>
+                    <select
+                        class=builder_css::ACTION_BTN
+                        on:change=move |ev| set_target.set(Target::from_key(&event_target_value(&ev)))
+                    >
+                        {Target::ALL.into_iter().map(|t| view! {
+                            <option value=t.key() selected=move || target.get() == t>{t.label()}</option>
+                        }).collect_view()}
+                    </select>
+                    <Show when=move || target.get() != Target::Original>
+                        <label class=builder_css::ACTION_BTN>
+                            {move || format!("Quality {}", quality.get())}
+                            <input
+                                type="range"
+                                min="10"
+                                max="100"
+                                prop:value=move || quality.get().to_string()
+                                on:input=move |ev| {
+                                    if let Ok(q) = event_target_value(&ev).parse() {
+                                        set_quality.set(q);
+                                    }
+                                }
+                            />
+                        </label>
+                    </Show>
+                    <button on:click=export_order class=builder_css::ACTION_BTN>
+                         <span class=builder_css::TEXT_INDIGO>"Export Order"</span>
+                    </button>
+                    <label class=builder_css::ACTION_BTN>
+                         <span class=builder_css::TEXT_INDIGO>"Import Order"</span>
+                         <input
+                            type="file"
+                            accept=".txt,text/plain"
+                            style="display:none"
+                            on:change=move |ev| {
+                                let input: HtmlInputElement = event_target(&ev);
+                                spawn_local(async move {
+                                    if let Some(text) = read_text(input).await {
+                                        import_order(text);
+                                    }
+                                });
+                            }
+                         />
+                    </label>
+                    <button on:click=export_sections class=builder_css::ACTION_BTN>
+                         <span class=builder_css::TEXT_INDIGO>"Export Sections"</span>
+                    </button>
+                    <label class=builder_css::ACTION_BTN>
+                         <span class=builder_css::TEXT_INDIGO>"Import Sections"</span>
+                         <input
+                            type="file"
+                            accept=".txt,text/plain"
+                            style="display:none"
+                            on:change=move |ev| {
+                                let input: HtmlInputElement = event_target(&ev);
+                                spawn_local(async move {
+                                    if let Some(text) = read_text(input).await {
+                                        import_sections(text);
+                                    }
+                                });
+                            }
+                         />
+                    </label>
+                    <button
+                        on:click=save_preset
+                        class=builder_css::ACTION_BTN
+                        title="Save the current metadata keys and values as My preset"
+                    >
+                         <span class=builder_css::TEXT_EMERALD>"Save as Preset"</span>
+                    </button>
                 </div>
             </div>
 
@@ -567,7 +1110,16 @@ pub fn Builder() -> impl IntoView {
 
                     <div
                         class=builder_css::LIST_CONTAINER
+                        attr:data-dropping=move || file_drag.get().to_string()
                         on:click=handle_container_click
+                        on:dragover=move |ev: web_sys::DragEvent| {
+                            if drag_id.get_untracked().is_none() && carries_files(&ev) {
+                                ev.prevent_default();
+                                set_file_drag.set(true);
+                            }
+                        }
+                        on:dragleave=move |_| set_file_drag.set(false)
+                        on:drop=handle_file_drop
                     >
                         <For
                             each=move || entries.get()
@@ -578,12 +1130,38 @@ pub fn Builder() -> impl IntoView {
 
                                 let is_editing = move || editing_id.get() == Some(id);
                                 let is_dragging = move || drag_id.get() == Some(id);
+                                let section = match &e {
+                                    BuilderEntry::Section { anchor, parent, .. } => Some((*anchor, *parent)),
+                                    BuilderEntry::File { .. } => None,
+                                };
+                                let file_info = match &e {
+                                    BuilderEntry::File { file, name, .. } => {
+                                        let media = media_type(name);
+                                        let unknown = media == BBFMediaType::Unknown;
+                                        let label = if unknown {
+                                            "Unknown type".to_string()
+                                        } else {
+                                            media.as_extension()[1..].to_uppercase()
+                                        };
+                                        Some((format!("{label} · {}", human_size(file.size())), unknown))
+                                    }
+                                    BuilderEntry::Section { .. } => None,
+                                };
+                                let depth = move || entries.with(|list| ancestors(list, id).len());
 
                                 view! {
                                     <div
                                         class=builder_css::LIST_ITEM
+                                        style=move || format!("margin-left: {}rem", depth() as f64 * 1.5)
                                         attr:data-dragging=move || is_dragging().to_string()
                                         attr:data-editing=move || is_editing().to_string()
+                                        attr:data-anchoring=move || (anchoring.get().is_some() && !is_section).to_string()
+                                        on:click=move |ev: web_sys::MouseEvent| {
+                                            if !is_section && anchoring.get_untracked().is_some() {
+                                                ev.stop_propagation();
+                                                anchor_to(id);
+                                            }
+                                        }
 
                                         draggable=move || if is_editing() { "false" } else { "true" }
                                         on:dragstart=move |_| handle_drag_start(id)
@@ -600,12 +1178,14 @@ pub fn Builder() -> impl IntoView {
                                         }
                                     >
                                         <div class=builder_css::LIST_ITEM_CONTENT>
-                                            <span class=builder_css::ITEM_ICON>
-                                                {match e {
-                                                    BuilderEntry::File { .. } => "📄",
-                                                    BuilderEntry::Section { .. } => "🔖",
-                                                }}
-                                            </span>
+                                            {match &e {
+                                                BuilderEntry::File { preview, .. } => view! {
+                                                    <img class=builder_css::ITEM_THUMB src=preview.clone() loading="lazy" alt="" />
+                                                }.into_any(),
+                                                BuilderEntry::Section { .. } => view! {
+                                                    <span class=builder_css::ITEM_ICON>"🔖"</span>
+                                                }.into_any(),
+                                            }}
 
                                             <div class="flex-1 min-w-0">
                                             {move || {
@@ -632,7 +1212,6 @@ pub fn Builder() -> impl IntoView {
                                                             />
                                                         }.into_any()
                                                     } else {
-                                                        let _: () = view! {};
                                                         ().into_any()
                                                     }
                                                 } else {
@@ -644,9 +1223,76 @@ pub fn Builder() -> impl IntoView {
                                                     }.into_any()
                                                 }
                                             }}
+                                            {match section {
+                                                Some((anchor, parent)) => view! {
+                                                    <span class=builder_css::SECTION_INFO>
+                                                        {move || {
+                                                            let page = entries.with(|list| section_start(list, id)) + 1;
+                                                            if anchor.get().is_some() {
+                                                                format!("Starts at page {page} (anchored)")
+                                                            } else {
+                                                                format!("Starts at page {page}")
+                                                            }
+                                                        }}
+                                                    </span>
+                                                    <select
+                                                        class=builder_css::SECTION_SELECT
+                                                        title="Parent section"
+                                                        on:click=move |ev: web_sys::MouseEvent| ev.stop_propagation()
+                                                        on:change=move |ev| parent.set(event_target_value(&ev).parse().ok())
+                                                    >
+                                                        <option value="" selected=move || parent.get().is_none()>"No parent"</option>
+                                                        {move || entries.with(|list| {
+                                                            list.iter().filter_map(|other| match other {
+                                                                // A section can't sit inside itself or its own children.
+                                                                BuilderEntry::Section { id: other_id, name: other_name, .. }
+                                                                    if *other_id != id && !ancestors(list, *other_id).contains(&id) =>
+                                                                {
+                                                                    let other_id = *other_id;
+                                                                    let other_name = *other_name;
+                                                                    Some(view! {
+                                                                        <option
+                                                                            value=other_id.to_string()
+                                                                            selected=move || parent.get() == Some(other_id)
+                                                                        >
+                                                                            {move || other_name.get()}
+                                                                        </option>
+                                                                    })
+                                                                }
+                                                                _ => None,
+                                                            }).collect_view()
+                                                        })}
+                                                    </select>
+                                                }.into_any(),
+                                                None => match file_info.clone() {
+                                                    Some((info, unknown)) => view! {
+                                                        <span class=builder_css::FILE_INFO attr:data-unknown=unknown.to_string()>
+                                                            {info}
+                                                            {move || savings.with(|s| s.get(&id).map(|&(before, after)| {
+                                                                format!(" → {} (−{:.0}%)", human_size(after), (1.0 - after / before) * 100.0)
+                                                            }))}
+                                                        </span>
+                                                    }.into_any(),
+                                                    None => ().into_any(),
+                                                },
+                                            }}
                                             </div>
                                         </div>
 
+                                        <Show when=move || is_section>
+                                            <button
+                                                class=builder_css::ANCHOR_BTN
+                                                attr:data-active=move || (anchoring.get() == Some(id)).to_string()
+                                                title="Anchor to a page: click this, then a file"
+                                                on:click=move |ev: web_sys::MouseEvent| {
+                                                    ev.stop_propagation();
+                                                    set_anchoring.update(|a| *a = if *a == Some(id) { None } else { Some(id) });
+                                                }
+                                            >
+                                                "⚓"
+                                            </button>
+                                        </Show>
+
                                         <button
                                             class=builder_css::REMOVE_BTN
                                             title="Remove"
@@ -662,7 +1308,7 @@ pub fn Builder() -> impl IntoView {
                             }
                         />
                          <Show when=move || entries.get().is_empty()>
-                            <div class=builder_css::EMPTY_TEXT>"No files added yet."</div>
+                            <div class=builder_css::EMPTY_TEXT>"No files added yet. Drop images or folders here."</div>
                          </Show>
                     </div>
                 </div>
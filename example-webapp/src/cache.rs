@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+use web_sys::Url;
+
+/// Object URLs for recently shown and prefetched assets, least recently used
+/// first. URLs are revoked when they fall out of the cache, so a URL handed
+/// out stays valid only while fewer than `capacity` other assets are used
+/// after it.
+pub struct UrlCache {
+    capacity: usize,
+    entries: VecDeque<(u32, String)>,
+}
+
+impl UrlCache {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// URL for asset `index` if it's cached, marking it as just used.
+    pub fn get(&mut self, index: u32) -> Option<String> {
+        let pos = self.entries.iter().position(|(i, _)| *i == index)?;
+        let entry = self.entries.remove(pos)?;
+        let url = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(url)
+    }
+
+    /// Caches `url` for asset `index` and returns the URL to use for it:
+    /// `url` itself, or the one already cached if another load got there
+    /// first, in which case `url` is revoked.
+    pub fn insert(&mut self, index: u32, url: String) -> String {
+        if let Some(existing) = self.get(index) {
+            let _ = Url::revoke_object_url(&url);
+            return existing;
+        }
+        self.entries.push_back((index, url.clone()));
+        while self.entries.len() > self.capacity {
+            if let Some((_, old)) = self.entries.pop_front() {
+                let _ = Url::revoke_object_url(&old);
+            }
+        }
+        url
+    }
+
+    /// Revokes every URL, for when the assets they point to go away.
+    pub fn clear(&mut self) {
+        for (_, url) in self.entries.drain(..) {
+            let _ = Url::revoke_object_url(&url);
+        }
+    }
+}
+
+impl Drop for UrlCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
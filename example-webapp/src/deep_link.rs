@@ -0,0 +1,53 @@
+//! The open book and page in the URL fragment, as
+//! `#book=<index hash>&page=<page>`, so refreshing or sharing the link comes
+//! back to the same page once the file is picked again.
+
+/// A position named by the fragment.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Link {
+    pub index_hash: u64,
+    /// Counting from zero; the fragment counts from one.
+    pub page: u32,
+}
+
+/// The position in the current URL, if it names one.
+pub fn current() -> Option<Link> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    let mut index_hash = None;
+    let mut page = None;
+    for pair in hash.trim_start_matches('#').split('&') {
+        match pair.split_once('=') {
+            Some(("book", v)) => index_hash = u64::from_str_radix(v, 16).ok(),
+            Some(("page", v)) => page = v.parse::<u32>().ok()?.checked_sub(1),
+            _ => {}
+        }
+    }
+    Some(Link {
+        index_hash: index_hash?,
+        page: page.unwrap_or(0),
+    })
+}
+
+/// Points the URL at `page` of the book, replacing rather than adding to the
+/// history so the back button doesn't step through every page turn.
+pub fn set(index_hash: u64, page: u32) {
+    replace(&format!("#book={index_hash:016x}&page={}", page + 1));
+}
+
+pub fn clear() {
+    if current().is_some() {
+        // An empty fragment would leave a bare `#`; drop it with the query kept.
+        let Some(location) = web_sys::window().map(|w| w.location()) else {
+            return;
+        };
+        let path = location.pathname().unwrap_or_default();
+        let search = location.search().unwrap_or_default();
+        replace(&format!("{path}{search}"));
+    }
+}
+
+fn replace(url: &str) {
+    if let Some(history) = web_sys::window().and_then(|w| w.history().ok()) {
+        let _ = history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(url));
+    }
+}
@@ -1,6 +1,14 @@
 mod app;
+mod arrangement;
 mod builder;
+mod cache;
+mod deep_link;
+mod library;
+mod presets;
+mod progress;
+mod random_access;
 mod reader;
+mod transcode;
 mod utils;
 
 use leptos::prelude::*;
@@ -0,0 +1,229 @@
+//! Recently opened books, kept in IndexedDB so the reader can list them on
+//! its landing page: title, page count, cover, and where the browser has the
+//! File System Access API a file handle to reopen the book with one click.
+
+use wasm_bindgen::prelude::*;
+use web_sys::js_sys::{self, Array, Function, Object, Promise, Reflect};
+use web_sys::{
+    Blob, DataTransferItem, File, IdbDatabase, IdbObjectStoreParameters, IdbRequest,
+    IdbTransactionMode,
+};
+
+const DB_NAME: &str = "bbf-library";
+const STORE: &str = "books";
+
+#[derive(Clone)]
+pub struct LibraryBook {
+    /// The book's index hash in hex, which identifies it across renames.
+    pub hash: String,
+    pub title: String,
+    pub pages: u32,
+    /// Milliseconds since the epoch.
+    pub opened_at: f64,
+    pub cover: Option<Blob>,
+    /// `FileSystemFileHandle` the book was opened through, if any.
+    pub handle: Option<JsValue>,
+}
+
+impl LibraryBook {
+    fn to_js(&self) -> Result<JsValue, JsValue> {
+        let obj = Object::new();
+        Reflect::set(&obj, &"hash".into(), &self.hash.as_str().into())?;
+        Reflect::set(&obj, &"title".into(), &self.title.as_str().into())?;
+        Reflect::set(&obj, &"pages".into(), &self.pages.into())?;
+        Reflect::set(&obj, &"openedAt".into(), &self.opened_at.into())?;
+        if let Some(cover) = &self.cover {
+            Reflect::set(&obj, &"cover".into(), cover)?;
+        }
+        if let Some(handle) = &self.handle {
+            Reflect::set(&obj, &"handle".into(), handle)?;
+        }
+        Ok(obj.into())
+    }
+
+    fn from_js(value: &JsValue) -> Option<Self> {
+        let get = |key: &str| {
+            Reflect::get(value, &key.into())
+                .ok()
+                .filter(|v| !v.is_undefined())
+        };
+        Some(Self {
+            hash: get("hash")?.as_string()?,
+            title: get("title")?.as_string().unwrap_or_default(),
+            pages: get("pages")?.as_f64().unwrap_or_default() as u32,
+            opened_at: get("openedAt")?.as_f64().unwrap_or_default(),
+            cover: get("cover").and_then(|c| c.dyn_into().ok()),
+            handle: get("handle"),
+        })
+    }
+}
+
+/// Resolves once `request` succeeds, with its result.
+async fn finish(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = Promise::new(&mut |resolve, reject| {
+        let onsuccess = Closure::once(Box::new(move || {
+            let _ = resolve.call0(&JsValue::NULL);
+        }));
+        let onerror = Closure::once(Box::new(move || {
+            let _ = reject.call0(&JsValue::NULL);
+        }));
+        request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+        request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        onsuccess.forget();
+        onerror.forget();
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await?;
+    request.result()
+}
+
+async fn open_db() -> Result<IdbDatabase, JsValue> {
+    let factory = web_sys::window()
+        .ok_or("no window")?
+        .indexed_db()?
+        .ok_or("IndexedDB is unavailable")?;
+    let request = factory.open_with_u32(DB_NAME, 1)?;
+
+    let db_request = request.clone();
+    let onupgradeneeded = Closure::once(Box::new(move || {
+        if let Ok(db) = db_request
+            .result()
+            .and_then(JsCast::dyn_into::<IdbDatabase>)
+        {
+            let params = IdbObjectStoreParameters::new();
+            params.set_key_path(&"hash".into());
+            let _ = db.create_object_store_with_optional_parameters(STORE, &params);
+        }
+    }));
+    request.set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
+    onupgradeneeded.forget();
+
+    finish(&request).await?.dyn_into()
+}
+
+async fn store(mode: IdbTransactionMode) -> Result<web_sys::IdbObjectStore, JsValue> {
+    open_db()
+        .await?
+        .transaction_with_str_and_mode(STORE, mode)?
+        .object_store(STORE)
+}
+
+/// Every recorded book, most recently opened first.
+pub async fn list() -> Vec<LibraryBook> {
+    let Ok(request) = store(IdbTransactionMode::Readonly)
+        .await
+        .and_then(|s| s.get_all())
+    else {
+        return Vec::new();
+    };
+    let Ok(all) = finish(&request).await else {
+        return Vec::new();
+    };
+    let mut books: Vec<LibraryBook> = Array::from(&all)
+        .iter()
+        .filter_map(|v| LibraryBook::from_js(&v))
+        .collect();
+    books.sort_by(|a, b| b.opened_at.total_cmp(&a.opened_at));
+    books
+}
+
+/// Records `book`, replacing any entry with the same hash. A book opened
+/// without a handle keeps the one it was recorded with before.
+pub async fn record(mut book: LibraryBook) -> Result<(), JsValue> {
+    if book.handle.is_none() {
+        let existing = store(IdbTransactionMode::Readonly)
+            .await?
+            .get(&book.hash.as_str().into())?;
+        book.handle = LibraryBook::from_js(&finish(&existing).await?).and_then(|b| b.handle);
+    }
+    let request = store(IdbTransactionMode::Readwrite)
+        .await?
+        .put(&book.to_js()?)?;
+    finish(&request).await.map(drop)
+}
+
+pub async fn remove(hash: &str) -> Result<(), JsValue> {
+    let request = store(IdbTransactionMode::Readwrite)
+        .await?
+        .delete(&hash.into())?;
+    finish(&request).await.map(drop)
+}
+
+/// Calls `obj.name(...args)`, awaiting the result if it's a promise.
+async fn call(obj: &JsValue, name: &str, args: &Array) -> Result<JsValue, JsValue> {
+    let method: Function = Reflect::get(obj, &name.into())?.dyn_into()?;
+    let result = method.apply(obj, args)?;
+    match result.dyn_into::<Promise>() {
+        Ok(promise) => wasm_bindgen_futures::JsFuture::from(promise).await,
+        Err(value) => Ok(value),
+    }
+}
+
+/// Whether books can be opened through file handles that can be kept.
+pub fn has_file_handles() -> bool {
+    web_sys::window()
+        .is_some_and(|w| Reflect::has(&w, &"showOpenFilePicker".into()).unwrap_or(false))
+}
+
+/// Asks for a book with the browser's file picker. Returns the file and a
+/// handle to record, or `None` if the user cancelled.
+pub async fn pick_book() -> Option<(File, JsValue)> {
+    let window: JsValue = web_sys::window()?.into();
+    let accept = Object::new();
+    Reflect::set(
+        &accept,
+        &"application/octet-stream".into(),
+        &Array::of1(&".bbf".into()),
+    )
+    .ok()?;
+    let kind = Object::new();
+    Reflect::set(&kind, &"description".into(), &"BBF books".into()).ok()?;
+    Reflect::set(&kind, &"accept".into(), &accept).ok()?;
+    let options = Object::new();
+    Reflect::set(&options, &"types".into(), &Array::of1(&kind)).ok()?;
+
+    let handles = call(&window, "showOpenFilePicker", &Array::of1(&options))
+        .await
+        .ok()?;
+    let handle = Array::from(&handles).get(0);
+    let file = call(&handle, "getFile", &Array::new()).await.ok()?;
+    Some((file.dyn_into().ok()?, handle))
+}
+
+/// The file behind a recorded handle, asking for permission again if the
+/// browser has forgotten it.
+pub async fn file_from_handle(handle: &JsValue) -> Option<File> {
+    let options = Object::new();
+    Reflect::set(&options, &"mode".into(), &"read".into()).ok()?;
+    let args = Array::of1(&options);
+    let state = call(handle, "queryPermission", &args).await.ok()?;
+    if state.as_string().as_deref() != Some("granted") {
+        let state = call(handle, "requestPermission", &args).await.ok()?;
+        if state.as_string().as_deref() != Some("granted") {
+            return None;
+        }
+    }
+    call(handle, "getFile", &Array::new())
+        .await
+        .ok()?
+        .dyn_into()
+        .ok()
+}
+
+/// Starts asking for a handle to a dropped file, where the browser gives
+/// them out. It has to be asked while the drop is being handled; the handle
+/// (or `null`) comes from the returned promise.
+pub fn dropped_handle(item: &DataTransferItem) -> Option<Promise> {
+    let method: Function = Reflect::get(item, &"getAsFileSystemHandle".into())
+        .ok()?
+        .dyn_into()
+        .ok()?;
+    method.call0(item).ok()?.dyn_into().ok()
+}
+
+/// `data` as a Blob of type `mime`.
+pub fn blob(data: &[u8], mime: &str) -> Option<Blob> {
+    let parts = Array::of1(&js_sys::Uint8Array::from(data));
+    let bag = web_sys::BlobPropertyBag::new();
+    bag.set_type(mime);
+    Blob::new_with_u8_array_sequence_and_options(&parts, &bag).ok()
+}
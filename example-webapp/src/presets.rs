@@ -0,0 +1,75 @@
+//! Metadata presets for the builder: the usual ComicInfo keys for a few kinds
+//! of book, plus one custom preset kept in localStorage.
+
+use crate::utils::local_storage;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::js_sys::{self, JSON};
+
+pub type Preset = &'static [(&'static str, &'static str)];
+
+pub const PRESETS: &[(&str, Preset)] = &[
+    (
+        "Manga",
+        &[
+            ("Title", ""),
+            ("Series", ""),
+            ("Number", ""),
+            ("Writer", ""),
+            ("Penciller", ""),
+            ("Publisher", ""),
+            ("LanguageISO", "ja"),
+            ("Manga", "YesAndRightToLeft"),
+        ],
+    ),
+    (
+        "Photo album",
+        &[
+            ("Title", ""),
+            ("Year", ""),
+            ("Month", ""),
+            ("Day", ""),
+            ("Locations", ""),
+            ("Tags", ""),
+            ("Format", "Photo Album"),
+        ],
+    ),
+    (
+        "Scanned documents",
+        &[
+            ("Title", ""),
+            ("Writer", ""),
+            ("Publisher", ""),
+            ("Year", ""),
+            ("LanguageISO", ""),
+            ("BlackAndWhite", "Yes"),
+            ("ScanInformation", ""),
+        ],
+    ),
+];
+
+const CUSTOM_PRESET_KEY: &str = "bbf-builder-preset";
+
+/// The saved custom preset, if there is one.
+pub fn load_custom() -> Option<Vec<(String, String)>> {
+    let json = local_storage()?.get_item(CUSTOM_PRESET_KEY).ok()??;
+    let pairs: js_sys::Array = JSON::parse(&json).ok()?.dyn_into().ok()?;
+    pairs
+        .iter()
+        .map(|pair| {
+            let pair: js_sys::Array = pair.dyn_into().ok()?;
+            Some((pair.get(0).as_string()?, pair.get(1).as_string()?))
+        })
+        .collect()
+}
+
+/// Saves `entries` as the custom preset, replacing any saved before.
+pub fn save_custom(entries: &[(String, String)]) -> Result<(), JsValue> {
+    let pairs: js_sys::Array = entries
+        .iter()
+        .map(|(k, v)| js_sys::Array::of2(&k.into(), &v.into()))
+        .collect();
+    let json = JSON::stringify(&pairs)?.as_string().unwrap_or_default();
+    local_storage()
+        .ok_or_else(|| JsValue::from_str("localStorage is unavailable"))?
+        .set_item(CUSTOM_PRESET_KEY, &json)
+}
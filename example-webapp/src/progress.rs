@@ -0,0 +1,49 @@
+//! Per-book reading state (the last page read and the reading direction),
+//! kept in localStorage under the book's index hash so it survives renames
+//! and re-downloads of the same file.
+
+use crate::utils::local_storage;
+
+fn key(index_hash: u64) -> String {
+    format!("bbf-progress-{index_hash:016x}")
+}
+
+fn direction_key(index_hash: u64) -> String {
+    format!("bbf-direction-{index_hash:016x}")
+}
+
+/// Page the book was left at, counting from zero.
+pub fn load(index_hash: u64) -> Option<u32> {
+    local_storage()?
+        .get_item(&key(index_hash))
+        .ok()??
+        .parse()
+        .ok()
+}
+
+pub fn save(index_hash: u64, page: u32) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(&key(index_hash), &page.to_string());
+    }
+}
+
+/// Whether the book was last read right to left, if its direction was ever
+/// changed by hand.
+pub fn load_direction(index_hash: u64) -> Option<bool> {
+    match local_storage()?
+        .get_item(&direction_key(index_hash))
+        .ok()??
+        .as_str()
+    {
+        "rtl" => Some(true),
+        "ltr" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn save_direction(index_hash: u64, right_to_left: bool) {
+    if let Some(storage) = local_storage() {
+        let value = if right_to_left { "rtl" } else { "ltr" };
+        let _ = storage.set_item(&direction_key(index_hash), value);
+    }
+}
@@ -0,0 +1,77 @@
+//! Books read straight from a `File` with `File.slice()`, so only the index
+//! and the pages being looked at are ever in memory, however big the book.
+
+use crate::utils::{blob_url, mime_type};
+use bbf::reader::{BBFError, Limits, decode_asset};
+use bbf::remote;
+use bbf::{BBFMediaType, BBFReader};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+use web_sys::{File, js_sys};
+
+pub enum OpenError {
+    Read,
+    Invalid(BBFError),
+}
+
+#[derive(Clone)]
+pub struct RandomAccess {
+    file: File,
+}
+
+impl RandomAccess {
+    pub const fn new(file: File) -> Self {
+        Self { file }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.file.size() as u64
+    }
+
+    /// Bytes `start..end` of the file.
+    pub async fn read(&self, start: u64, end: u64) -> Result<Vec<u8>, JsValue> {
+        let blob = self.file.slice_with_f64_and_f64(start as f64, end as f64)?;
+        let buffer = wasm_bindgen_futures::JsFuture::from(blob.array_buffer()).await?;
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+
+    /// Reads the header, footer and index, leaving the assets on disk.
+    pub async fn open(&self) -> Result<BBFReader<Arc<[u8]>>, OpenError> {
+        let len = self.len();
+        let footer = remote::footer_range(len).map_err(OpenError::Invalid)?;
+
+        let header = remote::header_range();
+        let header = self
+            .read(header.start, header.end)
+            .await
+            .map_err(|_| OpenError::Read)?;
+        let footer = self
+            .read(footer.start, footer.end)
+            .await
+            .map_err(|_| OpenError::Read)?;
+
+        let index =
+            remote::index_range(len, &footer, &Limits::default()).map_err(OpenError::Invalid)?;
+        let index = self
+            .read(index.start, index.end)
+            .await
+            .map_err(|_| OpenError::Read)?;
+        BBFReader::from_index(&header, Arc::from(index), len).map_err(OpenError::Invalid)
+    }
+
+    /// Stored bytes of asset `index`, as `BBFReader::get_asset` would return
+    /// them.
+    pub async fn asset(&self, reader: &BBFReader<Arc<[u8]>>, index: u32) -> Option<Vec<u8>> {
+        let range = remote::asset_range(reader, index).ok()?;
+        self.read(range.start, range.end).await.ok()
+    }
+
+    /// Object URL for asset `index`, or `None` if it can't be read. The
+    /// caller owns the URL.
+    pub async fn asset_url(&self, reader: &BBFReader<Arc<[u8]>>, index: u32) -> Option<String> {
+        let data = self.asset(reader, index).await?;
+        let entry = &reader.assets()[index as usize];
+        let decoded = decode_asset(entry, &data).ok()?;
+        blob_url(&decoded, mime_type(BBFMediaType::from(entry.type_))).ok()
+    }
+}
@@ -1,30 +1,202 @@
 #![allow(clippy::cast_possible_truncation)]
 
-use crate::utils::read_file_to_vec;
+use crate::cache::UrlCache;
+use crate::deep_link;
+use crate::library::{self, LibraryBook};
+use crate::progress;
+use crate::random_access::{OpenError, RandomAccess};
+use crate::utils::{is_right_to_left, mime_type, spread_groups, yield_now};
+use bbf::hash;
+use bbf::reader::decode_asset;
 use bbf::{BBFMediaType, BBFReader};
-use leptos::ev::{mousemove, mouseup};
+use leptos::ev::{keydown, mousemove, mouseup};
 use leptos::prelude::*;
 use leptos::task::spawn_local;
 use leptos_styling::inline_style_sheet;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlInputElement, MouseEvent, Url, js_sys};
+use wasm_bindgen::prelude::{Closure, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    DragEvent, File, HtmlImageElement, HtmlInputElement, IntersectionObserver,
+    IntersectionObserverEntry, IntersectionObserverInit, KeyboardEvent, MouseEvent,
+    ScrollIntoViewOptions, ScrollLogicalPosition, Url, js_sys,
+};
 use xxhash_rust::xxh3::xxh3_64;
 
+/// Bytes hashed between yields to the event loop while verifying.
+const VERIFY_SLICE_BYTES: usize = 8 << 20;
+
+/// Pages prepared ahead of the reader in each direction.
+const PREFETCH_PAGES: u32 = 3;
+
+/// A book being read. Only its index is in memory; pages are read from the
+/// file as they're needed.
 #[derive(Clone)]
 struct LoadedBook {
-    #[allow(dead_code)]
     name: String,
     reader: Arc<BBFReader<Arc<[u8]>>>,
+    source: RandomAccess,
+    /// Which open this is, to tell late results for an earlier book apart.
+    run: u32,
+}
+
+/// An asset whose bytes don't match the hash in the index.
+#[derive(Clone, PartialEq)]
+struct CorruptAsset {
+    asset: u32,
+    /// The pages showing it; empty for a thumbnail or an unused asset.
+    pages: Vec<u32>,
+    expected: u64,
+    /// `None` when the asset couldn't be read at all.
+    actual: Option<u64>,
+}
+
+/// Hashes every asset against the index, a slice at a time so the page stays
+/// responsive, with progress in `set_status` and mismatches in `set_corrupt`
+/// as they're found. Gives up quietly as soon as `current` returns false,
+/// and says so in `set_status` if the book isn't hashed with XXH3.
+async fn verify(
+    book: LoadedBook,
+    set_status: WriteSignal<String>,
+    set_corrupt: WriteSignal<Vec<CorruptAsset>>,
+    current: impl Fn() -> bool,
+) {
+    let reader = &book.reader;
+    let algorithm = reader.hash_algorithm();
+    if algorithm != hash::XXH3 {
+        // Mismatches against another algorithm's hashes aren't corruption.
+        let name = hash::name(algorithm).map_or_else(|| format!("#{algorithm}"), str::to_string);
+        set_status.set(format!("Integrity: can't check {name} hashes"));
+        return;
+    }
+    let total = reader.assets().len();
+    let mut bad = Vec::new();
+    let mut since_yield = 0;
+    for (i, asset) in reader.assets().iter().enumerate() {
+        let expected = asset.xxh3_hash.get();
+        let actual = match book.source.asset(reader, i as u32).await {
+            Some(data) => {
+                since_yield += data.len();
+                Some(xxh3_64(&data))
+            }
+            None => None,
+        };
+        if actual != Some(expected) {
+            let pages = reader.pages_for_asset(i as u32).to_vec();
+            bad.push(CorruptAsset {
+                asset: i as u32,
+                pages,
+                expected,
+                actual,
+            });
+        }
+
+        if since_yield >= VERIFY_SLICE_BYTES {
+            since_yield = 0;
+            set_status.set(format!("Verifying {}/{total}...", i + 1));
+            yield_now().await;
+            if !current() {
+                return;
+            }
+            set_corrupt.set(bad.clone());
+        }
+    }
+
+    if bad.is_empty() {
+        set_status.set("Integrity: OK".to_string());
+    } else {
+        set_status.set(format!("Integrity: {} CORRUPT", bad.len()));
+    }
+    set_corrupt.set(bad);
+}
+
+/// Records `book` in the library for the landing page, with its first page
+/// (or that page's thumbnail) as the cover.
+async fn add_to_library(book: LoadedBook, handle: Option<JsValue>) {
+    let reader = &book.reader;
+    let cover_asset = reader
+        .thumbnail(0)
+        .or_else(|| reader.pages().first().map(|p| p.asset_index.get()));
+    let mut cover = None;
+    if let Some(index) = cover_asset
+        && let Some(data) = book.source.asset(reader, index).await
+    {
+        let entry = &reader.assets()[index as usize];
+        if let Ok(decoded) = decode_asset(entry, &data) {
+            cover = library::blob(&decoded, mime_type(BBFMediaType::from(entry.type_)));
+        }
+    }
+
+    let title = reader
+        .metadata()
+        .iter()
+        .find(|m| reader.get_string(m.key_offset.get()) == Some("Title"))
+        .and_then(|m| reader.get_string(m.val_offset.get()))
+        .map_or_else(|| book.name.clone(), str::to_string);
+
+    let _ = library::record(LibraryBook {
+        hash: format!("{:016x}", reader.footer.index_hash.get()),
+        title,
+        pages: reader.pages().len() as u32,
+        opened_at: js_sys::Date::now(),
+        cover,
+        handle,
+    })
+    .await;
+}
+
+/// Object URL for page `page`, from the cache or read into it. `None` if the
+/// page can't be read or another book was opened meanwhile.
+async fn page_url(
+    cache: StoredValue<UrlCache, LocalStorage>,
+    open_run: StoredValue<u32>,
+    book: &LoadedBook,
+    page: u32,
+) -> Option<String> {
+    let asset = book.reader.pages().get(page as usize)?.asset_index.get();
+    if let Some(url) = cache.try_update_value(|c| c.get(asset)).flatten() {
+        return Some(url);
+    }
+
+    let url = book.source.asset_url(&book.reader, asset).await?;
+    if open_run.try_get_value() != Some(book.run) {
+        let _ = Url::revoke_object_url(&url);
+        return None;
+    }
+    cache.try_update_value(|c| c.insert(asset, url))
 }
 
 #[allow(clippy::too_many_lines)]
 #[component]
 pub fn Reader() -> impl IntoView {
-    let (book, set_book) = signal(Option::<LoadedBook>::None);
+    let (book, set_book) = signal_local(Option::<LoadedBook>::None);
     let (page_idx, set_page_idx) = signal(0u32);
     let (img_url, set_img_url) = signal(String::new());
     let (status, set_status) = signal(String::new());
+    let (show_thumbnails, set_show_thumbnails) = signal(true);
+    let (spread_mode, set_spread_mode) = signal(false);
+    // Reading direction: which way the tap zones, arrow keys and spreads go.
+    let (right_to_left, set_right_to_left) = signal(false);
+    let (partner_url, set_partner_url) = signal(String::new());
+    // Where the book was left last time, while the offer to go back stands.
+    let (resume_page, set_resume_page) = signal(Option::<u32>::None);
+    let (corrupt, set_corrupt) = signal(Vec::<CorruptAsset>::new());
+    // Narrows the sidebar's sections and metadata, lowercased.
+    let (search, set_search) = signal(String::new());
+    // Bumped for each book opened, so work on the previous book stops.
+    let open_run = StoredValue::new(0u32);
+    // Room for the prefetched pages on both sides of the current one and a
+    // spread partner, so nothing on screen is evicted.
+    let cache = StoredValue::new_local(UrlCache::new(2 * PREFETCH_PAGES as usize + 4));
+
+    // Fullscreen reading, with the bars only shown while `reveal` is set.
+    let viewer_ref = NodeRef::<leptos::html::Div>::new();
+    let (immersive, set_immersive) = signal(false);
+    let (reveal, set_reveal) = signal(false);
+    let reveal_run = StoredValue::new(0u32);
 
     let (sidebar_width, set_sidebar_width) = signal(250);
     let (is_resizing, set_is_resizing) = signal(false);
@@ -40,28 +212,26 @@ pub fn Reader() -> impl IntoView {
             color: #e2e8f0; /* text-slate-200 */
         }
 
-        .main-content {
-            flex: 1;
-            display: flex;
-            overflow: hidden;
-        }
-
-        .empty-state {
+        .drop-overlay {
+            position: fixed;
+            inset: 0;
+            z-index: 50;
             display: flex;
-            flex-direction: column;
             align-items: center;
             justify-content: center;
-            height: 100%;
-            color: #64748b; /* text-slate-500 */
-            cursor: pointer;
-            transition: all 0.2s;
+            background-color: rgba(15, 23, 42, 0.85);
+            border: 3px dashed #6366f1;
+            color: #a5b4fc;
+            font-size: 1.5rem;
+            font-weight: 700;
+            pointer-events: none;
         }
-        .empty-state:hover {
-            color: #94a3b8; /* text-slate-400 */
-            background-color: rgba(30, 41, 59, 0.5); /* bg-slate-800/50 */
+
+        .main-content {
+            flex: 1;
+            display: flex;
+            overflow: hidden;
         }
-        .empty-icon { font-size: 3.75rem; margin-bottom: 1rem; opacity: 0.5; }
-        .empty-text { font-size: 1.125rem; font-weight: 500; }
 
         .sidebar {
             background-color: #0f172a; /* bg-slate-900 */
@@ -119,6 +289,19 @@ pub fn Reader() -> impl IntoView {
         }
         .sidebar-btn:hover { background-color: #6366f1; }
 
+        .search {
+            width: 100%;
+            box-sizing: border-box;
+            padding: 0.375rem 0.5rem;
+            margin-bottom: 0.75rem;
+            background-color: #0f172a;
+            border: 1px solid #475569;
+            border-radius: 0.375rem;
+            color: #e2e8f0;
+            font-size: 0.8125rem;
+        }
+        .search:focus { outline: none; border-color: #6366f1; }
+
         .status {
             color: #a5b4fc; /* text-indigo-300 */
             font-family: monospace;
@@ -127,6 +310,23 @@ pub fn Reader() -> impl IntoView {
             word-break: break-word;
         }
 
+        .corrupt-list {
+            padding: 0.5rem;
+            list-style: none;
+            margin: 0;
+            font-size: 0.75rem;
+            border-bottom: 1px solid #334155;
+        }
+        .corrupt-item {
+            padding: 0.375rem 0.5rem;
+            border-radius: 0.25rem;
+            color: #fca5a5; /* red-300 */
+        }
+        .corrupt-item[data-page="true"] { cursor: pointer; }
+        .corrupt-item[data-page="true"]:hover { background-color: #1e293b; }
+        .corrupt-title { font-weight: 500; }
+        .corrupt-hash { font-family: monospace; opacity: 0.7; word-break: break-all; }
+
         .sidebar-header {
             padding: 1rem;
             background-color: #1e293b; /* bg-slate-800 */
@@ -197,6 +397,8 @@ pub fn Reader() -> impl IntoView {
             object-fit: contain;
             box-shadow: 0 25px 50px -12px rgba(0, 0, 0, 0.25);
         }
+        .half { max-width: 50%; }
+        .right-to-left { flex-direction: row-reverse; }
 
         .controls {
             background-color: #0f172a;
@@ -222,7 +424,80 @@ pub fn Reader() -> impl IntoView {
         .nav-btn:hover { background-color: #334155; }
 
         .page-counter { font-family: monospace; font-size: 0.875rem; color: #a5b4fc; }
+        .page-input {
+            width: 3.5rem;
+            padding: 0.125rem 0.25rem;
+            background-color: #1e293b;
+            border: 1px solid #475569;
+            border-radius: 0.25rem;
+            color: white;
+            font: inherit;
+            font-weight: 700;
+            text-align: right;
+        }
+
+        .scrubber {
+            position: relative;
+            padding: 0.375rem 0.75rem 0.25rem;
+            background-color: #0f172a;
+            border-top: 1px solid #334155;
+        }
+        .slider { width: 100%; margin: 0; accent-color: #6366f1; cursor: pointer; }
+        .tick {
+            position: absolute;
+            top: 0;
+            width: 3px;
+            height: 0.5rem;
+            margin: 0 -1px;
+            background-color: #818cf8;
+            border-radius: 1px;
+            cursor: pointer;
+        }
         .page-number { color: white; font-weight: 700; }
+
+        .controls-center {
+            display: flex;
+            align-items: center;
+            gap: 0.75rem;
+        }
+
+        .resume-bar {
+            display: flex;
+            justify-content: center;
+            gap: 0.5rem;
+            padding: 0.5rem;
+            background-color: #1e293b;
+            border-bottom: 1px solid #334155;
+        }
+
+        .top-bar, .bottom-bar { flex-shrink: 0; }
+        .top-bar[data-immersive="true"],
+        .bottom-bar[data-immersive="true"] {
+            position: absolute;
+            left: 0;
+            right: 0;
+            z-index: 30;
+            opacity: 0;
+            pointer-events: none;
+            transition: opacity 0.2s;
+        }
+        .top-bar[data-immersive="true"] { top: 0; }
+        .bottom-bar[data-immersive="true"] { bottom: 0; }
+        .top-bar[data-reveal="true"],
+        .bottom-bar[data-reveal="true"] {
+            opacity: 1;
+            pointer-events: auto;
+        }
+
+        .thumb-strip {
+            display: flex;
+            gap: 0.5rem;
+            overflow-x: auto;
+            padding: 0.5rem;
+            background-color: #0f172a;
+            border-top: 1px solid #334155;
+            flex-shrink: 0;
+        }
     }
 
     let start_resize = move |ev: MouseEvent| {
@@ -247,6 +522,43 @@ pub fn Reader() -> impl IntoView {
 
     on_cleanup(move || handle_up.remove());
 
+    let fullscreen_handle = window_event_listener_untyped("fullscreenchange", move |_| {
+        let fullscreen = document().fullscreen_element().is_some();
+        set_immersive.set(fullscreen);
+        set_reveal.set(false);
+    });
+
+    on_cleanup(move || fullscreen_handle.remove());
+
+    let toggle_fullscreen = move |_| {
+        if immersive.get_untracked() {
+            document().exit_fullscreen();
+        } else if let Some(viewer) = viewer_ref.get_untracked()
+            && let Err(e) = viewer.request_fullscreen()
+        {
+            set_status.set(format!("Fullscreen unavailable: {e:?}"));
+        }
+    };
+
+    // Shows the bars over the page, then hides them again unless the
+    // pointer keeps moving.
+    let show_bars = move || {
+        if !immersive.get_untracked() {
+            return;
+        }
+        set_reveal.set(true);
+        reveal_run.update_value(|r| *r = r.wrapping_add(1));
+        let run = reveal_run.get_value();
+        set_timeout(
+            move || {
+                if reveal_run.try_get_value() == Some(run) {
+                    set_reveal.try_set(false);
+                }
+            },
+            Duration::from_millis(2500),
+        );
+    };
+
     Effect::new(move |_| {
         if let Some(body) = web_sys::window()
             .and_then(|w| w.document())
@@ -260,96 +572,248 @@ pub fn Reader() -> impl IntoView {
         }
     });
 
+    let open_file = move |file: File, handle: Option<JsValue>| {
+        let fname = file.name();
+        spawn_local(async move {
+            set_status.set("Loading...".to_string());
+            let source = RandomAccess::new(file);
+            match source.open().await {
+                Ok(r) => {
+                    let run = open_run.get_value().wrapping_add(1);
+                    open_run.set_value(run);
+                    cache.update_value(UrlCache::clear);
+
+                    // A link to this book wins over the page it was left at.
+                    let index_hash = r.footer.index_hash.get();
+                    let linked = deep_link::current()
+                        .filter(|l| {
+                            l.index_hash == index_hash && (l.page as usize) < r.pages().len()
+                        })
+                        .map(|l| l.page);
+                    let saved = progress::load(index_hash)
+                        .filter(|&p| linked.is_none() && p > 0 && (p as usize) < r.pages().len());
+                    set_resume_page.set(saved);
+                    set_corrupt.set(Vec::new());
+                    set_right_to_left.set(
+                        progress::load_direction(index_hash)
+                            .unwrap_or_else(|| is_right_to_left(&r)),
+                    );
+
+                    let loaded = LoadedBook {
+                        name: fname,
+                        reader: Arc::new(r),
+                        source,
+                        run,
+                    };
+                    set_book.set(Some(loaded.clone()));
+                    set_page_idx.set(linked.unwrap_or(0));
+
+                    spawn_local(add_to_library(loaded.clone(), handle));
+                    verify(loaded, set_status, set_corrupt, move || {
+                        open_run.try_get_value() == Some(run)
+                    })
+                    .await;
+                }
+                Err(OpenError::Invalid(e)) => set_status.set(format!("Invalid BBF: {e:?}")),
+                Err(OpenError::Read) => set_status.set("Read error".to_string()),
+            }
+        });
+    };
+
     let handle_file = move |ev: web_sys::Event| {
         let target: HtmlInputElement = ev.target().unwrap().unchecked_into();
         if let Some(files) = target.files()
             && let Some(file) = files.get(0)
         {
-            let fname = file.name();
-            spawn_local(async move {
-                set_status.set("Loading & Verifying...".to_string());
-                match read_file_to_vec(&file).await {
-                    Ok(vec) => {
-                        let data_arc: Arc<[u8]> = Arc::from(vec);
-
-                        match BBFReader::new(data_arc) {
-                            Ok(r) => {
-                                let assets = r.assets();
-                                let mut bad = 0;
-                                for (i, asset) in assets.iter().enumerate() {
-                                    if let Ok(data) = r.get_asset(i as u32) {
-                                        if xxh3_64(data) != asset.xxh3_hash.get() {
-                                            bad += 1;
-                                        }
-                                    } else {
-                                        bad += 1;
-                                    }
-                                }
+            open_file(file, None);
+        }
+    };
 
-                                if bad == 0 {
-                                    set_status.set("Integrity: OK".to_string());
-                                } else {
-                                    set_status.set(format!("Integrity: {bad} CORRUPT"));
-                                }
+    // A book dragged in from outside opens wherever it's dropped.
+    let (file_drag, set_file_drag) = signal(false);
+    let handle_drop = move |ev: DragEvent| {
+        set_file_drag.set(false);
+        let Some(data) = ev.data_transfer() else {
+            return;
+        };
+        ev.prevent_default();
+        let items = data.items();
+        let Some((item, file)) = (0..items.length())
+            .filter_map(|i| items.get(i))
+            .filter_map(|item| Some((item.clone(), item.get_as_file().ok()??)))
+            .find(|(_, f)| f.name().to_lowercase().ends_with(".bbf"))
+        else {
+            set_status.set("Drop a .bbf file to open it".to_string());
+            return;
+        };
+        let handle = library::dropped_handle(&item);
+        spawn_local(async move {
+            let handle = match handle {
+                Some(promise) => JsFuture::from(promise)
+                    .await
+                    .ok()
+                    .filter(|h| !h.is_null() && !h.is_undefined()),
+                None => None,
+            };
+            open_file(file, handle);
+        });
+    };
 
-                                set_book.set(Some(LoadedBook {
-                                    name: fname,
-                                    reader: Arc::new(r),
-                                }));
-                                set_page_idx.set(0);
-                            }
-                            Err(e) => set_status.set(format!("Invalid BBF: {e:?}")),
-                        }
-                    }
-                    Err(_) => set_status.set("Read error".to_string()),
-                }
-            });
-        }
+    let close_book = move |_| {
+        open_run.update_value(|r| *r = r.wrapping_add(1));
+        cache.update_value(UrlCache::clear);
+        set_book.set(None);
+        set_img_url.set(String::new());
+        set_status.set(String::new());
+        set_resume_page.set(None);
+        set_corrupt.set(Vec::new());
+        set_search.set(String::new());
+        deep_link::clear();
     };
 
+    // Follow edits to the fragment while its book is open.
+    let hash_handle = window_event_listener_untyped("hashchange", move |_| {
+        let Some(link) = deep_link::current() else {
+            return;
+        };
+        let pages = book.with_untracked(|b| {
+            b.as_ref()
+                .filter(|b| b.reader.footer.index_hash.get() == link.index_hash)
+                .map(|b| b.reader.pages().len())
+        });
+        if pages.is_some_and(|n| (link.page as usize) < n) {
+            set_page_idx.set(link.page);
+        }
+    });
+
+    on_cleanup(move || hash_handle.remove());
+
+    // The innermost section holding the current page: the last one to start
+    // at or before it.
+    let active_section = Memo::new(move |_| {
+        let page = page_idx.get();
+        book.with(|b| {
+            b.as_ref().and_then(|b| {
+                b.reader
+                    .sections()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.section_start_index.get() <= page)
+                    .max_by_key(|(_, s)| s.section_start_index.get())
+                    .map(|(i, _)| i)
+            })
+        })
+    });
+
+    let corrupt_pages = Memo::new(move |_| {
+        corrupt.with(|c| {
+            c.iter()
+                .flat_map(|a| a.pages.iter().copied())
+                .collect::<HashSet<u32>>()
+        })
+    });
+
+    let spreads = Memo::new(move |_| {
+        book.get()
+            .map(|bk| spread_groups(&bk.reader))
+            .unwrap_or_default()
+    });
+    // Index into `spreads` of the group holding the current page.
+    let current_spread = Memo::new(move |_| {
+        let idx = page_idx.get();
+        spreads.with(|g| g.iter().position(|&(a, b)| a == idx || b == Some(idx)))
+    });
+    // The page shown beside the current one, if any.
+    let partner = Memo::new(move |_| {
+        if !spread_mode.get() {
+            return None;
+        }
+        current_spread.get().and_then(|i| spreads.with(|g| g[i].1))
+    });
+
+    // A spread always starts at its first page, however it was reached.
+    Effect::new(move |_| {
+        if spread_mode.get()
+            && let Some(first) = current_spread.get().map(|i| spreads.with(|g| g[i].0))
+            && first != page_idx.get()
+        {
+            set_page_idx.set(first);
+        }
+    });
+
     Effect::new(move |_| {
         if let Some(bk) = book.get() {
             let idx = page_idx.get();
-            let pages = bk.reader.pages();
-            if (idx as usize) < pages.len() {
-                let page = &pages[idx as usize];
-                let asset_idx = page.asset_index.get();
-                if let Ok(asset_data) = bk.reader.get_asset(asset_idx) {
-                    let assets = bk.reader.assets();
-                    let asset_entry = &assets[asset_idx as usize];
-                    let mime = BBFMediaType::from(asset_entry.type_).as_extension();
-
-                    let mime_str = match mime {
-                        ".png" => "image/png",
-                        ".jpg" | ".jpeg" => "image/jpeg",
-                        ".avif" => "image/avif",
-                        ".webp" => "image/webp",
-                        _ => "application/octet-stream",
-                    };
-
-                    let array = js_sys::Array::new();
-                    let u8arr = js_sys::Uint8Array::from(asset_data);
-                    array.push(&u8arr.buffer());
-
-                    let bag = web_sys::BlobPropertyBag::new();
-                    bag.set_type(mime_str);
+            spawn_local(async move {
+                if let Some(url) = page_url(cache, open_run, &bk, idx).await
+                    && page_idx.get_untracked() == idx
+                {
+                    set_img_url.set(url);
+                }
 
-                    if let Ok(blob) =
-                        web_sys::Blob::new_with_blob_sequence_and_options(&array, &bag)
-                        && let Ok(url) = Url::create_object_url_with_blob(&blob)
+                // Give the current page a chance to paint, then get the pages
+                // around it ready so turning to them doesn't wait on a read.
+                yield_now().await;
+                let ahead = (idx + 1..=idx + PREFETCH_PAGES + 1)
+                    .filter(|&p| (p as usize) < bk.reader.pages().len());
+                let behind = (idx.saturating_sub(PREFETCH_PAGES)..idx).rev();
+                for page in ahead.chain(behind) {
+                    if page_idx.try_get_untracked() != Some(idx) {
+                        return;
+                    }
+                    if let Some(url) = page_url(cache, open_run, &bk, page).await
+                        && let Ok(img) = HtmlImageElement::new()
                     {
-                        let old = img_url.get_untracked();
-                        if !old.is_empty() {
-                            let _ = Url::revoke_object_url(&old);
-                        }
-                        set_img_url.set(url);
+                        // Starts the browser decoding it too.
+                        img.set_src(&url);
                     }
                 }
+            });
+        }
+    });
+
+    Effect::new(move |_| {
+        let (Some(bk), Some(page)) = (book.get(), partner.get()) else {
+            set_partner_url.set(String::new());
+            return;
+        };
+        spawn_local(async move {
+            if let Some(url) = page_url(cache, open_run, &bk, page).await
+                && partner.get_untracked() == Some(page)
+            {
+                set_partner_url.set(url);
             }
+        });
+    });
+
+    // Remember the page, and put it in the URL. While the resume offer is up,
+    // the first page is only where the book opened, not a choice worth saving
+    // over the old one.
+    Effect::new(move |_| {
+        let idx = page_idx.get();
+        let Some(hash) = book.with(|b| b.as_ref().map(|b| b.reader.footer.index_hash.get())) else {
+            return;
+        };
+        deep_link::set(hash, idx);
+        if resume_page.get_untracked().is_some() {
+            if idx == 0 {
+                return;
+            }
+            set_resume_page.set(None);
         }
+        progress::save(hash, idx);
     });
 
     let next_page_logic = move || {
+        if spread_mode.get() {
+            if let Some(next) = current_spread
+                .get()
+                .and_then(|i| spreads.with(|g| g.get(i + 1).map(|s| s.0)))
+            {
+                set_page_idx.set(next);
+            }
+            return;
+        }
         if let Some(bk) = book.get() {
             let max = bk.reader.pages().len() as u32;
             if page_idx.get() + 1 < max {
@@ -359,19 +823,104 @@ pub fn Reader() -> impl IntoView {
     };
 
     let prev_page_logic = move || {
+        if spread_mode.get() {
+            if let Some(prev) = current_spread
+                .get()
+                .and_then(|i| i.checked_sub(1))
+                .map(|i| spreads.with(|g| g[i].0))
+            {
+                set_page_idx.set(prev);
+            }
+            return;
+        }
         if page_idx.get() > 0 {
             set_page_idx.update(|i| *i -= 1);
         }
     };
 
+    let page_count =
+        Memo::new(move |_| book.with(|b| b.as_ref().map_or(0, |b| b.reader.pages().len() as u32)));
+    let go_to_page = move |page: u32| {
+        let count = page_count.get_untracked();
+        if count > 0 {
+            set_page_idx.set(page.min(count - 1));
+        }
+    };
+    // Where each section starts, for the ticks on the slider.
+    let section_starts = Memo::new(move |_| {
+        book.with(|b| {
+            b.as_ref().map_or_else(Vec::new, |b| {
+                let reader = &b.reader;
+                reader
+                    .sections()
+                    .iter()
+                    .map(|s| {
+                        let title = reader
+                            .get_string(s.section_title_offset.get())
+                            .unwrap_or("?");
+                        (title.to_string(), s.section_start_index.get())
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+    });
+
+    let toggle_direction = move |_| {
+        let rtl = !right_to_left.get_untracked();
+        set_right_to_left.set(rtl);
+        if let Some(hash) =
+            book.with_untracked(|b| b.as_ref().map(|b| b.reader.footer.index_hash.get()))
+        {
+            progress::save_direction(hash, rtl);
+        }
+    };
+
+    // Arrow keys turn pages the way the book reads, unless a field has focus.
+    let key_handle = window_event_listener(keydown, move |ev: KeyboardEvent| {
+        let typing = ev.target().is_some_and(|t| {
+            t.dyn_ref::<web_sys::Element>()
+                .is_some_and(|el| matches!(el.tag_name().as_str(), "INPUT" | "SELECT" | "TEXTAREA"))
+        });
+        if typing || book.with_untracked(Option::is_none) {
+            return;
+        }
+        let forward = match ev.key().as_str() {
+            "ArrowRight" => !right_to_left.get_untracked(),
+            "ArrowLeft" => right_to_left.get_untracked(),
+            _ => return,
+        };
+        ev.prevent_default();
+        if forward {
+            next_page_logic();
+        } else {
+            prev_page_logic();
+        }
+    });
+
+    on_cleanup(move || key_handle.remove());
+
     view! {
-        <div class=reader_css::CONTAINER>
+        <div
+            class=reader_css::CONTAINER
+            on:dragover=move |ev: DragEvent| {
+                if ev.data_transfer().is_some_and(|d| d.types().includes(&"Files".into(), 0)) {
+                    ev.prevent_default();
+                    set_file_drag.set(true);
+                }
+            }
+            on:dragleave=move |ev: DragEvent| {
+                // Leaving for a child element isn't leaving the reader.
+                if ev.related_target().is_none() {
+                    set_file_drag.set(false);
+                }
+            }
+            on:drop=handle_drop
+        >
+            <Show when=move || file_drag.get()>
+                <div class=reader_css::DROP_OVERLAY>"Drop a .bbf to open it"</div>
+            </Show>
             <Show when=move || book.get().is_some() fallback=move || view! {
-                <label class=reader_css::EMPTY_STATE>
-                    <div class=reader_css::EMPTY_ICON>"📖"</div>
-                    <div class=reader_css::EMPTY_TEXT>"Select a BBF file to begin reading."</div>
-                    <input type="file" accept=".bbf" on:change=handle_file class="hidden" style="display:none" />
-                </label>
+                <Library open_file=open_file />
             }>
                 <div class=reader_css::MAIN_CONTENT>
                     <div
@@ -383,22 +932,67 @@ pub fn Reader() -> impl IntoView {
                                 "Open New File"
                                 <input type="file" accept=".bbf" on:change=handle_file class="hidden" style="display:none" />
                             </label>
+                            <button class=reader_css::SIDEBAR_BTN on:click=close_book>
+                                "Library"
+                            </button>
+                            <input
+                                type="search"
+                                class=reader_css::SEARCH
+                                placeholder="Search sections and metadata"
+                                prop:value=search
+                                on:input=move |ev| set_search.set(event_target_value(&ev).to_lowercase())
+                            />
                             <div class=reader_css::STATUS>{move || status.get()}</div>
                         </div>
 
+                        <Show when=move || corrupt.with(|c| !c.is_empty())>
+                            <div class=reader_css::SIDEBAR_HEADER>"Corrupt Assets"</div>
+                            <ul class=reader_css::CORRUPT_LIST>
+                                {move || corrupt.get().into_iter().map(|c| {
+                                    let first_page = c.pages.first().copied();
+                                    let title = if c.pages.is_empty() {
+                                        format!("Asset {}", c.asset)
+                                    } else {
+                                        let pages = c.pages.iter().map(|p| (p + 1).to_string()).collect::<Vec<_>>();
+                                        format!("Page {} (asset {})", pages.join(", "), c.asset)
+                                    };
+                                    let actual = c.actual.map_or_else(|| "unreadable".to_string(), |h| format!("{h:016x}"));
+                                    view! {
+                                        <li
+                                            class=reader_css::CORRUPT_ITEM
+                                            attr:data-page=first_page.is_some().to_string()
+                                            on:click=move |_| {
+                                                if let Some(page) = first_page {
+                                                    set_page_idx.set(page);
+                                                }
+                                            }
+                                        >
+                                            <div class=reader_css::CORRUPT_TITLE>{title}</div>
+                                            <div class=reader_css::CORRUPT_HASH>{format!("expected {:016x}", c.expected)}</div>
+                                            <div class=reader_css::CORRUPT_HASH>{format!("actual   {actual}")}</div>
+                                        </li>
+                                    }
+                                }).collect_view()}
+                            </ul>
+                        </Show>
+
                         <div class=reader_css::SIDEBAR_HEADER>"Sections"</div>
                         <ul class=reader_css::SIDEBAR_LIST>
                             {move || {
                                 book.get().map(|bk| {
                                     let reader = bk.reader;
                                     let reader_for_closure = reader.clone();
+                                    let query = search.get();
 
-                                    reader.sections().iter().map(move |s| {
+                                    reader.sections().iter().enumerate().filter_map(move |(i, s)| {
                                         let title = reader_for_closure.get_string(s.section_title_offset.get()).unwrap_or("?").to_string();
+                                        if !title.to_lowercase().contains(&query) {
+                                            return None;
+                                        }
                                         let page = s.section_start_index.get();
-                                        let is_active = page_idx.get() >= page;
+                                        let is_active = active_section.get() == Some(i);
 
-                                        view! {
+                                        Some(view! {
                                             <li
                                                 class=if is_active {
                                                     format!("{} {}", reader_css::SECTION_ITEM, reader_css::ACTIVE)
@@ -410,7 +1004,7 @@ pub fn Reader() -> impl IntoView {
                                                 <div class=reader_css::SECTION_TITLE>{title}</div>
                                                 <div class=reader_css::SECTION_PAGE>"Page " {page + 1}</div>
                                             </li>
-                                        }
+                                        })
                                     }).collect_view()
                                 })
                             }}
@@ -423,16 +1017,20 @@ pub fn Reader() -> impl IntoView {
                                 book.get().map(|bk| {
                                     let reader = bk.reader;
                                     let reader_for_closure = reader.clone();
+                                    let query = search.get();
 
-                                    reader.metadata().iter().map(move |m| {
+                                    reader.metadata().iter().filter_map(move |m| {
                                         let k = reader_for_closure.get_string(m.key_offset.get()).unwrap_or("?").to_string();
                                         let v = reader_for_closure.get_string(m.val_offset.get()).unwrap_or("?").to_string();
-                                        view! {
+                                        if !k.to_lowercase().contains(&query) && !v.to_lowercase().contains(&query) {
+                                            return None;
+                                        }
+                                        Some(view! {
                                             <li class=reader_css::META_ITEM>
                                                 <span class=reader_css::META_KEY>{k}</span>
                                                 <span class=reader_css::META_VAL>{v}</span>
                                             </li>
-                                        }
+                                        })
                                     }).collect_view()
                                 })
                             }}
@@ -448,30 +1046,175 @@ pub fn Reader() -> impl IntoView {
                         on:mousedown=start_resize
                     ></div>
 
-                    <div class=reader_css::VIEWER_AREA>
+                    <div
+                        class=reader_css::VIEWER_AREA
+                        node_ref=viewer_ref
+                        on:mousemove=move |_| show_bars()
+                    >
+                        {move || resume_page.get().map(|page| view! {
+                            <div
+                                class=format!("{} {}", reader_css::TOP_BAR, reader_css::RESUME_BAR)
+                                attr:data-immersive=move || immersive.get().to_string()
+                                attr:data-reveal=move || reveal.get().to_string()
+                            >
+                                <button
+                                    class=reader_css::NAV_BTN
+                                    on:click=move |_| {
+                                        set_resume_page.set(None);
+                                        set_page_idx.set(page);
+                                    }
+                                >
+                                    {format!("Continue from page {}", page + 1)}
+                                </button>
+                                <button class=reader_css::NAV_BTN on:click=move |_| set_resume_page.set(None)>
+                                    "Start over"
+                                </button>
+                            </div>
+                        })}
                         <div
-                            class=reader_css::IMAGE_CONTAINER
+                            class=move || if right_to_left.get() {
+                                format!("{} {}", reader_css::IMAGE_CONTAINER, reader_css::RIGHT_TO_LEFT)
+                            } else {
+                                reader_css::IMAGE_CONTAINER.to_string()
+                            }
                             on:click=move |ev| {
                                  let width = web_sys::window().unwrap().inner_width().unwrap().as_f64().unwrap();
                                  let x = f64::from(ev.client_x());
-                                 if x > width / 2.0 { next_page_logic(); } else { prev_page_logic(); }
+                                 // In fullscreen, a tap in the middle brings the bars back.
+                                 if immersive.get_untracked() && (x - width / 2.0).abs() < width / 6.0 {
+                                     if reveal.get_untracked() { set_reveal.set(false); } else { show_bars(); }
+                                     return;
+                                 }
+                                 // Right-to-left books turn forward on the left half.
+                                 if (x > width / 2.0) != right_to_left.get() { next_page_logic(); } else { prev_page_logic(); }
                             }
                         >
-                            <img src=move || img_url.get() class=reader_css::PAGE_IMAGE />
+                            {move || {
+                                let class = if partner.get().is_some() {
+                                    format!("{} {}", reader_css::PAGE_IMAGE, reader_css::HALF)
+                                } else {
+                                    reader_css::PAGE_IMAGE.to_string()
+                                };
+                                view! {
+                                    <img src=move || img_url.get() class=class.clone() />
+                                    <Show when=move || partner.get().is_some()>
+                                        <img src=move || partner_url.get() class=class.clone() />
+                                    </Show>
+                                }
+                            }}
                         </div>
 
-                        <div class=reader_css::CONTROLS>
-                             <button on:click=move |_| prev_page_logic() class=reader_css::NAV_BTN>
-                                "Previous"
-                             </button>
+                        <div
+                            class=reader_css::BOTTOM_BAR
+                            attr:data-immersive=move || immersive.get().to_string()
+                            attr:data-reveal=move || reveal.get().to_string()
+                        >
+                            <Show when=move || show_thumbnails.get()>
+                                <div class=reader_css::THUMB_STRIP>
+                                    {move || {
+                                        book.get().map(|bk| {
+                                            (0..bk.reader.pages().len() as u32).map(|page| {
+                                                view! {
+                                                    <Thumbnail
+                                                        book=bk.clone()
+                                                        page=page
+                                                        corrupt=Signal::derive(move || corrupt_pages.with(|c| c.contains(&page)))
+                                                        page_idx=page_idx
+                                                        set_page_idx=set_page_idx
+                                                    />
+                                                }
+                                            }).collect_view()
+                                        })
+                                    }}
+                                </div>
+                            </Show>
+
+                            <div
+                                class=reader_css::SCRUBBER
+                                dir=move || if right_to_left.get() { "rtl" } else { "ltr" }
+                            >
+                                <input
+                                    type="range"
+                                    class=reader_css::SLIDER
+                                    min="1"
+                                    max=move || page_count.get().to_string()
+                                    prop:value=move || (page_idx.get() + 1).to_string()
+                                    on:input=move |ev| {
+                                        if let Ok(page) = event_target_value(&ev).parse::<u32>() {
+                                            go_to_page(page.saturating_sub(1));
+                                        }
+                                    }
+                                />
+                                {move || {
+                                    let last = page_count.get().saturating_sub(1).max(1);
+                                    let side = if right_to_left.get() { "right" } else { "left" };
+                                    section_starts.get().into_iter().map(|(title, page)| {
+                                        let at = f64::from(page) / f64::from(last);
+                                        view! {
+                                            <div
+                                                class=reader_css::TICK
+                                                style=format!("{side}: calc(0.75rem + (100% - 1.5rem) * {at:.4})")
+                                                title=format!("{title} (page {})", page + 1)
+                                                on:click=move |_| go_to_page(page)
+                                            ></div>
+                                        }
+                                    }).collect_view()
+                                }}
+                            </div>
+
+                            <div class=reader_css::CONTROLS>
+                                 <button on:click=move |_| prev_page_logic() class=reader_css::NAV_BTN>
+                                    "Previous"
+                                 </button>
 
-                             <span class=reader_css::PAGE_COUNTER>
-                                "Page " <span class=reader_css::PAGE_NUMBER>{move || page_idx.get() + 1}</span>
-                             </span>
+                                 <div class=reader_css::CONTROLS_CENTER>
+                                    <button
+                                        on:click=move |_| set_show_thumbnails.update(|s| *s = !*s)
+                                        class=reader_css::NAV_BTN
+                                    >
+                                        {move || if show_thumbnails.get() { "Hide Thumbnails" } else { "Thumbnails" }}
+                                    </button>
+                                    <button
+                                        on:click=move |_| set_spread_mode.update(|s| *s = !*s)
+                                        class=reader_css::NAV_BTN
+                                    >
+                                        {move || if spread_mode.get() { "Single Page" } else { "Spreads" }}
+                                    </button>
+                                    <button
+                                        on:click=toggle_direction
+                                        class=reader_css::NAV_BTN
+                                        title="Reading direction"
+                                    >
+                                        {move || if right_to_left.get() { "Right to Left" } else { "Left to Right" }}
+                                    </button>
+                                    <button on:click=toggle_fullscreen class=reader_css::NAV_BTN>
+                                        {move || if immersive.get() { "Exit Fullscreen" } else { "Fullscreen" }}
+                                    </button>
+                                    <span class=reader_css::PAGE_COUNTER>
+                                        "Page "
+                                        <input
+                                            type="number"
+                                            class=reader_css::PAGE_INPUT
+                                            min="1"
+                                            max=move || page_count.get().to_string()
+                                            prop:value=move || (page_idx.get() + 1).to_string()
+                                            on:change=move |ev| {
+                                                if let Ok(page) = event_target_value(&ev).trim().parse::<u32>() {
+                                                    go_to_page(page.saturating_sub(1));
+                                                }
+                                            }
+                                        />
+                                        <span class=reader_css::PAGE_NUMBER>
+                                            {move || partner.get().map(|p| format!("–{}", p + 1))}
+                                        </span>
+                                        {move || format!(" / {}", page_count.get())}
+                                    </span>
+                                 </div>
 
-                             <button on:click=move |_| next_page_logic() class=reader_css::NAV_BTN>
-                                "Next"
-                             </button>
+                                 <button on:click=move |_| next_page_logic() class=reader_css::NAV_BTN>
+                                    "Next"
+                                 </button>
+                            </div>
                         </div>
                     </div>
                 </div>
@@ -479,3 +1222,359 @@ pub fn Reader() -> impl IntoView {
         </div>
     }
 }
+
+/// One entry of the reader's thumbnail strip. Its image is only created once
+/// the entry scrolls near the viewport, from the book's embedded thumbnail
+/// if it has one and the page itself otherwise.
+#[component]
+fn Thumbnail(
+    book: LoadedBook,
+    page: u32,
+    /// Whether the page failed verification.
+    corrupt: Signal<bool>,
+    page_idx: ReadSignal<u32>,
+    set_page_idx: WriteSignal<u32>,
+) -> impl IntoView {
+    let node = NodeRef::<leptos::html::Div>::new();
+    let (url, set_url) = signal(Option::<String>::None);
+
+    inline_style_sheet! {
+        thumb_css,
+        "thumb",
+
+        .thumb {
+            flex: 0 0 auto;
+            width: 64px;
+            height: 96px;
+            position: relative;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            background-color: #1e293b;
+            border: 2px solid transparent;
+            border-radius: 0.25rem;
+            cursor: pointer;
+            overflow: hidden;
+        }
+        .thumb:hover { border-color: #475569; }
+        .active, .active:hover { border-color: #6366f1; }
+        .thumb[data-corrupt="true"] { border-color: #ef4444; /* red-500 */ }
+
+        .image { max-width: 100%; max-height: 100%; object-fit: contain; }
+
+        .label {
+            position: absolute;
+            bottom: 0;
+            right: 0;
+            padding: 0 0.25rem;
+            font-size: 0.625rem;
+            font-family: monospace;
+            color: #e2e8f0;
+            background-color: rgba(15, 23, 42, 0.8);
+        }
+    }
+
+    Effect::new(move |_| {
+        let Some(el) = node.get() else {
+            return;
+        };
+        let book = book.clone();
+        // The observer only fires once per entry; its closure is leaked like
+        // the FileReader callbacks in `utils`.
+        let on_visible = Closure::<dyn FnMut(js_sys::Array, IntersectionObserver)>::new(
+            move |entries: js_sys::Array, observer: IntersectionObserver| {
+                let visible = entries.iter().any(|e| {
+                    e.unchecked_into::<IntersectionObserverEntry>()
+                        .is_intersecting()
+                });
+                if !visible {
+                    return;
+                }
+                observer.disconnect();
+                let book = book.clone();
+                spawn_local(async move {
+                    let reader = &book.reader;
+                    let asset = reader.thumbnail(page).or_else(|| {
+                        reader
+                            .pages()
+                            .get(page as usize)
+                            .map(|p| p.asset_index.get())
+                    });
+                    let Some(asset) = asset else {
+                        return;
+                    };
+                    // The entry may be gone by the time the read finishes.
+                    if let Some(u) = book.source.asset_url(reader, asset).await
+                        && let Some(Some(u)) = set_url.try_set(Some(u))
+                    {
+                        let _ = Url::revoke_object_url(&u);
+                    }
+                });
+            },
+        );
+        let options = IntersectionObserverInit::new();
+        options.set_root_margin("200px");
+        if let Ok(observer) =
+            IntersectionObserver::new_with_options(on_visible.as_ref().unchecked_ref(), &options)
+        {
+            observer.observe(&el);
+        }
+        on_visible.forget();
+    });
+
+    // Keep the current page in view as the reader moves through the book.
+    Effect::new(move |_| {
+        if page_idx.get() == page
+            && let Some(el) = node.get()
+        {
+            let options = ScrollIntoViewOptions::new();
+            options.set_block(ScrollLogicalPosition::Nearest);
+            options.set_inline(ScrollLogicalPosition::Center);
+            el.scroll_into_view_with_scroll_into_view_options(&options);
+        }
+    });
+
+    on_cleanup(move || {
+        if let Some(Some(u)) = url.try_get_untracked() {
+            let _ = Url::revoke_object_url(&u);
+        }
+    });
+
+    view! {
+        <div
+            node_ref=node
+            class=move || if page_idx.get() == page {
+                format!("{} {}", thumb_css::THUMB, thumb_css::ACTIVE)
+            } else {
+                thumb_css::THUMB.to_string()
+            }
+            attr:data-corrupt=move || corrupt.get().to_string()
+            title=move || if corrupt.get() {
+                format!("Page {} (corrupt)", page + 1)
+            } else {
+                format!("Page {}", page + 1)
+            }
+            on:click=move |_| set_page_idx.set(page)
+        >
+            {move || url.get().map(|u| view! { <img src=u class=thumb_css::IMAGE /> })}
+            <span class=thumb_css::LABEL>{page + 1}</span>
+        </div>
+    }
+}
+
+/// The reader's landing page: a way to open a book and the books opened
+/// before, newest first.
+#[component]
+fn Library(
+    open_file: impl Fn(File, Option<JsValue>) + Copy + Send + Sync + 'static,
+) -> impl IntoView {
+    let (books, set_books) = signal_local(Vec::<LibraryBook>::new());
+    spawn_local(async move {
+        let list = library::list().await;
+        let _ = set_books.try_set(list);
+    });
+    // A link to a book can only be followed once its file is picked again.
+    let link = deep_link::current();
+    let linked_hash = link.map(|l| format!("{:016x}", l.index_hash));
+
+    inline_style_sheet! {
+        library_css,
+        "library",
+
+        .library {
+            height: 100%;
+            overflow-y: auto;
+            padding: 1.5rem;
+            box-sizing: border-box;
+        }
+
+        .grid {
+            display: grid;
+            grid-template-columns: repeat(auto-fill, minmax(9rem, 1fr));
+            gap: 1rem;
+        }
+
+        .tile {
+            position: relative;
+            display: flex;
+            flex-direction: column;
+            background-color: #0f172a;
+            border: 1px solid #334155;
+            border-radius: 0.5rem;
+            overflow: hidden;
+            cursor: pointer;
+            transition: border-color 0.2s;
+            color: inherit;
+            text-align: left;
+            padding: 0;
+            font: inherit;
+        }
+        .tile:hover { border-color: #6366f1; }
+
+        .cover {
+            aspect-ratio: 2 / 3;
+            width: 100%;
+            object-fit: cover;
+            background-color: #1e293b;
+            display: flex;
+            align-items: center;
+            justify-content: center;
+            font-size: 3rem;
+            color: #64748b;
+        }
+
+        .caption { padding: 0.5rem; font-size: 0.75rem; }
+        .name {
+            font-weight: 600;
+            color: #e2e8f0;
+            white-space: nowrap;
+            overflow: hidden;
+            text-overflow: ellipsis;
+        }
+        .detail { color: #64748b; }
+
+        .remove {
+            position: absolute;
+            top: 0.25rem;
+            right: 0.25rem;
+            border: none;
+            border-radius: 0.25rem;
+            background-color: rgba(15, 23, 42, 0.8);
+            color: #94a3b8;
+            cursor: pointer;
+            padding: 0.125rem 0.375rem;
+        }
+        .remove:hover { color: #f87171; }
+
+        .tile[data-linked="true"] { border-color: #6366f1; }
+
+        .linked-note {
+            margin-bottom: 1rem;
+            padding: 0.75rem 1rem;
+            background-color: #1e293b;
+            border: 1px solid #334155;
+            border-radius: 0.5rem;
+            font-size: 0.875rem;
+            color: #a5b4fc;
+        }
+    }
+
+    let pick = move |_| {
+        spawn_local(async move {
+            if let Some((file, handle)) = library::pick_book().await {
+                open_file(file, Some(handle));
+            }
+        });
+    };
+    let on_input = move |ev: web_sys::Event| {
+        let input: HtmlInputElement = event_target(&ev);
+        if let Some(file) = input.files().and_then(|f| f.get(0)) {
+            open_file(file, None);
+        }
+    };
+
+    view! {
+        <div class=library_css::LIBRARY>
+            {link.map(|l| {
+                let linked_hash = linked_hash.clone();
+                view! {
+                    <div class=library_css::LINKED_NOTE>
+                        {move || {
+                            let title = books.with(|b| {
+                                b.iter().find(|b| Some(&b.hash) == linked_hash.as_ref()).map(|b| b.title.clone())
+                            });
+                            match title {
+                                Some(t) => format!("This link points to page {} of “{t}”. Open it to continue there.", l.page + 1),
+                                None => format!("This link points to page {} of a book not in your library. Open it to continue there.", l.page + 1),
+                            }
+                        }}
+                    </div>
+                }
+            })}
+            <div class=library_css::GRID>
+                {if library::has_file_handles() {
+                    view! {
+                        <button class=library_css::TILE on:click=pick>
+                            <div class=library_css::COVER>"📖"</div>
+                            <div class=library_css::CAPTION>
+                                <div class=library_css::NAME>"Open a book"</div>
+                            </div>
+                        </button>
+                    }.into_any()
+                } else {
+                    view! {
+                        <label class=library_css::TILE>
+                            <div class=library_css::COVER>"📖"</div>
+                            <div class=library_css::CAPTION>
+                                <div class=library_css::NAME>"Open a book"</div>
+                            </div>
+                            <input type="file" accept=".bbf" on:change=on_input style="display:none" />
+                        </label>
+                    }.into_any()
+                }}
+                {move || books.get().into_iter().map(|book| {
+                        let cover = book
+                            .cover
+                            .as_ref()
+                            .and_then(|c| Url::create_object_url_with_blob(c).ok());
+                        if let Some(url) = cover.clone() {
+                            on_cleanup(move || {
+                                let _ = Url::revoke_object_url(&url);
+                            });
+                        }
+                        let linked = Some(&book.hash) == linked_hash.as_ref();
+                        let hash = book.hash.clone();
+                        let remove = move |ev: MouseEvent| {
+                            ev.stop_propagation();
+                            ev.prevent_default();
+                            let hash = hash.clone();
+                            spawn_local(async move {
+                                if library::remove(&hash).await.is_ok() {
+                                    set_books.update(|b| b.retain(|x| x.hash != hash));
+                                }
+                            });
+                        };
+                        let contents = view! {
+                            {match cover {
+                                Some(url) => view! { <img class=library_css::COVER src=url alt="" /> }.into_any(),
+                                None => view! { <div class=library_css::COVER>"📕"</div> }.into_any(),
+                            }}
+                            <div class=library_css::CAPTION>
+                                <div class=library_css::NAME title=book.title.clone()>{book.title.clone()}</div>
+                                <div class=library_css::DETAIL>
+                                    {format!("{} pages", book.pages)}
+                                    {book.handle.is_none().then_some(" · pick to reopen")}
+                                </div>
+                            </div>
+                            <button class=library_css::REMOVE title="Remove from library" on:click=remove>"✕"</button>
+                        };
+                        // Without a handle the file has to be picked again.
+                        match book.handle {
+                            Some(handle) => view! {
+                                <div
+                                    class=library_css::TILE
+                                    attr:data-linked=linked.to_string()
+                                    on:click=move |_| {
+                                        let handle = handle.clone();
+                                        spawn_local(async move {
+                                            if let Some(file) = library::file_from_handle(&handle).await {
+                                                open_file(file, Some(handle));
+                                            }
+                                        });
+                                    }
+                                >
+                                    {contents}
+                                </div>
+                            }.into_any(),
+                            None => view! {
+                                <label class=library_css::TILE attr:data-linked=linked.to_string()>
+                                    {contents}
+                                    <input type="file" accept=".bbf" on:change=on_input style="display:none" />
+                                </label>
+                            }.into_any(),
+                        }
+                }).collect_view()}
+            </div>
+        </div>
+    }
+}
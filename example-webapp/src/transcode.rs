@@ -0,0 +1,108 @@
+//! Re-encoding pages in the browser before they go into a book, by drawing
+//! them onto an `OffscreenCanvas` and encoding that at a chosen quality.
+
+use bbf::BBFMediaType;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, File, ImageBitmap, ImageEncodeOptions, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d, js_sys,
+};
+
+/// What pages are re-encoded to when the book is compiled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    Original,
+    Webp,
+    Avif,
+}
+
+impl Target {
+    pub const ALL: [Self; 3] = [Self::Original, Self::Webp, Self::Avif];
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Original => "Keep original format",
+            Self::Webp => "Transcode to WebP",
+            Self::Avif => "Transcode to AVIF",
+        }
+    }
+
+    pub const fn key(self) -> &'static str {
+        match self {
+            Self::Original => "original",
+            Self::Webp => "webp",
+            Self::Avif => "avif",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.key() == key)
+            .unwrap_or(Self::Original)
+    }
+
+    const fn mime(self) -> Option<&'static str> {
+        match self {
+            Self::Original => None,
+            Self::Webp => Some("image/webp"),
+            Self::Avif => Some("image/avif"),
+        }
+    }
+
+    pub const fn media_type(self) -> Option<BBFMediaType> {
+        match self {
+            Self::Original => None,
+            Self::Webp => Some(BBFMediaType::Webp),
+            Self::Avif => Some(BBFMediaType::Avif),
+        }
+    }
+}
+
+/// Whether a page of type `media` can go through a canvas without losing
+/// anything besides quality. Animated GIFs would lose their animation.
+pub fn can_transcode(media: BBFMediaType) -> bool {
+    matches!(
+        media,
+        BBFMediaType::Png
+            | BBFMediaType::Jpg
+            | BBFMediaType::Bmp
+            | BBFMediaType::Webp
+            | BBFMediaType::Avif
+    )
+}
+
+/// `file` re-encoded as `target` at `quality` (0 to 1). `None` when the
+/// browser can't decode the file or can't encode to `target`; some fall back
+/// to PNG for formats they don't write, which is caught here too.
+pub async fn transcode(file: &File, target: Target, quality: f64) -> Option<Vec<u8>> {
+    let mime = target.mime()?;
+    let window = web_sys::window()?;
+    let bitmap: ImageBitmap = JsFuture::from(window.create_image_bitmap_with_blob(file).ok()?)
+        .await
+        .ok()?
+        .unchecked_into();
+
+    let canvas = OffscreenCanvas::new(bitmap.width(), bitmap.height()).ok()?;
+    let context: OffscreenCanvasRenderingContext2d =
+        canvas.get_context("2d").ok()??.dyn_into().ok()?;
+    context
+        .draw_image_with_image_bitmap(&bitmap, 0.0, 0.0)
+        .ok()?;
+    bitmap.close();
+
+    let options = ImageEncodeOptions::new();
+    options.set_type(mime);
+    options.set_quality(quality);
+    let blob: Blob = JsFuture::from(canvas.convert_to_blob_with_options(&options).ok()?)
+        .await
+        .ok()?
+        .unchecked_into();
+    if blob.type_() != mime {
+        return None;
+    }
+
+    let buffer = JsFuture::from(blob.array_buffer()).await.ok()?;
+    Some(js_sys::Uint8Array::new(&buffer).to_vec())
+}
@@ -1,5 +1,10 @@
+use bbf::format::BBFPageEntry;
+use bbf::{BBFMediaType, BBFReader};
 use wasm_bindgen::prelude::*;
-use web_sys::{Blob, File, FileReader, js_sys};
+use web_sys::{
+    Blob, DataTransfer, File, FileReader, FileSystemDirectoryEntry, FileSystemEntry,
+    FileSystemFileEntry, Url, js_sys,
+};
 
 pub async fn read_file_to_vec(file: &File) -> Result<Vec<u8>, JsValue> {
     let reader = FileReader::new()?;
@@ -65,3 +70,163 @@ pub fn download_blob(data: &[u8], filename: &str, mime: &str) -> Result<(), JsVa
 
     Ok(())
 }
+
+pub const fn mime_type(media_type: BBFMediaType) -> &'static str {
+    match media_type {
+        BBFMediaType::Png => "image/png",
+        BBFMediaType::Jpg => "image/jpeg",
+        BBFMediaType::Avif => "image/avif",
+        BBFMediaType::Webp => "image/webp",
+        BBFMediaType::Jxl => "image/jxl",
+        BBFMediaType::Bmp => "image/bmp",
+        BBFMediaType::Gif => "image/gif",
+        BBFMediaType::Tiff => "image/tiff",
+        BBFMediaType::Unknown | BBFMediaType::Other(_) => "application/octet-stream",
+    }
+}
+
+/// Copies `data` into a Blob and returns an object URL for it. The caller
+/// owns the URL and should revoke it once it's no longer shown.
+pub fn blob_url(data: &[u8], mime: &str) -> Result<String, JsValue> {
+    let array = js_sys::Array::new();
+    array.push(&js_sys::Uint8Array::from(data).buffer());
+
+    let bag = web_sys::BlobPropertyBag::new();
+    bag.set_type(mime);
+    let blob = Blob::new_with_blob_sequence_and_options(&array, &bag)?;
+    Url::create_object_url_with_blob(&blob)
+}
+
+/// Whether the book reads right to left, going by the ComicInfo `Manga`
+/// field that `bbfmux` carries over into metadata.
+pub fn is_right_to_left<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> bool {
+    reader.metadata().iter().any(|m| {
+        reader.get_string(m.key_offset.get()) == Some("Manga")
+            && reader.get_string(m.val_offset.get()) == Some("YesAndRightToLeft")
+    })
+}
+
+/// The pages shown together in spread mode, in reading order. The first
+/// page, covers and pages flagged as spreads stand alone; every other page
+/// is paired with the one after it.
+pub fn spread_groups<T: AsRef<[u8]>>(reader: &BBFReader<T>) -> Vec<(u32, Option<u32>)> {
+    let alone = |i: usize| {
+        i == 0 || reader.pages()[i].flags.get() & (BBFPageEntry::SPREAD | BBFPageEntry::COVER) != 0
+    };
+    let count = reader.pages().len();
+    let mut groups = Vec::new();
+    let mut i = 0;
+    while i < count {
+        if !alone(i) && i + 1 < count && !alone(i + 1) {
+            groups.push((i as u32, Some(i as u32 + 1)));
+            i += 2;
+        } else {
+            groups.push((i as u32, None));
+            i += 1;
+        }
+    }
+    groups
+}
+
+/// Lets the browser handle pending events and repaint before continuing.
+pub async fn yield_now() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let scheduled =
+            web_sys::window().is_some_and(|w| w.set_timeout_with_callback(&resolve).is_ok());
+        if !scheduled {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// `bytes` in the largest binary unit that keeps it above one.
+pub fn human_size(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+pub fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+/// The top-level entries of a drop. They have to be taken before the drop
+/// handler returns, after which the browser empties the list.
+pub fn dropped_entries(data: &DataTransfer) -> Vec<FileSystemEntry> {
+    let items = data.items();
+    (0..items.length())
+        .filter_map(|i| items.get(i))
+        .filter(|item| item.kind() == "file")
+        .filter_map(|item| item.webkit_get_as_entry().ok().flatten())
+        .collect()
+}
+
+/// Every file under `entries`, with directories walked depth-first in name
+/// order, each with its path inside the drop.
+pub async fn entry_files(entries: Vec<FileSystemEntry>) -> Vec<(String, File)> {
+    let mut files = Vec::new();
+    let mut stack: Vec<FileSystemEntry> = entries.into_iter().rev().collect();
+    while let Some(entry) = stack.pop() {
+        if entry.is_directory() {
+            let mut children = directory_entries(entry.unchecked_ref()).await;
+            children.sort_by_key(FileSystemEntry::name);
+            stack.extend(children.into_iter().rev());
+        } else if let Some(file) = entry_file(entry.unchecked_ref()).await {
+            let path = entry.full_path().trim_start_matches('/').to_string();
+            files.push((path, file));
+        }
+    }
+    files
+}
+
+/// Waits for a callback-style API that reports through `success` or `error`.
+async fn callback_result(
+    start: impl FnOnce(&js_sys::Function, &js_sys::Function) -> Result<(), JsValue>,
+) -> Result<JsValue, JsValue> {
+    let mut start = Some(start);
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        if let Some(start) = start.take()
+            && let Err(e) = start(&resolve, &reject)
+        {
+            let _ = reject.call1(&JsValue::NULL, &e);
+        }
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await
+}
+
+async fn directory_entries(dir: &FileSystemDirectoryEntry) -> Vec<FileSystemEntry> {
+    let reader = dir.create_reader();
+    let mut entries = Vec::new();
+    // Entries come in batches until an empty one.
+    while let Ok(batch) =
+        callback_result(|ok, err| reader.read_entries_with_callback_and_callback(ok, err)).await
+    {
+        let batch = js_sys::Array::from(&batch);
+        if batch.length() == 0 {
+            break;
+        }
+        entries.extend(batch.iter().map(JsCast::unchecked_into::<FileSystemEntry>));
+    }
+    entries
+}
+
+async fn entry_file(entry: &FileSystemFileEntry) -> Option<File> {
+    callback_result(|ok, err| {
+        entry.file_with_callback_and_callback(ok, err);
+        Ok(())
+    })
+    .await
+    .ok()?
+    .dyn_into()
+    .ok()
+}
@@ -0,0 +1,55 @@
+// Keeps the app shell and WASM bundle available offline. Books never pass
+// through here: they're read from local files and the IndexedDB library.
+
+const CACHE = "bbf-web-tools-v1";
+const SHELL = [
+  "./",
+  "index.html",
+  "manifest.webmanifest",
+  "icon.svg",
+  "pkg/example_webapp.js",
+  "pkg/example_webapp_bg.wasm",
+];
+
+self.addEventListener("install", (event) => {
+  event.waitUntil(
+    caches.open(CACHE).then((cache) => cache.addAll(SHELL)).then(() => self.skipWaiting()),
+  );
+});
+
+self.addEventListener("activate", (event) => {
+  event.waitUntil(
+    caches
+      .keys()
+      .then((keys) => Promise.all(keys.filter((k) => k !== CACHE).map((k) => caches.delete(k))))
+      .then(() => self.clients.claim()),
+  );
+});
+
+// Serve from the cache straight away and refresh it from the network, so a
+// new deployment is picked up on the next visit.
+self.addEventListener("fetch", (event) => {
+  const request = event.request;
+  if (request.method !== "GET" || new URL(request.url).origin !== self.location.origin) {
+    return;
+  }
+
+  event.respondWith(
+    caches.open(CACHE).then(async (cache) => {
+      const cached = await cache.match(request, { ignoreSearch: true });
+      const fetched = fetch(request)
+        .then((response) => {
+          if (response.ok) {
+            cache.put(request, response.clone());
+          }
+          return response;
+        })
+        .catch(() => cached);
+      if (cached) {
+        event.waitUntil(fetched);
+        return cached;
+      }
+      return fetched;
+    }),
+  );
+});
@@ -0,0 +1,24 @@
+//! Every asset read and decoded, plus the ranges a remote reader would fetch.
+
+#![no_main]
+#![allow(clippy::cast_possible_truncation)]
+
+use bbf::BBFReader;
+use bbf::remote::asset_range;
+use bbf_fuzz::Book;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|book: Book| {
+    let data = book.to_bytes();
+    let Ok(reader) = BBFReader::new(&data[..]) else {
+        return;
+    };
+    let count = reader.assets().len() as u32;
+    for i in 0..=count {
+        let _ = reader.get_asset_decoded(i);
+        let _ = asset_range(&reader, i);
+    }
+    for page in reader.pages() {
+        let _ = reader.get_asset(page.asset_index.get());
+    }
+});
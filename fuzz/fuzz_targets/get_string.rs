@@ -0,0 +1,25 @@
+//! String pool lookups at the offsets the tables hold and at arbitrary ones.
+
+#![no_main]
+
+use bbf::BBFReader;
+use bbf_fuzz::Book;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Book, Vec<u32>)| {
+    let (book, offsets) = input;
+    let data = book.to_bytes();
+    let Ok(reader) = BBFReader::new(&data[..]) else {
+        return;
+    };
+    for offset in offsets {
+        let _ = reader.get_string(offset);
+    }
+    for s in reader.sections() {
+        let _ = reader.get_string(s.section_title_offset.get());
+    }
+    for m in reader.metadata() {
+        let _ = reader.get_string(m.key_offset.get());
+        let _ = reader.get_string(m.val_offset.get());
+    }
+});
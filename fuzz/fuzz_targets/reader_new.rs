@@ -0,0 +1,23 @@
+//! Opens arbitrary bytes and reads everything the index points at.
+
+#![no_main]
+
+use bbf::BBFReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(reader) = BBFReader::new(data) else {
+        return;
+    };
+    let _ = (reader.assets(), reader.pages(), reader.sections());
+    let _ = reader.metadata();
+    for ext in reader.extensions() {
+        let _ = reader.extension_data(ext.extension_type.get());
+    }
+    for t in reader.thumbnails() {
+        let _ = reader.thumbnail(t.page_index.get());
+    }
+    for n in reader.page_names() {
+        let _ = reader.page_name(n.page_index.get());
+    }
+});
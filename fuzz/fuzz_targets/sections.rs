@@ -0,0 +1,23 @@
+//! The walks over the section table's parent links: statistics, validation
+//! and comparing a book with itself.
+
+#![no_main]
+
+use bbf::validate::{Options, Profile, validate};
+use bbf::{BBFReader, compare, stats::BookStats};
+use bbf_fuzz::Book;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|book: Book| {
+    let data = book.to_bytes();
+    let Ok(reader) = BBFReader::new(&data[..]) else {
+        return;
+    };
+    let _ = BookStats::compute(&reader, 10);
+    let options = Options {
+        check_hashes: false,
+        ..Options::for_profile(Profile::Standard)
+    };
+    let _ = validate(&reader, &options);
+    let _ = compare(&reader, &reader);
+});
@@ -0,0 +1,76 @@
+//! Shared input for the fuzz targets: a book laid out like the builder would
+//! write it, with every table entry left to the fuzzer. Raw bytes rarely get
+//! past the magic and footer checks; this reaches the code behind them.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use bbf::format::{
+    BBFAssetEntry, BBFExpansionHeader, BBFFooter, BBFHeader, BBFMetadata, BBFPageEntry, BBFSection,
+};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use zerocopy::IntoBytes;
+
+#[derive(Debug, Arbitrary)]
+pub struct Book {
+    pub header: BBFHeader,
+    pub asset_data: Vec<u8>,
+    pub strings: Vec<u8>,
+    pub assets: Vec<BBFAssetEntry>,
+    pub pages: Vec<BBFPageEntry>,
+    pub sections: Vec<BBFSection>,
+    pub metadata: Vec<BBFMetadata>,
+    /// Written after the tables, without a terminator unless the fuzzer
+    /// adds one.
+    pub extensions: Vec<BBFExpansionHeader>,
+    pub extension_data: Vec<u8>,
+    /// Left as generated instead of pointing at the tables above.
+    pub raw_footer: Option<BBFFooter>,
+}
+
+impl Book {
+    /// The file: header, asset and extension data, string pool, tables,
+    /// expansion table, footer.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = self.header;
+        header.magic = *b"BBF1";
+
+        let mut out = header.as_bytes().to_vec();
+        out.extend_from_slice(&self.asset_data);
+        out.extend_from_slice(&self.extension_data);
+
+        let string_pool_offset = out.len() as u64;
+        out.extend_from_slice(&self.strings);
+        let asset_table_offset = out.len() as u64;
+        out.extend_from_slice(self.assets.as_bytes());
+        let page_table_offset = out.len() as u64;
+        out.extend_from_slice(self.pages.as_bytes());
+        let section_table_offset = out.len() as u64;
+        out.extend_from_slice(self.sections.as_bytes());
+        let meta_table_offset = out.len() as u64;
+        out.extend_from_slice(self.metadata.as_bytes());
+        let extra_offset = if self.extensions.is_empty() {
+            0
+        } else {
+            out.len() as u64
+        };
+        out.extend_from_slice(self.extensions.as_bytes());
+
+        let footer = self.raw_footer.unwrap_or_else(|| BBFFooter {
+            string_pool_offset: string_pool_offset.into(),
+            asset_table_offset: asset_table_offset.into(),
+            asset_count: (self.assets.len() as u32).into(),
+            page_table_offset: page_table_offset.into(),
+            page_count: (self.pages.len() as u32).into(),
+            section_table_offset: section_table_offset.into(),
+            section_count: (self.sections.len() as u32).into(),
+            meta_table_offset: meta_table_offset.into(),
+            key_count: (self.metadata.len() as u32).into(),
+            extra_offset: extra_offset.into(),
+            index_hash: 0.into(),
+            magic: *b"BBF1",
+        });
+        out.extend_from_slice(footer.as_bytes());
+        out
+    }
+}